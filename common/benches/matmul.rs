@@ -0,0 +1,87 @@
+use common::linalg::{ColumnVector, Matrix};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn random_matrix(rows: usize, columns: usize) -> Matrix {
+    Matrix::new_matrix_with_random_values_from_uniform_distribution(rows, columns, -1.0, 1.0)
+}
+
+fn bench_multiply(c: &mut Criterion) {
+    let mut group = c.benchmark_group("multiply");
+    group.sample_size(10);
+
+    for size in [32, 128, 512] {
+        let a = random_matrix(size, size);
+        let b = random_matrix(size, size);
+
+        group.bench_with_input(BenchmarkId::new("naive", size), &size, |bencher, _| {
+            bencher.iter(|| black_box(a.multiply_naive(&b)))
+        });
+        group.bench_with_input(BenchmarkId::new("gemm", size), &size, |bencher, _| {
+            bencher.iter(|| black_box(a.multiply(&b)))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_multiply_transposed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("multiply_transposed");
+    group.sample_size(10);
+
+    for size in [128, 512] {
+        let a = random_matrix(size, size);
+        let b = random_matrix(size, size);
+
+        group.bench_with_input(
+            BenchmarkId::new("naive_materialised_transpose", size),
+            &size,
+            |bencher, _| bencher.iter(|| black_box(a.transpose().multiply_naive(&b))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("transpose_multiply", size),
+            &size,
+            |bencher, _| bencher.iter(|| black_box(a.transpose_multiply(&b))),
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_mult_vector(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mult_vector");
+
+    // roughly the shapes of the MNIST network's layers, plus a large one
+    for (rows, columns) in [(100, 784), (10, 50), (2000, 2000)] {
+        let m = random_matrix(rows, columns);
+        let v = ColumnVector::from(random_matrix(columns, 1));
+        let v_t = ColumnVector::from(random_matrix(rows, 1));
+        let id = format!("{}x{}", rows, columns);
+
+        group.bench_with_input(BenchmarkId::new("naive", &id), &id, |bencher, _| {
+            bencher.iter(|| black_box(m.mult_vector_naive(&v)))
+        });
+        group.bench_with_input(BenchmarkId::new("gemv", &id), &id, |bencher, _| {
+            bencher.iter(|| black_box(m.mult_vector(&v)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("naive_materialised_transpose", &id),
+            &id,
+            |bencher, _| bencher.iter(|| black_box(m.transpose().mult_vector_naive(&v_t))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("transpose_mult_vector", &id),
+            &id,
+            |bencher, _| bencher.iter(|| black_box(m.transpose_mult_vector(&v_t))),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_multiply,
+    bench_multiply_transposed,
+    bench_mult_vector
+);
+criterion_main!(benches);
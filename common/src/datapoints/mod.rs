@@ -1,16 +1,24 @@
-use crate::linalg::ColumnVector;
+use crate::linalg::{ColumnVector, Float};
 
 #[derive(Debug)]
-pub struct NDTrainingDataPoint {
-    pub input_v: ColumnVector,
-    pub desired_output_v: ColumnVector,
+pub struct NDTrainingDataPoint<T = f64> {
+    pub input_v: ColumnVector<T>,
+    pub desired_output_v: ColumnVector<T>,
 }
 
-impl NDTrainingDataPoint {
-    pub fn new(input_v: ColumnVector, desired_output_v: ColumnVector) -> Self {
+impl<T: Float> NDTrainingDataPoint<T> {
+    pub fn new(input_v: ColumnVector<T>, desired_output_v: ColumnVector<T>) -> Self {
         NDTrainingDataPoint {
             input_v,
             desired_output_v,
         }
     }
+
+    /// Converts the input and desired output to another float type, e.g. `f64` to `f32`.
+    pub fn cast<U: Float>(&self) -> NDTrainingDataPoint<U> {
+        NDTrainingDataPoint {
+            input_v: self.input_v.cast(),
+            desired_output_v: self.desired_output_v.cast(),
+        }
+    }
 }
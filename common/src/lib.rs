@@ -10,7 +10,7 @@ pub mod softmax;
 use rand::distributions::Distribution;
 use rand_distr::Normal;
 
-use linalg::{ColumnVector, Matrix};
+use linalg::{ColumnVector, Float, Matrix};
 
 // pub enum DistributionType {
 //     Uniform,
@@ -76,18 +76,34 @@ pub fn column_vec_of_random_values(min: f64, max: f64, size: usize) -> Matrix {
     Matrix::new_column_vector(&values)
 }
 
-pub fn column_vec_of_random_values_from_distribution(
+/// The values are drawn as `f64`s and then converted to `T`.
+pub fn column_vec_of_random_values_from_distribution<T: Float>(
     mean: f64,
     std_dev: f64,
     size: usize,
-) -> ColumnVector {
-    let mut rng = rand::thread_rng();
+) -> ColumnVector<T> {
+    column_vec_of_random_values_from_distribution_with_rng(
+        mean,
+        std_dev,
+        size,
+        &mut rand::thread_rng(),
+    )
+}
+
+/// Like `column_vec_of_random_values_from_distribution`, but draws from `rng` so that the values can be reproduced
+/// by seeding it.
+pub fn column_vec_of_random_values_from_distribution_with_rng<T: Float, R: Rng + ?Sized>(
+    mean: f64,
+    std_dev: f64,
+    size: usize,
+    rng: &mut R,
+) -> ColumnVector<T> {
     let normal = Normal::new(mean, std_dev).unwrap();
 
     let mut res = ColumnVector::empty();
     for _ in 0..size {
-        let x = normal.sample(&mut rng);
-        res.push(x);
+        let x = normal.sample(rng);
+        res.push(T::from_f64(x));
     }
     res
 }
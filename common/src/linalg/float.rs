@@ -0,0 +1,61 @@
+use std::fmt;
+use std::iter::Sum;
+
+/// The element type of a `Matrix` or `ColumnVector`.
+///
+/// Implemented for `f64`, which is the default everywhere, and `f32`, which halves the memory needed and makes
+/// the hot loops faster at the cost of precision.
+pub trait Float:
+    num_traits::Float
+    + num_traits::NumAssign
+    + Sum
+    + Default
+    + fmt::Debug
+    + fmt::Display
+    + Send
+    + Sync
+    + 'static
+{
+    /// Converts from an `f64`, rounding to the nearest representable value.
+    fn from_f64(x: f64) -> Self;
+
+    /// Converts to an `f64`. This is lossless for both `f32` and `f64`.
+    fn as_f64(self) -> f64;
+}
+
+impl Float for f64 {
+    #[inline]
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    #[inline]
+    fn as_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Float for f32 {
+    #[inline]
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    #[inline]
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_work() {
+        assert_eq!(f64::from_f64(0.1), 0.1);
+        assert_eq!(0.1_f64.as_f64(), 0.1);
+        assert_eq!(f32::from_f64(0.1), 0.1_f32);
+        assert_eq!(0.5_f32.as_f64(), 0.5);
+    }
+}
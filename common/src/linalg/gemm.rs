@@ -0,0 +1,357 @@
+//! Cache-blocked, multithreaded matrix-matrix (GEMM) and matrix-vector (GEMV) multiplication.
+//!
+//! The speedup comes from memory access patterns and threads. The results match `Matrix::multiply_naive` and
+//! `Matrix::mult_vector_naive` to within floating-point rounding, but aren't guaranteed to be bit-for-bit the same -
+//! the tests compare them with a tolerance.
+
+use rayon::prelude::*;
+
+use super::{Float, Matrix};
+
+/// Rows of the output computed by a single task.
+const MC: usize = 64;
+/// Depth of the inner dimension packed into each tile.
+const KC: usize = 128;
+/// Columns of the output computed per packed tile of the right hand operand.
+const NC: usize = 256;
+
+/// Below this many multiply-adds the work is done on the calling thread; spreading it across threads costs more than it saves.
+const PARALLEL_THRESHOLD: usize = 64 * 64 * 64;
+
+/// Whether an operand should be used as is or transposed.
+/// Transposing this way avoids materialising the transposed matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transpose {
+    No,
+    Yes,
+}
+
+/// A read-only view of a matrix, possibly transposed.
+#[derive(Clone, Copy)]
+struct Operand<'a, T> {
+    data: &'a [T],
+    /// The number of columns of the underlying (stored) matrix.
+    stride: usize,
+    rows: usize,
+    columns: usize,
+    transposed: bool,
+}
+
+impl<'a, T: Float> Operand<'a, T> {
+    fn new(m: &'a Matrix<T>, op: Transpose) -> Self {
+        match op {
+            Transpose::No => Self {
+                data: &m.data,
+                stride: m.num_columns,
+                rows: m.num_rows,
+                columns: m.num_columns,
+                transposed: false,
+            },
+            Transpose::Yes => Self {
+                data: &m.data,
+                stride: m.num_columns,
+                rows: m.num_columns,
+                columns: m.num_rows,
+                transposed: true,
+            },
+        }
+    }
+
+    #[inline]
+    fn get(&self, row: usize, column: usize) -> T {
+        if self.transposed {
+            self.data[column * self.stride + row]
+        } else {
+            self.data[row * self.stride + column]
+        }
+    }
+
+    /// Copies the `rows` x `columns` block starting at (`row0`, `column0`) into `packed`, row-major.
+    fn pack(&self, row0: usize, rows: usize, column0: usize, columns: usize, packed: &mut Vec<T>) {
+        packed.clear();
+        if self.transposed {
+            for i in row0..row0 + rows {
+                packed.extend((column0..column0 + columns).map(|j| self.get(i, j)));
+            }
+        } else {
+            for i in row0..row0 + rows {
+                let start = i * self.stride + column0;
+                packed.extend_from_slice(&self.data[start..start + columns]);
+            }
+        }
+    }
+}
+
+/// Computes `op_a(a) * op_b(b)`.
+///
+/// Panics if the inner dimensions don't match.
+pub fn gemm<T: Float>(a: &Matrix<T>, op_a: Transpose, b: &Matrix<T>, op_b: Transpose) -> Matrix<T> {
+    let a = Operand::new(a, op_a);
+    let b = Operand::new(b, op_b);
+
+    if a.columns != b.rows {
+        panic!(
+            "Matrix dimensions are not compatible for multiplication. The number of columns in the left operand ({}) must equal the number of rows in the right operand ({}).",
+            a.columns, b.rows
+        );
+    }
+
+    let m = a.rows;
+    let k = a.columns;
+    let n = b.columns;
+
+    let mut result = Matrix::new_zero_matrix(m, n);
+    if m == 0 || n == 0 || k == 0 {
+        return result;
+    }
+
+    // each task computes a block of MC full rows of the result
+    let compute_row_block = |(block_index, c_block): (usize, &mut [T])| {
+        let row0 = block_index * MC;
+        let rows = c_block.len() / n;
+
+        let mut a_packed = Vec::with_capacity(rows * KC);
+        let mut b_packed = Vec::with_capacity(KC * NC);
+
+        for pc in (0..k).step_by(KC) {
+            let kc = KC.min(k - pc);
+            a.pack(row0, rows, pc, kc, &mut a_packed);
+
+            for jc in (0..n).step_by(NC) {
+                let nc = NC.min(n - jc);
+                b.pack(pc, kc, jc, nc, &mut b_packed);
+
+                for i in 0..rows {
+                    let a_row = &a_packed[i * kc..(i + 1) * kc];
+                    let c_row = &mut c_block[i * n + jc..i * n + jc + nc];
+
+                    for (p, a_ip) in a_row.iter().enumerate() {
+                        let b_row = &b_packed[p * nc..(p + 1) * nc];
+                        c_row
+                            .iter_mut()
+                            .zip(b_row.iter())
+                            .for_each(|(c, b_pj)| *c += *a_ip * *b_pj);
+                    }
+                }
+            }
+        }
+    };
+
+    if m * n * k >= PARALLEL_THRESHOLD {
+        result
+            .data
+            .par_chunks_mut(MC * n)
+            .enumerate()
+            .for_each(compute_row_block);
+    } else {
+        result
+            .data
+            .chunks_mut(MC * n)
+            .enumerate()
+            .for_each(compute_row_block);
+    }
+
+    result
+}
+
+/// Computes `op_a(a) * x`.
+///
+/// Panics if the number of columns of `op_a(a)` doesn't match the length of `x`.
+pub fn gemv<T: Float>(a: &Matrix<T>, op_a: Transpose, x: &[T]) -> Vec<T> {
+    let a = Operand::new(a, op_a);
+
+    if a.columns != x.len() {
+        panic!(
+            "Matrix dimensions are not compatible for multiplication. The number of columns in the matrix ({}) must equal the number of elements in the vector ({}).",
+            a.columns,
+            x.len()
+        );
+    }
+
+    let parallel = a.rows * a.columns >= PARALLEL_THRESHOLD;
+    let mut result = vec![T::zero(); a.rows];
+
+    if a.transposed {
+        // result[j] = sum over i of stored[i][j] * x[i]; walking the stored rows in order keeps the reads contiguous
+        let compute_column_block = |(block_index, out): (usize, &mut [T])| {
+            let column0 = block_index * NC;
+            for (i, x_i) in x.iter().enumerate() {
+                let start = i * a.stride + column0;
+                let stored_row = &a.data[start..start + out.len()];
+                out.iter_mut()
+                    .zip(stored_row.iter())
+                    .for_each(|(o, a_ij)| *o += *a_ij * *x_i);
+            }
+        };
+
+        if parallel {
+            result
+                .par_chunks_mut(NC)
+                .enumerate()
+                .for_each(compute_column_block);
+        } else {
+            result
+                .chunks_mut(NC)
+                .enumerate()
+                .for_each(compute_column_block);
+        }
+    } else {
+        let dot_row = |(i, out): (usize, &mut T)| {
+            let row = &a.data[i * a.stride..i * a.stride + a.columns];
+            let mut sum = T::zero();
+            for (a_ik, x_k) in row.iter().zip(x.iter()) {
+                sum += *a_ik * *x_k;
+            }
+            *out = sum;
+        };
+
+        if parallel {
+            result
+                .par_iter_mut()
+                .enumerate()
+                .for_each(dot_row);
+        } else {
+            result
+                .iter_mut()
+                .enumerate()
+                .for_each(dot_row);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linalg::ColumnVector;
+
+    const TOLERANCE: f64 = 1e-10;
+
+    fn random_matrix(rows: usize, columns: usize) -> Matrix {
+        Matrix::new_matrix_with_random_values_from_uniform_distribution(rows, columns, -1.0, 1.0)
+    }
+
+    fn assert_close(actual: &Matrix, expected: &Matrix) {
+        assert_eq!(actual.shape(), expected.shape());
+        for (a, e) in actual.data.iter().zip(expected.data.iter()) {
+            assert!(
+                (a - e).abs() <= TOLERANCE * (1.0 + e.abs()),
+                "{} vs {}",
+                a,
+                e
+            );
+        }
+    }
+
+    // shapes that are smaller than, equal to, and not a multiple of the block sizes, in both serial and parallel territory
+    const SHAPES: [(usize, usize, usize); 7] = [
+        (1, 1, 1),
+        (3, 5, 2),
+        (1, 300, 1),
+        (64, 128, 256),
+        (65, 129, 257),
+        (130, 70, 300),
+        (200, 300, 30),
+    ];
+
+    #[test]
+    fn gemm_matches_naive_multiply() {
+        for (m, k, n) in SHAPES {
+            let a = random_matrix(m, k);
+            let b = random_matrix(k, n);
+            let expected = a.multiply_naive(&b);
+            assert_close(&gemm(&a, Transpose::No, &b, Transpose::No), &expected);
+        }
+    }
+
+    #[test]
+    fn gemm_with_transposed_operands_matches_naive_multiply() {
+        for (m, k, n) in SHAPES {
+            let a = random_matrix(m, k);
+            let b = random_matrix(k, n);
+            let a_t = a.transpose();
+            let b_t = b.transpose();
+            let expected = a.multiply_naive(&b);
+
+            assert_close(&gemm(&a_t, Transpose::Yes, &b, Transpose::No), &expected);
+            assert_close(&gemm(&a, Transpose::No, &b_t, Transpose::Yes), &expected);
+            assert_close(&gemm(&a_t, Transpose::Yes, &b_t, Transpose::Yes), &expected);
+        }
+    }
+
+    #[test]
+    fn gemm_works_for_f32() {
+        for (m, k, n) in SHAPES {
+            let a = random_matrix(m, k);
+            let b = random_matrix(k, n);
+            let expected = a.multiply_naive(&b);
+
+            let a_f32 = Matrix::new_with_shape_and_values(
+                &a.shape(),
+                &a.data
+                    .iter()
+                    .map(|x| *x as f32)
+                    .collect::<Vec<f32>>(),
+            );
+            let b_f32 = Matrix::new_with_shape_and_values(
+                &b.shape(),
+                &b.data
+                    .iter()
+                    .map(|x| *x as f32)
+                    .collect::<Vec<f32>>(),
+            );
+            let actual = gemm(&a_f32, Transpose::No, &b_f32, Transpose::No);
+
+            assert_eq!(actual.shape(), expected.shape());
+            for (a, e) in actual.data.iter().zip(expected.data.iter()) {
+                // the inputs were rounded to f32 and each product accumulates k rounding errors
+                assert!((*a as f64 - e).abs() <= 1e-5 * k as f64, "{} vs {}", a, e);
+            }
+        }
+    }
+
+    #[test]
+    fn gemm_handles_empty_matrices() {
+        let a: Matrix = Matrix::new_zero_matrix(3, 0);
+        let b = Matrix::new_zero_matrix(0, 4);
+        let result = gemm(&a, Transpose::No, &b, Transpose::No);
+        assert_eq!(result, Matrix::new_zero_matrix(3, 4));
+    }
+
+    #[test]
+    #[should_panic]
+    fn gemm_panics_on_dimension_mismatch() {
+        let a = random_matrix(2, 3);
+        let b = random_matrix(2, 3);
+        gemm(&a, Transpose::No, &b, Transpose::No);
+    }
+
+    #[test]
+    fn gemv_matches_naive_mult_vector() {
+        for (m, k, _) in SHAPES {
+            let a = random_matrix(m, k);
+            let x = ColumnVector::from(random_matrix(k, 1));
+            let expected = a.mult_vector_naive(&x);
+
+            let actual = gemv(&a, Transpose::No, &x);
+            assert_close(
+                &Matrix::new_column_vector(&actual),
+                &Matrix::new_column_vector(&expected),
+            );
+
+            let actual = gemv(&a.transpose(), Transpose::Yes, &x);
+            assert_close(
+                &Matrix::new_column_vector(&actual),
+                &Matrix::new_column_vector(&expected),
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn gemv_panics_on_dimension_mismatch() {
+        let a = random_matrix(2, 3);
+        gemv(&a, Transpose::No, &[1.0, 2.0]);
+    }
+}
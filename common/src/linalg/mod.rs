@@ -1,37 +1,43 @@
 use rand::distributions::{Distribution, Uniform};
+use rand::Rng;
 use rand_distr::Normal;
 use rayon::prelude::*;
 use std::fmt;
 use std::ops::Deref;
 use std::ops::DerefMut;
 
-pub fn square_ref(x: &f64) -> f64 {
-    x * x
+mod float;
+mod gemm;
+pub use float::Float;
+pub use gemm::{gemm, gemv, Transpose};
+
+pub fn square_ref<T: Float>(x: &T) -> T {
+    *x * *x
 }
 
-pub fn square(x: f64) -> f64 {
+pub fn square<T: Float>(x: T) -> T {
     x * x
 }
 
 // TODO: also make this a method on ColumnVector
-pub fn euclidian_length(v: &[f64]) -> f64 {
+pub fn euclidian_length<T: Float>(v: &[T]) -> T {
     v.iter()
         .map(|vi| vi.powi(2))
-        .sum::<f64>()
+        .sum::<T>()
         .sqrt()
 }
 
 // TODO: also make this a method on ColumnVector
-// consider making an `into` so you can go from a Vec<f64> to a ColumnVector (if I haven't already)
-pub fn euclidian_distance(v0: &[f64], v1: &[f64]) -> f64 {
+// consider making an `into` so you can go from a Vec<T> to a ColumnVector (if I haven't already)
+pub fn euclidian_distance<T: Float>(v0: &[T], v1: &[T]) -> T {
     if v0.len() != v1.len() {
         panic!("v0 and v1 must have the same length");
     }
 
     v0.iter()
         .zip(v1.iter())
-        .map(|(x0, x1)| (x1 - x0).powi(2))
-        .sum::<f64>()
+        .map(|(x0, x1)| (*x1 - *x0).powi(2))
+        .sum::<T>()
         .sqrt()
 }
 
@@ -66,13 +72,13 @@ impl MatrixShape {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Matrix {
+pub struct Matrix<T = f64> {
     num_rows: usize,
     num_columns: usize,
-    pub data: Vec<f64>,
+    pub data: Vec<T>,
 }
 
-impl Matrix {
+impl<T: Float> Matrix<T> {
     pub fn num_rows(&self) -> usize {
         self.num_rows
     }
@@ -85,7 +91,7 @@ impl Matrix {
         MatrixShape::new(self.num_rows, self.num_columns)
     }
 
-    pub fn empty_with_num_rows(num_rows: usize) -> Matrix {
+    pub fn empty_with_num_rows(num_rows: usize) -> Matrix<T> {
         Matrix {
             num_rows,
             num_columns: 0,
@@ -93,7 +99,7 @@ impl Matrix {
         }
     }
 
-    pub fn empty_with_num_cols(num_columns: usize) -> Matrix {
+    pub fn empty_with_num_cols(num_columns: usize) -> Matrix<T> {
         Matrix {
             num_rows: 0,
             num_columns,
@@ -101,7 +107,7 @@ impl Matrix {
         }
     }
 
-    pub fn init(num_rows: usize, num_columns: usize, init_value: f64) -> Matrix {
+    pub fn init(num_rows: usize, num_columns: usize, init_value: T) -> Matrix<T> {
         Matrix {
             num_rows: num_rows,
             num_columns: num_columns,
@@ -109,7 +115,7 @@ impl Matrix {
         }
     }
 
-    pub fn new_with_shape_and_values(shape: &MatrixShape, values: &[f64]) -> Self {
+    pub fn new_with_shape_and_values(shape: &MatrixShape, values: &[T]) -> Self {
         Self {
             num_rows: shape.rows,
             num_columns: shape.columns,
@@ -117,7 +123,7 @@ impl Matrix {
         }
     }
 
-    pub fn new_column_vector(items: &[f64]) -> Self {
+    pub fn new_column_vector(items: &[T]) -> Self {
         Self {
             num_rows: items.len(),
             num_columns: 1,
@@ -125,7 +131,7 @@ impl Matrix {
         }
     }
 
-    pub fn new_row_vector(items: &[f64]) -> Self {
+    pub fn new_row_vector(items: &[T]) -> Self {
         Self {
             num_rows: 1,
             num_columns: items.len(),
@@ -134,9 +140,9 @@ impl Matrix {
     }
 
     pub fn new_identity_matrix(size: usize) -> Self {
-        let mut m = Self::init(size, size, 0.0);
+        let mut m = Self::init(size, size, T::zero());
         for i in 0..size {
-            m.set(i, i, 1.0);
+            m.set(i, i, T::one());
         }
         m
     }
@@ -145,7 +151,7 @@ impl Matrix {
         Self {
             num_rows,
             num_columns,
-            data: vec![0.0; num_rows * num_columns],
+            data: vec![T::zero(); num_rows * num_columns],
         }
     }
 
@@ -153,46 +159,82 @@ impl Matrix {
         Self {
             num_rows: shape.rows,
             num_columns: shape.columns,
-            data: vec![0.0; shape.rows * shape.columns],
+            data: vec![T::zero(); shape.rows * shape.columns],
         }
     }
 
+    /// The values are drawn as `f64`s and then converted to `T`, so the distribution parameters are always `f64`s.
     pub fn new_matrix_with_random_values_from_normal_distribution(
         num_rows: usize,
         num_columns: usize,
         mean: f64,
         std_dev: f64,
+    ) -> Self {
+        Self::new_matrix_with_random_values_from_normal_distribution_with_rng(
+            num_rows,
+            num_columns,
+            mean,
+            std_dev,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Like `new_matrix_with_random_values_from_normal_distribution`, but draws from `rng` so that the values can be
+    /// reproduced by seeding it. The values are drawn row by row.
+    pub fn new_matrix_with_random_values_from_normal_distribution_with_rng<R: Rng + ?Sized>(
+        num_rows: usize,
+        num_columns: usize,
+        mean: f64,
+        std_dev: f64,
+        rng: &mut R,
     ) -> Self {
         let mut matrix = Self::new_zero_matrix(num_rows, num_columns);
 
-        let mut rng = rand::thread_rng();
         let normal = Normal::new(mean, std_dev).unwrap();
 
         for m in 0..num_rows {
             for n in 0..num_columns {
-                let x = normal.sample(&mut rng);
-                matrix.set(m, n, x);
+                let x = normal.sample(rng);
+                matrix.set(m, n, T::from_f64(x));
             }
         }
 
         matrix
     }
 
+    /// The values are drawn as `f64`s and then converted to `T`, so the distribution parameters are always `f64`s.
     pub fn new_matrix_with_random_values_from_uniform_distribution(
         num_rows: usize,
         num_columns: usize,
         min: f64,
         max: f64,
+    ) -> Self {
+        Self::new_matrix_with_random_values_from_uniform_distribution_with_rng(
+            num_rows,
+            num_columns,
+            min,
+            max,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Like `new_matrix_with_random_values_from_uniform_distribution`, but draws from `rng` so that the values can be
+    /// reproduced by seeding it. The values are drawn row by row.
+    pub fn new_matrix_with_random_values_from_uniform_distribution_with_rng<R: Rng + ?Sized>(
+        num_rows: usize,
+        num_columns: usize,
+        min: f64,
+        max: f64,
+        rng: &mut R,
     ) -> Self {
         let mut matrix = Self::new_zero_matrix(num_rows, num_columns);
 
-        let mut rng = rand::thread_rng();
         let distribution = Uniform::new(min, max); // TODO: creates a uniform distribution over [min, max). Consider finding a way to make this a Uniform distribution over [min, max]
 
         for m in 0..num_rows {
             for n in 0..num_columns {
-                let x = distribution.sample(&mut rng);
-                matrix.set(m, n, x);
+                let x = distribution.sample(rng);
+                matrix.set(m, n, T::from_f64(x));
             }
         }
 
@@ -204,7 +246,7 @@ impl Matrix {
     // from_rows![(1.0, 2.0, 3.0), (4.0, 5.0, 6.0)];
 
     // this isn't great. The macro version could be nicer
-    pub fn from_columns(columns: Vec<Vec<f64>>) -> Self {
+    pub fn from_columns(columns: Vec<Vec<T>>) -> Self {
         // check that the dimension of each inner vec is the same
 
         if columns.len() == 0 {
@@ -232,19 +274,19 @@ impl Matrix {
         matrix
     }
 
-    pub fn get(&self, row: usize, column: usize) -> f64 {
+    pub fn get(&self, row: usize, column: usize) -> T {
         self.data[row * self.num_columns + column]
     }
 
-    pub fn set(&mut self, row: usize, column: usize, value: f64) {
+    pub fn set(&mut self, row: usize, column: usize, value: T) {
         self.data[row * self.num_columns + column] = value;
     }
 
-    pub fn push_row(&mut self, row: &[f64]) {
+    pub fn push_row(&mut self, row: &[T]) {
         self.data.extend_from_slice(row);
     }
 
-    pub fn push_column(&mut self, column: &[f64]) {
+    pub fn push_column(&mut self, column: &[T]) {
         let mut i = 0_usize;
         for item in column {
             i += self.num_columns;
@@ -272,57 +314,91 @@ impl Matrix {
         }
     }
 
-    pub fn mult_scalar(&self, scalar: f64) -> Self {
+    pub fn mult_scalar(&self, scalar: T) -> Self {
         Self {
             num_rows: self.num_rows,
             num_columns: self.num_columns,
             data: self
                 .data
                 .iter()
-                .map(|x| x * scalar)
+                .map(|x| *x * scalar)
                 .collect(),
         }
     }
 
-    pub fn mult_scalar_mut(&mut self, scalar: f64) {
+    pub fn mult_scalar_mut(&mut self, scalar: T) {
         self.data
             .iter_mut()
             .for_each(|x| *x *= scalar);
     }
 
-    pub fn mult_scalar_mut_chain(&mut self, scalar: f64) -> &mut Self {
+    pub fn mult_scalar_mut_chain(&mut self, scalar: T) -> &mut Self {
         self.data
             .iter_mut()
             .for_each(|x| *x *= scalar);
         self
     }
 
-    pub fn div_scalar(&self, scalar: f64) -> Self {
+    pub fn div_scalar(&self, scalar: T) -> Self {
         Self {
             num_rows: self.num_rows,
             num_columns: self.num_columns,
             data: self
                 .data
                 .iter()
-                .map(|x| x / scalar)
+                .map(|x| *x / scalar)
                 .collect(),
         }
     }
 
-    pub fn div_scalar_mut(&mut self, scalar: f64) {
+    pub fn div_scalar_mut(&mut self, scalar: T) {
         self.data
             .iter_mut()
             .for_each(|x| *x /= scalar);
     }
 
+    /// Computes self * other. See `gemm` for how this is done.
     pub fn multiply(&self, other: &Self) -> Self {
+        if self.num_columns != other.num_rows {
+            panic!("Matrix dimensions are not compatible for multiplication. The number of columns in self must equal the number of rows in other.");
+        }
+        gemm(self, Transpose::No, other, Transpose::No)
+    }
+
+    /// Computes self.transpose() * other without materialising the transpose.
+    pub fn transpose_multiply(&self, other: &Self) -> Self {
+        gemm(self, Transpose::Yes, other, Transpose::No)
+    }
+
+    /// Computes self * other.transpose() without materialising the transpose.
+    pub fn multiply_transpose(&self, other: &Self) -> Self {
+        gemm(self, Transpose::No, other, Transpose::Yes)
+    }
+
+    pub fn mult_vector(&self, v: &ColumnVector<T>) -> ColumnVector<T> {
+        if self.num_columns != v.num_elements() {
+            panic!("Matrix dimensions are not compatible for multiplication. The number of columns in self must equal the number of elements (rows) in v.");
+        }
+        ColumnVector::from_vec(gemv(self, Transpose::No, v))
+    }
+
+    /// Computes self.transpose() * v without materialising the transpose.
+    pub fn transpose_mult_vector(&self, v: &ColumnVector<T>) -> ColumnVector<T> {
+        if self.num_rows != v.num_elements() {
+            panic!("Matrix dimensions are not compatible for multiplication. The number of rows in self must equal the number of elements (rows) in v.");
+        }
+        ColumnVector::from_vec(gemv(self, Transpose::Yes, v))
+    }
+
+    /// The straightforward triple loop version of `multiply`. Kept as a reference for tests and benchmarks.
+    pub fn multiply_naive(&self, other: &Self) -> Self {
         if self.num_columns != other.num_rows {
             panic!("Matrix dimensions are not compatible for multiplication. The number of columns in self must equal the number of rows in other.");
         }
         let mut result = Self::new_zero_matrix(self.num_rows, other.num_columns);
         for i in 0..self.num_rows {
             for j in 0..other.num_columns {
-                let mut sum = 0.0;
+                let mut sum = T::zero();
                 for k in 0..self.num_columns {
                     sum += self.get(i, k) * other.get(k, j);
                 }
@@ -342,13 +418,14 @@ impl Matrix {
         result
     }
 
-    pub fn mult_vector(&self, v: &ColumnVector) -> ColumnVector {
+    /// The straightforward version of `mult_vector`. Kept as a reference for tests and benchmarks.
+    pub fn mult_vector_naive(&self, v: &ColumnVector<T>) -> ColumnVector<T> {
         if self.num_columns != v.num_elements() {
             panic!("Matrix dimensions are not compatible for multiplication. The number of columns in self must equal the number of elements (rows) in v.");
         }
         let mut res = ColumnVector::empty();
         for i in 0..self.num_rows {
-            let mut sum = 0.0;
+            let mut sum = T::zero();
             for k in 0..self.num_columns {
                 sum += self.get(i, k) * v.get(k);
             }
@@ -366,7 +443,7 @@ impl Matrix {
             .data
             .iter()
             .zip(other.data.iter())
-            .map(|(x, y)| x * y)
+            .map(|(x, y)| *x * *y)
             .collect();
 
         Self {
@@ -413,7 +490,7 @@ impl Matrix {
             .data
             .iter()
             .zip(other.data.iter())
-            .map(|(x, y)| x / y)
+            .map(|(x, y)| *x / *y)
             .collect();
 
         Self {
@@ -423,7 +500,7 @@ impl Matrix {
         }
     }
 
-    pub fn add_scalar_to_each_element_in_place(&mut self, scalar: f64) {
+    pub fn add_scalar_to_each_element_in_place(&mut self, scalar: T) {
         for i in 0..self.data.len() {
             let val_in_self = self.data.get_mut(i).unwrap();
             *val_in_self = *val_in_self + scalar;
@@ -437,6 +514,18 @@ impl Matrix {
         }
     }
 
+    /// Replaces each element with the larger of it and the corresponding element of `other`.
+    pub fn elementwise_max_in_place(&mut self, other: &Self) {
+        if self.num_rows != other.num_rows || self.num_columns != other.num_columns {
+            panic!("Matrix dimensions are not compatible for elementwise max. Both matricies must be the same dimensions.");
+        }
+
+        for i in 0..self.data.len() {
+            let val_in_self = self.data.get_mut(i).unwrap();
+            *val_in_self = (*val_in_self).max(other.data[i]);
+        }
+    }
+
     pub fn plus(&self, other: &Self) -> Self {
         if self.num_rows != other.num_rows || self.num_columns != other.num_columns {
             println!(
@@ -548,7 +637,7 @@ impl Matrix {
         }
     }
 
-    pub fn into_value(self) -> f64 {
+    pub fn into_value(self) -> T {
         if self.num_rows == 1 && self.num_columns == 1 {
             let x = self.get(0, 0);
             return x;
@@ -556,9 +645,9 @@ impl Matrix {
         panic!("into_value is not valid for a non-1x1 matrix");
     }
 
-    pub fn vec_length(&self) -> f64 {
+    pub fn vec_length(&self) -> T {
         if self.num_columns == 1 {
-            let mut sum = 0.0;
+            let mut sum = T::zero();
             for i in 0..self.data.len() {
                 sum += self.data[i] * self.data[i];
             }
@@ -569,7 +658,7 @@ impl Matrix {
     }
 
     // TODO there's probably a better way to impelement this
-    pub fn extract_column_vector_as_matrix(&self, column_index: usize) -> Matrix {
+    pub fn extract_column_vector_as_matrix(&self, column_index: usize) -> Matrix<T> {
         if column_index >= self.num_columns {
             panic!("column_index must be less than the number of columns");
         }
@@ -587,7 +676,7 @@ impl Matrix {
         }
     }
 
-    pub fn extract_column(&self, column_index: usize) -> ColumnVector {
+    pub fn extract_column(&self, column_index: usize) -> ColumnVector<T> {
         if column_index >= self.num_columns {
             panic!("column_index must be less than the number of columns");
         }
@@ -599,9 +688,22 @@ impl Matrix {
 
         res
     }
+
+    /// Converts each element to another float type, e.g. `f64` to `f32`.
+    pub fn cast<U: Float>(&self) -> Matrix<U> {
+        Matrix {
+            num_rows: self.num_rows,
+            num_columns: self.num_columns,
+            data: self
+                .data
+                .iter()
+                .map(|x| U::from_f64(x.as_f64()))
+                .collect(),
+        }
+    }
 }
 
-impl fmt::Display for Matrix {
+impl<T: Float> fmt::Display for Matrix<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut width_pad = String::new();
         let width_per_column = 9;
@@ -627,25 +729,25 @@ impl fmt::Display for Matrix {
     }
 }
 
-impl From<ColumnVector> for Matrix {
-    fn from(column_vector: ColumnVector) -> Matrix {
+impl<T> From<ColumnVector<T>> for Matrix<T> {
+    fn from(column_vector: ColumnVector<T>) -> Matrix<T> {
         column_vector.inner_matrix
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct ColumnVector {
-    inner_matrix: Matrix,
+pub struct ColumnVector<T = f64> {
+    inner_matrix: Matrix<T>,
 }
 
-pub struct IterWith<'a> {
-    v_0_values: &'a Vec<f64>,
-    v_1_values: &'a Vec<f64>,
+pub struct IterWith<'a, T> {
+    v_0_values: &'a Vec<T>,
+    v_1_values: &'a Vec<T>,
     index: usize,
 }
 
-impl<'a> Iterator for IterWith<'a> {
-    type Item = (f64, f64);
+impl<'a, T: Float> Iterator for IterWith<'a, T> {
+    type Item = (T, T);
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.v_0_values.len() {
@@ -659,17 +761,17 @@ impl<'a> Iterator for IterWith<'a> {
     }
 }
 
-impl ColumnVector {
+impl<T: Float> ColumnVector<T> {
     /// Warning: this makes a clone of the incoming data
-    /// So if you have a Vec<f64> and you're ok consuming it, it's better to use from_vec
-    pub fn new(data: &[f64]) -> ColumnVector {
+    /// So if you have a Vec<T> and you're ok consuming it, it's better to use from_vec
+    pub fn new(data: &[T]) -> ColumnVector<T> {
         ColumnVector {
             inner_matrix: Matrix::new_column_vector(data),
         }
     }
 
     // because new() ends up making a copy of the incoming data which is inefficient when not required
-    pub fn from_vec(data: Vec<f64>) -> ColumnVector {
+    pub fn from_vec(data: Vec<T>) -> ColumnVector<T> {
         let inner_matrix = Matrix {
             num_rows: data.len(),
             num_columns: 1,
@@ -679,13 +781,13 @@ impl ColumnVector {
         ColumnVector { inner_matrix }
     }
 
-    pub fn new_zero_vector(num_rows: usize) -> ColumnVector {
+    pub fn new_zero_vector(num_rows: usize) -> ColumnVector<T> {
         ColumnVector {
             inner_matrix: Matrix::new_zero_matrix(num_rows, 1),
         }
     }
 
-    pub fn fill_new(value: f64, size: usize) -> Self {
+    pub fn fill_new(value: T, size: usize) -> Self {
         let mut cv = ColumnVector::empty();
         for _ in 0..size {
             cv.push(value);
@@ -703,15 +805,15 @@ impl ColumnVector {
         self.inner_matrix.num_rows
     }
 
-    pub fn get(&self, row_index: usize) -> f64 {
+    pub fn get(&self, row_index: usize) -> T {
         self.inner_matrix.get(row_index, 0)
     }
 
-    pub fn set(&mut self, row_index: usize, value: f64) {
+    pub fn set(&mut self, row_index: usize, value: T) {
         self.inner_matrix.set(row_index, 0, value);
     }
 
-    pub fn iter_with<'a>(&'a self, other: &'a ColumnVector) -> IterWith {
+    pub fn iter_with<'a>(&'a self, other: &'a ColumnVector<T>) -> IterWith<'a, T> {
         if self.inner_matrix.data.len() != other.inner_matrix.data.len() {
             panic!("self and other must have the same length");
         }
@@ -723,7 +825,7 @@ impl ColumnVector {
         }
     }
 
-    pub fn plus(&self, other: &ColumnVector) -> ColumnVector {
+    pub fn plus(&self, other: &ColumnVector<T>) -> ColumnVector<T> {
         if self.num_elements() != other.num_elements() {
             panic!("self and other must have the same length");
         }
@@ -735,7 +837,7 @@ impl ColumnVector {
         ColumnVector::from_vec(data)
     }
 
-    pub fn add_mut(&mut self, other: &ColumnVector) {
+    pub fn add_mut(&mut self, other: &ColumnVector<T>) {
         if self.num_elements() != other.num_elements() {
             panic!("self and other must have the same length");
         }
@@ -746,7 +848,7 @@ impl ColumnVector {
     }
 
     /// Variant of addition for use in chaining
-    pub fn add_chaining(mut self, other: &ColumnVector) -> ColumnVector {
+    pub fn add_chaining(mut self, other: &ColumnVector<T>) -> ColumnVector<T> {
         if self.num_elements() != other.num_elements() {
             panic!("self and other must have the same length");
         }
@@ -757,7 +859,7 @@ impl ColumnVector {
         self
     }
 
-    pub fn subtract(&self, other: &ColumnVector) -> ColumnVector {
+    pub fn subtract(&self, other: &ColumnVector<T>) -> ColumnVector<T> {
         let mut data = Vec::new();
         for i in 0..self.num_elements() {
             data.push(self.get(i) - other.get(i));
@@ -765,30 +867,30 @@ impl ColumnVector {
         ColumnVector::from_vec(data)
     }
 
-    pub fn subtract_mut(&mut self, other: &ColumnVector) {
+    pub fn subtract_mut(&mut self, other: &ColumnVector<T>) {
         for i in 0..self.num_elements() {
             self.set(i, self.get(i) - other.get(i));
         }
     }
 
-    pub fn multiply_by_scalar(&self, scalar: f64) -> ColumnVector {
+    pub fn multiply_by_scalar(&self, scalar: T) -> ColumnVector<T> {
         let data = self
             .inner_matrix
             .data
             .iter()
             .map(|x| *x * scalar)
-            .collect::<Vec<f64>>();
+            .collect::<Vec<T>>();
         ColumnVector::from_vec(data)
     }
 
-    pub fn mult_scalar_mut(&mut self, scalar: f64) {
+    pub fn mult_scalar_mut(&mut self, scalar: T) {
         self.inner_matrix
             .data
             .iter_mut()
             .for_each(|x| *x *= scalar);
     }
 
-    pub fn mult_scalar_mut_chain(&mut self, scalar: f64) -> &mut Self {
+    pub fn mult_scalar_mut_chain(&mut self, scalar: T) -> &mut Self {
         self.inner_matrix
             .data
             .iter_mut()
@@ -796,45 +898,45 @@ impl ColumnVector {
         self
     }
 
-    pub fn div_scalar(&self, scalar: f64) -> ColumnVector {
+    pub fn div_scalar(&self, scalar: T) -> ColumnVector<T> {
         let data = self
             .inner_matrix
             .data
             .iter()
             .map(|x| *x / scalar)
-            .collect::<Vec<f64>>();
+            .collect::<Vec<T>>();
 
         ColumnVector::from_vec(data)
     }
 
-    pub fn div_scalar_mut(&mut self, scalar: f64) {
+    pub fn div_scalar_mut(&mut self, scalar: T) {
         self.inner_matrix
             .data
             .iter_mut()
             .for_each(|x| *x /= scalar);
     }
 
-    pub fn mult_matrix(&self, other: &Matrix) -> Matrix {
+    pub fn mult_matrix(&self, other: &Matrix<T>) -> Matrix<T> {
         // TODO(perf): re-implement without cloning
         let self_clone = self.clone();
-        let m: Matrix = self_clone.into();
+        let m: Matrix<T> = self_clone.into();
         let res = m.multiply(&other);
         res
     }
 
-    pub fn dot_product(&self, other: &ColumnVector) -> f64 {
+    pub fn dot_product(&self, other: &ColumnVector<T>) -> T {
         if self.num_elements() != other.num_elements() {
             panic!("dot_product requires two vectors of the same length");
         }
 
-        let mut sum = 0.0;
+        let mut sum = T::zero();
         for (x, y) in self.iter_with(other) {
             sum += x * y;
         }
         sum
     }
 
-    pub fn hadamard_product(&self, other: &ColumnVector) -> ColumnVector {
+    pub fn hadamard_product(&self, other: &ColumnVector<T>) -> ColumnVector<T> {
         if self.num_elements() != other.num_elements() {
             panic!("hadamard_product on column vectors requires that the two vectors have of the same length");
         }
@@ -846,7 +948,7 @@ impl ColumnVector {
         ColumnVector::from_vec(data)
     }
 
-    pub fn hadamard_product_in_place(&mut self, other: &ColumnVector) {
+    pub fn hadamard_product_in_place(&mut self, other: &ColumnVector<T>) {
         if self.num_elements() != other.num_elements() {
             panic!("hadamard_product_in_place on column vectors requires that the two vectors have of the same length");
         }
@@ -856,12 +958,12 @@ impl ColumnVector {
         }
     }
 
-    pub fn hadamard_product_chaining(mut self, other: &ColumnVector) -> Self {
+    pub fn hadamard_product_chaining(mut self, other: &ColumnVector<T>) -> Self {
         self.hadamard_product_in_place(other);
         self
     }
 
-    pub fn elementwise_divide(&self, other: &ColumnVector) -> ColumnVector {
+    pub fn elementwise_divide(&self, other: &ColumnVector<T>) -> ColumnVector<T> {
         if self.num_elements() != other.num_elements() {
             panic!("elementwise_divide on column vectors requires that the two vectors have of the same length");
         }
@@ -873,7 +975,7 @@ impl ColumnVector {
         ColumnVector::from_vec(data)
     }
 
-    pub fn add_scalar_to_each_element_in_place(&mut self, scalar: f64) {
+    pub fn add_scalar_to_each_element_in_place(&mut self, scalar: T) {
         self.inner_matrix
             .data
             .iter_mut()
@@ -887,7 +989,12 @@ impl ColumnVector {
             .for_each(|x| *x = x.sqrt());
     }
 
-    pub fn elementwise_divide_in_place(&mut self, other: &ColumnVector) {
+    pub fn elementwise_max_in_place(&mut self, other: &ColumnVector<T>) {
+        self.inner_matrix
+            .elementwise_max_in_place(&other.inner_matrix);
+    }
+
+    pub fn elementwise_divide_in_place(&mut self, other: &ColumnVector<T>) {
         if self.num_elements() != other.num_elements() {
             panic!("elementwise_divide_in_place on column vectors requires that the two vectors have of the same length");
         }
@@ -897,14 +1004,11 @@ impl ColumnVector {
         }
     }
 
-    pub fn vec_length(&self) -> f64 {
-        self.iter()
-            .map(square_ref)
-            .sum::<f64>()
-            .sqrt()
+    pub fn vec_length(&self) -> T {
+        self.iter().map(square_ref).sum::<T>().sqrt()
     }
 
-    pub fn push(&mut self, value: f64) {
+    pub fn push(&mut self, value: T) {
         self.inner_matrix.data.push(value);
         self.inner_matrix.num_rows += 1;
     }
@@ -913,11 +1017,11 @@ impl ColumnVector {
     // just copy the self.inner_matrix.data and create a Matrix specifying the opposite shape (i.e. A x 1 -> 1 x A).
     // TODO: see how transpose is used in my NN and, if it would be useful, make a transpose_in_place in which case I wouldn't
     // even need to copy the data, just re-shape it.
-    pub fn transpose(&self) -> Matrix {
+    pub fn transpose(&self) -> Matrix<T> {
         self.inner_matrix.transpose()
     }
 
-    pub fn transpose_into_row_vector_matrix(mut self) -> Matrix {
+    pub fn transpose_into_row_vector_matrix(mut self) -> Matrix<T> {
         let orig_num_rows = self.inner_matrix.num_rows;
         let orig_num_cols = self.inner_matrix.num_columns;
         self.inner_matrix.num_rows = orig_num_cols;
@@ -927,7 +1031,7 @@ impl ColumnVector {
 
     // If you have to Vectors, u and v, then the outer product u x v is u x v.transpose()
     // So this is sinlge operation to do the equivalent of u x v.transpose()
-    pub fn outer_product(&self, other: &ColumnVector) -> Matrix {
+    pub fn outer_product(&self, other: &ColumnVector<T>) -> Matrix<T> {
         let mut result = Matrix::new_zero_matrix(self.num_elements(), other.num_elements());
 
         let self_num_rows = self.num_elements();
@@ -942,24 +1046,31 @@ impl ColumnVector {
         result
     }
 
-    pub fn into_value(self) -> f64 {
+    pub fn into_value(self) -> T {
         if self.num_elements() != 1 {
             panic!("into_value is only valid for a column vector with one element");
         }
         self.get(0)
     }
 
-    pub fn get_data_as_vec(&self) -> Vec<f64> {
+    pub fn get_data_as_vec(&self) -> Vec<T> {
         self.inner_matrix.data.clone()
     }
 
-    pub fn get_data_as_slice(&self) -> &[f64] {
+    pub fn get_data_as_slice(&self) -> &[T] {
         self.inner_matrix.data.as_slice()
     }
+
+    /// Converts each element to another float type, e.g. `f64` to `f32`.
+    pub fn cast<U: Float>(&self) -> ColumnVector<U> {
+        ColumnVector {
+            inner_matrix: self.inner_matrix.cast(),
+        }
+    }
 }
 
-impl From<Matrix> for ColumnVector {
-    fn from(matrix: Matrix) -> ColumnVector {
+impl<T> From<Matrix<T>> for ColumnVector<T> {
+    fn from(matrix: Matrix<T>) -> ColumnVector<T> {
         if matrix.num_columns != 1 {
             panic!(
                 "Cannot convert a {}x{} matrix into a column vector",
@@ -973,7 +1084,7 @@ impl From<Matrix> for ColumnVector {
     }
 }
 
-impl fmt::Display for ColumnVector {
+impl<T: Float> fmt::Display for ColumnVector<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut width_pad = String::new();
         let width_per_column = 9;
@@ -999,26 +1110,26 @@ impl fmt::Display for ColumnVector {
     }
 }
 
-impl Deref for ColumnVector {
-    type Target = Vec<f64>;
+impl<T> Deref for ColumnVector<T> {
+    type Target = Vec<T>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner_matrix.data
     }
 }
 
-impl DerefMut for ColumnVector {
+impl<T> DerefMut for ColumnVector<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.inner_matrix.data
     }
 }
 
-pub struct RowsMatrixBuilder {
+pub struct RowsMatrixBuilder<T = f64> {
     num_columns: Option<usize>,
-    rows: Vec<Vec<f64>>,
+    rows: Vec<Vec<T>>,
 }
 
-impl RowsMatrixBuilder {
+impl<T: Float> RowsMatrixBuilder<T> {
     pub fn new() -> Self {
         Self {
             num_columns: None,
@@ -1034,7 +1145,7 @@ impl RowsMatrixBuilder {
     }
 
     /// For non-chaining use
-    pub fn push_row(&mut self, row: &[f64]) {
+    pub fn push_row(&mut self, row: &[T]) {
         if let Some(num_columns) = self.num_columns {
             if row.len() != num_columns {
                 panic!("row must have the same number of columns as previous row or the predefined number of columns");
@@ -1048,7 +1159,7 @@ impl RowsMatrixBuilder {
     }
 
     /// For chaining use
-    pub fn with_row(mut self, row: &[f64]) -> Self {
+    pub fn with_row(mut self, row: &[T]) -> Self {
         if let Some(num_columns) = self.num_columns {
             if row.len() != num_columns {
                 panic!("row must have the same number of columns as previous columns or the predefined number of columns");
@@ -1062,7 +1173,7 @@ impl RowsMatrixBuilder {
         self
     }
 
-    pub fn build(self) -> Matrix {
+    pub fn build(self) -> Matrix<T> {
         if let Some(num_columns) = self.num_columns {
             if self.rows.len() == 0 {
                 panic!("rows must have at least one row");
@@ -1086,12 +1197,12 @@ impl RowsMatrixBuilder {
     }
 }
 
-pub struct ColumnsMatrixBuilder {
+pub struct ColumnsMatrixBuilder<T = f64> {
     num_rows: Option<usize>,
-    columns: Vec<Vec<f64>>,
+    columns: Vec<Vec<T>>,
 }
 
-impl ColumnsMatrixBuilder {
+impl<T: Float> ColumnsMatrixBuilder<T> {
     pub fn new() -> Self {
         Self {
             num_rows: None,
@@ -1099,7 +1210,7 @@ impl ColumnsMatrixBuilder {
         }
     }
 
-    pub fn push_column(&mut self, column: &[f64]) {
+    pub fn push_column(&mut self, column: &[T]) {
         if let Some(num_rows) = self.num_rows {
             if column.len() != num_rows {
                 panic!("column must have the same number of rows as previous columns");
@@ -1113,7 +1224,7 @@ impl ColumnsMatrixBuilder {
     }
 
     /// For chaining use
-    pub fn with_column(mut self, column: &[f64]) -> Self {
+    pub fn with_column(mut self, column: &[T]) -> Self {
         if let Some(num_rows) = self.num_rows {
             if column.len() != num_rows {
                 panic!("column must have the same number of rows as previous columns");
@@ -1127,7 +1238,7 @@ impl ColumnsMatrixBuilder {
         self
     }
 
-    pub fn build(self) -> Matrix {
+    pub fn build(self) -> Matrix<T> {
         if let Some(num_rows) = self.num_rows {
             if self.columns.len() == 0 {
                 panic!("columns must have at least one column");
@@ -1178,6 +1289,8 @@ mod test_components_in_the_module_root {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn set_and_get_work() {
@@ -1205,7 +1318,7 @@ mod tests {
 
     #[test]
     fn new_identity_matrix_works() {
-        let m = Matrix::new_identity_matrix(3);
+        let m: Matrix = Matrix::new_identity_matrix(3);
         assert_eq!(m.num_rows, 3);
         assert_eq!(m.num_columns, 3);
         assert_eq!(m.data, vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
@@ -1327,6 +1440,98 @@ mod tests {
         assert_eq!(m3.get(1, 1), 335.0);
     }
 
+    #[test]
+    fn transposed_multiplies_work() {
+        let m1 = RowsMatrixBuilder::new()
+            .with_row(&[1.0, 2.0, 3.0])
+            .with_row(&[4.0, 5.0, 6.0])
+            .build();
+        let m2 = RowsMatrixBuilder::new()
+            .with_row(&[10.0, 20.0, 30.0])
+            .with_row(&[11.0, 21.0, 31.0])
+            .build();
+
+        // m1 * m2^T
+        let m = m1.multiply_transpose(&m2);
+        assert_eq!(m, m1.multiply(&m2.transpose()));
+        assert_eq!(m.data, vec![140.0, 146.0, 320.0, 335.0]);
+
+        // m1^T * m2
+        let m = m1.transpose_multiply(&m2);
+        assert_eq!(m, m1.transpose().multiply(&m2));
+        assert_eq!(m.num_rows, 3);
+        assert_eq!(m.num_columns, 3);
+
+        // m1^T * v
+        let v = ColumnVector::new(&[1.0, 2.0]);
+        let v_out = m1.transpose_mult_vector(&v);
+        assert_eq!(v_out, m1.transpose().mult_vector(&v));
+        assert_eq!(v_out.get_data_as_vec(), vec![9.0, 12.0, 15.0]);
+    }
+
+    #[test]
+    fn f32_matrices_work() {
+        let m = RowsMatrixBuilder::new()
+            .with_row(&[1.0_f32, 2.0, 3.0])
+            .with_row(&[4.0, 5.0, 6.0])
+            .build();
+        let v = ColumnVector::new(&[1.0_f32, 0.5, 0.25]);
+
+        assert_eq!(m.mult_vector(&v).get_data_as_vec(), vec![2.75, 8.0]);
+        assert_eq!(
+            m.transpose()
+                .mult_vector(&column_vector![1.0_f32, 1.0]),
+            ColumnVector::new(&[5.0_f32, 7.0, 9.0])
+        );
+    }
+
+    #[test]
+    fn cast_works() {
+        let m = RowsMatrixBuilder::new()
+            .with_row(&[1.0, 0.1])
+            .with_row(&[-2.5, 1e-3])
+            .build();
+
+        let m_f32: Matrix<f32> = m.cast();
+        assert_eq!(m_f32.shape(), m.shape());
+        assert_eq!(m_f32.data, vec![1.0_f32, 0.1, -2.5, 1e-3]);
+
+        // f32 -> f64 is exact, so casting back only loses what was lost going to f32
+        let round_tripped: Matrix = m_f32.cast();
+        assert_eq!(round_tripped.get(0, 0), 1.0);
+        assert_eq!(round_tripped.get(0, 1), 0.1_f32 as f64);
+
+        let v = ColumnVector::new(&[0.5, 0.1]);
+        assert_eq!(v.cast::<f32>().get_data_as_vec(), vec![0.5_f32, 0.1]);
+    }
+
+    #[test]
+    fn random_matrices_are_reproducible_with_a_seeded_rng() {
+        let normal = |seed| -> Matrix {
+            Matrix::new_matrix_with_random_values_from_normal_distribution_with_rng(
+                3,
+                4,
+                0.0,
+                1.0,
+                &mut StdRng::seed_from_u64(seed),
+            )
+        };
+        let uniform = |seed| -> Matrix {
+            Matrix::new_matrix_with_random_values_from_uniform_distribution_with_rng(
+                3,
+                4,
+                -1.0,
+                1.0,
+                &mut StdRng::seed_from_u64(seed),
+            )
+        };
+
+        assert_eq!(normal(42), normal(42));
+        assert_ne!(normal(42), normal(43));
+        assert_eq!(uniform(42), uniform(42));
+        assert_ne!(uniform(42), uniform(43));
+    }
+
     #[test]
     fn test_matrix_vector_multiplication() {
         // 90 degree counterclockwise rotation
@@ -1694,6 +1899,28 @@ mod tests {
         assert_eq!(m.get(1, 2), 6.0);
     }
 
+    #[test]
+    pub fn test_elementwise_max_in_place() {
+        let mut m = RowsMatrixBuilder::new()
+            .with_row(&[1.0, 5.0, -3.0])
+            .with_row(&[4.0, 0.0, 6.0])
+            .build();
+        let other = RowsMatrixBuilder::new()
+            .with_row(&[2.0, 4.0, -1.0])
+            .with_row(&[4.0, -2.0, 7.0])
+            .build();
+
+        m.elementwise_max_in_place(&other);
+        assert_eq!(m.num_rows, 2);
+        assert_eq!(m.num_columns, 3);
+        assert_eq!(m.get(0, 0), 2.0);
+        assert_eq!(m.get(0, 1), 5.0);
+        assert_eq!(m.get(0, 2), -1.0);
+        assert_eq!(m.get(1, 0), 4.0);
+        assert_eq!(m.get(1, 1), 0.0);
+        assert_eq!(m.get(1, 2), 7.0);
+    }
+
     #[test]
     fn test_vec_length() {
         assert_eq!((column_vector_matrix![0.0]).vec_length(), 0.0);
@@ -1862,7 +2089,7 @@ mod column_vector_tests {
 
     #[test]
     pub fn new_zero_vector_works() {
-        let cv = ColumnVector::new_zero_vector(3);
+        let cv: ColumnVector = ColumnVector::new_zero_vector(3);
         assert_eq!(cv.num_elements(), 3);
         assert_eq!(cv.get(0), 0.0);
         assert_eq!(cv.get(1), 0.0);
@@ -2196,7 +2423,7 @@ mod column_vector_tests {
 
     #[test]
     fn empty_works() {
-        let cv = ColumnVector::empty();
+        let cv: ColumnVector = ColumnVector::empty();
         assert_eq!(cv.num_elements(), 0);
         assert_eq!(cv.inner_matrix.num_columns, 1);
     }
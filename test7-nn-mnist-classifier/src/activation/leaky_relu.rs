@@ -1,3 +1,5 @@
+use common::linalg::Float;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct LeakyReLU {
     tail_slope: f64,
@@ -27,19 +29,19 @@ impl LeakyReLU {
     }
 }
 
-pub fn activate(z: f64, tail_slope: f64) -> f64 {
-    if z < 0.0 {
+pub fn activate<T: Float>(z: T, tail_slope: T) -> T {
+    if z < T::zero() {
         tail_slope * z
     } else {
         z
     }
 }
 
-pub fn activate_derivative(z: f64, tail_slope: f64) -> f64 {
-    if z <= 0.0 {
+pub fn activate_derivative<T: Float>(z: T, tail_slope: T) -> T {
+    if z <= T::zero() {
         tail_slope
     } else {
-        1.0
+        T::one()
     }
 }
 
@@ -1,11 +1,12 @@
-use common::linalg::ColumnVector;
+use common::linalg::{ColumnVector, Float, Matrix};
+use serde_derive::{Deserialize, Serialize};
 
 pub mod leaky_relu;
 pub mod relu;
 pub mod sigmoid;
 pub mod softmax;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ActivationFunction {
     Sigmoid,
     ReLU,
@@ -22,27 +23,77 @@ impl ActivationFunction {
     //         ActivationFunction::Softmax(sm) => Box::new(sm.clone()),
     //     }
     // }
+
+    /// Activates each column of `z` (i.e. each example in a batch) as `activate_vector` would.
+    pub fn activate_matrix<T: Float>(&self, z: &Matrix<T>) -> Matrix<T> {
+        match self {
+            ActivationFunction::Softmax => map_columns(z, softmax::softmax),
+            _ => map_elements(z, |z| self.activate_element(z)),
+        }
+    }
+
+    /// Computes the derivative for each column of `z` (i.e. each example in a batch) as `activate_derivative_vector` would.
+    pub fn activate_derivative_matrix<T: Float>(&self, z: &Matrix<T>) -> Matrix<T> {
+        match self {
+            ActivationFunction::Sigmoid => map_elements(z, sigmoid::activate_derivative),
+            ActivationFunction::ReLU => map_elements(z, relu::activate_derivative),
+            ActivationFunction::LeakyReLU(tail_slope) => map_elements(z, |z| {
+                leaky_relu::activate_derivative(z, T::from_f64(*tail_slope))
+            }),
+            ActivationFunction::Softmax => {
+                unimplemented!("activate_derivative is not implemented for softmax.")
+            }
+        }
+    }
+
+    /// The activation of a single element for the activation functions that work element-wise.
+    fn activate_element<T: Float>(&self, z: T) -> T {
+        match self {
+            ActivationFunction::Sigmoid => sigmoid::activate(z),
+            ActivationFunction::ReLU => relu::activate(z),
+            ActivationFunction::LeakyReLU(tail_slope) => {
+                leaky_relu::activate(z, T::from_f64(*tail_slope))
+            }
+            ActivationFunction::Softmax => panic!("softmax can't be applied element-wise"),
+        }
+    }
+}
+
+fn map_elements<T: Float, F: Fn(T) -> T>(m: &Matrix<T>, f: F) -> Matrix<T> {
+    let data = m.data.iter().map(|x| f(*x)).collect::<Vec<T>>();
+    Matrix::new_with_shape_and_values(&m.shape(), &data)
+}
+
+fn map_columns<T: Float, F: Fn(&[T]) -> Vec<T>>(m: &Matrix<T>, f: F) -> Matrix<T> {
+    let mut result = Matrix::new_zero_matrix_with_shape(&m.shape());
+    for column_index in 0..m.num_columns() {
+        let column = m.extract_column(column_index);
+        for (row_index, x) in f(&column).into_iter().enumerate() {
+            result.set(row_index, column_index, x);
+        }
+    }
+    result
 }
 
 impl VectorActivator for ActivationFunction {
-    fn activate_vector(&self, z: &ColumnVector) -> ColumnVector {
+    fn activate_vector<T: Float>(&self, z: &ColumnVector<T>) -> ColumnVector<T> {
         match self {
             ActivationFunction::Sigmoid => {
                 let data = z.iter()
                     .map(|z| sigmoid::activate(*z))
-                    .collect::<Vec<f64>>();
+                    .collect::<Vec<T>>();
                 ColumnVector::from_vec(data)
             },
             ActivationFunction::ReLU => {
                 let data = z.iter()
                     .map(|z| relu::activate(*z))
-                    .collect::<Vec<f64>>();
+                    .collect::<Vec<T>>();
                 ColumnVector::from_vec(data)
             },
             ActivationFunction::LeakyReLU(tail_slope) => {
                 let data = z.iter()
-                    .map(|z| leaky_relu::activate(*z, *tail_slope))
-                    .collect::<Vec<f64>>();
+                    .map(|z| leaky_relu::activate(*z, T::from_f64(*tail_slope)))
+                    .collect::<Vec<T>>();
                 ColumnVector::from_vec(data)
             },
             ActivationFunction::Softmax => {
@@ -51,24 +102,24 @@ impl VectorActivator for ActivationFunction {
         }
     }
 
-    fn activate_derivative_vector(&self, z: &ColumnVector) -> ColumnVector {
+    fn activate_derivative_vector<T: Float>(&self, z: &ColumnVector<T>) -> ColumnVector<T> {
         match self {
             ActivationFunction::Sigmoid => {
                 let data = z.iter()
                     .map(|z| sigmoid::activate_derivative(*z))
-                    .collect::<Vec<f64>>();
+                    .collect::<Vec<T>>();
                 ColumnVector::from_vec(data)
             },
             ActivationFunction::ReLU => {
                 let data = z.iter()
                     .map(|z| relu::activate_derivative(*z))
-                    .collect::<Vec<f64>>();
+                    .collect::<Vec<T>>();
                 ColumnVector::from_vec(data)
             },
             ActivationFunction::LeakyReLU(tail_slope) => {
                 let data = z.iter()
-                    .map(|z| leaky_relu::activate_derivative(*z, *tail_slope))
-                    .collect::<Vec<f64>>();
+                    .map(|z| leaky_relu::activate_derivative(*z, T::from_f64(*tail_slope)))
+                    .collect::<Vec<T>>();
                 ColumnVector::from_vec(data)
             },
             ActivationFunction::Softmax => {
@@ -82,8 +133,8 @@ impl VectorActivator for ActivationFunction {
 
 
 pub trait VectorActivator {
-    fn activate_vector(&self, z: &ColumnVector) -> ColumnVector;
-    fn activate_derivative_vector(&self, z: &ColumnVector) -> ColumnVector;
+    fn activate_vector<T: Float>(&self, z: &ColumnVector<T>) -> ColumnVector<T>;
+    fn activate_derivative_vector<T: Float>(&self, z: &ColumnVector<T>) -> ColumnVector<T>;
 }
 
 
@@ -1,16 +1,18 @@
-pub fn activate(z: f64) -> f64 {
-    if z < 0.0 {
-        0.0
+use common::linalg::Float;
+
+pub fn activate<T: Float>(z: T) -> T {
+    if z < T::zero() {
+        T::zero()
     } else {
         z
     }
 }
 
-pub fn activate_derivative(z: f64) -> f64 {
-    if z > 0.0 {
-        1.0
+pub fn activate_derivative<T: Float>(z: T) -> T {
+    if z > T::zero() {
+        T::one()
     } else {
-        0.0
+        T::zero()
     }
 }
 
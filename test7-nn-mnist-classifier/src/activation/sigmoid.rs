@@ -1,11 +1,13 @@
-pub fn activate(z: f64) -> f64 {
-    1.0 / (1.0 + (-z).exp())
+use common::linalg::Float;
+
+pub fn activate<T: Float>(z: T) -> T {
+    T::one() / (T::one() + (-z).exp())
 }
 
 /// Compute the derivative of the sigmoid function at the given z
-pub fn activate_derivative(z: f64) -> f64 {
+pub fn activate_derivative<T: Float>(z: T) -> T {
     let az = activate(z);
-    az * (1.0 - az)
+    az * (T::one() - az)
 }
 
 #[cfg(test)]
@@ -1,13 +1,13 @@
-use common::linalg::ColumnVector;
+use common::linalg::{ColumnVector, Float};
 
-pub fn softmax(logits: &[f64]) -> Vec<f64> {
-    let max_logit = logits.iter().fold(f64::MIN, |max, &val| max.max(val));
-    let exps: Vec<f64> = logits.iter().map(|&val| (val - max_logit).exp()).collect();
-    let sum_exps: f64 = exps.iter().sum();
+pub fn softmax<T: Float>(logits: &[T]) -> Vec<T> {
+    let max_logit = logits.iter().fold(T::min_value(), |max, &val| max.max(val));
+    let exps: Vec<T> = logits.iter().map(|&val| (val - max_logit).exp()).collect();
+    let sum_exps: T = exps.iter().copied().sum();
     exps.iter().map(|&exp| exp / sum_exps).collect()
 }
 
-pub fn activate_vector(z: &ColumnVector) -> ColumnVector {
+pub fn activate_vector<T: Float>(z: &ColumnVector<T>) -> ColumnVector<T> {
     let data = softmax(z);
     ColumnVector::from_vec(data)
 }
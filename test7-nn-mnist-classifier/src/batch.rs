@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+
+use common::datapoints::NDTrainingDataPoint;
+use common::linalg::{ColumnVector, Float, Matrix, MatrixShape};
+use rand::RngCore;
+use rayon::prelude::*;
+
+use crate::activation::ActivationFunction;
+use crate::big_theta::BigTheta;
+use crate::cost::Coster;
+use crate::dropout::dropout_mask;
+use crate::errors::VectorDimensionMismatch;
+use crate::health::ActivationSummary;
+use crate::normalization::{scale_rows, BatchStatistics, Standardized};
+use crate::{cost, LayerIndex, NeuralNetwork, GRADIENT_SUM_CHUNK_SIZE};
+
+/// The z and activation matrices computed in a batched forward pass, keyed by layer.
+/// Each column corresponds to one example in the batch.
+struct BatchIntermediates<T> {
+    z: HashMap<LayerIndex, Matrix<T>>,
+    activations: HashMap<LayerIndex, Matrix<T>>,
+    /// The dropout masks the activations were multiplied by, for the layers that have a dropout rate.
+    dropout_masks: HashMap<LayerIndex, Matrix<T>>,
+    /// The standardized z's etc. of the layers that have a `Normalization`.
+    normalized: HashMap<LayerIndex, NormalizedBatch<T>>,
+    /// For the health checks, taken before any dropout.
+    activation_summaries: HashMap<LayerIndex, ActivationSummary>,
+}
+
+/// What `compute_mini_batch_gradients` found for a mini batch.
+pub(crate) struct MiniBatchGradients<T> {
+    pub gradients: BigTheta<T>,
+    /// The mini batch's cost (including any regularization) from the forward pass the gradients were computed with, so
+    /// with dropout and before the weights are updated.
+    pub loss: f64,
+    /// The mini batch's statistics for each batch normalized layer, for updating its running statistics.
+    pub batch_statistics: HashMap<LayerIndex, BatchStatistics<T>>,
+    pub activation_summaries: HashMap<LayerIndex, ActivationSummary>,
+}
+
+/// What a normalized layer computes between its z's and its activations in a batched forward pass.
+struct NormalizedBatch<T> {
+    standardized: Standardized<T>,
+    /// The standardized z's scaled and shifted, which the activation function is applied to.
+    activation_inputs: Matrix<T>,
+    /// The batch's mean and variance of each neuron's z, for batch norm.
+    batch_statistics: Option<BatchStatistics<T>>,
+}
+
+/// The errors computed in a batched backward pass, keyed by layer. Each column corresponds to one example in the batch.
+struct BatchErrors<T> {
+    /// ∂C/∂z for each layer.
+    z: HashMap<LayerIndex, Matrix<T>>,
+    /// ∂C/∂(activation inputs) for the normalized layers, from which the gradients of their scales and shifts follow.
+    activation_inputs: HashMap<LayerIndex, Matrix<T>>,
+}
+
+/// Builds a matrix whose columns are the given vectors.
+pub(crate) fn columns_to_matrix<'a, T, I>(num_rows: usize, columns: I) -> Matrix<T>
+where
+    T: Float,
+    I: ExactSizeIterator<Item = &'a ColumnVector<T>>,
+{
+    let num_columns = columns.len();
+    let mut m = Matrix::new_zero_matrix(num_rows, num_columns);
+    for (column_index, column) in columns.enumerate() {
+        for (row_index, x) in column.iter().enumerate() {
+            m.data[row_index * num_columns + column_index] = *x;
+        }
+    }
+    m
+}
+
+/// Copies columns `start..end` of `m` into a new matrix.
+fn column_range<T: Float>(m: &Matrix<T>, start: usize, end: usize) -> Matrix<T> {
+    let mut data = Vec::with_capacity(m.num_rows() * (end - start));
+    for row in m.data.chunks(m.num_columns()) {
+        data.extend_from_slice(&row[start..end]);
+    }
+    Matrix::new_with_shape_and_values(&MatrixShape::new(m.num_rows(), end - start), &data)
+}
+
+/// Adds `v` to each column of `m`, i.e. broadcasts the bias across the examples in a batch.
+fn add_to_each_column<T: Float>(mut m: Matrix<T>, v: &ColumnVector<T>) -> Matrix<T> {
+    let num_columns = m.num_columns();
+    for (row, v_i) in m.data.chunks_mut(num_columns).zip(v.iter()) {
+        row.iter_mut().for_each(|x| *x += *v_i);
+    }
+    m
+}
+
+/// Sums each row of `m` into a column vector.
+fn row_sums<T: Float>(m: &Matrix<T>) -> ColumnVector<T> {
+    let mut sums = ColumnVector::new_zero_vector(m.num_rows());
+    for (sum, row) in sums.iter_mut().zip(m.data.chunks(m.num_columns())) {
+        for x in row {
+            *sum += *x;
+        }
+    }
+    sums
+}
+
+impl<T: Float> NeuralNetwork<T> {
+    /// Feeds a batch of inputs, one example per column, forward through the network and returns the output activations
+    /// (again one column per example).
+    pub fn feed_forward_batch(&self, inputs: &Matrix<T>) -> Matrix<T> {
+        let mut activations = inputs.clone();
+
+        for l in 1..self.sizes.len() {
+            let z = add_to_each_column(
+                self.w.get(&l).unwrap().multiply(&activations),
+                self.b.get(&l).unwrap(),
+            );
+            activations = self
+                .activation_function(l)
+                .activate_matrix(&self.activation_input_for_inference(l, z));
+        }
+
+        activations
+    }
+
+    fn activation_function(&self, layer_index: LayerIndex) -> &ActivationFunction {
+        self.layer_configs
+            .get(&layer_index)
+            .unwrap()
+            .activation_function
+            .as_ref()
+            .expect("need an activation function for any layer input layer")
+    }
+
+    /// Draws the dropout masks for a batch of `num_examples` examples, one column per example, for the layers that have
+    /// a dropout rate. They're drawn an example at a time, in the same order that
+    /// `feed_forward_capturing_intermediates_with_dropout` draws them when called for each example in turn.
+    fn dropout_masks_batch(
+        &self,
+        num_examples: usize,
+        rng: &mut dyn RngCore,
+    ) -> HashMap<LayerIndex, Matrix<T>> {
+        let dropout_rates = (1..self.num_layers())
+            .filter_map(|l| {
+                let layer_config = self.layer_configs.get(&l).unwrap();
+                layer_config.dropout_rate.map(|rate| (l, rate))
+            })
+            .collect::<Vec<(LayerIndex, f64)>>();
+
+        let mut mask_columns: HashMap<LayerIndex, Vec<ColumnVector<T>>> = HashMap::new();
+        for _ in 0..num_examples {
+            for &(l, rate) in dropout_rates.iter() {
+                mask_columns
+                    .entry(l)
+                    .or_default()
+                    .push(dropout_mask(rate, self.sizes[l], rng));
+            }
+        }
+
+        mask_columns
+            .into_iter()
+            .map(|(l, columns)| (l, columns_to_matrix(self.sizes[l], columns.iter())))
+            .collect()
+    }
+
+    fn feed_forward_batch_capturing_intermediates(
+        &self,
+        inputs: &Matrix<T>,
+        dropout_masks: HashMap<LayerIndex, Matrix<T>>,
+    ) -> BatchIntermediates<T> {
+        let mut z = HashMap::new();
+        let mut activations = HashMap::new();
+        let mut normalized = HashMap::new();
+        let mut activation_summaries = HashMap::new();
+        activations.insert(0, inputs.clone());
+
+        for l in 1..self.sizes.len() {
+            let z_l = add_to_each_column(
+                self.w.get(&l).unwrap().multiply(&activations[&(l - 1)]),
+                self.b.get(&l).unwrap(),
+            );
+            let mut activations_l = match self.normalization(l) {
+                Some(normalization) => {
+                    let (standardized, batch_statistics) = normalization.standardize_batch(&z_l);
+                    let activation_inputs = self.scale_and_shift(l, standardized.x_hat.clone());
+                    let activations_l = self
+                        .activation_function(l)
+                        .activate_matrix(&activation_inputs);
+                    normalized.insert(
+                        l,
+                        NormalizedBatch {
+                            standardized,
+                            activation_inputs,
+                            batch_statistics,
+                        },
+                    );
+                    activations_l
+                }
+                None => self.activation_function(l).activate_matrix(&z_l),
+            };
+            activation_summaries.insert(l, ActivationSummary::of(&activations_l));
+            if let Some(dropout_mask) = dropout_masks.get(&l) {
+                activations_l.hadamard_product_in_place(dropout_mask);
+            }
+            activations.insert(l, activations_l);
+            z.insert(l, z_l);
+        }
+
+        BatchIntermediates {
+            z,
+            activations,
+            dropout_masks,
+            normalized,
+            activation_summaries,
+        }
+    }
+
+    /// The batched equivalent of `grad_z_of_c_output_layer`: ∂C/∂z for the output layer, one column per example.
+    fn grad_z_of_c_output_layer_batch(
+        &self,
+        z: &Matrix<T>,
+        a: &Matrix<T>,
+        y: &Matrix<T>,
+    ) -> anyhow::Result<Matrix<T>> {
+        let act_fn = self.activation_function(self.output_layer_index());
+
+        match self.cost {
+            cost::CostFunc::CrossEntropy => {
+                if act_fn != &ActivationFunction::Softmax {
+                    return Err(anyhow::anyhow!(
+                        "CrossEntropy is only implemented to work with Softmax in the output layer"
+                    ));
+                }
+                // special case for softmax + cross entropy
+                Ok(a.subtract(y))
+            }
+            cost::CostFunc::QuadraticCost => Ok(a
+                .subtract(y)
+                .hadamard_product_chaining(&act_fn.activate_derivative_matrix(z))),
+        }
+    }
+
+    /// The batched equivalent of `backprop`: returns the error matrices (∂C/∂z, one column per example) for layers L-1 to 1.
+    /// For a normalized layer, the error is first found with respect to the activation inputs, then backpropagated
+    /// through the scale and shift and the standardization.
+    fn backprop_batch(
+        &self,
+        desired_outputs: &Matrix<T>,
+        intermediates: &BatchIntermediates<T>,
+    ) -> BatchErrors<T> {
+        let mut errors = BatchErrors {
+            z: HashMap::new(),
+            activation_inputs: HashMap::new(),
+        };
+        let last_layer_index = self.num_layers() - 1;
+
+        for l in (1..self.num_layers()).rev() {
+            let normalized = intermediates.normalized.get(&l);
+            let activation_inputs = match normalized {
+                Some(normalized) => &normalized.activation_inputs,
+                None => &intermediates.z[&l],
+            };
+
+            let err = if l == last_layer_index {
+                self.grad_z_of_c_output_layer_batch(
+                    activation_inputs,
+                    &intermediates.activations[&l],
+                    desired_outputs,
+                )
+                .expect("no error")
+            } else {
+                let err = self.w[&(l + 1)]
+                    .transpose_multiply(&errors.z[&(l + 1)])
+                    .hadamard_product_chaining(
+                        &self
+                            .activation_function(l)
+                            .activate_derivative_matrix(activation_inputs),
+                    );
+                match intermediates.dropout_masks.get(&l) {
+                    Some(dropout_mask) => err.hadamard_product_chaining(dropout_mask),
+                    None => err,
+                }
+            };
+
+            let err = match normalized {
+                Some(normalized) => {
+                    let grad_x_hat = scale_rows(err.clone(), &self.scales[&l]);
+                    errors.activation_inputs.insert(l, err);
+                    self.normalization(l)
+                        .unwrap()
+                        .standardize_batch_backward(&grad_x_hat, &normalized.standardized)
+                }
+                None => err,
+            };
+
+            errors.z.insert(l, err);
+        }
+
+        errors
+    }
+
+    /// Just the gradients from `compute_mini_batch_gradients`.
+    #[cfg(test)]
+    pub(crate) fn compute_gradients_batched(
+        &self,
+        training_data: &[NDTrainingDataPoint<T>],
+        mini_batch: &[usize],
+        dropout_rng: Option<&mut dyn RngCore>,
+    ) -> BigTheta<T> {
+        self.compute_mini_batch_gradients(training_data, mini_batch, dropout_rng)
+            .gradients
+    }
+
+    /// Computes the gradients averaged over the training examples at the given indexes, doing the forward and backward passes
+    /// for the whole mini batch at once. Also returns the mini batch's statistics for each batch normalized layer, for
+    /// updating its running statistics, and a summary of each layer's activations for the health checks.
+    ///
+    /// Without normalized layers, the result is identical to feeding forward and backpropagating each example separately
+    /// and summing them with `compute_gradients_par_6`: the matrix products accumulate each element in the same order,
+    /// and the per-example contributions to the gradients are summed in the same `GRADIENT_SUM_CHUNK_SIZE` chunks.
+    ///
+    /// When training, the dropout masks are drawn from `dropout_rng`. Without it, dropout is off.
+    pub(crate) fn compute_mini_batch_gradients(
+        &self,
+        training_data: &[NDTrainingDataPoint<T>],
+        mini_batch: &[usize],
+        dropout_rng: Option<&mut dyn RngCore>,
+    ) -> MiniBatchGradients<T> {
+        let num_training_examples = mini_batch.len();
+
+        let inputs = columns_to_matrix(
+            self.sizes[0],
+            mini_batch.iter().map(|&i| &training_data[i].input_v),
+        );
+        let desired_outputs = columns_to_matrix(
+            self.sizes[self.output_layer_index()],
+            mini_batch
+                .iter()
+                .map(|&i| &training_data[i].desired_output_v),
+        );
+
+        let dropout_masks = match dropout_rng {
+            Some(rng) => self.dropout_masks_batch(num_training_examples, rng),
+            None => HashMap::new(),
+        };
+        let intermediates = self.feed_forward_batch_capturing_intermediates(&inputs, dropout_masks);
+        let errors = self.backprop_batch(&desired_outputs, &intermediates);
+
+        let outputs = &intermediates.activations[&self.output_layer_index()];
+        let loss_sum: f64 = mini_batch
+            .iter()
+            .enumerate()
+            .map(|(i, &tr_ex)| {
+                self.cost
+                    .cost(
+                        &training_data[tr_ex].desired_output_v,
+                        &outputs.extract_column(i),
+                    )
+                    .expect("the desired outputs were already fed through backprop")
+            })
+            .sum();
+        let loss = loss_sum / num_training_examples as f64 + self.regularization_cost();
+
+        let chunk_starts: Vec<usize> = (0..num_training_examples)
+            .step_by(GRADIENT_SUM_CHUNK_SIZE)
+            .collect();
+
+        let mut weights_matrices = HashMap::new();
+        let mut bias_vectors = HashMap::new();
+
+        for l in (1..self.num_layers()).rev() {
+            let err = &errors.z[&l];
+            let prev_activations = &intermediates.activations[&(l - 1)];
+
+            let chunk_sums = chunk_starts
+                .par_iter()
+                .map(|&start| {
+                    let end = (start + GRADIENT_SUM_CHUNK_SIZE).min(num_training_examples);
+                    let err_chunk = column_range(err, start, end);
+                    let prev_activations_chunk = column_range(prev_activations, start, end);
+
+                    (
+                        err_chunk.multiply_transpose(&prev_activations_chunk),
+                        row_sums(&err_chunk),
+                    )
+                })
+                .collect::<Vec<(Matrix<T>, ColumnVector<T>)>>();
+
+            let mut weights_grad = Matrix::new_zero_matrix(self.sizes[l], self.sizes[l - 1]);
+            let mut bias_grad = ColumnVector::new_zero_vector(self.sizes[l]);
+            for (weights_sum, bias_sum) in chunk_sums.iter() {
+                weights_grad.add_mut(weights_sum);
+                bias_grad.add_mut(bias_sum);
+            }
+
+            weights_grad.div_scalar_mut(T::from_f64(num_training_examples as f64));
+            bias_grad.div_scalar_mut(T::from_f64(num_training_examples as f64));
+            self.add_regularization_gradient(l, &mut weights_grad);
+
+            weights_matrices.insert(l, weights_grad);
+            bias_vectors.insert(l, bias_grad);
+        }
+
+        let mut scale_vectors = HashMap::new();
+        let mut shift_vectors = HashMap::new();
+        for (l, err) in errors.activation_inputs.iter() {
+            let x_hat = &intermediates.normalized[l].standardized.x_hat;
+            let mut scale_grad = row_sums(&err.hadamard_product(x_hat));
+            let mut shift_grad = row_sums(err);
+            scale_grad.div_scalar_mut(T::from_f64(num_training_examples as f64));
+            shift_grad.div_scalar_mut(T::from_f64(num_training_examples as f64));
+            scale_vectors.insert(*l, scale_grad);
+            shift_vectors.insert(*l, shift_grad);
+        }
+
+        let batch_statistics = intermediates
+            .normalized
+            .into_iter()
+            .filter_map(|(l, normalized)| normalized.batch_statistics.map(|s| (l, s)))
+            .collect();
+
+        MiniBatchGradients {
+            gradients: BigTheta {
+                sizes: self.sizes.clone(),
+                weights_matrices,
+                bias_vectors,
+                scale_vectors,
+                shift_vectors,
+            },
+            loss,
+            batch_statistics,
+            activation_summaries: intermediates.activation_summaries,
+        }
+    }
+
+    /// The cost over `training_data` fed forward as a single batch in training mode (without dropout), i.e. with batch
+    /// normalized layers standardizing by the statistics of `training_data` rather than their running statistics.
+    /// This is the cost whose gradient `compute_gradients_batched` computes when given all of `training_data`.
+    pub(crate) fn cost_training_set_as_one_batch(
+        &self,
+        training_data: &[NDTrainingDataPoint<T>],
+    ) -> Result<f64, VectorDimensionMismatch> {
+        let inputs = columns_to_matrix(
+            self.sizes[0],
+            training_data.iter().map(|tr_ex| &tr_ex.input_v),
+        );
+        let intermediates =
+            self.feed_forward_batch_capturing_intermediates(&inputs, HashMap::new());
+        let outputs = &intermediates.activations[&self.output_layer_index()];
+
+        let mut sum = 0.0;
+        for (i, tr_ex) in training_data.iter().enumerate() {
+            sum += self
+                .cost
+                .cost(&tr_ex.desired_output_v, &outputs.extract_column(i))?;
+        }
+
+        Ok(sum / training_data.len() as f64 + self.regularization_cost())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::NeuralNetworkBuilder;
+    use crate::cost::CostFunc;
+    use crate::initializer::Initializer;
+    use crate::rng::{seeded_rng, RngStream};
+    use common::column_vector;
+
+    fn random_training_data(
+        num_examples: usize,
+        input_size: usize,
+        output_size: usize,
+    ) -> Vec<NDTrainingDataPoint> {
+        (0..num_examples)
+            .map(|i| {
+                let input_v = ColumnVector::from(
+                    Matrix::new_matrix_with_random_values_from_uniform_distribution(
+                        input_size, 1, -2.0, 2.0,
+                    ),
+                );
+                let mut desired_output_v = ColumnVector::new_zero_vector(output_size);
+                desired_output_v.set(i % output_size, 1.0);
+                NDTrainingDataPoint::new(input_v, desired_output_v)
+            })
+            .collect()
+    }
+
+    fn per_example_gradients(
+        nn: &mut NeuralNetwork,
+        training_data: &[NDTrainingDataPoint],
+        mini_batch: &[usize],
+        mut dropout_rng: Option<&mut dyn RngCore>,
+    ) -> BigTheta {
+        let mut per_tr_ex_data = Vec::with_capacity(mini_batch.len());
+        for &i in mini_batch {
+            let intermediates = nn.feed_forward_capturing_intermediates_with_dropout(
+                &training_data[i].input_v,
+                dropout_rng
+                    .as_mut()
+                    .map(|rng| &mut **rng as &mut dyn RngCore),
+            );
+            let errors = nn.backprop(&training_data[i].desired_output_v, &intermediates);
+            per_tr_ex_data.push((intermediates, errors));
+        }
+        nn.compute_gradients_par_6(&per_tr_ex_data)
+    }
+
+    #[test]
+    fn columns_helpers_work() {
+        let columns = [
+            column_vector![1.0, 2.0],
+            column_vector![3.0, 4.0],
+            column_vector![5.0, 6.0],
+        ];
+        let m = columns_to_matrix(2, columns.iter());
+        assert_eq!(m.data, vec![1.0, 3.0, 5.0, 2.0, 4.0, 6.0]);
+
+        assert_eq!(column_range(&m, 1, 3).data, vec![3.0, 5.0, 4.0, 6.0]);
+        assert_eq!(row_sums(&m), column_vector![9.0, 12.0]);
+        assert_eq!(
+            add_to_each_column(m, &column_vector![10.0, 20.0]).data,
+            vec![11.0, 13.0, 15.0, 22.0, 24.0, 26.0]
+        );
+    }
+
+    #[test]
+    fn feed_forward_batch_matches_feed_forward() {
+        let nn = NeuralNetworkBuilder::new()
+            .with_input_layer(4)
+            .with_hidden_layer(
+                5,
+                Initializer::HeForReLUAndVariants,
+                ActivationFunction::ReLU,
+            )
+            .with_output_layer(
+                3,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(CostFunc::CrossEntropy)
+            .build();
+
+        let training_data = random_training_data(7, 4, 3);
+        let inputs = columns_to_matrix(4, training_data.iter().map(|tr_ex| &tr_ex.input_v));
+        let outputs = nn.feed_forward_batch(&inputs);
+
+        for (i, tr_ex) in training_data.iter().enumerate() {
+            assert_eq!(outputs.extract_column(i), nn.feed_forward(&tr_ex.input_v));
+        }
+    }
+
+    #[test]
+    fn batched_gradients_are_identical_to_per_example_gradients() {
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(6)
+            .with_hidden_layer(
+                8,
+                Initializer::HeForReLUAndVariants,
+                ActivationFunction::LeakyReLU(0.1),
+            )
+            .with_hidden_layer(
+                5,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            )
+            .with_output_layer(
+                4,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(CostFunc::CrossEntropy)
+            .build();
+
+        // more than two gradient summing chunks, with a partial last one, in a scrambled order
+        let training_data = random_training_data(100, 6, 4);
+        let mini_batch: Vec<usize> = (0..75).map(|i| (i * 37) % 100).collect();
+
+        let expected = per_example_gradients(&mut nn, &training_data, &mini_batch, None);
+        assert_eq!(
+            nn.compute_gradients_batched(&training_data, &mini_batch, None),
+            expected
+        );
+    }
+
+    #[test]
+    fn batched_gradients_are_identical_to_per_example_gradients_with_quadratic_cost() {
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(3)
+            .with_hidden_layer(
+                4,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            )
+            .with_output_layer(
+                2,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            )
+            .with_cost_fn(CostFunc::QuadraticCost)
+            .build();
+
+        let training_data = random_training_data(10, 3, 2);
+        let mini_batch: Vec<usize> = (0..10).collect();
+
+        let expected = per_example_gradients(&mut nn, &training_data, &mini_batch, None);
+        let mini_batch_gradients =
+            nn.compute_mini_batch_gradients(&training_data, &mini_batch, None);
+        assert_eq!(mini_batch_gradients.gradients, expected);
+        assert_eq!(
+            mini_batch_gradients.loss,
+            nn.cost_training_set(&training_data).unwrap()
+        );
+    }
+
+    #[test]
+    fn batched_gradients_are_identical_to_per_example_gradients_with_dropout() {
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(6)
+            .with_hidden_layer(
+                8,
+                Initializer::HeForReLUAndVariants,
+                ActivationFunction::LeakyReLU(0.1),
+            )
+            .with_dropout(0.5)
+            .with_hidden_layer(
+                5,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            )
+            .with_output_layer(
+                4,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(CostFunc::CrossEntropy)
+            .build();
+
+        let training_data = random_training_data(40, 6, 4);
+        let mini_batch: Vec<usize> = (0..40).collect();
+
+        let expected = per_example_gradients(
+            &mut nn,
+            &training_data,
+            &mini_batch,
+            Some(&mut seeded_rng(3, RngStream::Training)),
+        );
+        let gradients = nn.compute_gradients_batched(
+            &training_data,
+            &mini_batch,
+            Some(&mut seeded_rng(3, RngStream::Training)),
+        );
+        assert_eq!(gradients, expected);
+
+        // and the masks made a difference
+        assert_ne!(
+            gradients,
+            nn.compute_gradients_batched(&training_data, &mini_batch, None)
+        );
+    }
+}
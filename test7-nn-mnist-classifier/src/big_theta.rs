@@ -1,18 +1,30 @@
-use common::linalg::{ColumnVector, Matrix, MatrixShape};
+use common::linalg::{ColumnVector, Float, Matrix, MatrixShape};
 use std::collections::HashMap;
 
 use crate::errors::{IndexOutOfBoundsError, InvalidLayerIndex, NeuralNetworkError};
 use crate::LayerIndex;
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct BigTheta {
+pub struct BigTheta<T = f64> {
     pub sizes: Vec<usize>,
-    pub weights_matrices: HashMap<LayerIndex, Matrix>,
-    pub bias_vectors: HashMap<LayerIndex, ColumnVector>,
+    pub weights_matrices: HashMap<LayerIndex, Matrix<T>>,
+    pub bias_vectors: HashMap<LayerIndex, ColumnVector<T>>,
+    /// The scales (gamma) of the normalized layers, if any - see `Normalization`.
+    pub scale_vectors: HashMap<LayerIndex, ColumnVector<T>>,
+    /// The shifts (beta) of the normalized layers, if any.
+    pub shift_vectors: HashMap<LayerIndex, ColumnVector<T>>,
 }
 
-impl BigTheta {
+impl<T: Float> BigTheta<T> {
     pub fn zero_from_sizes(sizes: &[usize]) -> Self {
+        Self::zero_from_sizes_with_normalized_layers(sizes, &[])
+    }
+
+    /// Like `zero_from_sizes`, but with (zero) scales and shifts for the given layers too.
+    pub fn zero_from_sizes_with_normalized_layers(
+        sizes: &[usize],
+        normalized_layers: &[LayerIndex],
+    ) -> Self {
         let mut weights_matrices = HashMap::new();
         let mut bias_vectors = HashMap::new();
 
@@ -25,19 +37,36 @@ impl BigTheta {
             bias_vectors.insert(layer_index, b_empty);
         }
 
+        let zero_vectors = || {
+            normalized_layers
+                .iter()
+                .map(|l| (*l, ColumnVector::new_zero_vector(sizes[*l])))
+                .collect::<HashMap<LayerIndex, ColumnVector<T>>>()
+        };
+
         BigTheta {
             sizes: sizes.to_vec(),
             weights_matrices,
             bias_vectors,
+            scale_vectors: zero_vectors(),
+            shift_vectors: zero_vectors(),
         }
     }
 
-    /// Returns a `Vec<f64>` containing the unrolled weights and biases of the neural network.
+    /// The layers that have scales and shifts, in order.
+    pub fn normalized_layers(&self) -> Vec<LayerIndex> {
+        let mut normalized_layers = self.scale_vectors.keys().copied().collect::<Vec<_>>();
+        normalized_layers.sort();
+        normalized_layers
+    }
+
+    /// Returns a `Vec<T>` containing the unrolled weights and biases of the neural network.
     ///
     /// The unrolled vector is constructed by concatenating the weights and biases of each layer
-    /// in the network, starting from layer 1. At each layer, the weights of a layer are included before its biases.
+    /// in the network, starting from layer 1. At each layer, the weights of a layer are included before its biases,
+    /// followed by its scales and shifts if it's a normalized layer.
     ///
-    pub fn unroll(&self) -> Vec<f64> {
+    pub fn unroll(&self) -> Vec<T> {
         let mut unrolled_vec = Vec::new();
         for l in 1..self.sizes.len() {
             let w = self.get_weights_matrix(&l);
@@ -45,10 +74,85 @@ impl BigTheta {
 
             unrolled_vec.extend(w.data.as_slice());
             unrolled_vec.extend(b.get_data_as_slice());
+
+            if let (Some(scale), Some(shift)) =
+                (self.scale_vectors.get(&l), self.shift_vectors.get(&l))
+            {
+                unrolled_vec.extend(scale.get_data_as_slice());
+                unrolled_vec.extend(shift.get_data_as_slice());
+            }
         }
         unrolled_vec
     }
 
+    /// The inverse of `unroll` - builds a `BigTheta` for the given `sizes` from the unrolled weights and biases.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `unrolled` doesn't match the number of weights and biases implied by `sizes`.
+    ///
+    pub fn from_unrolled(sizes: &[usize], unrolled: &[T]) -> Self {
+        Self::from_unrolled_with_normalized_layers(sizes, &[], unrolled)
+    }
+
+    /// Like `from_unrolled`, for a `BigTheta` that also has scales and shifts for the given layers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `unrolled` doesn't match the number of values implied by `sizes` and
+    /// `normalized_layers`.
+    ///
+    pub fn from_unrolled_with_normalized_layers(
+        sizes: &[usize],
+        normalized_layers: &[LayerIndex],
+        unrolled: &[T],
+    ) -> Self {
+        let mut weights_matrices = HashMap::new();
+        let mut bias_vectors = HashMap::new();
+        let mut scale_vectors = HashMap::new();
+        let mut shift_vectors = HashMap::new();
+        let mut ptr = 0;
+
+        for layer_index in 1..sizes.len() {
+            let m_shape = MatrixShape::new(sizes[layer_index], sizes[layer_index - 1]);
+            let w_data = &unrolled[ptr..(ptr + m_shape.data_length())];
+            ptr += m_shape.data_length();
+            let b_data = &unrolled[ptr..(ptr + sizes[layer_index])];
+            ptr += sizes[layer_index];
+
+            weights_matrices.insert(
+                layer_index,
+                Matrix::new_with_shape_and_values(&m_shape, w_data),
+            );
+            bias_vectors.insert(layer_index, ColumnVector::new(b_data));
+
+            if normalized_layers.contains(&layer_index) {
+                for vectors in [&mut scale_vectors, &mut shift_vectors] {
+                    let data = &unrolled[ptr..(ptr + sizes[layer_index])];
+                    ptr += sizes[layer_index];
+                    vectors.insert(layer_index, ColumnVector::new(data));
+                }
+            }
+        }
+
+        if ptr != unrolled.len() {
+            panic!(
+                "unrolled has {} values but sizes {:?} require {}",
+                unrolled.len(),
+                sizes,
+                ptr
+            );
+        }
+
+        BigTheta {
+            sizes: sizes.to_vec(),
+            weights_matrices,
+            bias_vectors,
+            scale_vectors,
+            shift_vectors,
+        }
+    }
+
     /// Returns a mutable reference to the `Matrix` representing the weights for the specified layer.
     ///
     /// # Arguments
@@ -64,7 +168,7 @@ impl BigTheta {
         // TODO(dedupe): note that there's get_weights_matrix_mut below which is the same except that it uses unwrap
         &mut self,
         layer_index: LayerIndex,
-    ) -> Result<&mut Matrix, NeuralNetworkError> {
+    ) -> Result<&mut Matrix<T>, NeuralNetworkError> {
         self.weights_matrices
             .get_mut(&layer_index)
             .ok_or(NeuralNetworkError::InvalidLayerIndex(InvalidLayerIndex(
@@ -86,7 +190,7 @@ impl BigTheta {
     pub fn bias_at_layer_mut(
         &mut self,
         layer_index: LayerIndex,
-    ) -> Result<&mut ColumnVector, NeuralNetworkError> {
+    ) -> Result<&mut ColumnVector<T>, NeuralNetworkError> {
         self.bias_vectors
             .get_mut(&layer_index)
             .ok_or(NeuralNetworkError::IndexOutOfBoundsError(
@@ -94,49 +198,73 @@ impl BigTheta {
             ))
     }
 
-    pub fn get_weights_matrix(&self, layer_index: &LayerIndex) -> &Matrix {
+    pub fn get_weights_matrix(&self, layer_index: &LayerIndex) -> &Matrix<T> {
         self.weights_matrices.get(layer_index).unwrap()
     }
 
-    pub fn get_bias_vector(&self, layer_index: &LayerIndex) -> &ColumnVector {
+    pub fn get_bias_vector(&self, layer_index: &LayerIndex) -> &ColumnVector<T> {
         self.bias_vectors.get(layer_index).unwrap()
     }
 
-    pub fn get_weights_matrix_mut(&mut self, layer_index: &LayerIndex) -> &mut Matrix {
+    pub fn get_weights_matrix_mut(&mut self, layer_index: &LayerIndex) -> &mut Matrix<T> {
         self.weights_matrices.get_mut(layer_index).unwrap()
     }
 
-    pub fn get_bias_vector_mut(&mut self, layer_index: &LayerIndex) -> &mut ColumnVector {
+    pub fn get_bias_vector_mut(&mut self, layer_index: &LayerIndex) -> &mut ColumnVector<T> {
         self.bias_vectors.get_mut(layer_index).unwrap()
     }
 
-    pub fn mult_scalar_in_place(&mut self, scalar: f64) {
+    /// The biases, scales and shifts - i.e. everything but the weights.
+    fn vectors_mut(&mut self) -> impl Iterator<Item = (&LayerIndex, &mut ColumnVector<T>)> {
+        self.bias_vectors
+            .iter_mut()
+            .chain(self.scale_vectors.iter_mut())
+            .chain(self.shift_vectors.iter_mut())
+    }
+
+    /// `vectors_mut`, each paired with the corresponding vector of `other`.
+    fn vectors_mut_with<'a>(
+        &'a mut self,
+        other: &'a Self,
+    ) -> impl Iterator<Item = (&'a mut ColumnVector<T>, &'a ColumnVector<T>)> {
+        paired_vectors(&mut self.bias_vectors, &other.bias_vectors)
+            .chain(paired_vectors(
+                &mut self.scale_vectors,
+                &other.scale_vectors,
+            ))
+            .chain(paired_vectors(
+                &mut self.shift_vectors,
+                &other.shift_vectors,
+            ))
+    }
+
+    pub fn mult_scalar_in_place(&mut self, scalar: T) {
         for (_, w) in self.weights_matrices.iter_mut() {
             w.mult_scalar_mut(scalar);
         }
 
-        for (_, b) in self.bias_vectors.iter_mut() {
+        for (_, b) in self.vectors_mut() {
             b.mult_scalar_mut(scalar);
         }
     }
 
-    pub fn mult_scalar_return_new(&self, scalar: f64) -> BigTheta {
+    pub fn mult_scalar_return_new(&self, scalar: T) -> BigTheta<T> {
         let mut new_big_theta = self.clone();
         new_big_theta.mult_scalar_in_place(scalar);
         new_big_theta
     }
 
-    pub fn divide_scalar_in_place(&mut self, scalar: f64) {
+    pub fn divide_scalar_in_place(&mut self, scalar: T) {
         for (_, w) in self.weights_matrices.iter_mut() {
             w.div_scalar_mut(scalar);
         }
 
-        for (_, b) in self.bias_vectors.iter_mut() {
+        for (_, b) in self.vectors_mut() {
             b.div_scalar_mut(scalar);
         }
     }
 
-    pub fn divide_scalar_return_new(&self, scalar: f64) -> BigTheta {
+    pub fn divide_scalar_return_new(&self, scalar: T) -> BigTheta<T> {
         let mut new_big_theta = self.clone();
         new_big_theta.divide_scalar_in_place(scalar);
         new_big_theta
@@ -148,8 +276,7 @@ impl BigTheta {
             w.subtract_mut(other_w);
         }
 
-        for (layer_index, b) in self.bias_vectors.iter_mut() {
-            let other_b = other.bias_vectors.get(layer_index).unwrap();
+        for (b, other_b) in self.vectors_mut_with(other) {
             b.subtract_mut(other_b);
         }
     }
@@ -160,8 +287,7 @@ impl BigTheta {
             w.add_mut(other_w);
         }
 
-        for (layer_index, b) in self.bias_vectors.iter_mut() {
-            let other_b = other.bias_vectors.get(layer_index).unwrap();
+        for (b, other_b) in self.vectors_mut_with(other) {
             b.add_mut(other_b);
         }
     }
@@ -172,8 +298,7 @@ impl BigTheta {
             w.hadamard_product_in_place(other_w);
         }
 
-        for (layer_index, b) in self.bias_vectors.iter_mut() {
-            let other_b = other.bias_vectors.get(layer_index).unwrap();
+        for (b, other_b) in self.vectors_mut_with(other) {
             b.hadamard_product_in_place(other_b);
         }
     }
@@ -184,18 +309,17 @@ impl BigTheta {
             w.elementwise_divide_in_place(other_w);
         }
 
-        for (layer_index, b) in self.bias_vectors.iter_mut() {
-            let other_b = other.bias_vectors.get(layer_index).unwrap();
+        for (b, other_b) in self.vectors_mut_with(other) {
             b.elementwise_divide_in_place(other_b);
         }
     }
 
-    pub fn add_scalar_to_each_element_in_place(&mut self, scalar: f64) {
+    pub fn add_scalar_to_each_element_in_place(&mut self, scalar: T) {
         for (_, w) in self.weights_matrices.iter_mut() {
             w.add_scalar_to_each_element_in_place(scalar);
         }
 
-        for (_, b) in self.bias_vectors.iter_mut() {
+        for (_, b) in self.vectors_mut() {
             b.add_scalar_to_each_element_in_place(scalar);
         }
     }
@@ -205,10 +329,68 @@ impl BigTheta {
             w.elementwise_square_root_in_place();
         }
 
-        for (_, b) in self.bias_vectors.iter_mut() {
+        for (_, b) in self.vectors_mut() {
             b.elementwise_square_root_in_place();
         }
     }
+
+    /// The L2 norm of all the values together, as though they were one long vector. The values are summed in the same
+    /// order as `unroll`, so the result doesn't depend on the order of the HashMaps.
+    pub fn l2_norm(&self) -> f64 {
+        let mut sum_of_squares = 0.0;
+        for l in 1..self.sizes.len() {
+            let vectors = [
+                self.bias_vectors.get(&l),
+                self.scale_vectors.get(&l),
+                self.shift_vectors.get(&l),
+            ];
+            let values = self.weights_matrices[&l]
+                .data
+                .iter()
+                .chain(vectors.into_iter().flatten().flat_map(|v| v.iter()));
+            for x in values {
+                sum_of_squares += x.as_f64() * x.as_f64();
+            }
+        }
+        sum_of_squares.sqrt()
+    }
+
+    /// Clamps each value to `[-max_abs, max_abs]`. NaNs are left as they are, so they can still be noticed.
+    pub fn clamp_in_place(&mut self, max_abs: T) {
+        let clamp = |x: &mut T| {
+            if x.abs() > max_abs {
+                *x = max_abs * x.signum();
+            }
+        };
+
+        for (_, w) in self.weights_matrices.iter_mut() {
+            w.data.iter_mut().for_each(clamp);
+        }
+
+        for (_, b) in self.vectors_mut() {
+            b.iter_mut().for_each(clamp);
+        }
+    }
+
+    pub fn elementwise_max_in_place(&mut self, other: &Self) {
+        for (layer_index, w) in self.weights_matrices.iter_mut() {
+            let other_w = other.weights_matrices.get(layer_index).unwrap();
+            w.elementwise_max_in_place(other_w);
+        }
+
+        for (b, other_b) in self.vectors_mut_with(other) {
+            b.elementwise_max_in_place(other_b);
+        }
+    }
+}
+
+fn paired_vectors<'a, T>(
+    vectors: &'a mut HashMap<LayerIndex, ColumnVector<T>>,
+    other_vectors: &'a HashMap<LayerIndex, ColumnVector<T>>,
+) -> impl Iterator<Item = (&'a mut ColumnVector<T>, &'a ColumnVector<T>)> {
+    vectors
+        .iter_mut()
+        .map(move |(layer_index, v)| (v, other_vectors.get(layer_index).unwrap()))
 }
 
 // test module
@@ -355,6 +537,61 @@ mod tests {
         assert_eq!(b2, &column_vector![26.0]);
     }
 
+    #[test]
+    fn from_unrolled_is_the_inverse_of_unroll() {
+        let sizes = vec![2, 3, 1];
+        let big_theta = create_big_theta_for_test(&sizes);
+
+        let unrolled = big_theta.unroll();
+        assert_eq!(unrolled, (1..=13).map(|x| x as f64).collect::<Vec<f64>>());
+
+        let rolled = BigTheta::from_unrolled(&sizes, &unrolled);
+        assert_eq!(rolled, big_theta);
+    }
+
+    #[test]
+    fn scales_and_shifts_are_unrolled_after_the_biases_of_their_layer() {
+        let sizes = vec![2, 3, 1];
+        let unrolled = (1..=19).map(|x| x as f64).collect::<Vec<f64>>();
+
+        let big_theta = BigTheta::from_unrolled_with_normalized_layers(&sizes, &[1], &unrolled);
+        assert_eq!(big_theta.normalized_layers(), vec![1]);
+        assert_eq!(
+            big_theta.get_bias_vector(&1),
+            &column_vector![7.0, 8.0, 9.0]
+        );
+        assert_eq!(
+            big_theta.scale_vectors[&1],
+            column_vector![10.0, 11.0, 12.0]
+        );
+        assert_eq!(
+            big_theta.shift_vectors[&1],
+            column_vector![13.0, 14.0, 15.0]
+        );
+        assert_eq!(
+            &big_theta.get_weights_matrix(&2).data,
+            &vec![16.0, 17.0, 18.0]
+        );
+        assert_eq!(big_theta.unroll(), unrolled);
+
+        // the scales and shifts are updated along with the weights and biases
+        let mut doubled = big_theta.clone();
+        doubled.add_in_place(&big_theta);
+        assert_eq!(
+            doubled.unroll(),
+            unrolled.iter().map(|x| 2.0 * x).collect::<Vec<f64>>()
+        );
+
+        let zero = BigTheta::<f64>::zero_from_sizes_with_normalized_layers(&sizes, &[1]);
+        assert_eq!(zero.unroll(), vec![0.0; 19]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_unrolled_panics_on_wrong_length() {
+        BigTheta::from_unrolled(&[2, 3, 1], &[1.0; 14]);
+    }
+
     #[test]
     fn test_mult_scalar_in_place_works() {
         let sizes = vec![2, 3, 1];
@@ -478,6 +715,38 @@ mod tests {
         assert_eq!(big_theta_1, big_theta_3);
     }
 
+    #[test]
+    fn test_elementwise_max_in_place_works() {
+        let sizes = vec![2, 3, 1];
+        let mut big_theta_1 = create_big_theta_for_test(&sizes);
+        let big_theta_2 = create_big_theta_for_test_with_scale_factor(&sizes, 2.0);
+
+        big_theta_1.elementwise_max_in_place(&big_theta_2);
+        assert_eq!(big_theta_1, big_theta_2);
+
+        let big_theta_3 = create_big_theta_for_test(&sizes);
+        big_theta_1.elementwise_max_in_place(&big_theta_3);
+        assert_eq!(big_theta_1, big_theta_2);
+    }
+
+    #[test]
+    fn l2_norm_and_clamp_cover_all_the_values() {
+        // layer 1 is normalized, so has 6 weights, 3 biases, 3 scales and 3 shifts, then layer 2 has 3 weights and a bias
+        let values = (0..19).map(|i| i as f64 - 9.0).collect::<Vec<f64>>();
+        let mut big_theta =
+            BigTheta::from_unrolled_with_normalized_layers(&[2, 3, 1], &[1], &values);
+
+        let sum_of_squares: f64 = values.iter().map(|x| x * x).sum();
+        assert_eq!(big_theta.l2_norm(), sum_of_squares.sqrt());
+
+        big_theta.clamp_in_place(2.5);
+        let clamped = values
+            .iter()
+            .map(|x| x.clamp(-2.5, 2.5))
+            .collect::<Vec<f64>>();
+        assert_eq!(big_theta.unroll(), clamped);
+    }
+
     #[test]
     fn test_get_weights_matrix_mut() {
         let sizes = vec![2, 3, 1];
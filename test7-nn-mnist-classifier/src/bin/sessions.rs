@@ -0,0 +1,373 @@
+//! Lists the training sessions logged by `TrainingSessionLogger`, and compares them.
+//!
+//! Usage:
+//!   sessions [--dir <training sessions dir>] list
+//!   sessions [--dir <training sessions dir>] compare <session>... [--metric <metric>] [--svg <file.svg>]
+//!
+//! The training sessions directory defaults to `$TRAINING_LOG_HOME`, or `./training-sessions`. A session is given by
+//! its id (the name of its directory) or the path to its directory.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+
+use test7_nn_mnist_classifier::charts::{self, Series};
+use test7_nn_mnist_classifier::training_log::{self, SessionLog, TrainingUpdate};
+
+const USAGE: &str = "usage: sessions [--dir <training sessions dir>] list
+       sessions [--dir <training sessions dir>] compare <session>... [--metric <metric>] [--svg <file.svg>]
+
+metrics: training-cost (the default), validation-cost, test-cost, training-accuracy, validation-accuracy,
+         test-accuracy";
+
+const CHART_WIDTH: usize = 72;
+const CHART_HEIGHT: usize = 20;
+
+/// What the compare chart plots against the epochs completed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Metric {
+    TrainingCost,
+    ValidationCost,
+    TestCost,
+    TrainingAccuracy,
+    ValidationAccuracy,
+    TestAccuracy,
+}
+
+impl Metric {
+    fn from_arg(arg: &str) -> anyhow::Result<Self> {
+        match arg {
+            "training-cost" => Ok(Metric::TrainingCost),
+            "validation-cost" => Ok(Metric::ValidationCost),
+            "test-cost" => Ok(Metric::TestCost),
+            "training-accuracy" => Ok(Metric::TrainingAccuracy),
+            "validation-accuracy" => Ok(Metric::ValidationAccuracy),
+            "test-accuracy" => Ok(Metric::TestAccuracy),
+            _ => Err(anyhow!("unknown metric {:?}\n{}", arg, USAGE)),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Metric::TrainingCost => "training set cost",
+            Metric::ValidationCost => "validation set cost",
+            Metric::TestCost => "test set cost",
+            Metric::TrainingAccuracy => "training set accuracy",
+            Metric::ValidationAccuracy => "validation set accuracy",
+            Metric::TestAccuracy => "test set accuracy",
+        }
+    }
+
+    fn value(&self, update: &TrainingUpdate) -> Option<f64> {
+        match self {
+            Metric::TrainingCost => Some(update.training_set_cost),
+            Metric::ValidationCost => update.validation_set_cost,
+            Metric::TestCost => update.test_set_cost,
+            Metric::TrainingAccuracy => Some(update.training_set_accuracy),
+            Metric::ValidationAccuracy => update.validation_set_accuracy,
+            Metric::TestAccuracy => update.test_set_accuracy,
+        }
+    }
+}
+
+enum Command {
+    List,
+    Compare {
+        sessions: Vec<String>,
+        metric: Metric,
+        svg_path: Option<PathBuf>,
+    },
+}
+
+struct Args {
+    training_sessions_path: Option<PathBuf>,
+    command: Command,
+}
+
+fn args_from_env() -> anyhow::Result<Args> {
+    let mut args = std::env::args().skip(1);
+    let mut training_sessions_path = None;
+    let mut command_name = None;
+    let mut sessions = Vec::new();
+    let mut metric = Metric::TrainingCost;
+    let mut svg_path = None;
+
+    while let Some(arg) = args.next() {
+        let mut value_for = |flag: &str| {
+            args.next()
+                .ok_or_else(|| anyhow!("{} needs a value\n{}", flag, USAGE))
+        };
+        match arg.as_str() {
+            "--dir" => training_sessions_path = Some(PathBuf::from(value_for("--dir")?)),
+            "--metric" => metric = Metric::from_arg(&value_for("--metric")?)?,
+            "--svg" => svg_path = Some(PathBuf::from(value_for("--svg")?)),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ if arg.starts_with("--") => {
+                return Err(anyhow!("unexpected argument {:?}\n{}", arg, USAGE))
+            }
+            _ if command_name.is_none() => command_name = Some(arg),
+            _ => sessions.push(arg),
+        }
+    }
+
+    let command = match command_name.as_deref() {
+        Some("list") if sessions.is_empty() => Command::List,
+        Some("compare") if !sessions.is_empty() => Command::Compare {
+            sessions,
+            metric,
+            svg_path,
+        },
+        Some("compare") => return Err(anyhow!("compare needs at least one session\n{}", USAGE)),
+        _ => return Err(anyhow!("{}", USAGE)),
+    };
+
+    Ok(Args {
+        training_sessions_path,
+        command,
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = args_from_env()?;
+    let training_sessions_path = match args.training_sessions_path {
+        Some(training_sessions_path) => training_sessions_path,
+        None => training_log::default_training_sessions_directory()?,
+    };
+
+    match args.command {
+        Command::List => list(&training_sessions_path),
+        Command::Compare {
+            sessions,
+            metric,
+            svg_path,
+        } => {
+            let session_logs = sessions
+                .iter()
+                .map(|session| read_session(&training_sessions_path, session))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            compare(&session_logs, metric, svg_path.as_deref())
+        }
+    }
+}
+
+/// Reads a session given by its id, or the path to its directory.
+fn read_session(training_sessions_path: &Path, session: &str) -> anyhow::Result<SessionLog> {
+    let session_directory = if Path::new(session).is_dir() {
+        PathBuf::from(session)
+    } else {
+        training_sessions_path.join(session)
+    };
+    SessionLog::read_from_directory(&session_directory)
+        .with_context(|| format!("failed reading session {:?}", session))
+}
+
+fn list(training_sessions_path: &Path) -> anyhow::Result<()> {
+    let session_directories = training_log::session_directories_in(training_sessions_path)
+        .with_context(|| format!("failed reading {:?}", training_sessions_path))?;
+
+    let header = [
+        "session",
+        "layers",
+        "optimizer",
+        "epochs",
+        "steps",
+        "training cost",
+        "validation cost",
+        "test cost",
+        "test accuracy",
+    ];
+    let mut rows = Vec::new();
+    for session_directory in &session_directories {
+        match SessionLog::read_from_directory(session_directory) {
+            Ok(session_log) => {
+                let last_update = session_log.updates.last();
+                rows.push(vec![
+                    session_log.session.training_session_id.to_string(),
+                    layer_sizes(&session_log),
+                    optimizer_name(&session_log.session.optimizer).to_string(),
+                    format_optional_count(last_update.map(|u| u.epochs_completed)),
+                    format_optional_count(last_update.map(|u| u.steps_completed)),
+                    format_optional(last_update.map(|u| u.training_set_cost)),
+                    format_optional(last_update.and_then(|u| u.validation_set_cost)),
+                    format_optional(last_update.and_then(|u| u.test_set_cost)),
+                    format_optional_percentage(last_update.and_then(|u| u.test_set_accuracy)),
+                ]);
+            }
+            Err(e) => eprintln!("skipping {:?}: {:#}", session_directory, e),
+        }
+    }
+
+    print!("{}", table(&header, &rows));
+    println!("{} sessions in {:?}", rows.len(), training_sessions_path);
+    Ok(())
+}
+
+fn compare(
+    session_logs: &[SessionLog],
+    metric: Metric,
+    svg_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    // one column per session
+    let mut header = vec![String::new()];
+    header.extend(
+        session_logs
+            .iter()
+            .map(|session_log| session_log.session.training_session_id.to_string()),
+    );
+    let row = |name: &str, value: &dyn Fn(&SessionLog) -> String| {
+        let mut row = vec![name.to_string()];
+        row.extend(session_logs.iter().map(value));
+        row
+    };
+    let last_value = |metric: Metric| {
+        move |session_log: &SessionLog| {
+            format_optional(session_log.updates.last().and_then(|u| metric.value(u)))
+        }
+    };
+    let rows = vec![
+        row("layers", &layer_sizes),
+        row("activations", &activations),
+        row("optimizer", &|s| {
+            optimizer_name(&s.session.optimizer).to_string()
+        }),
+        row("seed", &|s| s.session.seed.to_string()),
+        row("initialization seed", &|s| {
+            s.session
+                .initialization_seed
+                .map(|seed| seed.to_string())
+                .unwrap_or_else(|| String::from("-"))
+        }),
+        row("initial cost", &|s| format_value(s.session.initial_cost)),
+        row("epochs", &|s| {
+            format_optional_count(s.updates.last().map(|u| u.epochs_completed))
+        }),
+        row("steps", &|s| {
+            format_optional_count(s.updates.last().map(|u| u.steps_completed))
+        }),
+        row("training cost", &last_value(Metric::TrainingCost)),
+        row("validation cost", &last_value(Metric::ValidationCost)),
+        row("best validation cost", &|s| {
+            format_optional(
+                s.updates
+                    .iter()
+                    .filter_map(|u| u.validation_set_cost)
+                    .min_by(f64::total_cmp),
+            )
+        }),
+        row("test cost", &last_value(Metric::TestCost)),
+        row("test accuracy", &|s| {
+            format_optional_percentage(s.updates.last().and_then(|u| u.test_set_accuracy))
+        }),
+    ];
+    let header: Vec<&str> = header.iter().map(String::as_str).collect();
+    print!("{}", table(&header, &rows));
+
+    println!();
+    for session_log in session_logs {
+        println!(
+            "{}: {}",
+            session_log.session.training_session_id, session_log.session.optimizer
+        );
+    }
+
+    let series: Vec<Series> = session_logs
+        .iter()
+        .map(|session_log| {
+            let points = session_log
+                .updates
+                .iter()
+                .filter_map(|u| metric.value(u).map(|v| (u.epochs_completed as f64, v)))
+                .collect();
+            Series::new(session_log.session.training_session_id.to_string(), points)
+        })
+        .collect();
+
+    println!("\n{} by epochs completed:\n", metric.label());
+    print!(
+        "{}",
+        charts::ascii_chart(&series, CHART_WIDTH, CHART_HEIGHT)
+    );
+
+    if let Some(svg_path) = svg_path {
+        let title = format!("{} by epochs completed", metric.label());
+        let svg = charts::svg_chart(&series, &title, "epochs completed", metric.label());
+        std::fs::write(svg_path, svg).with_context(|| format!("failed writing {:?}", svg_path))?;
+        println!("\nwrote {:?}", svg_path);
+    }
+
+    Ok(())
+}
+
+/// Left-aligns the columns, two spaces apart.
+fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: &mut dyn Iterator<Item = &str>| {
+        let line: Vec<String> = cells
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("{}\n", line.join("  ").trim_end())
+    };
+
+    let mut table = format_row(&mut header.iter().copied());
+    for row in rows {
+        table.push_str(&format_row(&mut row.iter().map(String::as_str)));
+    }
+    table
+}
+
+/// e.g. `784-100-10`
+fn layer_sizes(session_log: &SessionLog) -> String {
+    let layers = &session_log.session.network_config.layers;
+    let sizes: Vec<String> = layers.iter().map(|layer| layer.size.to_string()).collect();
+    sizes.join("-")
+}
+
+/// The hidden and output layers' activation functions, which are logged as e.g. `Some(LeakyReLU(0.1))`.
+fn activations(session_log: &SessionLog) -> String {
+    let layers = &session_log.session.network_config.layers;
+    let activations: Vec<&str> = layers
+        .iter()
+        .filter_map(|layer| {
+            layer
+                .activation_function
+                .strip_prefix("Some(")
+                .and_then(|rest| rest.strip_suffix(')'))
+        })
+        .collect();
+    activations.join(", ")
+}
+
+/// The optimizer is logged with its hyperparameters, e.g. `Adam(AdamConfig { .. })`, which is too long for a column.
+fn optimizer_name(optimizer: &str) -> &str {
+    optimizer.split(['(', '{', ' ']).next().unwrap_or(optimizer)
+}
+
+fn format_value(value: f64) -> String {
+    format!("{:.6}", value)
+}
+
+fn format_optional(value: Option<f64>) -> String {
+    value.map(format_value).unwrap_or_else(|| String::from("-"))
+}
+
+fn format_optional_count(count: Option<usize>) -> String {
+    count
+        .map(|c| c.to_string())
+        .unwrap_or_else(|| String::from("-"))
+}
+
+fn format_optional_percentage(fraction: Option<f64>) -> String {
+    fraction
+        .map(|f| format!("{:.2}%", f * 100.0))
+        .unwrap_or_else(|| String::from("-"))
+}
@@ -0,0 +1,119 @@
+//! Trains a network described by a config file.
+//!
+//! Usage: train_from_config --config <file.toml|file.json>
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use metrics::SimpleTimer;
+use tracing_subscriber::EnvFilter;
+
+use test7_nn_mnist_classifier::config::{ConfigFormat, ExperimentConfig};
+use test7_nn_mnist_classifier::rng;
+
+const USAGE: &str = "usage: train_from_config --config <file.toml|file.json>";
+
+fn config_path_from_args() -> anyhow::Result<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    let mut config_path = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--config needs a path\n{}", USAGE))?;
+                config_path = Some(PathBuf::from(path));
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(anyhow!("unexpected argument {:?}\n{}", arg, USAGE)),
+        }
+    }
+
+    config_path.ok_or_else(|| anyhow!("missing --config\n{}", USAGE))
+}
+
+fn main() -> anyhow::Result<()> {
+    // the training progress is logged at info, and per-step details at debug - override with RUST_LOG
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let config_path = config_path_from_args()?;
+    let mut config = ExperimentConfig::from_file(&config_path)?;
+    println!("loaded config from {:?}", config_path);
+
+    // pick the seed up front, so that the copy of the config saved with the session reproduces this run
+    let seed = *config.seed.get_or_insert_with(rng::random_seed);
+    println!("seed: {}", seed);
+
+    let mut nn = config
+        .network_builder()
+        .try_build()
+        .context("invalid network configuration")?;
+
+    let data = config.data.load(seed);
+    println!(
+        "got {} training, {} validation and {} test examples",
+        data.training.len(),
+        data.validation.len(),
+        data.test.len()
+    );
+
+    let session_logger = config
+        .logging
+        .session_logger()
+        .context("failed creating training log directory")?;
+    if let Some(session_logger) = &session_logger {
+        println!(
+            "training session id: {:?}",
+            &session_logger.training_session_id
+        );
+
+        // keep a copy of the config with the session so the run can be reviewed and repeated
+        if let Some(session_directory) = &session_logger.full_session_output_directory {
+            let mut copied_config_path = session_directory.clone();
+            copied_config_path.push("config.toml");
+            std::fs::write(
+                &copied_config_path,
+                config.to_string_with_format(ConfigFormat::Toml)?,
+            )?;
+        }
+    }
+
+    let mut t_total = SimpleTimer::start_new("t_total");
+
+    nn.train_stochastic(
+        data.datasets(),
+        config.training.epochs,
+        &config.training.optimizer,
+        config.training.mini_batch_size,
+        None,
+        config.training.early_stop_config(),
+        config.training.full_cost_update_every,
+        session_logger,
+        Some(&config.training_options()),
+    )?;
+    println!("done training");
+
+    if let Some(model_path) = &config.logging.model_path {
+        nn.save(model_path)?;
+        println!("saved trained model to {:?}", model_path);
+    }
+
+    let test_set_cost = nn.cost_training_set(&data.test)?;
+    println!("\ntest_set_cost: {}", test_set_cost);
+
+    let test_set_report = nn.evaluate_classification(&data.test, 3)?;
+    println!("\ntest set evaluation:\n{}", test_set_report);
+
+    t_total.stop();
+    println!("\nt_total: {}", t_total);
+
+    Ok(())
+}
@@ -1,17 +1,33 @@
 use std::collections::HashMap;
 
 use crate::activation::ActivationFunction;
+use crate::errors::BuilderError;
 use crate::initializer::get_init_weights_and_biases;
 use crate::layer_config::LayerConfig;
+use crate::normalization::{Normalization, RunningStatistics};
+use crate::regularization::Regularization;
+use crate::rng::{random_seed, seeded_rng, RngStream};
 use crate::{cost, Initializer, NeuralNetwork};
-use common::column_vec_of_random_values_from_distribution;
-use common::linalg::{ColumnVector, Matrix};
+use common::column_vec_of_random_values_from_distribution_with_rng;
+use common::linalg::{ColumnVector, Matrix, MatrixShape};
+use tracing::debug;
 
 pub struct NeuralNetworkBuilder {
     input_layer_size: Option<usize>,
     hidden_layers_info: Vec<HiddenLayerConfig>,
     output_layer_info: Option<OutputLayerConfig>,
-    cost_fn: Option<cost::CostFunc>
+    cost_fn: Option<cost::CostFunc>,
+    seed: Option<u64>,
+    last_layer_added: Option<LayerKind>,
+    /// The first misuse of the `with_*` methods (e.g. a layer added before the input layer), reported by `try_build`.
+    deferred_error: Option<BuilderError>,
+}
+
+/// Which kind of layer was added last, for the methods that configure it (e.g. `with_regularization`).
+#[derive(Debug, Clone, Copy)]
+enum LayerKind {
+    Hidden,
+    Output,
 }
 
 #[derive(Debug, Clone)]
@@ -19,6 +35,9 @@ pub struct HiddenLayerConfig {
     size: usize,
     weights_and_biases: Initializer, // rename this field to initializer
     activation_function: ActivationFunction,
+    regularization: Regularization,
+    dropout_rate: Option<f64>,
+    normalization: Option<Normalization>,
 }
 
 #[derive(Debug, Clone)]
@@ -26,6 +45,7 @@ pub struct OutputLayerConfig {
     size: usize,
     weights_and_biases: Initializer,
     activation_function: ActivationFunction,
+    regularization: Regularization,
 }
 
 impl NeuralNetworkBuilder {
@@ -35,6 +55,9 @@ impl NeuralNetworkBuilder {
             hidden_layers_info: Vec::new(),
             output_layer_info: None,
             cost_fn: None,
+            seed: None,
+            last_layer_added: None,
+            deferred_error: None,
         }
     }
 
@@ -48,6 +71,13 @@ impl NeuralNetworkBuilder {
         self
     }
 
+    /// Seeds the RNG the initial weights and biases are drawn from, so that building the same configuration with the
+    /// same seed gives the same network. Without a seed, a random one is used.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
     pub fn with_hidden_layer(
         mut self,
         size: usize,
@@ -55,47 +85,18 @@ impl NeuralNetworkBuilder {
         activation_function: ActivationFunction,
     ) -> Self {
         if self.input_layer_size.is_none() {
-            panic!("Input layer size must be set before adding hidden layers");
-        }
-
-        if let Initializer::Manual(weights, bias_v) = &weights_and_biases {
-            if weights.num_rows() != size {
-                panic!(
-                  "The number of rows in the weights matrix ({}) does not match the size of the layer ({})",
-                  weights.num_rows(),
-                  size
-              );
-            }
-            let previous_row_size = if self.hidden_layers_info.is_empty() {
-                self.input_layer_size.unwrap()
-            } else {
-                self.hidden_layers_info.last().unwrap().size
-            };
-
-            println!("previous row size: {}", previous_row_size);
-
-            if weights.num_columns() != previous_row_size {
-                panic!(
-                  "The number of columns in the weights matrix ({}) does not match the size of the previous layer ({})",
-                  weights.num_columns(),
-                  previous_row_size
-              );
-            }
-
-            if bias_v.num_elements() != size {
-                panic!(
-                  "The number of elements in the bias vector ({}) does not match the size of the layer ({})",
-                  bias_v.num_elements(),
-                  size
-              );
-            }
+            self.defer_error(BuilderError::LayerAddedBeforeInputLayer);
         }
 
         self.hidden_layers_info.push(HiddenLayerConfig {
             size,
             weights_and_biases,
             activation_function,
+            regularization: Regularization::default(),
+            dropout_rate: None,
+            normalization: None,
         });
+        self.last_layer_added = Some(LayerKind::Hidden);
         self
     }
 
@@ -106,75 +107,190 @@ impl NeuralNetworkBuilder {
         activation_function: ActivationFunction,
     ) -> Self {
         if self.input_layer_size.is_none() {
-            panic!("Input layer size must be set before adding the output layer");
+            self.defer_error(BuilderError::LayerAddedBeforeInputLayer);
         }
 
-        if let Initializer::Manual(weights, bias_v) = &weights_and_biases {
-            if weights.num_rows() != size {
-                panic!(
-                  "The number of rows in the weights matrix ({}) does not match the size of the layer ({})",
-                  weights.num_rows(),
-                  size
-              );
+        self.output_layer_info = Some(OutputLayerConfig {
+            size,
+            weights_and_biases,
+            activation_function,
+            regularization: Regularization::default(),
+        });
+        self.last_layer_added = Some(LayerKind::Output);
+        self
+    }
+
+    /// Sets the L1/L2 penalties on the weights of the layer that was added last, e.g.
+    /// `.with_hidden_layer(...).with_regularization(Regularization::l2(1e-4))`. Layers aren't regularized by default.
+    pub fn with_regularization(mut self, regularization: Regularization) -> Self {
+        match self.last_layer_added {
+            Some(LayerKind::Hidden) => {
+                self.hidden_layers_info.last_mut().unwrap().regularization = regularization;
             }
-            let previous_row_size = if self.hidden_layers_info.is_empty() {
-                self.input_layer_size.unwrap()
-            } else {
-                self.hidden_layers_info.last().unwrap().size
-            };
+            Some(LayerKind::Output) => {
+                self.output_layer_info.as_mut().unwrap().regularization = regularization;
+            }
+            None => self.defer_error(BuilderError::RegularizationBeforeAnyLayer),
+        }
+        self
+    }
 
-            println!("previous row size: {}", previous_row_size);
+    /// Adds inverted dropout to the hidden layer that was added last: while training, each of its activations is
+    /// dropped with probability `rate` (and the rest are scaled up to make up for it). Dropout is off in `feed_forward`.
+    pub fn with_dropout(mut self, rate: f64) -> Self {
+        match self.last_layer_added {
+            Some(LayerKind::Hidden) => {
+                self.hidden_layers_info.last_mut().unwrap().dropout_rate = Some(rate);
+            }
+            _ => self.defer_error(BuilderError::DropoutNotAfterHiddenLayer),
+        }
+        self
+    }
 
-            if weights.num_columns() != previous_row_size {
-                panic!(
-                  "The number of columns in the weights matrix ({}) does not match the size of the previous layer ({})",
-                  weights.num_columns(),
-                  previous_row_size
-              );
+    /// Normalizes the weighted inputs of the hidden layer that was added last, e.g.
+    /// `.with_hidden_layer(...).with_normalization(Normalization::batch_norm())`. The layer gets a learned scale and
+    /// shift per neuron, starting at 1 and 0.
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        match self.last_layer_added {
+            Some(LayerKind::Hidden) => {
+                self.hidden_layers_info.last_mut().unwrap().normalization = Some(normalization);
             }
+            _ => self.defer_error(BuilderError::NormalizationNotAfterHiddenLayer),
+        }
+        self
+    }
 
-            if bias_v.num_elements() != size {
-                panic!(
-                  "The number of elements in the bias vector ({}) does not match the size of the layer ({})",
-                  bias_v.num_elements(),
-                  size
-              );
+    /// Keeps `error` for `try_build` to return, unless an earlier misuse was already kept.
+    fn defer_error(&mut self, error: BuilderError) {
+        self.deferred_error.get_or_insert(error);
+    }
+
+    /// Checks that the layers and cost function make up a network that can be trained.
+    fn validate(&self) -> Result<(), BuilderError> {
+        if let Some(error) = &self.deferred_error {
+            return Err(error.clone());
+        }
+        let Some(input_layer_size) = self.input_layer_size else {
+            return Err(BuilderError::MissingInputLayer);
+        };
+        let Some(output_layer_info) = &self.output_layer_info else {
+            return Err(BuilderError::MissingOutputLayer);
+        };
+        let Some(cost_fn) = &self.cost_fn else {
+            return Err(BuilderError::MissingCostFunction);
+        };
+
+        // (size, initializer, activation function) for layers 1 to L-1
+        let layers = self
+            .hidden_layers_info
+            .iter()
+            .map(|h| (h.size, &h.weights_and_biases, &h.activation_function))
+            .chain(std::iter::once((
+                output_layer_info.size,
+                &output_layer_info.weights_and_biases,
+                &output_layer_info.activation_function,
+            )));
+
+        if input_layer_size == 0 {
+            return Err(BuilderError::EmptyLayer { layer_index: 0 });
+        }
+
+        for (layer_index, h) in (1..).zip(self.hidden_layers_info.iter()) {
+            if !h.regularization.is_valid() {
+                return Err(BuilderError::InvalidRegularization { layer_index });
             }
+            if let Some(rate) = h.dropout_rate {
+                if !(0.0..1.0).contains(&rate) {
+                    return Err(BuilderError::InvalidDropoutRate { layer_index, rate });
+                }
+            }
+            if let Some(normalization) = h.normalization {
+                if !normalization.is_valid() {
+                    return Err(BuilderError::InvalidNormalization { layer_index });
+                }
+            }
+        }
+        if !output_layer_info.regularization.is_valid() {
+            return Err(BuilderError::InvalidRegularization {
+                layer_index: self.hidden_layers_info.len() + 1,
+            });
         }
 
-        self.output_layer_info = Some(OutputLayerConfig {
-            size,
-            weights_and_biases,
-            activation_function,
-        });
-        self
+        let mut previous_layer_size = input_layer_size;
+        for (layer_index, (size, initializer, activation_function)) in (1..).zip(layers) {
+            if size == 0 {
+                return Err(BuilderError::EmptyLayer { layer_index });
+            }
+
+            if let Initializer::Manual(weights, bias_v) = initializer {
+                let expected = MatrixShape::new(size, previous_layer_size);
+                if weights.shape() != expected {
+                    return Err(BuilderError::InvalidWeightsMatrixShape {
+                        layer_index,
+                        expected,
+                        actual: weights.shape(),
+                    });
+                }
+
+                if bias_v.num_elements() != size {
+                    return Err(BuilderError::InvalidBiasVectorLength {
+                        layer_index,
+                        expected: size,
+                        actual: bias_v.num_elements(),
+                    });
+                }
+            }
+
+            if layer_index <= self.hidden_layers_info.len()
+                && activation_function == &ActivationFunction::Softmax
+            {
+                return Err(BuilderError::SoftmaxInHiddenLayer { layer_index });
+            }
+
+            previous_layer_size = size;
+        }
+
+        let softmax_output = output_layer_info.activation_function == ActivationFunction::Softmax;
+        match cost_fn {
+            cost::CostFunc::CrossEntropy if !softmax_output => {
+                Err(BuilderError::CrossEntropyRequiresSoftmaxOutput)
+            }
+            cost::CostFunc::QuadraticCost if softmax_output => {
+                Err(BuilderError::SoftmaxOutputRequiresCrossEntropy)
+            }
+            _ => Ok(()),
+        }
     }
 
+    /// Builds the network, panicking if the configuration is invalid. See `try_build` for the checks that are done.
     pub fn build(self) -> NeuralNetwork {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Builds the network, or returns an error if the layers and cost function don't make up a network that can be
+    /// trained - e.g. a layer is missing, an `Initializer::Manual` has the wrong shape or `CostFunc::CrossEntropy`
+    /// is used without a Softmax output layer.
+    pub fn try_build(self) -> Result<NeuralNetwork, BuilderError> {
+        self.validate()?;
+
         // first setup the sizes
         let mut sizes = Vec::new();
         let mut layer_infos = HashMap::new();
         layer_infos.insert(0, LayerConfig::new_with_initializer(None, None));
 
-        if let Some(input_layer_size) = self.input_layer_size {
-            sizes.push(input_layer_size);
-        } else {
-            panic!("Input layer size not specified");
-        }
+        sizes.push(self.input_layer_size.expect("validated"));
 
         self.hidden_layers_info.iter().for_each(|layer_info| {
             sizes.push(layer_info.size);
         });
 
-        if let Some(output_layer_info) = &self.output_layer_info {
-            sizes.push(output_layer_info.size);
-        } else {
-            panic!("Output layer size not specified");
-        }
+        let output_layer_info = self.output_layer_info.expect("validated");
+        sizes.push(output_layer_info.size);
 
-        let Some(cost_fn) = self.cost_fn else {
-            panic!("Cost function not specified");
-        };
+        let cost_fn = self.cost_fn.expect("validated");
+
+        let seed = self.seed.unwrap_or_else(random_seed);
+        let rng = &mut seeded_rng(seed, RngStream::Initialization);
 
         // initial weights and biases
         let mut weights = HashMap::new();
@@ -187,15 +303,19 @@ impl NeuralNetworkBuilder {
 
             match h.weights_and_biases {
                 Initializer::RandomBasic => {
-                    let weights_m = Matrix::new_matrix_with_random_values_from_normal_distribution(
-                        sizes[l],
-                        sizes[l - 1],
-                        0.0,
-                        1.0,
+                    let weights_m =
+                        Matrix::new_matrix_with_random_values_from_normal_distribution_with_rng(
+                            sizes[l],
+                            sizes[l - 1],
+                            0.0,
+                            1.0,
+                            rng,
+                        );
+
+                    let bias_v = column_vec_of_random_values_from_distribution_with_rng(
+                        0.0, 1.0, sizes[l], rng,
                     );
 
-                    let bias_v = column_vec_of_random_values_from_distribution(0.0, 1.0, sizes[l]);
-
                     weights.insert(l, weights_m);
                     biases.insert(l, bias_v);
                 }
@@ -208,12 +328,14 @@ impl NeuralNetworkBuilder {
                     let min = -1.0 / (num_nodes_in_previous_layer as f64).sqrt();
                     let max = 1.0 / (num_nodes_in_previous_layer as f64).sqrt();
 
-                    let weights_m = Matrix::new_matrix_with_random_values_from_uniform_distribution(
-                        sizes[l],
-                        sizes[l - 1],
-                        min,
-                        max,
-                    );
+                    let weights_m =
+                        Matrix::new_matrix_with_random_values_from_uniform_distribution_with_rng(
+                            sizes[l],
+                            sizes[l - 1],
+                            min,
+                            max,
+                            rng,
+                        );
 
                     let bias_v = ColumnVector::new_zero_vector(sizes[l]);
 
@@ -224,29 +346,28 @@ impl NeuralNetworkBuilder {
                     let num_nodes_in_this_layer = sizes[l];
                     let num_nodes_in_next_layer = sizes[l + 1];
 
-                    println!(
-                        "XavierNormalized: num_nodes_in_this_layer: {}",
-                        num_nodes_in_this_layer
-                    );
-                    println!(
-                        "XavierNormalized: num_nodes_in_next_layer: {}",
-                        num_nodes_in_next_layer
-                    );
-
                     let x = 6.0_f64.sqrt()
                         / (num_nodes_in_this_layer as f64 + num_nodes_in_next_layer as f64).sqrt();
                     let min = -x;
                     let max = x;
 
-                    println!("using XavierNormalized with +/- {}", x);
-
-                    let weights_m = Matrix::new_matrix_with_random_values_from_uniform_distribution(
-                        sizes[l],
-                        sizes[l - 1],
-                        min,
-                        max,
+                    debug!(
+                        layer = l,
+                        num_nodes_in_this_layer,
+                        num_nodes_in_next_layer,
+                        limit = x,
+                        "using XavierNormalized"
                     );
 
+                    let weights_m =
+                        Matrix::new_matrix_with_random_values_from_uniform_distribution_with_rng(
+                            sizes[l],
+                            sizes[l - 1],
+                            min,
+                            max,
+                            rng,
+                        );
+
                     let bias_v = ColumnVector::new_zero_vector(sizes[l]);
 
                     weights.insert(l, weights_m);
@@ -257,13 +378,18 @@ impl NeuralNetworkBuilder {
                         l,
                         &sizes,
                         Initializer::XavierNormalHOMLForSigmoid,
+                        rng,
                     );
                     weights.insert(l, weights_m);
                     biases.insert(l, bias_v);
                 }
                 Initializer::HeForReLUAndVariants => {
-                    let (weights_m, bias_v) =
-                        get_init_weights_and_biases(l, &sizes, Initializer::HeForReLUAndVariants);
+                    let (weights_m, bias_v) = get_init_weights_and_biases(
+                        l,
+                        &sizes,
+                        Initializer::HeForReLUAndVariants,
+                        rng,
+                    );
                     weights.insert(l, weights_m);
                     biases.insert(l, bias_v);
                 }
@@ -273,27 +399,34 @@ impl NeuralNetworkBuilder {
 
             layer_infos.insert(
                 l,
-                LayerConfig::new_with_initializer(
-                    Some(h.activation_function),
-                    Some(initializer_str),
-                ),
+                LayerConfig {
+                    regularization: h.regularization,
+                    dropout_rate: h.dropout_rate,
+                    normalization: h.normalization,
+                    ..LayerConfig::new_with_initializer(
+                        Some(h.activation_function),
+                        Some(initializer_str),
+                    )
+                },
             );
             l += 1;
         }
 
         // l is now the output layer
-        let output_layer_info = self.output_layer_info.unwrap();
         let initializer_str = format!("{}", &output_layer_info.weights_and_biases);
         match output_layer_info.weights_and_biases {
             Initializer::RandomBasic => {
-                let weights_m = Matrix::new_matrix_with_random_values_from_normal_distribution(
-                    sizes[l],
-                    sizes[l - 1],
-                    0.0,
-                    1.0,
-                );
+                let weights_m =
+                    Matrix::new_matrix_with_random_values_from_normal_distribution_with_rng(
+                        sizes[l],
+                        sizes[l - 1],
+                        0.0,
+                        1.0,
+                        rng,
+                    );
 
-                let bias_v = column_vec_of_random_values_from_distribution(0.0, 1.0, sizes[l]);
+                let bias_v =
+                    column_vec_of_random_values_from_distribution_with_rng(0.0, 1.0, sizes[l], rng);
 
                 weights.insert(l, weights_m);
                 biases.insert(l, bias_v);
@@ -307,12 +440,14 @@ impl NeuralNetworkBuilder {
                 let min = -1.0 / (num_nodes_in_previous_layer as f64).sqrt();
                 let max = 1.0 / (num_nodes_in_previous_layer as f64).sqrt();
 
-                let weights_m = Matrix::new_matrix_with_random_values_from_uniform_distribution(
-                    sizes[l],
-                    sizes[l - 1],
-                    min,
-                    max,
-                );
+                let weights_m =
+                    Matrix::new_matrix_with_random_values_from_uniform_distribution_with_rng(
+                        sizes[l],
+                        sizes[l - 1],
+                        min,
+                        max,
+                        rng,
+                    );
 
                 let bias_v = ColumnVector::new_zero_vector(sizes[l]);
 
@@ -327,48 +462,80 @@ impl NeuralNetworkBuilder {
                 let min = -x;
                 let max = x;
 
-                println!("using XavierNormalized with +/- {}", x);
-
-                let weights_m = Matrix::new_matrix_with_random_values_from_uniform_distribution(
-                    sizes[l],
-                    sizes[l - 1],
-                    min,
-                    max,
+                debug!(
+                    layer = l,
+                    num_nodes_in_this_layer,
+                    limit = x,
+                    "using XavierNormalized"
                 );
 
+                let weights_m =
+                    Matrix::new_matrix_with_random_values_from_uniform_distribution_with_rng(
+                        sizes[l],
+                        sizes[l - 1],
+                        min,
+                        max,
+                        rng,
+                    );
+
                 let bias_v = ColumnVector::new_zero_vector(sizes[l]);
 
                 weights.insert(l, weights_m);
                 biases.insert(l, bias_v);
             }
             Initializer::XavierNormalHOMLForSigmoid => {
-                let (weights_m, bias_v) =
-                    get_init_weights_and_biases(l, &sizes, Initializer::XavierNormalHOMLForSigmoid);
+                let (weights_m, bias_v) = get_init_weights_and_biases(
+                    l,
+                    &sizes,
+                    Initializer::XavierNormalHOMLForSigmoid,
+                    rng,
+                );
                 weights.insert(l, weights_m);
                 biases.insert(l, bias_v);
             }
             Initializer::HeForReLUAndVariants => {
                 let (weights_m, bias_v) =
-                    get_init_weights_and_biases(l, &sizes, Initializer::HeForReLUAndVariants);
+                    get_init_weights_and_biases(l, &sizes, Initializer::HeForReLUAndVariants, rng);
                 weights.insert(l, weights_m);
                 biases.insert(l, bias_v);
             }
         }
         layer_infos.insert(
             l,
-            LayerConfig::new_with_initializer(
-                Some(output_layer_info.activation_function),
-                Some(initializer_str),
-            ),
+            LayerConfig {
+                regularization: output_layer_info.regularization,
+                ..LayerConfig::new_with_initializer(
+                    Some(output_layer_info.activation_function),
+                    Some(initializer_str),
+                )
+            },
         );
 
-        NeuralNetwork {
+        // normalized layers start out scaling by 1 and shifting by 0, i.e. just standardizing
+        let mut scales = HashMap::new();
+        let mut shifts = HashMap::new();
+        let mut running_statistics = HashMap::new();
+        for (l, layer_config) in layer_infos.iter() {
+            if let Some(normalization) = layer_config.normalization {
+                scales.insert(*l, ColumnVector::fill_new(1.0, sizes[*l]));
+                shifts.insert(*l, ColumnVector::new_zero_vector(sizes[*l]));
+                if let Normalization::BatchNorm { .. } = normalization {
+                    running_statistics.insert(*l, RunningStatistics::new(sizes[*l]));
+                }
+            }
+        }
+
+        Ok(NeuralNetwork {
             sizes,
             w: weights,
             b: biases,
+            scales,
+            shifts,
+            running_statistics,
             layer_configs: layer_infos,
             cost: cost_fn,
-        }
+            initialization_seed: Some(seed),
+        })
     }
 }
 
@@ -377,7 +544,6 @@ mod test_nn_builder {
     use super::*;
     use common::column_vector;
     use common::linalg::{MatrixShape, RowsMatrixBuilder};
-    use std::panic;
 
     #[test]
     fn test_nn_builder_manual_wb_values() {
@@ -431,34 +597,297 @@ mod test_nn_builder {
     }
 
     #[test]
-    #[should_panic]
-    fn cannot_add_hiddlen_layer_before_input_layer() {
-        let _ = NeuralNetworkBuilder::new().with_hidden_layer(
-            3,
-            Initializer::RandomBasic,
-            ActivationFunction::Sigmoid,
+    fn same_seed_gives_same_initial_weights_and_biases() {
+        let build_with_seed = |seed| {
+            NeuralNetworkBuilder::new()
+                .with_input_layer(3)
+                .with_hidden_layer(4, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+                .with_hidden_layer(5, Initializer::Xavier, ActivationFunction::Sigmoid)
+                .with_hidden_layer(
+                    4,
+                    Initializer::HeForReLUAndVariants,
+                    ActivationFunction::ReLU,
+                )
+                .with_output_layer(
+                    2,
+                    Initializer::XavierNormalHOMLForSigmoid,
+                    ActivationFunction::Sigmoid,
+                )
+                .with_cost_fn(cost::CostFunc::QuadraticCost)
+                .with_seed(seed)
+                .build()
+        };
+
+        let nn_1 = build_with_seed(1);
+        let nn_2 = build_with_seed(1);
+        let nn_3 = build_with_seed(2);
+
+        assert_eq!(nn_1.w, nn_2.w);
+        assert_eq!(nn_1.b, nn_2.b);
+        assert_ne!(nn_1.w, nn_3.w);
+        assert_eq!(nn_1.initialization_seed(), Some(1));
+    }
+
+    #[test]
+    fn random_initialization_seed_is_kept_so_the_network_can_be_rebuilt() {
+        let builder = || {
+            NeuralNetworkBuilder::new()
+                .with_input_layer(3)
+                .with_output_layer(2, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+                .with_cost_fn(cost::CostFunc::QuadraticCost)
+        };
+
+        let nn = builder().build();
+        let seed = nn.initialization_seed().unwrap();
+        let rebuilt_nn = builder().with_seed(seed).build();
+        assert_eq!(rebuilt_nn.w, nn.w);
+        assert_eq!(rebuilt_nn.b, nn.b);
+    }
+
+    #[test]
+    fn regularization_applies_to_the_layer_added_last() {
+        let nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_regularization(Regularization::l2(0.01))
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_regularization(Regularization::l1(0.001))
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .build();
+
+        let regularization = |l| nn.layer_configs.get(&l).unwrap().regularization;
+        assert_eq!(regularization(1), Regularization::default());
+        assert_eq!(regularization(2), Regularization::l2(0.01));
+        assert_eq!(regularization(3), Regularization::l1(0.001));
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_regularization(Regularization::l2(0.01))
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::RegularizationBeforeAnyLayer)
+        );
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_regularization(Regularization::l1(-0.001))
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::InvalidRegularization { layer_index: 1 })
+        );
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_regularization(Regularization::l2(f64::NAN))
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::InvalidRegularization { layer_index: 2 })
         );
     }
 
     #[test]
-    #[should_panic]
+    fn dropout_applies_to_the_hidden_layer_added_last() {
+        let nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_dropout(0.5)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .build();
+
+        let dropout_rate = |l| nn.layer_configs.get(&l).unwrap().dropout_rate;
+        assert_eq!(dropout_rate(1), Some(0.5));
+        assert_eq!(dropout_rate(2), None);
+        assert_eq!(dropout_rate(3), None);
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_dropout(0.5)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::DropoutNotAfterHiddenLayer));
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_dropout(1.0)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::InvalidDropoutRate {
+                layer_index: 2,
+                rate: 1.0
+            })
+        );
+    }
+
+    #[test]
+    fn normalization_applies_to_the_hidden_layer_added_last() {
+        let nn = NeuralNetworkBuilder::new()
+            .with_input_layer(3)
+            .with_hidden_layer(4, Initializer::RandomBasic, ActivationFunction::ReLU)
+            .with_normalization(Normalization::batch_norm())
+            .with_hidden_layer(2, Initializer::RandomBasic, ActivationFunction::ReLU)
+            .with_normalization(Normalization::layer_norm())
+            .with_output_layer(2, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .build();
+
+        assert_eq!(nn.normalized_layers(), vec![1, 2]);
+        assert_eq!(nn.scales[&1], ColumnVector::fill_new(1.0, 4));
+        assert_eq!(nn.shifts[&2], ColumnVector::new_zero_vector(2));
+        // only batch norm keeps running statistics
+        assert_eq!(nn.running_statistics[&1], RunningStatistics::new(4));
+        assert!(!nn.running_statistics.contains_key(&2));
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(3)
+            .with_output_layer(2, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_normalization(Normalization::batch_norm())
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::NormalizationNotAfterHiddenLayer)
+        );
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(3)
+            .with_hidden_layer(4, Initializer::RandomBasic, ActivationFunction::ReLU)
+            .with_normalization(Normalization::LayerNorm { epsilon: 0.0 })
+            .with_output_layer(2, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::InvalidNormalization { layer_index: 1 })
+        );
+    }
+
+    #[test]
+    fn cannot_add_hiddlen_layer_before_input_layer() {
+        let result = NeuralNetworkBuilder::new()
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_input_layer(2)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::LayerAddedBeforeInputLayer));
+    }
+
+    #[test]
     fn cannot_add_output_layer_before_input_layer() {
-        let _ = NeuralNetworkBuilder::new().with_output_layer(
-            1,
-            Initializer::RandomBasic,
-            ActivationFunction::Sigmoid,
+        let result = NeuralNetworkBuilder::new()
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_input_layer(2)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::LayerAddedBeforeInputLayer));
+    }
+
+    #[test]
+    #[should_panic]
+    fn build_panics_on_invalid_configuration() {
+        let _ = NeuralNetworkBuilder::new()
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .build();
+    }
+
+    #[test]
+    fn try_build_returns_error_for_missing_parts() {
+        let result = NeuralNetworkBuilder::new()
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::MissingInputLayer));
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::MissingOutputLayer));
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::MissingCostFunction));
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(0, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::EmptyLayer { layer_index: 1 })
+        );
+    }
+
+    #[test]
+    fn try_build_validates_cost_function_and_activation_combinations() {
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_output_layer(3, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::CrossEntropy)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::CrossEntropyRequiresSoftmaxOutput)
+        );
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_output_layer(3, Initializer::RandomBasic, ActivationFunction::Softmax)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::SoftmaxOutputRequiresCrossEntropy)
+        );
+
+        let result = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(4, Initializer::RandomBasic, ActivationFunction::Softmax)
+            .with_output_layer(3, Initializer::RandomBasic, ActivationFunction::Softmax)
+            .with_cost_fn(cost::CostFunc::CrossEntropy)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::SoftmaxInHiddenLayer { layer_index: 1 })
         );
+
+        let nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(4, Initializer::RandomBasic, ActivationFunction::ReLU)
+            .with_output_layer(3, Initializer::RandomBasic, ActivationFunction::Softmax)
+            .with_cost_fn(cost::CostFunc::CrossEntropy)
+            .try_build()
+            .unwrap();
+        assert_eq!(nn.sizes, vec![2, 4, 3]);
     }
 
     #[test]
-    fn panics_on_hidden_layer_with_invalid_weight_or_bias_dimensions() {
-        // TODO: writing these tests is making it obvious that the builder should be returning an errors rather than panicking
-        // ex you could have NNBuilderError::InvalidWeightsMatrixShape
-        // ex you could have NNBuilderError::InvalidBiasVectorLength
-        // ex you could have NNBuilderError::InvalidLayerOrder or MissingInputLayer would probably be better
-        // then in the tests above, I could make sure that, for example, the panic is a result of the invalid weight matrix and not the ok-length bias vector, etc
-
-        // All these test scenarios verify that the builder panics when passed a weights matrix or bias vector that is not the correct dimensions.
+    fn try_build_returns_error_on_invalid_weight_or_bias_dimensions() {
         // For a NN with the layer sizes 2x3x4x1,
         // the correct shapes of the weight matricies should be:
         // l0: no weights matrix
@@ -470,146 +899,124 @@ mod test_nn_builder {
         let l1b_ok = ColumnVector::new_zero_vector(3);
         let l2w_ok = Matrix::new_zero_matrix(4, 3);
         let l2b_ok = ColumnVector::new_zero_vector(4);
+        let l3w_ok = Matrix::new_zero_matrix(1, 4);
+        let l3b_ok = ColumnVector::new_zero_vector(1);
 
-        // panics if the first hidden layer has an invalid weight matrix shape
-        assert_eq!(
-            panic::catch_unwind(|| {
-                let _ = NeuralNetworkBuilder::new()
+        let try_build =
+            |l1: (Matrix, ColumnVector), l2: (Matrix, ColumnVector), l3: (Matrix, ColumnVector)| {
+                NeuralNetworkBuilder::new()
                     .with_input_layer(2)
                     .with_hidden_layer(
                         3,
-                        Initializer::Manual(
-                            Matrix::new_zero_matrix(1, 3), // should be 3x2,
-                            l1b_ok.clone(),
-                        ),
+                        Initializer::Manual(l1.0, l1.1),
                         ActivationFunction::Sigmoid,
-                    );
-            })
-            .is_err(),
-            true
+                    )
+                    .with_hidden_layer(
+                        4,
+                        Initializer::Manual(l2.0, l2.1),
+                        ActivationFunction::Sigmoid,
+                    )
+                    .with_output_layer(
+                        1,
+                        Initializer::Manual(l3.0, l3.1),
+                        ActivationFunction::Sigmoid,
+                    )
+                    .with_cost_fn(cost::CostFunc::QuadraticCost)
+                    .try_build()
+                    .err()
+            };
+
+        assert_eq!(
+            try_build(
+                (l1w_ok.clone(), l1b_ok.clone()),
+                (l2w_ok.clone(), l2b_ok.clone()),
+                (l3w_ok.clone(), l3b_ok.clone())
+            ),
+            None
         );
 
-        // panics if the first hidden layer has an invalid bias vector length
+        // the first hidden layer has an invalid weight matrix shape
         assert_eq!(
-            panic::catch_unwind(|| {
-                let _ = NeuralNetworkBuilder::new()
-                    .with_input_layer(2)
-                    .with_hidden_layer(
-                        3,
-                        Initializer::Manual(
-                            l1w_ok.clone(),
-                            ColumnVector::new_zero_vector(2), // should be 3
-                        ),
-                        ActivationFunction::Sigmoid,
-                    );
+            try_build(
+                (Matrix::new_zero_matrix(1, 3), l1b_ok.clone()),
+                (l2w_ok.clone(), l2b_ok.clone()),
+                (l3w_ok.clone(), l3b_ok.clone())
+            ),
+            Some(BuilderError::InvalidWeightsMatrixShape {
+                layer_index: 1,
+                expected: MatrixShape::new(3, 2),
+                actual: MatrixShape::new(1, 3),
             })
-            .is_err(),
-            true
         );
 
-        // panics if a non-first hidden layer has an invalid weight matrix shape
-        let builder = NeuralNetworkBuilder::new()
-            .with_input_layer(2)
-            .with_hidden_layer(
-                3,
-                Initializer::Manual(l1w_ok.clone(), l1b_ok.clone()),
-                ActivationFunction::Sigmoid,
-            );
-
+        // the first hidden layer has an invalid bias vector length
         assert_eq!(
-            panic::catch_unwind(|| {
-                let _ = builder.with_hidden_layer(
-                    4,
-                    Initializer::Manual(
-                        Matrix::new_zero_matrix(4, 2), // should be 4x3
-                        l2b_ok.clone(),
-                    ),
-                    ActivationFunction::Sigmoid,
-                );
+            try_build(
+                (l1w_ok.clone(), ColumnVector::new_zero_vector(2)),
+                (l2w_ok.clone(), l2b_ok.clone()),
+                (l3w_ok.clone(), l3b_ok.clone())
+            ),
+            Some(BuilderError::InvalidBiasVectorLength {
+                layer_index: 1,
+                expected: 3,
+                actual: 2,
             })
-            .is_err(),
-            true
         );
 
-        // panics if a non-first hidden layer has an invalid bias vector length
-        let builder = NeuralNetworkBuilder::new()
-            .with_input_layer(2)
-            .with_hidden_layer(
-                3,
-                Initializer::Manual(l1w_ok.clone(), l1b_ok.clone()),
-                ActivationFunction::Sigmoid,
-            );
+        // a non-first hidden layer has an invalid weight matrix shape
         assert_eq!(
-            panic::catch_unwind(|| {
-                let _ = builder.with_hidden_layer(
-                    4,
-                    Initializer::Manual(
-                        l2w_ok.clone().clone(),
-                        ColumnVector::new_zero_vector(2), // should be 4
-                    ),
-                    ActivationFunction::Sigmoid,
-                );
+            try_build(
+                (l1w_ok.clone(), l1b_ok.clone()),
+                (Matrix::new_zero_matrix(4, 2), l2b_ok.clone()),
+                (l3w_ok.clone(), l3b_ok.clone())
+            ),
+            Some(BuilderError::InvalidWeightsMatrixShape {
+                layer_index: 2,
+                expected: MatrixShape::new(4, 3),
+                actual: MatrixShape::new(4, 2),
             })
-            .is_err(),
-            true
         );
 
-        // panics if the output layer has an invalid weight matrix shape
-        let builder = NeuralNetworkBuilder::new()
-            .with_input_layer(2)
-            .with_hidden_layer(
-                3,
-                Initializer::Manual(l1w_ok.clone(), l1b_ok.clone()),
-                ActivationFunction::Sigmoid,
-            )
-            .with_hidden_layer(
-                4,
-                Initializer::Manual(l2w_ok.clone(), l2b_ok.clone()),
-                ActivationFunction::Sigmoid,
-            );
-
+        // a non-first hidden layer has an invalid bias vector length
         assert_eq!(
-            panic::catch_unwind(|| {
-                let _ = builder.with_output_layer(
-                    1,
-                    Initializer::Manual(
-                        Matrix::new_zero_matrix(2, 4), // should be 1x4
-                        ColumnVector::new_zero_vector(1),
-                    ),
-                    ActivationFunction::Sigmoid,
-                );
+            try_build(
+                (l1w_ok.clone(), l1b_ok.clone()),
+                (l2w_ok.clone(), ColumnVector::new_zero_vector(2)),
+                (l3w_ok.clone(), l3b_ok.clone())
+            ),
+            Some(BuilderError::InvalidBiasVectorLength {
+                layer_index: 2,
+                expected: 4,
+                actual: 2,
             })
-            .is_err(),
-            true
         );
 
-        // panics if the output layer has an invalid bias vector length
-        let builder = NeuralNetworkBuilder::new()
-            .with_input_layer(2)
-            .with_hidden_layer(
-                3,
-                Initializer::Manual(l1w_ok, l1b_ok),
-                ActivationFunction::Sigmoid,
-            )
-            .with_hidden_layer(
-                4,
-                Initializer::Manual(l2w_ok, l2b_ok),
-                ActivationFunction::Sigmoid,
-            );
+        // the output layer has an invalid weight matrix shape
+        assert_eq!(
+            try_build(
+                (l1w_ok.clone(), l1b_ok.clone()),
+                (l2w_ok.clone(), l2b_ok.clone()),
+                (Matrix::new_zero_matrix(2, 4), l3b_ok)
+            ),
+            Some(BuilderError::InvalidWeightsMatrixShape {
+                layer_index: 3,
+                expected: MatrixShape::new(1, 4),
+                actual: MatrixShape::new(2, 4),
+            })
+        );
 
+        // the output layer has an invalid bias vector length
         assert_eq!(
-            panic::catch_unwind(|| {
-                let _ = builder.with_output_layer(
-                    1,
-                    Initializer::Manual(
-                        Matrix::new_zero_matrix(1, 4),
-                        ColumnVector::new_zero_vector(3), // should be 1
-                    ),
-                    ActivationFunction::Sigmoid,
-                );
+            try_build(
+                (l1w_ok, l1b_ok),
+                (l2w_ok, l2b_ok),
+                (l3w_ok, ColumnVector::new_zero_vector(3))
+            ),
+            Some(BuilderError::InvalidBiasVectorLength {
+                layer_index: 3,
+                expected: 1,
+                actual: 3,
             })
-            .is_err(),
-            true
         );
     }
 }
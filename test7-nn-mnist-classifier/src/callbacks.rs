@@ -0,0 +1,125 @@
+//! Hooks into training, so that logging, progress reporting, early stopping and custom metrics can be plugged into
+//! `train_with_callbacks` rather than each needing another argument to the training methods.
+
+use common::linalg::Float;
+
+use crate::big_theta::BigTheta;
+use crate::checkpoint::TrainingState;
+use crate::early_stopping::EarlyStopState;
+use crate::errors::NeuralNetworkError;
+use crate::optimizer::Optimizer;
+use crate::training_log::FullCostUpdate;
+use crate::NeuralNetwork;
+
+/// Whether a callback wants training to carry on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainingControl {
+    Continue,
+    /// Ends training after the current step or epoch. The remaining callbacks still see the step or epoch, and
+    /// `on_train_end` is still called.
+    Stop,
+}
+
+/// What `on_train_begin` is told about the run that's starting.
+pub struct TrainBegin<'a, T = f64> {
+    /// The cost across the whole training set, before the first step.
+    pub initial_cost: f64,
+    pub optimizer: &'a dyn Optimizer<T>,
+    /// What training starts from - partway through a run, when it's resumed from a checkpoint.
+    pub training_state: &'a TrainingState<T>,
+}
+
+/// What `on_step_end` is told about the step that just finished.
+pub struct StepEnd<'a, T = f64> {
+    /// The number of epochs completed before this step, i.e. the (zero based) epoch it was part of.
+    pub epochs_completed: usize,
+    /// Including this step.
+    pub steps_completed: usize,
+    /// The learning rate used for the step, after the learning rate schedule was applied.
+    pub learning_rate: f64,
+    /// The mini batch's cost, from the forward pass the gradients were computed with (so with dropout, and before the
+    /// weights were updated).
+    pub loss: f64,
+    /// The gradients the step was taken with, after any clipping.
+    pub gradients: &'a BigTheta<T>,
+    /// The global L2 norm of the gradients from before they were clipped.
+    pub gradient_norm: f64,
+}
+
+/// What `on_epoch_end` is told about the epoch that just finished.
+///
+/// The callbacks see the epoch in order, and can fill in the metrics for the ones after them, e.g. a
+/// `full_cost_updates::FullCostUpdates` before an `early_stopping::EarlyStopping` saves it computing the validation cost
+/// again.
+pub struct EpochEnd {
+    /// Including this epoch.
+    pub epochs_completed: usize,
+    pub steps_completed: usize,
+    /// The learning rate used for the epoch's last step.
+    pub learning_rate: f64,
+    /// The mean of the epoch's mini batch losses (see `StepEnd::loss`). Callbacks that want the cost or accuracy on a
+    /// whole dataset can compute them from the network.
+    pub mean_loss: f64,
+    /// The global L2 norm of the epoch's last step's gradients, from before they were clipped.
+    pub gradient_norm: f64,
+    /// The cost and accuracy across each dataset, if a callback computed them for this epoch.
+    pub full_cost_update: Option<FullCostUpdate>,
+    /// The validation set cost, if a callback computed it for this epoch. The learning rate schedule watches it (see
+    /// `LearningRateSchedule::ReduceOnPlateau`) once every callback has seen the epoch.
+    pub validation_cost: Option<f64>,
+    /// What an `early_stopping::EarlyStopping` has seen so far, if it checked this epoch. It's saved in the training
+    /// state, so that a run resumed after this epoch carries on with it.
+    pub early_stop_state: Option<EarlyStopState>,
+}
+
+/// Something that observes training, and can end it early. Every hook does nothing by default.
+///
+/// The hooks get the network as it is at that point, e.g. `on_epoch_end` can compute metrics on a validation set, and
+/// `on_train_end` can change it, e.g. to put back the best weights seen. An error from `on_train_begin` (e.g. the
+/// callback's data doesn't fit the network) ends training before the first step. `on_train_end` isn't called if
+/// training fails (e.g. a health check aborts it).
+///
+/// `on_epoch_state_updated` comes after every callback's `on_epoch_end`, once the training state has caught up with what
+/// they found, e.g. the learning rate schedule has seen the validation cost. So it's the place to write checkpoints.
+pub trait TrainingCallback<T: Float = f64> {
+    fn on_train_begin(
+        &mut self,
+        _nn: &NeuralNetwork<T>,
+        _train_begin: &TrainBegin<T>,
+    ) -> Result<(), NeuralNetworkError> {
+        Ok(())
+    }
+
+    fn on_step_end(&mut self, _nn: &NeuralNetwork<T>, _step: &StepEnd<T>) -> TrainingControl {
+        TrainingControl::Continue
+    }
+
+    fn on_epoch_end(&mut self, _nn: &NeuralNetwork<T>, _epoch: &mut EpochEnd) -> TrainingControl {
+        TrainingControl::Continue
+    }
+
+    /// `training_state` is what a run resumed after this epoch starts from.
+    fn on_epoch_state_updated(
+        &mut self,
+        _nn: &NeuralNetwork<T>,
+        _training_state: &TrainingState<T>,
+    ) {
+    }
+
+    fn on_train_end(&mut self, _nn: &mut NeuralNetwork<T>, _epochs_completed: usize) {}
+}
+
+/// Calls `hook` on every callback - even after one has asked to stop, so that they all see every step and epoch - and
+/// returns `Stop` if any of them asked to.
+pub(crate) fn call_each<T: Float>(
+    callbacks: &mut [&mut dyn TrainingCallback<T>],
+    mut hook: impl FnMut(&mut dyn TrainingCallback<T>) -> TrainingControl,
+) -> TrainingControl {
+    callbacks
+        .iter_mut()
+        .map(|callback| hook(&mut **callback))
+        .fold(TrainingControl::Continue, |control, c| match c {
+            TrainingControl::Stop => TrainingControl::Stop,
+            TrainingControl::Continue => control,
+        })
+}
@@ -0,0 +1,331 @@
+//! Line charts of training curves (e.g. cost vs epoch), drawn as text for the terminal or as standalone SVG files.
+
+use std::fmt::Write;
+
+/// One line on a chart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    /// (x, y) points, in increasing x order. Points that aren't finite are left out.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Series {
+    pub fn new(name: impl Into<String>, points: Vec<(f64, f64)>) -> Self {
+        Self {
+            name: name.into(),
+            points,
+        }
+    }
+
+    fn finite_points(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.points
+            .iter()
+            .copied()
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+    }
+}
+
+/// What each series is drawn with in text charts, in order.
+const MARKERS: [char; 6] = ['*', '+', 'o', 'x', '#', '@'];
+
+/// The SVG line colours, in order.
+const COLOURS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+/// The smallest and largest x and y values across all the series, widened where they're all the same so that there's
+/// something to scale by. `None` if there are no points.
+fn bounds(series: &[Series]) -> Option<((f64, f64), (f64, f64))> {
+    let mut points = series.iter().flat_map(Series::finite_points).peekable();
+    let (x0, y0) = *points.peek()?;
+
+    let (mut x_range, mut y_range) = points.fold(((x0, x0), (y0, y0)), |(xs, ys), (x, y)| {
+        ((xs.0.min(x), xs.1.max(x)), (ys.0.min(y), ys.1.max(y)))
+    });
+    for range in [&mut x_range, &mut y_range] {
+        if range.0 == range.1 {
+            range.0 -= 0.5;
+            range.1 += 0.5;
+        }
+    }
+    Some((x_range, y_range))
+}
+
+/// Scales `value` in `range` to `0..=(steps - 1)`.
+fn scale(value: f64, range: (f64, f64), steps: usize) -> usize {
+    let fraction = (value - range.0) / (range.1 - range.0);
+    (fraction * (steps - 1) as f64).round() as usize
+}
+
+/// Draws the series as a `width` by `height` character plot, with the y axis labelled on the left, the x range
+/// underneath and a legend at the bottom. Consecutive points are joined, so that each series reads as a line.
+pub fn ascii_chart(series: &[Series], width: usize, height: usize) -> String {
+    let Some((x_range, y_range)) = bounds(series) else {
+        return String::from("(no data)\n");
+    };
+    let width = width.max(2);
+    let height = height.max(2);
+
+    let mut grid = vec![vec![' '; width]; height];
+    for (i, s) in series.iter().enumerate() {
+        let marker = MARKERS[i % MARKERS.len()];
+        let mut plot = |column: usize, y: f64| {
+            let row = height - 1 - scale(y, y_range, height);
+            grid[row][column] = marker;
+        };
+
+        let points: Vec<(f64, f64)> = s.finite_points().collect();
+        for (&(x, y), next) in points
+            .iter()
+            .zip(points.iter().skip(1).map(Some).chain([None]))
+        {
+            let column = scale(x, x_range, width);
+            plot(column, y);
+
+            if let Some(&(next_x, next_y)) = next {
+                let next_column = scale(next_x, x_range, width);
+                for c in (column + 1)..next_column {
+                    let t = (c - column) as f64 / (next_column - column) as f64;
+                    plot(c, y + t * (next_y - y));
+                }
+            }
+        }
+    }
+
+    let y_labels = [
+        format_value(y_range.1),
+        format_value((y_range.0 + y_range.1) / 2.0),
+        format_value(y_range.0),
+    ];
+    let label_width = y_labels.iter().map(String::len).max().unwrap_or(0);
+
+    let mut chart = String::new();
+    for (row, cells) in grid.iter().enumerate() {
+        let label = match row {
+            0 => &y_labels[0],
+            r if r == height / 2 => &y_labels[1],
+            r if r == height - 1 => &y_labels[2],
+            _ => "",
+        };
+        let line: String = cells.iter().collect();
+        writeln!(chart, "{:>label_width$} |{}", label, line.trim_end()).unwrap();
+    }
+    writeln!(chart, "{:>label_width$} +{}", "", "-".repeat(width)).unwrap();
+
+    let x_min = format_value(x_range.0);
+    let x_max = format_value(x_range.1);
+    let gap = width.saturating_sub(x_min.len() + x_max.len()).max(1);
+    writeln!(
+        chart,
+        "{:>label_width$}  {}{}{}",
+        "",
+        x_min,
+        " ".repeat(gap),
+        x_max
+    )
+    .unwrap();
+
+    for (i, s) in series.iter().enumerate() {
+        writeln!(chart, "  {} {}", MARKERS[i % MARKERS.len()], s.name).unwrap();
+    }
+    chart
+}
+
+/// Draws the series as a standalone SVG document, with axes, tick labels, a title and a legend.
+pub fn svg_chart(series: &[Series], title: &str, x_label: &str, y_label: &str) -> String {
+    const WIDTH: f64 = 800.0;
+    const HEIGHT: f64 = 500.0;
+    const LEFT: f64 = 80.0;
+    const RIGHT: f64 = 200.0;
+    const TOP: f64 = 50.0;
+    const BOTTOM: f64 = 60.0;
+    const TICKS: usize = 5;
+
+    let plot_width = WIDTH - LEFT - RIGHT;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let ((x_min, x_max), (y_min, y_max)) = bounds(series).unwrap_or(((0.0, 1.0), (0.0, 1.0)));
+    let to_x = |x: f64| LEFT + (x - x_min) / (x_max - x_min) * plot_width;
+    let to_y = |y: f64| TOP + plot_height - (y - y_min) / (y_max - y_min) * plot_height;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="sans-serif" font-size="12">"#
+    )
+    .unwrap();
+    writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+    writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle" font-size="16">{}</text>"#,
+        LEFT + plot_width / 2.0,
+        TOP / 2.0 + 5.0,
+        escape_xml(title)
+    )
+    .unwrap();
+
+    // grid lines and tick labels
+    for i in 0..=TICKS {
+        let fraction = i as f64 / TICKS as f64;
+        let x_value = x_min + fraction * (x_max - x_min);
+        let y_value = y_min + fraction * (y_max - y_min);
+        let (x, y) = (to_x(x_value), to_y(y_value));
+        writeln!(
+            svg,
+            r##"<line x1="{x:.1}" y1="{TOP}" x2="{x:.1}" y2="{}" stroke="#e0e0e0"/>"##,
+            TOP + plot_height
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<text x="{x:.1}" y="{}" text-anchor="middle">{}</text>"#,
+            TOP + plot_height + 18.0,
+            format_value(x_value)
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r##"<line x1="{LEFT}" y1="{y:.1}" x2="{}" y2="{y:.1}" stroke="#e0e0e0"/>"##,
+            LEFT + plot_width
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<text x="{}" y="{:.1}" text-anchor="end">{}</text>"#,
+            LEFT - 6.0,
+            y + 4.0,
+            format_value(y_value)
+        )
+        .unwrap();
+    }
+
+    // axes and their labels
+    writeln!(
+        svg,
+        r#"<polyline points="{LEFT},{TOP} {LEFT},{bottom} {right},{bottom}" fill="none" stroke="black"/>"#,
+        bottom = TOP + plot_height,
+        right = LEFT + plot_width
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+        LEFT + plot_width / 2.0,
+        HEIGHT - 15.0,
+        escape_xml(x_label)
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"<text x="20" y="{0}" text-anchor="middle" transform="rotate(-90 20 {0})">{1}</text>"#,
+        TOP + plot_height / 2.0,
+        escape_xml(y_label)
+    )
+    .unwrap();
+
+    // the series and the legend
+    for (i, s) in series.iter().enumerate() {
+        let colour = COLOURS[i % COLOURS.len()];
+        let points: Vec<String> = s
+            .finite_points()
+            .map(|(x, y)| format!("{:.1},{:.1}", to_x(x), to_y(y)))
+            .collect();
+        writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{colour}" stroke-width="2"/>"#,
+            points.join(" ")
+        )
+        .unwrap();
+
+        let legend_x = LEFT + plot_width + 20.0;
+        let legend_y = TOP + 10.0 + 20.0 * i as f64;
+        writeln!(
+            svg,
+            r#"<line x1="{legend_x}" y1="{legend_y}" x2="{}" y2="{legend_y}" stroke="{colour}" stroke-width="2"/>"#,
+            legend_x + 20.0
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<text x="{}" y="{}">{}</text>"#,
+            legend_x + 26.0,
+            legend_y + 4.0,
+            escape_xml(&s.name)
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Short enough for an axis label, but without losing small costs.
+fn format_value(value: f64) -> String {
+    if value != 0.0 && (value.abs() < 1e-3 || value.abs() >= 1e5) {
+        format!("{:.2e}", value)
+    } else if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.4}", value)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_chart_draws_a_falling_line_from_top_left_to_bottom_right() {
+        let series = [Series::new(
+            "cost",
+            vec![(0.0, 4.0), (1.0, 2.0), (2.0, 0.0)],
+        )];
+        let chart = ascii_chart(&series, 10, 5);
+        let lines: Vec<&str> = chart.lines().collect();
+
+        assert_eq!(lines[0], "4 |**");
+        assert_eq!(lines[2], "2 |    ***");
+        assert_eq!(lines[4], "0 |         *");
+        assert_eq!(lines[5], "  +----------");
+        assert_eq!(lines[6], "   0        2");
+        assert_eq!(lines[7], "  * cost");
+        // the points in between are joined up
+        assert!(lines.iter().take(5).all(|line| line.contains('*')));
+    }
+
+    #[test]
+    fn ascii_chart_gives_each_series_its_own_marker() {
+        let series = [
+            Series::new("a", vec![(0.0, 0.0), (1.0, 0.0)]),
+            Series::new("b", vec![(0.0, 1.0), (1.0, 1.0), (2.0, f64::NAN)]),
+        ];
+        let chart = ascii_chart(&series, 5, 3);
+
+        assert!(chart.lines().next().unwrap().ends_with("+++"));
+        assert!(chart.lines().nth(2).unwrap().ends_with("***"));
+        assert!(chart.contains("  + b\n"));
+        assert_eq!(ascii_chart(&[], 5, 3), "(no data)\n");
+    }
+
+    #[test]
+    fn svg_chart_has_a_line_and_legend_entry_per_series() {
+        let series = [
+            Series::new("1700000000 <adam>", vec![(0.0, 2.0), (1.0, 1.0)]),
+            Series::new("1700000001", vec![(0.0, 3.0), (1.0, 0.5)]),
+        ];
+        let svg = svg_chart(&series, "cost", "epochs", "training set cost");
+
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches(r#"stroke-width="2"/>"#).count(), 4);
+        assert!(svg.contains("1700000000 &lt;adam&gt;"));
+        assert!(!svg.contains("<adam>"));
+    }
+}
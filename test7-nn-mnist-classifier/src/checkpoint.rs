@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use rand_chacha::ChaCha8Rng;
+use serde_derive::{Deserialize, Serialize};
+
+use common::linalg::Float;
+
+use crate::big_theta::BigTheta;
+use crate::early_stopping::EarlyStopState;
+use crate::lr_schedule::ScheduleState;
+use crate::optimizer::OptimizerState;
+use crate::rng::{seeded_rng, RngStream};
+use crate::saved_model::SavedModel;
+use crate::{LayerIndex, NeuralNetwork};
+
+/// Bump this whenever the layout of `Checkpoint` changes in a way that older files can't be read.
+pub const CHECKPOINT_FORMAT_VERSION: u32 = 11;
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"NNCHKPT\0";
+
+/// Everything `train_stochastic` carries from one epoch to the next, apart from the network itself.
+/// Capturing this (along with the network) is what lets a training run be resumed exactly where it left off.
+#[derive(Debug, Clone)]
+pub struct TrainingState<T = f64> {
+    /// The number of epochs completed so far.
+    pub epochs_count: usize,
+    /// The optimizer's running averages etc., along with the number of optimization steps (i.e. mini batches)
+    /// completed so far.
+    pub optimizer_state: OptimizerState<T>,
+    /// The seed `rng` was created from, recorded so the run can be reproduced.
+    pub seed: u64,
+    /// Used for selecting the mini batches and drawing the dropout masks
+    pub rng: ChaCha8Rng,
+    /// The last cost computed by the cost decreasing check, if any.
+    pub prev_cost: Option<f64>,
+    /// What the learning rate schedule has seen of the validation cost so far.
+    pub schedule_state: ScheduleState,
+    /// What early stopping has seen of the validation set so far.
+    pub early_stop_state: EarlyStopState,
+}
+
+impl<T: Float> TrainingState<T> {
+    /// `optimizer_state` is the optimizer's initial state - see `Optimizer::init_state`.
+    pub fn new(optimizer_state: OptimizerState<T>, seed: u64) -> Self {
+        Self {
+            epochs_count: 0,
+            optimizer_state,
+            seed,
+            rng: seeded_rng(seed, RngStream::Training),
+            prev_cost: None,
+            schedule_state: ScheduleState::default(),
+            early_stop_state: EarlyStopState::default(),
+        }
+    }
+}
+
+/// A `BigTheta` in a form that can be serialized. The values are always stored as `f64`s.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedBigTheta {
+    pub sizes: Vec<usize>,
+    /// The layers with scales and shifts.
+    pub normalized_layers: Vec<LayerIndex>,
+    /// The weights and biases (and scales and shifts) as produced by `BigTheta::unroll`.
+    pub values: Vec<f64>,
+}
+
+impl SavedBigTheta {
+    pub fn from_big_theta<T: Float>(big_theta: &BigTheta<T>) -> Self {
+        Self {
+            sizes: big_theta.sizes.clone(),
+            normalized_layers: big_theta.normalized_layers(),
+            values: big_theta.unroll().iter().map(|x| x.as_f64()).collect(),
+        }
+    }
+
+    pub fn into_big_theta<T: Float>(
+        self,
+        sizes: &[usize],
+        normalized_layers: &[LayerIndex],
+    ) -> anyhow::Result<BigTheta<T>> {
+        if self.sizes != sizes {
+            return Err(anyhow!(
+                "optimizer state has sizes {:?} but the network has sizes {:?}",
+                self.sizes,
+                sizes
+            ));
+        }
+
+        if self.normalized_layers != normalized_layers {
+            return Err(anyhow!(
+                "optimizer state has normalized layers {:?} but the network has {:?}",
+                self.normalized_layers,
+                normalized_layers
+            ));
+        }
+
+        let expected_len =
+            BigTheta::<T>::zero_from_sizes_with_normalized_layers(sizes, normalized_layers)
+                .unroll()
+                .len();
+        if self.values.len() != expected_len {
+            return Err(anyhow!(
+                "optimizer state has {} values but {} are required",
+                self.values.len(),
+                expected_len
+            ));
+        }
+
+        let values = self
+            .values
+            .iter()
+            .map(|x| T::from_f64(*x))
+            .collect::<Vec<T>>();
+        Ok(BigTheta::from_unrolled_with_normalized_layers(
+            sizes,
+            normalized_layers,
+            &values,
+        ))
+    }
+}
+
+/// An `OptimizerState` in a form that can be serialized.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedOptimizerState {
+    pub steps_count: usize,
+    pub learning_rate_factor: f64,
+    pub buffers: BTreeMap<String, SavedBigTheta>,
+}
+
+impl SavedOptimizerState {
+    pub fn from_optimizer_state<T: Float>(optimizer_state: &OptimizerState<T>) -> Self {
+        Self {
+            steps_count: optimizer_state.steps_count,
+            learning_rate_factor: optimizer_state.learning_rate_factor,
+            buffers: optimizer_state
+                .buffers
+                .iter()
+                .map(|(name, buffer)| (name.clone(), SavedBigTheta::from_big_theta(buffer)))
+                .collect(),
+        }
+    }
+
+    pub fn into_optimizer_state<T: Float>(
+        self,
+        sizes: &[usize],
+        normalized_layers: &[LayerIndex],
+    ) -> anyhow::Result<OptimizerState<T>> {
+        let mut buffers = BTreeMap::new();
+        for (name, buffer) in self.buffers {
+            let buffer = buffer
+                .into_big_theta(sizes, normalized_layers)
+                .with_context(|| format!("invalid optimizer state buffer {:?}", name))?;
+            buffers.insert(name, buffer);
+        }
+
+        Ok(OptimizerState {
+            steps_count: self.steps_count,
+            learning_rate_factor: self.learning_rate_factor,
+            buffers,
+        })
+    }
+}
+
+/// A snapshot of a training run: the network parameters plus the `TrainingState`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkpoint {
+    pub format_version: u32,
+    pub model: SavedModel,
+    pub epochs_count: usize,
+    pub optimizer_state: SavedOptimizerState,
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+    pub prev_cost: Option<f64>,
+    pub schedule_state: ScheduleState,
+    pub early_stop_state: EarlyStopState,
+}
+
+impl Checkpoint {
+    pub fn new<T: Float>(nn: &NeuralNetwork<T>, training_state: &TrainingState<T>) -> Self {
+        Self {
+            format_version: CHECKPOINT_FORMAT_VERSION,
+            model: SavedModel::from_neural_network(nn),
+            epochs_count: training_state.epochs_count,
+            optimizer_state: SavedOptimizerState::from_optimizer_state(
+                &training_state.optimizer_state,
+            ),
+            seed: training_state.seed,
+            rng: training_state.rng.clone(),
+            prev_cost: training_state.prev_cost,
+            schedule_state: training_state.schedule_state.clone(),
+            early_stop_state: training_state.early_stop_state.clone(),
+        }
+    }
+
+    /// The network and optimizer state are converted to `T`, which doesn't have to be the type they were saved from.
+    pub fn into_parts<T: Float>(self) -> anyhow::Result<(NeuralNetwork<T>, TrainingState<T>)> {
+        let nn = self.model.into_neural_network()?;
+
+        let training_state = TrainingState {
+            epochs_count: self.epochs_count,
+            optimizer_state: self
+                .optimizer_state
+                .into_optimizer_state(&nn.sizes, &nn.normalized_layers())?,
+            seed: self.seed,
+            rng: self.rng,
+            prev_cost: self.prev_cost,
+            schedule_state: self.schedule_state,
+            early_stop_state: self.early_stop_state,
+        };
+
+        Ok((nn, training_state))
+    }
+
+    /// Writes the checkpoint in a compact binary format.
+    /// The file is written to a temporary path first and then renamed so that a run killed mid-write
+    /// doesn't leave a truncated checkpoint behind.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+
+        let mut bytes = CHECKPOINT_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.format_version.to_le_bytes());
+        bytes.extend(bincode::serialize(self)?);
+
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, bytes)
+            .with_context(|| format!("failed writing checkpoint to {:?}", tmp_path))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed moving checkpoint to {:?}", path))?;
+
+        Ok(())
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            fs::read(path).with_context(|| format!("failed reading checkpoint from {:?}", path))?;
+
+        let header_len = CHECKPOINT_MAGIC.len() + 4;
+        if bytes.len() < header_len || &bytes[..CHECKPOINT_MAGIC.len()] != CHECKPOINT_MAGIC {
+            return Err(anyhow!("{:?} is not a checkpoint file - bad header", path));
+        }
+
+        let mut version_bytes = [0u8; 4];
+        version_bytes.copy_from_slice(&bytes[CHECKPOINT_MAGIC.len()..header_len]);
+        let format_version = u32::from_le_bytes(version_bytes);
+        if format_version != CHECKPOINT_FORMAT_VERSION {
+            return Err(anyhow!(
+                "unsupported checkpoint format version {} (expected {})",
+                format_version,
+                CHECKPOINT_FORMAT_VERSION
+            ));
+        }
+
+        Ok(bincode::deserialize(&bytes[header_len..])?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn temp_checkpoint_path(file_name: &str) -> std::path::PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "checkpoint-test-{}-{}",
+            std::process::id(),
+            file_name
+        ));
+        path
+    }
+
+    #[test]
+    fn checkpoint_round_trip_restores_training_state() {
+        let nn = NeuralNetwork::new(vec![3, 4, 2]);
+
+        let optimizer_state =
+            OptimizerState::with_zero_buffers(&nn.zero_params(), &["momentum", "s", "s_max"]);
+        let mut training_state = TrainingState::new(optimizer_state, 42);
+        training_state.epochs_count = 17;
+        training_state.optimizer_state.steps_count = 68;
+        let [momentum, s, s_max] = training_state
+            .optimizer_state
+            .buffers_mut(["momentum", "s", "s_max"]);
+        momentum.add_scalar_to_each_element_in_place(0.25);
+        s.add_scalar_to_each_element_in_place(0.5);
+        s_max.add_scalar_to_each_element_in_place(0.75);
+        training_state.optimizer_state.learning_rate_factor = 0.5;
+        training_state.prev_cost = Some(1.5);
+        training_state.schedule_state.plateau_factor = 0.5;
+        training_state.schedule_state.best_cost = Some(1.25);
+        training_state.early_stop_state = EarlyStopState {
+            best_value: Some(0.75),
+            checks_without_improvement: 2,
+            best_model: Some(SavedModel::from_neural_network(&nn)),
+        };
+        let _: f64 = training_state.rng.gen();
+
+        let path = temp_checkpoint_path("round-trip.bin");
+        Checkpoint::new(&nn, &training_state)
+            .write_to_file(&path)
+            .unwrap();
+        let (loaded_nn, mut loaded_state) = Checkpoint::read_from_file(&path)
+            .unwrap()
+            .into_parts()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded_nn.w, nn.w);
+        assert_eq!(loaded_nn.b, nn.b);
+        assert_eq!(loaded_state.epochs_count, 17);
+        assert_eq!(loaded_state.optimizer_state.steps_count, 68);
+        assert_eq!(loaded_state.optimizer_state, training_state.optimizer_state);
+        assert_eq!(loaded_state.prev_cost, Some(1.5));
+        assert_eq!(loaded_state.seed, 42);
+        assert_eq!(loaded_state.schedule_state, training_state.schedule_state);
+        assert_eq!(
+            loaded_state.early_stop_state,
+            training_state.early_stop_state
+        );
+
+        // the restored RNG must continue the same stream
+        for _ in 0..10 {
+            assert_eq!(
+                loaded_state.rng.gen::<u64>(),
+                training_state.rng.gen::<u64>()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_optimizer_state_with_wrong_sizes() {
+        let saved = SavedBigTheta::from_big_theta(&BigTheta::<f64>::zero_from_sizes(&[3, 4, 2]));
+        assert!(saved
+            .clone()
+            .into_big_theta::<f64>(&[3, 5, 2], &[])
+            .is_err());
+        assert!(saved.into_big_theta::<f64>(&[3, 4, 2], &[1]).is_err());
+    }
+
+    #[test]
+    fn rejects_non_checkpoint_file() {
+        let path = temp_checkpoint_path("not-a-checkpoint.bin");
+        fs::write(&path, b"nope").unwrap();
+        let result = Checkpoint::read_from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
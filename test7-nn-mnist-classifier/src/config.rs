@@ -0,0 +1,629 @@
+//! Declarative experiment configuration, so that a training run can be described (and reviewed, and repeated)
+//! in a TOML or JSON file instead of by editing `main.rs`.
+//!
+//! See `configs/mnist.toml` for an example.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::activation::ActivationFunction;
+use crate::builder::NeuralNetworkBuilder;
+use crate::cost::CostFunc;
+use crate::data_split::DataSplit;
+use crate::early_stopping::EarlyStopMonitor;
+use crate::errors::TrainingOptionsError;
+use crate::gradient_clipping::GradientClipping;
+use crate::health::HealthChecks;
+use crate::initializer::Initializer;
+use crate::lr_schedule::LearningRateSchedule;
+use crate::mini_batch::MiniBatchSampling;
+use crate::normalization::Normalization;
+use crate::optimizer::OptimizerConfig;
+use crate::regularization::Regularization;
+use crate::training_log::{FlushPolicy, TrainingSessionLogger, UpdateFormat};
+use crate::{EarlyStopConfig, TrainingOptions};
+
+/// The format of a config file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Picks the format from the file extension - `.json` is JSON, anything else is TOML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+/// Everything needed to run a training session: the network, how to train it, what to train it on and where to log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentConfig {
+    /// Seeds both the initial weights and biases and the training, so the same config and seed give the same run.
+    /// Without a seed, a random one is used.
+    pub seed: Option<u64>,
+    pub network: ArchitectureConfig,
+    pub training: TrainingConfig,
+    pub data: DataConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ArchitectureConfig {
+    pub input_size: usize,
+    #[serde(default)]
+    pub hidden_layers: Vec<LayerSpec>,
+    pub output_layer: LayerSpec,
+    pub cost_function: CostFunc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LayerSpec {
+    pub size: usize,
+    pub initializer: Initializer,
+    pub activation: ActivationFunction,
+    /// L1/L2 penalties on the layer's weights. None by default.
+    #[serde(default, skip_serializing_if = "Regularization::is_none")]
+    pub regularization: Regularization,
+    /// The inverted dropout rate while training. Only hidden layers can have dropout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dropout_rate: Option<f64>,
+    /// Batch or layer normalization of the layer's weighted inputs. Only hidden layers can be normalized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalization: Option<Normalization>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TrainingConfig {
+    pub epochs: usize,
+    pub mini_batch_size: usize,
+    pub optimizer: OptimizerConfig,
+    #[serde(default)]
+    pub mini_batch_sampling: MiniBatchSampling,
+    #[serde(default)]
+    pub learning_rate_schedule: LearningRateSchedule,
+    #[serde(default, skip_serializing_if = "GradientClipping::is_none")]
+    pub gradient_clipping: GradientClipping,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_checks: Option<HealthChecks>,
+    /// After how many epochs to compute the cost and accuracy across the whole training and test sets, if at all.
+    pub full_cost_update_every: Option<usize>,
+    pub early_stop: Option<EarlyStopSpec>,
+}
+
+/// An `EarlyStopConfig`, which checks against the validation set (see `DataConfig`'s `validation_fraction`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EarlyStopSpec {
+    pub check_every: usize,
+    #[serde(default)]
+    pub cost_threshold: Option<f64>,
+    #[serde(default)]
+    pub patience: Option<usize>,
+    #[serde(default)]
+    pub min_delta: f64,
+    #[serde(default)]
+    pub monitor: EarlyStopMonitor,
+    #[serde(default = "default_restore_best_weights")]
+    pub restore_best_weights: bool,
+}
+
+fn default_restore_best_weights() -> bool {
+    true
+}
+
+/// Where the training, validation and test data come from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "source")]
+pub enum DataConfig {
+    Mnist {
+        training_examples: usize,
+        test_examples: usize,
+        /// The fraction of the training examples to hold out as a validation set, chosen at random with the
+        /// experiment's seed. No validation set by default.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        validation_fraction: Option<f64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Whether to write the session info, training updates and checkpoints to a session directory.
+    #[serde(default = "default_session_logs")]
+    pub session_logs: bool,
+    /// The directory the session directory is created in. Defaults to `$TRAINING_LOG_HOME`, or `./training-sessions`.
+    pub output_directory: Option<PathBuf>,
+    pub checkpoint_every: Option<usize>,
+    /// How the training updates are written: "JsonFiles" (the default), "JsonLines" or "Csv".
+    #[serde(default)]
+    pub update_format: UpdateFormat,
+    /// When appended updates are flushed: "Buffered", "EveryUpdate" (the default) or "Sync".
+    #[serde(default)]
+    pub flush_policy: FlushPolicy,
+    /// Where to save the trained model, if anywhere. The format is picked from the extension (see `ModelFormat`).
+    pub model_path: Option<PathBuf>,
+}
+
+fn default_session_logs() -> bool {
+    true
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            session_logs: default_session_logs(),
+            output_directory: None,
+            checkpoint_every: None,
+            update_format: UpdateFormat::default(),
+            flush_policy: FlushPolicy::default(),
+            model_path: None,
+        }
+    }
+}
+
+impl ExperimentConfig {
+    /// Reads a config file, in the format given by its extension (see `ConfigFormat::from_path`).
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed reading config from {:?}", path))?;
+        Self::from_str_with_format(&contents, ConfigFormat::from_path(path))
+            .with_context(|| format!("invalid config in {:?}", path))
+    }
+
+    /// Parses a config, and checks the training options that would otherwise only fail partway through training.
+    pub fn from_str_with_format(contents: &str, format: ConfigFormat) -> anyhow::Result<Self> {
+        let config: Self = match format {
+            ConfigFormat::Toml => toml::from_str(contents)?,
+            ConfigFormat::Json => serde_json::from_str(contents)?,
+        };
+        config.training_options().validate()?;
+        config.training.validate()?;
+        config.logging.validate()?;
+        config.data.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_string_with_format(&self, format: ConfigFormat) -> anyhow::Result<String> {
+        let contents = match format {
+            ConfigFormat::Toml => toml::to_string_pretty(self)?,
+            ConfigFormat::Json => serde_json::to_string_pretty(self)?,
+        };
+        Ok(contents)
+    }
+
+    /// Returns a builder for the network, seeded with `seed` if there is one.
+    pub fn network_builder(&self) -> NeuralNetworkBuilder {
+        let builder = self.network.to_builder();
+        match self.seed {
+            Some(seed) => builder.with_seed(seed),
+            None => builder,
+        }
+    }
+
+    pub fn training_options(&self) -> TrainingOptions {
+        TrainingOptions {
+            mini_batch_sampling: self.training.mini_batch_sampling,
+            seed: self.seed,
+            learning_rate_schedule: self.training.learning_rate_schedule.clone(),
+            gradient_clipping: self.training.gradient_clipping,
+            health_checks: self.training.health_checks,
+        }
+    }
+}
+
+impl ArchitectureConfig {
+    /// Returns a builder for the network. Use `try_build` on it to check the architecture makes sense.
+    pub fn to_builder(&self) -> NeuralNetworkBuilder {
+        let builder = self.hidden_layers.iter().fold(
+            NeuralNetworkBuilder::new().with_input_layer(self.input_size),
+            |builder, layer| {
+                let builder = builder
+                    .with_hidden_layer(
+                        layer.size,
+                        layer.initializer.clone(),
+                        layer.activation.clone(),
+                    )
+                    .with_regularization(layer.regularization);
+                let builder = match layer.dropout_rate {
+                    Some(rate) => builder.with_dropout(rate),
+                    None => builder,
+                };
+                match layer.normalization {
+                    Some(normalization) => builder.with_normalization(normalization),
+                    None => builder,
+                }
+            },
+        );
+
+        let builder = builder
+            .with_output_layer(
+                self.output_layer.size,
+                self.output_layer.initializer.clone(),
+                self.output_layer.activation.clone(),
+            )
+            .with_regularization(self.output_layer.regularization);
+        // so that try_build rejects it, rather than ignoring it
+        let builder = match self.output_layer.dropout_rate {
+            Some(rate) => builder.with_dropout(rate),
+            None => builder,
+        };
+        let builder = match self.output_layer.normalization {
+            Some(normalization) => builder.with_normalization(normalization),
+            None => builder,
+        };
+        builder.with_cost_fn(self.cost_function.clone())
+    }
+}
+
+impl TrainingConfig {
+    /// Checks the settings that aren't part of the `TrainingOptions`, which `train_stochastic` would reject.
+    pub fn validate(&self) -> Result<(), TrainingOptionsError> {
+        if self.mini_batch_size == 0 {
+            return Err(TrainingOptionsError::InvalidMiniBatchSize);
+        }
+        if self.full_cost_update_every == Some(0) {
+            return Err(TrainingOptionsError::InvalidFullCostUpdateEvery);
+        }
+        if self
+            .early_stop_config()
+            .is_some_and(|early_stop_config| !early_stop_config.is_valid())
+        {
+            return Err(TrainingOptionsError::InvalidEarlyStopConfig);
+        }
+        Ok(())
+    }
+
+    pub fn early_stop_config(&self) -> Option<EarlyStopConfig> {
+        self.early_stop.as_ref().map(|early_stop| EarlyStopConfig {
+            check_every: early_stop.check_every,
+            cost_threshold: early_stop.cost_threshold,
+            patience: early_stop.patience,
+            min_delta: early_stop.min_delta,
+            monitor: early_stop.monitor,
+            restore_best_weights: early_stop.restore_best_weights,
+        })
+    }
+}
+
+impl DataConfig {
+    /// Checks the settings that `load` would otherwise panic on.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            DataConfig::Mnist {
+                validation_fraction,
+                ..
+            } => {
+                if let Some(validation_fraction) = validation_fraction {
+                    if !(0.0..=1.0).contains(validation_fraction) {
+                        return Err(anyhow!(
+                            "the validation_fraction is {} but must be from 0 to 1",
+                            validation_fraction
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the training, validation and test data, using `seed` to pick the validation set.
+    pub fn load(&self, seed: u64) -> DataSplit {
+        match self {
+            DataConfig::Mnist {
+                training_examples,
+                test_examples,
+                validation_fraction,
+            } => {
+                let (training_data, test_data) =
+                    mnist_data::get_mnist_data(*training_examples, *test_examples);
+                let split =
+                    DataSplit::split(training_data, validation_fraction.unwrap_or(0.0), 0.0, seed);
+                DataSplit {
+                    test: test_data,
+                    ..split
+                }
+            }
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Checks the settings that the session logger would reject when training starts.
+    pub fn validate(&self) -> Result<(), TrainingOptionsError> {
+        if self.checkpoint_every == Some(0) {
+            return Err(TrainingOptionsError::InvalidCheckpointEvery);
+        }
+        Ok(())
+    }
+
+    /// Creates the session directory and returns a logger for it, or `None` if session logs are turned off.
+    pub fn session_logger(&self) -> std::io::Result<Option<TrainingSessionLogger>> {
+        if !self.session_logs {
+            return Ok(None);
+        }
+
+        let mut session_logger = TrainingSessionLogger::new()
+            .with_update_format(self.update_format)
+            .with_flush_policy(self.flush_policy);
+        if let Some(checkpoint_every) = self.checkpoint_every {
+            session_logger = session_logger.with_checkpoint_every(checkpoint_every);
+        }
+
+        match &self.output_directory {
+            Some(output_directory) => {
+                session_logger.create_training_log_directory_in(output_directory.clone())?
+            }
+            None => session_logger.create_training_log_directory()?,
+        }
+
+        Ok(Some(session_logger))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::BuilderError;
+    use crate::health::OnHealthCheckFailure;
+    use crate::lr_schedule::ScheduleUnit;
+    use crate::mini_batch::PartialBatch;
+    use crate::optimizer::AdamConfig;
+
+    const MNIST_CONFIG: &str = include_str!("../configs/mnist.toml");
+
+    #[test]
+    fn example_config_is_valid() {
+        let config =
+            ExperimentConfig::from_str_with_format(MNIST_CONFIG, ConfigFormat::Toml).unwrap();
+
+        let nn = config.network_builder().try_build().unwrap();
+        assert_eq!(nn.sizes, vec![784, 100, 100, 100, 50, 10]);
+        assert_eq!(
+            nn.layer_configs.get(&1).unwrap().activation_function,
+            Some(ActivationFunction::LeakyReLU(0.1))
+        );
+        assert_eq!(nn.cost, CostFunc::CrossEntropy);
+
+        assert_eq!(
+            config.training.optimizer,
+            OptimizerConfig::Adam(AdamConfig::default())
+        );
+        assert_eq!(
+            config.training.mini_batch_sampling,
+            MiniBatchSampling::default()
+        );
+        assert_eq!(
+            config.training.learning_rate_schedule,
+            LearningRateSchedule::Constant
+        );
+        assert!(config.training.gradient_clipping.is_none());
+        assert_eq!(
+            config.data,
+            DataConfig::Mnist {
+                training_examples: 50000,
+                test_examples: 10000,
+                validation_fraction: Some(0.1)
+            }
+        );
+        let early_stop_config = config.training.early_stop_config().unwrap();
+        assert_eq!(early_stop_config.patience, Some(5));
+        assert_eq!(early_stop_config.monitor, EarlyStopMonitor::Cost);
+        assert!(early_stop_config.restore_best_weights);
+        assert_eq!(config.logging.checkpoint_every, Some(100));
+        assert_eq!(config.logging.update_format, UpdateFormat::JsonLines);
+        assert_eq!(config.logging.flush_policy, FlushPolicy::EveryUpdate);
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.training_options().seed, Some(42));
+    }
+
+    #[test]
+    fn toml_and_json_round_trip() {
+        let mut config =
+            ExperimentConfig::from_str_with_format(MNIST_CONFIG, ConfigFormat::Toml).unwrap();
+        config.training.mini_batch_sampling = MiniBatchSampling::ShuffledEpochs {
+            partial_batch: PartialBatch::Drop,
+        };
+        config.training.optimizer = OptimizerConfig::momentum(0.01, 0.9);
+        config.network.hidden_layers[1].regularization = Regularization::l2(1e-4);
+        config.network.hidden_layers[2].dropout_rate = Some(0.25);
+        config.network.hidden_layers[0].normalization = Some(Normalization::layer_norm());
+        config.network.output_layer.regularization = Regularization { l1: 1e-5, l2: 0.0 };
+        config.training.learning_rate_schedule = LearningRateSchedule::LinearWarmup {
+            length: 5,
+            per: ScheduleUnit::Epoch,
+            then: Box::new(LearningRateSchedule::ReduceOnPlateau {
+                factor: 0.5,
+                patience: 2,
+                min_delta: 0.0,
+                min_factor: 0.01,
+            }),
+        };
+        config.training.gradient_clipping = GradientClipping {
+            max_value: Some(10.0),
+            max_norm: Some(1.0),
+        };
+        config.training.health_checks = Some(HealthChecks {
+            max_dead_fraction: Some(0.9),
+            on_failure: OnHealthCheckFailure::RollBack {
+                max_rollbacks: 2,
+                learning_rate_factor: 0.1,
+            },
+        });
+        config.training.early_stop = Some(EarlyStopSpec {
+            check_every: 5,
+            cost_threshold: None,
+            patience: Some(3),
+            min_delta: 0.001,
+            monitor: EarlyStopMonitor::Accuracy,
+            restore_best_weights: false,
+        });
+
+        for format in [ConfigFormat::Toml, ConfigFormat::Json] {
+            let contents = config.to_string_with_format(format).unwrap();
+            assert_eq!(
+                ExperimentConfig::from_str_with_format(&contents, format).unwrap(),
+                config
+            );
+        }
+    }
+
+    #[test]
+    fn missing_optimizer_hyperparameters_take_their_defaults() {
+        let config = ExperimentConfig::from_str_with_format(
+            r#"
+            [network]
+            input_size = 2
+            cost_function = "QuadraticCost"
+            output_layer = { size = 1, initializer = "RandomBasic", activation = "Sigmoid" }
+
+            [training]
+            epochs = 10
+            mini_batch_size = 4
+            optimizer = { Adam = { learning_rate = 0.01 } }
+
+            [data]
+            source = "Mnist"
+            training_examples = 100
+            test_examples = 10
+            "#,
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.training.optimizer,
+            OptimizerConfig::Adam(AdamConfig::with_learning_rate(0.01))
+        );
+        assert!(config.network.hidden_layers.is_empty());
+        assert_eq!(config.logging, LoggingConfig::default());
+        assert!(config.training.early_stop_config().is_none());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let contents = MNIST_CONFIG.replace("epochs = ", "epocs = ");
+        assert!(ExperimentConfig::from_str_with_format(&contents, ConfigFormat::Toml).is_err());
+    }
+
+    #[test]
+    fn learning_rate_schedules_that_never_advance_are_rejected() {
+        let contents = MNIST_CONFIG.replace(
+            "[training]",
+            "[training]\nlearning_rate_schedule = { StepDecay = { every = 0, gamma = 0.5 } }",
+        );
+        let err =
+            ExperimentConfig::from_str_with_format(&contents, ConfigFormat::Toml).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<TrainingOptionsError>(),
+            Some(&TrainingOptionsError::InvalidLearningRateSchedule)
+        );
+    }
+
+    #[test]
+    fn gradient_clipping_limits_that_are_not_positive_are_rejected() {
+        let contents = MNIST_CONFIG.replace(
+            "[training]",
+            "[training]\ngradient_clipping = { max_norm = -1.0 }",
+        );
+        let err =
+            ExperimentConfig::from_str_with_format(&contents, ConfigFormat::Toml).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<TrainingOptionsError>(),
+            Some(&TrainingOptionsError::InvalidGradientClipping)
+        );
+    }
+
+    #[test]
+    fn a_zero_mini_batch_size_is_rejected() {
+        let contents = MNIST_CONFIG.replace("mini_batch_size = 5000", "mini_batch_size = 0");
+        let err =
+            ExperimentConfig::from_str_with_format(&contents, ConfigFormat::Toml).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<TrainingOptionsError>(),
+            Some(&TrainingOptionsError::InvalidMiniBatchSize)
+        );
+    }
+
+    #[test]
+    fn full_cost_updates_checkpoints_and_early_stop_checks_every_0_epochs_are_rejected() {
+        for (from, to, expected) in [
+            (
+                "full_cost_update_every = 10",
+                "full_cost_update_every = 0",
+                TrainingOptionsError::InvalidFullCostUpdateEvery,
+            ),
+            (
+                "checkpoint_every = 100",
+                "checkpoint_every = 0",
+                TrainingOptionsError::InvalidCheckpointEvery,
+            ),
+            (
+                "check_every = 10",
+                "check_every = 0",
+                TrainingOptionsError::InvalidEarlyStopConfig,
+            ),
+        ] {
+            let contents = MNIST_CONFIG.replace(from, to);
+            let err =
+                ExperimentConfig::from_str_with_format(&contents, ConfigFormat::Toml).unwrap_err();
+            assert_eq!(err.downcast_ref::<TrainingOptionsError>(), Some(&expected));
+        }
+    }
+
+    #[test]
+    fn validation_fractions_outside_0_to_1_are_rejected() {
+        for validation_fraction in ["-0.1", "1.5", "nan"] {
+            let contents = MNIST_CONFIG.replace(
+                "validation_fraction = 0.1",
+                &format!("validation_fraction = {}", validation_fraction),
+            );
+            let err =
+                ExperimentConfig::from_str_with_format(&contents, ConfigFormat::Toml).unwrap_err();
+            assert!(
+                err.to_string().contains("must be from 0 to 1"),
+                "validation_fraction = {}: {}",
+                validation_fraction,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_architecture_is_reported_by_try_build() {
+        let contents = MNIST_CONFIG.replace(
+            r#"cost_function = "CrossEntropy""#,
+            r#"cost_function = "QuadraticCost""#,
+        );
+        let config = ExperimentConfig::from_str_with_format(&contents, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.network.to_builder().try_build().err(),
+            Some(BuilderError::SoftmaxOutputRequiresCrossEntropy)
+        );
+    }
+
+    #[test]
+    fn format_is_picked_from_extension() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("run.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("run.toml")),
+            ConfigFormat::Toml
+        );
+    }
+}
@@ -1,6 +1,14 @@
+use super::Coster;
 use crate::errors::VectorDimensionMismatch;
-use common::linalg::{square, ColumnVector};
+use common::linalg::{ColumnVector, Float};
 
+/// Output probabilities are clamped to at least this before taking the log so that a (possibly underflowed)
+/// probability of 0 for the desired class gives a large but finite cost rather than infinity.
+const MIN_PROBABILITY: f64 = 1e-15;
+
+/// Categorical cross-entropy: `C = -Σ y_i ln(a_i)`.
+/// Expects `actual_v` to be a probability distribution (i.e. the output of Softmax) and `desired_v` to be one-hot
+/// (or at least sum to 1). With a Softmax output layer, ∂C/∂z is then simply `a - y`, which is what backprop uses.
 pub struct CrossEntropyCost {}
 
 impl CrossEntropyCost {
@@ -9,11 +17,17 @@ impl CrossEntropyCost {
     }
 }
 
+impl Default for CrossEntropyCost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Coster for CrossEntropyCost {
-    fn cost(
-        &self, 
-        desired_v: &ColumnVector,
-        actual_v: &ColumnVector,
+    fn cost<T: Float>(
+        &self,
+        desired_v: &ColumnVector<T>,
+        actual_v: &ColumnVector<T>,
     ) -> Result<f64, VectorDimensionMismatch> {
         if desired_v.num_elements() != actual_v.num_elements() {
             return Err(VectorDimensionMismatch::new_with_msg(
@@ -23,19 +37,52 @@ impl Coster for CrossEntropyCost {
             ));
         }
 
-        // Using epsilon to avoid log(0) which is undefined
-        let epsilon = 1e-10;
+        Ok(desired_v
+            .iter_with(actual_v)
+            // terms where the desired value is 0 contribute nothing; skipping them also avoids 0 * ln(0) = NaN
+            .filter(|(des, _)| *des != T::zero())
+            .map(|(des, act)| -des * act.max(T::from_f64(MIN_PROBABILITY)).ln())
+            .sum::<T>()
+            .as_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::column_vector;
+    use float_cmp::approx_eq;
 
-        let mut total_loss: f32 = 0.0;
-        for (&act, &des) in actual_v.iter().zip(desired_v.iter()) {
-            // Using epsilon to avoid log(0) which is undefined
-            let pred = pred.max(epsilon).min(1.0 - epsilon);
+    #[test]
+    fn cross_entropy_cost_works() {
+        let coster = CrossEntropyCost::new();
 
-            // Compute the loss for current element
-            let loss = act * pred.log10() + (1.0 - act) * (1.0 - pred).log10();
-            total_loss -= loss; // Sum up the negative log likelihood
-        }
+        let desired_v = column_vector![0.0, 1.0, 0.0];
+        let actual_v = column_vector![0.2, 0.7, 0.1];
+        let cost = coster.cost(&desired_v, &actual_v).unwrap();
+        assert!(approx_eq!(f64, cost, -(0.7_f64.ln()), ulps = 2));
+
+        // a perfect prediction costs nothing
+        let cost = coster
+            .cost(&desired_v, &column_vector![0.0, 1.0, 0.0])
+            .unwrap();
+        assert_eq!(cost, 0.0);
+    }
+
+    #[test]
+    fn cross_entropy_cost_is_finite_when_desired_class_has_zero_probability() {
+        let coster = CrossEntropyCost::new();
+        let cost = coster
+            .cost(&column_vector![1.0, 0.0], &column_vector![0.0, 1.0])
+            .unwrap();
+        assert!(cost.is_finite());
+        assert!(approx_eq!(f64, cost, -(MIN_PROBABILITY.ln()), ulps = 2));
+    }
 
-        Ok(total_loss / actual.len() as f32) // Return the average loss
+    #[test]
+    fn cross_entropy_cost_dimension_mismatch() {
+        let coster = CrossEntropyCost::new();
+        let result = coster.cost(&column_vector![1.0, 0.0], &column_vector![0.5, 0.25, 0.25]);
+        assert!(result.is_err());
     }
 }
@@ -1,24 +1,66 @@
 use crate::errors::VectorDimensionMismatch;
-use common::linalg::{square, ColumnVector};
+use common::linalg::{square, ColumnVector, Float};
+use serde_derive::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+pub mod cross_entropy;
+use cross_entropy::CrossEntropyCost;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CostFunc {
     QuadraticCost,
     CrossEntropy,
 }
 
+/// Costs are computed in the precision of the network but always returned as `f64`.
 pub trait Coster {
-    fn cost(
+    fn cost<T: Float>(
         &self,
-        desired_v: &ColumnVector,
-        actual_v: &ColumnVector,
+        desired_v: &ColumnVector<T>,
+        actual_v: &ColumnVector<T>,
     ) -> Result<f64, VectorDimensionMismatch>;
 }
 
+impl Coster for CostFunc {
+    fn cost<T: Float>(
+        &self,
+        desired_v: &ColumnVector<T>,
+        actual_v: &ColumnVector<T>,
+    ) -> Result<f64, VectorDimensionMismatch> {
+        match self {
+            CostFunc::QuadraticCost => QuadraticCost::new().cost(desired_v, actual_v),
+            CostFunc::CrossEntropy => CrossEntropyCost::new().cost(desired_v, actual_v),
+        }
+    }
+}
+
+pub struct QuadraticCost {}
+
+impl QuadraticCost {
+    pub fn new() -> QuadraticCost {
+        QuadraticCost {}
+    }
+}
+
+impl Default for QuadraticCost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Coster for QuadraticCost {
+    fn cost<T: Float>(
+        &self,
+        desired_v: &ColumnVector<T>,
+        actual_v: &ColumnVector<T>,
+    ) -> Result<f64, VectorDimensionMismatch> {
+        quadratic_cost(desired_v, actual_v)
+    }
+}
+
 /// Calculates the quadratic cost between the desired and actual output column vectors.
-pub fn quadratic_cost(
-    desired_v: &ColumnVector,
-    actual_v: &ColumnVector,
+pub fn quadratic_cost<T: Float>(
+    desired_v: &ColumnVector<T>,
+    actual_v: &ColumnVector<T>,
 ) -> Result<f64, VectorDimensionMismatch> {
     // Note that 3B1B does not do the divide by 2 and he ends up with a 2 in the derivative function.
     // Neilson does the divide by 2
@@ -32,12 +74,13 @@ pub fn quadratic_cost(
         ));
     }
 
-    Ok(desired_v
+    Ok((desired_v
         .iter_with(actual_v)
         .map(|(exp, act)| exp - act)
         .map(square)
-        .sum::<f64>()
-        / 2.0)
+        .sum::<T>()
+        / T::from_f64(2.0))
+    .as_f64())
 }
 
 #[cfg(test)]
@@ -73,4 +116,19 @@ mod tests {
         );
         assert_eq!(result.unwrap_err(), expected_error);
     }
+
+    #[test]
+    pub fn cost_func_dispatches_to_the_matching_coster() {
+        let desired_v = column_vector![0.0, 1.0];
+        let actual_v = column_vector![0.25, 0.75];
+
+        assert_eq!(
+            CostFunc::QuadraticCost.cost(&desired_v, &actual_v),
+            quadratic_cost(&desired_v, &actual_v)
+        );
+        assert_eq!(
+            CostFunc::CrossEntropy.cost(&desired_v, &actual_v),
+            CrossEntropyCost::new().cost(&desired_v, &actual_v)
+        );
+    }
 }
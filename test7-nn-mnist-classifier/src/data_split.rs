@@ -0,0 +1,147 @@
+//! Training, validation and test sets. The validation set is for decisions made during training (early stopping,
+//! reduce-on-plateau), so that the test set stays untouched until the end and its cost and accuracy are a fair estimate
+//! of how the network does on new data.
+
+use rand::seq::SliceRandom;
+
+use common::datapoints::NDTrainingDataPoint;
+
+use crate::rng::{self, RngStream};
+
+/// The data `train_stochastic` trains on and periodically evaluates against. A `&Vec` of training data converts into
+/// one without validation or test sets.
+#[derive(Debug, Clone, Copy)]
+pub struct Datasets<'a, T = f64> {
+    pub training: &'a [NDTrainingDataPoint<T>],
+    pub validation: Option<&'a [NDTrainingDataPoint<T>]>,
+    pub test: Option<&'a [NDTrainingDataPoint<T>]>,
+}
+
+impl<'a, T> Datasets<'a, T> {
+    pub fn new(training: &'a [NDTrainingDataPoint<T>]) -> Self {
+        Self {
+            training,
+            validation: None,
+            test: None,
+        }
+    }
+
+    pub fn with_validation(mut self, validation: &'a [NDTrainingDataPoint<T>]) -> Self {
+        self.validation = Some(validation);
+        self
+    }
+
+    pub fn with_test(mut self, test: &'a [NDTrainingDataPoint<T>]) -> Self {
+        self.test = Some(test);
+        self
+    }
+}
+
+impl<'a, T> From<&'a Vec<NDTrainingDataPoint<T>>> for Datasets<'a, T> {
+    fn from(training: &'a Vec<NDTrainingDataPoint<T>>) -> Self {
+        Datasets::new(training)
+    }
+}
+
+/// Examples split into training, validation and test sets.
+#[derive(Debug)]
+pub struct DataSplit<T = f64> {
+    pub training: Vec<NDTrainingDataPoint<T>>,
+    pub validation: Vec<NDTrainingDataPoint<T>>,
+    pub test: Vec<NDTrainingDataPoint<T>>,
+}
+
+impl<T> DataSplit<T> {
+    /// Shuffles `data` and splits `validation_fraction` of it off into the validation set and `test_fraction` into the
+    /// test set (rounding down), leaving the rest for training. The same seed always gives the same split.
+    ///
+    /// Panics if either fraction is negative (or NaN) or they add up to more than 1 - fractions from a user should be
+    /// checked before they get here (see `DataConfig::validate`).
+    pub fn split(
+        mut data: Vec<NDTrainingDataPoint<T>>,
+        validation_fraction: f64,
+        test_fraction: f64,
+        seed: u64,
+    ) -> Self {
+        assert!(
+            validation_fraction >= 0.0
+                && test_fraction >= 0.0
+                && validation_fraction + test_fraction <= 1.0,
+            "the validation and test fractions must be non-negative and add up to at most 1"
+        );
+
+        let num_validation = (data.len() as f64 * validation_fraction) as usize;
+        let num_test = (data.len() as f64 * test_fraction) as usize;
+
+        data.shuffle(&mut rng::seeded_rng(seed, RngStream::DataSplit));
+        let test = data.split_off(data.len() - num_test);
+        let validation = data.split_off(data.len() - num_validation);
+
+        Self {
+            training: data,
+            validation,
+            test,
+        }
+    }
+
+    /// Borrows the sets for `train_stochastic`, leaving out the validation and/or test sets if they're empty.
+    pub fn datasets(&self) -> Datasets<'_, T> {
+        Datasets {
+            training: &self.training,
+            validation: (!self.validation.is_empty()).then_some(self.validation.as_slice()),
+            test: (!self.test.is_empty()).then_some(self.test.as_slice()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::column_vector;
+    use common::linalg::ColumnVector;
+
+    fn numbered_examples(n: usize) -> Vec<NDTrainingDataPoint> {
+        (0..n)
+            .map(|i| NDTrainingDataPoint::new(column_vector![i as f64], column_vector![0.0]))
+            .collect()
+    }
+
+    fn numbers(set: &[NDTrainingDataPoint]) -> Vec<usize> {
+        set.iter().map(|p| p.input_v[0] as usize).collect()
+    }
+
+    #[test]
+    fn split_uses_every_example_once() {
+        let split = DataSplit::split(numbered_examples(100), 0.1, 0.25, 7);
+        assert_eq!(split.training.len(), 65);
+        assert_eq!(split.validation.len(), 10);
+        assert_eq!(split.test.len(), 25);
+
+        let mut all = [
+            numbers(&split.training),
+            numbers(&split.validation),
+            numbers(&split.test),
+        ]
+        .concat();
+        all.sort();
+        assert_eq!(all, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn split_depends_only_on_the_seed() {
+        let a = DataSplit::split(numbered_examples(50), 0.2, 0.2, 1);
+        let b = DataSplit::split(numbered_examples(50), 0.2, 0.2, 1);
+        let c = DataSplit::split(numbered_examples(50), 0.2, 0.2, 2);
+        assert_eq!(numbers(&a.validation), numbers(&b.validation));
+        assert_ne!(numbers(&a.validation), numbers(&c.validation));
+    }
+
+    #[test]
+    fn empty_sets_are_left_out_of_the_datasets() {
+        let split = DataSplit::split(numbered_examples(10), 0.2, 0.0, 1);
+        let datasets = split.datasets();
+        assert_eq!(datasets.training.len(), 8);
+        assert_eq!(datasets.validation.map(|v| v.len()), Some(2));
+        assert!(datasets.test.is_none());
+    }
+}
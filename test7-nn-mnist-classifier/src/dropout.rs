@@ -0,0 +1,54 @@
+//! Inverted dropout. While training, each activation of a layer with a dropout rate is zeroed with probability `rate`
+//! and the rest are scaled up by `1 / (1 - rate)`, so the expected activations are unchanged and nothing needs to be
+//! done at inference time, when dropout is off.
+//!
+//! See https://jmlr.org/papers/v15/srivastava14a.html
+
+use common::linalg::{ColumnVector, Float};
+use rand::{Rng, RngCore};
+
+/// Draws a mask to multiply a layer's `len` activations by.
+pub(crate) fn dropout_mask<T: Float>(
+    rate: f64,
+    len: usize,
+    rng: &mut dyn RngCore,
+) -> ColumnVector<T> {
+    let kept = T::from_f64(1.0 / (1.0 - rate));
+    let mask = (0..len)
+        .map(|_| {
+            if rng.gen::<f64>() < rate {
+                T::zero()
+            } else {
+                kept
+            }
+        })
+        .collect();
+    ColumnVector::from_vec(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::{seeded_rng, RngStream};
+
+    #[test]
+    fn mask_drops_about_rate_of_the_activations_and_scales_up_the_rest() {
+        let mut rng = seeded_rng(1, RngStream::Training);
+        let mask: ColumnVector<f64> = dropout_mask(0.2, 10_000, &mut rng);
+
+        let dropped = mask.iter().filter(|x| **x == 0.0).count();
+        assert!(dropped > 1800 && dropped < 2200, "dropped {}", dropped);
+        assert!(mask.iter().all(|x| *x == 0.0 || *x == 1.25));
+
+        // so the expected activation is unchanged
+        let mean = mask.iter().sum::<f64>() / 10_000.0;
+        assert!((mean - 1.0).abs() < 0.05, "mean {}", mean);
+    }
+
+    #[test]
+    fn mask_is_all_ones_for_a_zero_rate() {
+        let mut rng = seeded_rng(1, RngStream::Training);
+        let mask: ColumnVector<f64> = dropout_mask(0.0, 5, &mut rng);
+        assert_eq!(mask, ColumnVector::fill_new(1.0, 5));
+    }
+}
@@ -0,0 +1,328 @@
+//! Patience-based early stopping: training stops once the cost or accuracy on a validation set hasn't improved for a
+//! number of checks in a row, and the weights from the best check can be put back at the end, since by the time
+//! training stops they've usually started to overfit.
+
+use serde_derive::{Deserialize, Serialize};
+use tracing::info;
+
+use common::datapoints::NDTrainingDataPoint;
+use common::linalg::Float;
+
+use crate::callbacks::{EpochEnd, TrainBegin, TrainingCallback, TrainingControl};
+use crate::errors::{NeuralNetworkError, TrainingOptionsError, VectorDimensionMismatch};
+use crate::saved_model::SavedModel;
+use crate::training_log::SetMetrics;
+use crate::{EarlyStopConfig, NeuralNetwork};
+
+/// What the early stop checks watch on the validation set.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum EarlyStopMonitor {
+    /// Lower is better.
+    #[default]
+    Cost,
+    /// The top-1 accuracy, as a fraction. Higher is better.
+    Accuracy,
+}
+
+impl EarlyStopMonitor {
+    /// Whether `value` beats `best` by more than `min_delta`.
+    pub fn improves_on(&self, value: f64, best: f64, min_delta: f64) -> bool {
+        match self {
+            EarlyStopMonitor::Cost => value < best - min_delta,
+            EarlyStopMonitor::Accuracy => value > best + min_delta,
+        }
+    }
+}
+
+/// What early stopping has seen of the validation set so far. Saved with checkpoints (see `TrainingState`) so that a
+/// resumed run keeps its patience and its best weights.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct EarlyStopState {
+    /// The best monitored value so far.
+    pub best_value: Option<f64>,
+    /// The number of checks since `best_value` improved.
+    pub checks_without_improvement: usize,
+    /// The network at the best check, if the weights are to be restored.
+    pub best_model: Option<SavedModel>,
+}
+
+/// Runs the checks an `EarlyStopConfig` describes against `validation_data`, as a `TrainingCallback`. `train_stochastic`
+/// adds one for the validation set when it's given an `EarlyStopConfig`.
+pub struct EarlyStopping<'a, T = f64> {
+    config: EarlyStopConfig,
+    validation_data: &'a [NDTrainingDataPoint<T>],
+    tracker: EarlyStopTracker,
+}
+
+/// What an early stop check found.
+struct EarlyStopCheck {
+    validation_cost: f64,
+    stop: bool,
+}
+
+impl<'a, T: Float> EarlyStopping<'a, T> {
+    pub fn new(config: EarlyStopConfig, validation_data: &'a [NDTrainingDataPoint<T>]) -> Self {
+        let tracker = EarlyStopTracker::new(config.monitor, config.min_delta);
+        Self {
+            config,
+            validation_data,
+            tracker,
+        }
+    }
+
+    /// Checks against the validation set, if a check is due after `epochs_completed` epochs. `validation_set_metrics`
+    /// are used rather than computed again when they were already computed for this epoch (by a full cost update).
+    fn check(
+        &mut self,
+        nn: &NeuralNetwork<T>,
+        epochs_completed: usize,
+        validation_set_metrics: Option<SetMetrics>,
+    ) -> Result<Option<EarlyStopCheck>, VectorDimensionMismatch> {
+        let config = &self.config;
+        if !epochs_completed.is_multiple_of(config.check_every) {
+            return Ok(None);
+        }
+        let validation_cost = match validation_set_metrics {
+            Some(metrics) => metrics.cost,
+            None => {
+                let validation_cost = nn.cost_training_set(self.validation_data)?;
+                info!(
+                    epochs = epochs_completed,
+                    cost = validation_cost,
+                    "early stop check: validation set cost"
+                );
+                validation_cost
+            }
+        };
+
+        let monitored_value = match (config.monitor, validation_set_metrics) {
+            (EarlyStopMonitor::Cost, _) => validation_cost,
+            (EarlyStopMonitor::Accuracy, Some(metrics)) => metrics.accuracy,
+            (EarlyStopMonitor::Accuracy, None) => {
+                let validation_accuracy = nn.accuracy(self.validation_data)?;
+                info!(
+                    epochs = epochs_completed,
+                    accuracy = validation_accuracy,
+                    "early stop check: validation set accuracy"
+                );
+                validation_accuracy
+            }
+        };
+        self.tracker
+            .observe(monitored_value, nn, config.restore_best_weights);
+
+        let checks_without_improvement = self.tracker.checks_without_improvement();
+        let stop = if config.cost_threshold.is_some_and(|t| validation_cost <= t) {
+            info!(
+                epochs = epochs_completed,
+                "validation cost reached the threshold, stopping"
+            );
+            true
+        } else if config
+            .patience
+            .is_some_and(|p| checks_without_improvement >= p)
+        {
+            info!(
+                epochs = epochs_completed,
+                checks_without_improvement, "no improvement in the validation set, stopping"
+            );
+            true
+        } else {
+            false
+        };
+
+        Ok(Some(EarlyStopCheck {
+            validation_cost,
+            stop,
+        }))
+    }
+
+    /// Puts back the weights from the best check, if `restore_best_weights` is set and there was a check.
+    fn restore_best_weights(&mut self, nn: &mut NeuralNetwork<T>) {
+        let best_value = self.tracker.best_value();
+        if self.tracker.restore_best(nn) {
+            info!(
+                best_value = ?best_value,
+                "restored the weights from the best validation check"
+            );
+        }
+    }
+}
+
+impl<'a, T: Float> TrainingCallback<T> for EarlyStopping<'a, T> {
+    fn on_train_begin(
+        &mut self,
+        nn: &NeuralNetwork<T>,
+        train_begin: &TrainBegin<T>,
+    ) -> Result<(), NeuralNetworkError> {
+        if !self.config.is_valid() {
+            return Err(TrainingOptionsError::InvalidEarlyStopConfig.into());
+        }
+        nn.check_dimensions(self.validation_data)
+            .map_err(NeuralNetworkError::VectorDimensionMismatch)?;
+        // carry on from the checks before the checkpoint, when resuming
+        self.tracker.state = train_begin.training_state.early_stop_state.clone();
+        Ok(())
+    }
+
+    fn on_epoch_end(&mut self, nn: &NeuralNetwork<T>, epoch: &mut EpochEnd) -> TrainingControl {
+        let validation_set_metrics = epoch
+            .full_cost_update
+            .and_then(|full_cost_update| full_cost_update.validation_set);
+        let check = self
+            .check(nn, epoch.epochs_completed, validation_set_metrics)
+            .expect("the validation data's dimensions were checked in on_train_begin");
+        match check {
+            Some(check) => {
+                epoch.validation_cost = Some(check.validation_cost);
+                epoch.early_stop_state = Some(self.tracker.state.clone());
+                if check.stop {
+                    TrainingControl::Stop
+                } else {
+                    TrainingControl::Continue
+                }
+            }
+            None => TrainingControl::Continue,
+        }
+    }
+
+    fn on_train_end(&mut self, nn: &mut NeuralNetwork<T>, _epochs_completed: usize) {
+        self.restore_best_weights(nn);
+    }
+}
+
+/// The best check so far, and how many checks there have been since.
+struct EarlyStopTracker {
+    monitor: EarlyStopMonitor,
+    min_delta: f64,
+    state: EarlyStopState,
+}
+
+impl EarlyStopTracker {
+    fn new(monitor: EarlyStopMonitor, min_delta: f64) -> Self {
+        Self {
+            monitor,
+            min_delta,
+            state: EarlyStopState::default(),
+        }
+    }
+
+    /// Records a check's `value`, and returns whether it was an improvement. With `snapshot_best`, the network is
+    /// saved whenever it is, so that it can be restored with `restore_best`. A NaN or infinite value is never an
+    /// improvement - otherwise nothing after a NaN could beat it.
+    fn observe<T: Float>(
+        &mut self,
+        value: f64,
+        nn: &NeuralNetwork<T>,
+        snapshot_best: bool,
+    ) -> bool {
+        let state = &mut self.state;
+        let improved = value.is_finite()
+            && match state.best_value {
+                Some(best_value) => self.monitor.improves_on(value, best_value, self.min_delta),
+                None => true,
+            };
+
+        if improved {
+            state.best_value = Some(value);
+            state.checks_without_improvement = 0;
+            if snapshot_best {
+                state.best_model = Some(SavedModel::from_neural_network(nn));
+            }
+        } else {
+            state.checks_without_improvement += 1;
+        }
+        improved
+    }
+
+    fn checks_without_improvement(&self) -> usize {
+        self.state.checks_without_improvement
+    }
+
+    fn best_value(&self) -> Option<f64> {
+        self.state.best_value
+    }
+
+    /// Replaces the network with the one saved at the best check, if there is one.
+    fn restore_best<T: Float>(&mut self, nn: &mut NeuralNetwork<T>) -> bool {
+        match self.state.best_model.take() {
+            Some(best_model) => {
+                *nn = best_model
+                    .into_neural_network()
+                    .expect("a model saved during training should load");
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationFunction;
+    use crate::builder::NeuralNetworkBuilder;
+    use crate::cost::CostFunc;
+    use crate::initializer::Initializer;
+
+    fn get_nn_for_test() -> NeuralNetwork {
+        NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(CostFunc::QuadraticCost)
+            .with_seed(1)
+            .build()
+    }
+
+    #[test]
+    fn only_improvements_by_more_than_min_delta_reset_the_patience() {
+        let nn = get_nn_for_test();
+        let mut tracker = EarlyStopTracker::new(EarlyStopMonitor::Cost, 0.1);
+
+        assert!(tracker.observe(1.0, &nn, false));
+        assert!(!tracker.observe(0.95, &nn, false));
+        assert!(!tracker.observe(1.5, &nn, false));
+        assert_eq!(tracker.checks_without_improvement(), 2);
+
+        assert!(tracker.observe(0.8, &nn, false));
+        assert_eq!(tracker.checks_without_improvement(), 0);
+        assert_eq!(tracker.best_value(), Some(0.8));
+    }
+
+    #[test]
+    fn non_finite_values_are_never_the_best() {
+        let nn = get_nn_for_test();
+        let mut tracker = EarlyStopTracker::new(EarlyStopMonitor::Cost, 0.0);
+
+        assert!(!tracker.observe(f64::NAN, &nn, false));
+        assert!(tracker.observe(1.0, &nn, false));
+        assert!(!tracker.observe(f64::NAN, &nn, false));
+        assert!(!tracker.observe(f64::NEG_INFINITY, &nn, false));
+        assert_eq!(tracker.best_value(), Some(1.0));
+        assert_eq!(tracker.checks_without_improvement(), 2);
+
+        assert!(tracker.observe(0.5, &nn, false));
+    }
+
+    #[test]
+    fn higher_accuracy_is_an_improvement() {
+        let monitor = EarlyStopMonitor::Accuracy;
+        assert!(monitor.improves_on(0.9, 0.8, 0.05));
+        assert!(!monitor.improves_on(0.82, 0.8, 0.05));
+        assert!(!monitor.improves_on(0.7, 0.8, 0.0));
+    }
+
+    #[test]
+    fn restores_the_network_from_the_best_check() {
+        let mut nn = get_nn_for_test();
+        let best_weights = nn.w.clone();
+        let mut tracker = EarlyStopTracker::new(EarlyStopMonitor::Cost, 0.0);
+        tracker.observe(0.5, &nn, true);
+
+        nn.w.get_mut(&1).unwrap().data[0] += 1.0;
+        tracker.observe(0.7, &nn, true);
+
+        assert!(tracker.restore_best(&mut nn));
+        assert_eq!(nn.w, best_weights);
+    }
+}
@@ -1,10 +1,17 @@
 use std::fmt;
 
+use common::linalg::MatrixShape;
+
+use crate::health::HealthProblem;
+
 #[derive(Debug)]
 pub enum NeuralNetworkError {
     IndexOutOfBoundsError(IndexOutOfBoundsError),
     InvalidLayerIndex(InvalidLayerIndex),
     VectorDimensionMismatch(VectorDimensionMismatch),
+    InvalidConfiguration(BuilderError),
+    InvalidTrainingOptions(TrainingOptionsError),
+    UnhealthyTraining(HealthCheckFailure),
 }
 
 impl fmt::Display for NeuralNetworkError {
@@ -19,12 +26,100 @@ impl fmt::Display for NeuralNetworkError {
             NeuralNetworkError::VectorDimensionMismatch(e) => {
                 write!(f, "Neural Network Error - VectorDimensionMismatch: {}", e)
             }
+            NeuralNetworkError::InvalidConfiguration(e) => {
+                write!(f, "Neural Network Error - InvalidConfiguration: {}", e)
+            }
+            NeuralNetworkError::InvalidTrainingOptions(e) => {
+                write!(f, "Neural Network Error - InvalidTrainingOptions: {}", e)
+            }
+            NeuralNetworkError::UnhealthyTraining(e) => {
+                write!(f, "Neural Network Error - UnhealthyTraining: {}", e)
+            }
         }
     }
 }
 
 impl std::error::Error for NeuralNetworkError {}
 
+impl From<BuilderError> for NeuralNetworkError {
+    fn from(e: BuilderError) -> Self {
+        NeuralNetworkError::InvalidConfiguration(e)
+    }
+}
+
+impl From<TrainingOptionsError> for NeuralNetworkError {
+    fn from(e: TrainingOptionsError) -> Self {
+        NeuralNetworkError::InvalidTrainingOptions(e)
+    }
+}
+
+/// The reasons `TrainingOptions::validate`, or `train_stochastic` itself, can reject how to train before training starts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrainingOptionsError {
+    /// See `LearningRateSchedule::is_valid`.
+    InvalidLearningRateSchedule,
+    /// See `GradientClipping::is_valid`.
+    InvalidGradientClipping,
+    /// The mini batch size passed to `train_stochastic` must be at least 1.
+    InvalidMiniBatchSize,
+    /// `FullCostUpdates` has to compute the full cost update every 1 or more epochs.
+    InvalidFullCostUpdateEvery,
+    /// `TrainingSessionLogger::with_checkpoint_every` has to write a checkpoint every 1 or more epochs.
+    InvalidCheckpointEvery,
+    /// See `EarlyStopConfig::is_valid`.
+    InvalidEarlyStopConfig,
+}
+
+impl fmt::Display for TrainingOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrainingOptionsError::InvalidLearningRateSchedule => write!(
+                f,
+                "The learning rate schedule's StepDecay every and CosineWithWarmRestarts period must be at least 1, and a ReduceOnPlateau factor must be above 0 and at most 1, its min_factor from 0 to 1 and its min_delta at least 0"
+            ),
+            TrainingOptionsError::InvalidGradientClipping => write!(
+                f,
+                "The gradient clipping max_value and max_norm must be positive and finite"
+            ),
+            TrainingOptionsError::InvalidMiniBatchSize => {
+                write!(f, "The mini batch size must be at least 1")
+            }
+            TrainingOptionsError::InvalidFullCostUpdateEvery => {
+                write!(f, "full_cost_update_every must be at least 1")
+            }
+            TrainingOptionsError::InvalidCheckpointEvery => {
+                write!(f, "checkpoint_every must be at least 1")
+            }
+            TrainingOptionsError::InvalidEarlyStopConfig => {
+                write!(f, "The early stopping check_every must be at least 1")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrainingOptionsError {}
+
+/// A health check failed while training (see `HealthChecks`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthCheckFailure {
+    pub epochs_completed: usize,
+    /// The number of steps completed before the one that failed.
+    pub steps_completed: usize,
+    pub problem: HealthProblem,
+}
+
+impl fmt::Display for HealthCheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "health check failed at step {} (epoch {}): {}",
+            self.steps_completed, self.epochs_completed, self.problem
+        )
+    }
+}
+
+impl std::error::Error for HealthCheckFailure {}
+
 #[derive(Debug)]
 pub struct IndexOutOfBoundsError(pub usize);
 
@@ -87,3 +182,132 @@ impl fmt::Display for VectorDimensionMismatch {
 }
 
 impl std::error::Error for VectorDimensionMismatch {}
+
+/// The reasons `NeuralNetworkBuilder::try_build` can reject a network configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuilderError {
+    MissingInputLayer,
+    /// A hidden or output layer was added before `with_input_layer` was called.
+    LayerAddedBeforeInputLayer,
+    /// `with_regularization` was called before any hidden or output layer was added.
+    RegularizationBeforeAnyLayer,
+    /// The L1 and L2 penalties must be at least 0.
+    InvalidRegularization {
+        layer_index: usize,
+    },
+    /// `with_dropout` was called when the layer added last wasn't a hidden layer.
+    DropoutNotAfterHiddenLayer,
+    /// A dropout rate must be at least 0 and less than 1.
+    InvalidDropoutRate {
+        layer_index: usize,
+        rate: f64,
+    },
+    /// `with_normalization` was called when the layer added last wasn't a hidden layer.
+    NormalizationNotAfterHiddenLayer,
+    /// A normalization's epsilon must be positive, and a batch norm momentum at least 0 and less than 1.
+    InvalidNormalization {
+        layer_index: usize,
+    },
+    MissingOutputLayer,
+    MissingCostFunction,
+    EmptyLayer {
+        layer_index: usize,
+    },
+    /// An `Initializer::Manual` weights matrix doesn't have the shape (size of the layer) x (size of the previous layer).
+    InvalidWeightsMatrixShape {
+        layer_index: usize,
+        expected: MatrixShape,
+        actual: MatrixShape,
+    },
+    /// An `Initializer::Manual` bias vector doesn't have one element per neuron in the layer.
+    InvalidBiasVectorLength {
+        layer_index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// Softmax has no element-wise derivative, so it can only be used in the output layer.
+    SoftmaxInHiddenLayer {
+        layer_index: usize,
+    },
+    /// Backprop for CrossEntropy is only implemented for a Softmax output layer.
+    CrossEntropyRequiresSoftmaxOutput,
+    /// Backprop for a Softmax output layer is only implemented for the CrossEntropy cost function.
+    SoftmaxOutputRequiresCrossEntropy,
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuilderError::MissingInputLayer => write!(f, "Input layer size not specified"),
+            BuilderError::LayerAddedBeforeInputLayer => write!(
+                f,
+                "Input layer size must be set before adding hidden layers or the output layer"
+            ),
+            BuilderError::RegularizationBeforeAnyLayer => write!(
+                f,
+                "Regularization applies to the layer added last, so a hidden or output layer must be added first"
+            ),
+            BuilderError::InvalidRegularization { layer_index } => write!(
+                f,
+                "The L1 and L2 penalties for layer {} must be at least 0",
+                layer_index
+            ),
+            BuilderError::DropoutNotAfterHiddenLayer => write!(
+                f,
+                "Dropout applies to the layer added last, which must be a hidden layer"
+            ),
+            BuilderError::InvalidDropoutRate { layer_index, rate } => write!(
+                f,
+                "The dropout rate for layer {} is {} but must be at least 0 and less than 1",
+                layer_index, rate
+            ),
+            BuilderError::NormalizationNotAfterHiddenLayer => write!(
+                f,
+                "Normalization applies to the layer added last, which must be a hidden layer"
+            ),
+            BuilderError::InvalidNormalization { layer_index } => write!(
+                f,
+                "The normalization for layer {} must have a positive epsilon, and a momentum of at least 0 and less than 1",
+                layer_index
+            ),
+            BuilderError::MissingOutputLayer => write!(f, "Output layer size not specified"),
+            BuilderError::MissingCostFunction => write!(f, "Cost function not specified"),
+            BuilderError::EmptyLayer { layer_index } => {
+                write!(f, "Layer {} must have at least one neuron", layer_index)
+            }
+            BuilderError::InvalidWeightsMatrixShape {
+                layer_index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "The weights matrix for layer {} is {}x{} but should be {}x{} (the size of the layer x the size of the previous layer)",
+                layer_index, actual.rows, actual.columns, expected.rows, expected.columns
+            ),
+            BuilderError::InvalidBiasVectorLength {
+                layer_index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "The bias vector for layer {} has {} elements but should have {} (the size of the layer)",
+                layer_index, actual, expected
+            ),
+            BuilderError::SoftmaxInHiddenLayer { layer_index } => write!(
+                f,
+                "Softmax can only be used in the output layer, not in hidden layer {}",
+                layer_index
+            ),
+            BuilderError::CrossEntropyRequiresSoftmaxOutput => write!(
+                f,
+                "CrossEntropy is only implemented to work with Softmax in the output layer"
+            ),
+            BuilderError::SoftmaxOutputRequiresCrossEntropy => write!(
+                f,
+                "Softmax in the output layer is only implemented to work with CrossEntropy"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
@@ -0,0 +1,387 @@
+use std::fmt;
+
+use common::datapoints::NDTrainingDataPoint;
+use common::linalg::{ColumnVector, Float};
+use rayon::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::VectorDimensionMismatch;
+use crate::NeuralNetwork;
+
+/// Precision, recall and F1 score for a single class.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClassMetrics {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    /// The number of examples whose actual class is this class.
+    pub support: usize,
+}
+
+/// Precision, recall and F1 score averaged across classes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AveragedMetrics {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClassificationReport {
+    pub num_examples: usize,
+    pub num_classes: usize,
+    pub top_1_accuracy: f64,
+    pub top_k: usize,
+    pub top_k_accuracy: f64,
+    /// `confusion_matrix[actual][predicted]` is the number of examples of class `actual` that were predicted as `predicted`.
+    pub confusion_matrix: Vec<Vec<usize>>,
+    pub per_class: Vec<ClassMetrics>,
+    /// The unweighted mean of the per-class metrics.
+    pub macro_avg: AveragedMetrics,
+    /// Computed from the true/false positive/negative counts summed across classes.
+    /// For single-label classification, these are all equal to the top-1 accuracy.
+    pub micro_avg: AveragedMetrics,
+}
+
+/// Returns the class indices ordered from most to least likely according to `output_v`.
+///
+/// A single output neuron is treated as a binary classifier (class 1 if the output is >= 0.5, otherwise class 0),
+/// otherwise each output neuron corresponds to a class.
+fn ranked_classes<T: Float>(output_v: &ColumnVector<T>) -> Vec<usize> {
+    if output_v.num_elements() == 1 {
+        if output_v.get(0) >= T::from_f64(0.5) {
+            vec![1, 0]
+        } else {
+            vec![0, 1]
+        }
+    } else {
+        let mut classes: Vec<usize> = (0..output_v.num_elements()).collect();
+        // stable sort, so ties go to the lower class index
+        classes.sort_by(|&i, &j| {
+            output_v
+                .get(j)
+                .as_f64()
+                .total_cmp(&output_v.get(i).as_f64())
+        });
+        classes
+    }
+}
+
+/// Returns the class for the given desired output, which is either one-hot or, for a single output neuron, 0 or 1.
+fn class_of<T: Float>(desired_output_v: &ColumnVector<T>) -> usize {
+    ranked_classes(desired_output_v)[0]
+}
+
+fn num_classes_for_output_size(output_size: usize) -> usize {
+    if output_size == 1 {
+        2
+    } else {
+        output_size
+    }
+}
+
+fn safe_divide(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+fn f1_score(precision: f64, recall: f64) -> f64 {
+    safe_divide(2.0 * precision * recall, precision + recall)
+}
+
+impl ClassificationReport {
+    /// Builds the report from (actual class, classes ranked by predicted likelihood) pairs.
+    fn from_ranked_predictions(
+        num_classes: usize,
+        top_k: usize,
+        predictions: &[(usize, Vec<usize>)],
+    ) -> Self {
+        let num_examples = predictions.len();
+        let mut confusion_matrix = vec![vec![0usize; num_classes]; num_classes];
+        let mut top_k_correct = 0;
+
+        for (actual, ranked) in predictions.iter() {
+            confusion_matrix[*actual][ranked[0]] += 1;
+            if ranked.iter().take(top_k).any(|c| c == actual) {
+                top_k_correct += 1;
+            }
+        }
+
+        let mut per_class = Vec::new();
+        let mut total_tp = 0;
+        let mut total_fp = 0;
+        let mut total_fn = 0;
+
+        for c in 0..num_classes {
+            let tp = confusion_matrix[c][c];
+            let support: usize = confusion_matrix[c].iter().sum();
+            let predicted: usize = confusion_matrix.iter().map(|row| row[c]).sum();
+            let fp = predicted - tp;
+            let fn_ = support - tp;
+
+            total_tp += tp;
+            total_fp += fp;
+            total_fn += fn_;
+
+            let precision = safe_divide(tp as f64, predicted as f64);
+            let recall = safe_divide(tp as f64, support as f64);
+
+            per_class.push(ClassMetrics {
+                precision,
+                recall,
+                f1: f1_score(precision, recall),
+                support,
+            });
+        }
+
+        let macro_avg = AveragedMetrics {
+            precision: per_class.iter().map(|m| m.precision).sum::<f64>() / num_classes as f64,
+            recall: per_class.iter().map(|m| m.recall).sum::<f64>() / num_classes as f64,
+            f1: per_class.iter().map(|m| m.f1).sum::<f64>() / num_classes as f64,
+        };
+
+        let micro_precision = safe_divide(total_tp as f64, (total_tp + total_fp) as f64);
+        let micro_recall = safe_divide(total_tp as f64, (total_tp + total_fn) as f64);
+        let micro_avg = AveragedMetrics {
+            precision: micro_precision,
+            recall: micro_recall,
+            f1: f1_score(micro_precision, micro_recall),
+        };
+
+        Self {
+            num_examples,
+            num_classes,
+            top_1_accuracy: safe_divide(total_tp as f64, num_examples as f64),
+            top_k,
+            top_k_accuracy: safe_divide(top_k_correct as f64, num_examples as f64),
+            confusion_matrix,
+            per_class,
+            macro_avg,
+            micro_avg,
+        }
+    }
+}
+
+impl fmt::Display for ClassificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "examples: {}", self.num_examples)?;
+        writeln!(f, "top-1 accuracy: {:.4}", self.top_1_accuracy)?;
+        writeln!(f, "top-{} accuracy: {:.4}", self.top_k, self.top_k_accuracy)?;
+
+        writeln!(f, "\nclass  precision  recall     f1         support")?;
+        for (c, m) in self.per_class.iter().enumerate() {
+            writeln!(
+                f,
+                "{:<6} {:<10.4} {:<10.4} {:<10.4} {}",
+                c, m.precision, m.recall, m.f1, m.support
+            )?;
+        }
+        writeln!(
+            f,
+            "{:<6} {:<10.4} {:<10.4} {:<10.4}",
+            "macro", self.macro_avg.precision, self.macro_avg.recall, self.macro_avg.f1
+        )?;
+        writeln!(
+            f,
+            "{:<6} {:<10.4} {:<10.4} {:<10.4}",
+            "micro", self.micro_avg.precision, self.micro_avg.recall, self.micro_avg.f1
+        )?;
+
+        writeln!(f, "\nconfusion matrix (rows: actual, columns: predicted)")?;
+        for row in self.confusion_matrix.iter() {
+            let row_str = row
+                .iter()
+                .map(|count| format!("{:>6}", count))
+                .collect::<Vec<String>>()
+                .join("");
+            writeln!(f, "{}", row_str)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Float> NeuralNetwork<T> {
+    /// Feeds each example forward (in parallel) and compares the predicted class to the desired one.
+    /// `top_k` is the number of most likely predicted classes that count as a hit for `top_k_accuracy`.
+    pub fn evaluate_classification(
+        &self,
+        data: &[NDTrainingDataPoint<T>],
+        top_k: usize,
+    ) -> Result<ClassificationReport, VectorDimensionMismatch> {
+        let output_size = self.sizes[self.output_layer_index()];
+        let num_classes = num_classes_for_output_size(output_size);
+
+        let predictions = data
+            .par_iter()
+            .map(|tr_ex| {
+                if tr_ex.input_v.num_elements() != self.sizes[0] {
+                    return Err(VectorDimensionMismatch::new_with_msg(
+                        tr_ex.input_v.num_elements(),
+                        self.sizes[0],
+                        "input_v must have the same number of elements as the number of neurons in the input layer",
+                    ));
+                }
+                if tr_ex.desired_output_v.num_elements() != output_size {
+                    return Err(VectorDimensionMismatch::new_with_msg(
+                        tr_ex.desired_output_v.num_elements(),
+                        output_size,
+                        "desired_output_v must have the same number of elements as the number of neurons in the output layer",
+                    ));
+                }
+
+                let output_v = self.feed_forward(&tr_ex.input_v);
+                Ok((class_of(&tr_ex.desired_output_v), ranked_classes(&output_v)))
+            })
+            .collect::<Result<Vec<(usize, Vec<usize>)>, VectorDimensionMismatch>>()?;
+
+        Ok(ClassificationReport::from_ranked_predictions(
+            num_classes,
+            top_k,
+            &predictions,
+        ))
+    }
+
+    /// The fraction of examples whose most likely predicted class is the desired class.
+    pub fn accuracy(
+        &self,
+        data: &[NDTrainingDataPoint<T>],
+    ) -> Result<f64, VectorDimensionMismatch> {
+        Ok(self.evaluate_classification(data, 1)?.top_1_accuracy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationFunction;
+    use crate::builder::NeuralNetworkBuilder;
+    use crate::cost::CostFunc;
+    use crate::initializer::Initializer;
+    use common::column_vector;
+    use common::linalg::RowsMatrixBuilder;
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn ranked_classes_works() {
+        assert_eq!(
+            ranked_classes(&column_vector![0.1, 0.7, 0.2]),
+            vec![1, 2, 0]
+        );
+        assert_eq!(ranked_classes(&column_vector![0.5, 0.5]), vec![0, 1]);
+        assert_eq!(ranked_classes(&column_vector![0.9]), vec![1, 0]);
+        assert_eq!(ranked_classes(&column_vector![0.1]), vec![0, 1]);
+    }
+
+    #[test]
+    fn report_from_ranked_predictions() {
+        // 3 classes, 6 examples
+        let predictions = vec![
+            (0, vec![0, 1, 2]), // correct
+            (0, vec![1, 0, 2]), // wrong, but in top 2
+            (1, vec![1, 2, 0]), // correct
+            (1, vec![1, 0, 2]), // correct
+            (2, vec![0, 1, 2]), // wrong, not in top 2
+            (2, vec![2, 0, 1]), // correct
+        ];
+
+        let report = ClassificationReport::from_ranked_predictions(3, 2, &predictions);
+
+        assert_eq!(report.num_examples, 6);
+        assert_eq!(
+            report.confusion_matrix,
+            vec![vec![1, 1, 0], vec![0, 2, 0], vec![1, 0, 1]]
+        );
+        assert!(approx_eq!(f64, report.top_1_accuracy, 4.0 / 6.0));
+        assert!(approx_eq!(f64, report.top_k_accuracy, 5.0 / 6.0));
+
+        // class 0: tp 1, predicted 2, support 2
+        assert!(approx_eq!(f64, report.per_class[0].precision, 0.5));
+        assert!(approx_eq!(f64, report.per_class[0].recall, 0.5));
+        assert!(approx_eq!(f64, report.per_class[0].f1, 0.5));
+        assert_eq!(report.per_class[0].support, 2);
+
+        // class 1: tp 2, predicted 3, support 2
+        assert!(approx_eq!(f64, report.per_class[1].precision, 2.0 / 3.0));
+        assert!(approx_eq!(f64, report.per_class[1].recall, 1.0));
+        assert!(approx_eq!(f64, report.per_class[1].f1, 0.8));
+
+        // class 2: tp 1, predicted 1, support 2
+        assert!(approx_eq!(f64, report.per_class[2].precision, 1.0));
+        assert!(approx_eq!(f64, report.per_class[2].recall, 0.5));
+        assert!(approx_eq!(f64, report.per_class[2].f1, 2.0 / 3.0));
+
+        assert!(approx_eq!(
+            f64,
+            report.macro_avg.precision,
+            (0.5 + 2.0 / 3.0 + 1.0) / 3.0
+        ));
+        assert!(approx_eq!(f64, report.macro_avg.recall, 2.0 / 3.0));
+
+        // for single-label classification, the micro averages are the accuracy
+        assert!(approx_eq!(f64, report.micro_avg.precision, 4.0 / 6.0));
+        assert!(approx_eq!(f64, report.micro_avg.recall, 4.0 / 6.0));
+        assert!(approx_eq!(f64, report.micro_avg.f1, 4.0 / 6.0));
+    }
+
+    #[test]
+    fn classes_without_predictions_have_zero_precision() {
+        let predictions = vec![(0, vec![0, 1]), (1, vec![0, 1])];
+        let report = ClassificationReport::from_ranked_predictions(2, 1, &predictions);
+        assert_eq!(report.per_class[1].precision, 0.0);
+        assert_eq!(report.per_class[1].recall, 0.0);
+        assert_eq!(report.per_class[1].f1, 0.0);
+    }
+
+    #[test]
+    fn evaluate_classification_uses_feed_forward() {
+        // the output layer just passes the inputs through, so the predicted class is the largest input
+        let nn = NeuralNetworkBuilder::new()
+            .with_input_layer(3)
+            .with_output_layer(
+                3,
+                Initializer::Manual(
+                    RowsMatrixBuilder::new()
+                        .with_row(&[1.0, 0.0, 0.0])
+                        .with_row(&[0.0, 1.0, 0.0])
+                        .with_row(&[0.0, 0.0, 1.0])
+                        .build(),
+                    column_vector![0.0, 0.0, 0.0],
+                ),
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(CostFunc::CrossEntropy)
+            .build();
+
+        let data = vec![
+            NDTrainingDataPoint::new(column_vector![3.0, 1.0, 0.0], column_vector![1.0, 0.0, 0.0]),
+            NDTrainingDataPoint::new(column_vector![0.0, 3.0, 1.0], column_vector![0.0, 1.0, 0.0]),
+            NDTrainingDataPoint::new(column_vector![0.0, 1.0, 3.0], column_vector![1.0, 0.0, 0.0]),
+            NDTrainingDataPoint::new(column_vector![0.0, 0.0, 3.0], column_vector![0.0, 0.0, 1.0]),
+        ];
+
+        let report = nn.evaluate_classification(&data, 2).unwrap();
+        assert_eq!(report.num_classes, 3);
+        assert_eq!(
+            report.confusion_matrix,
+            vec![vec![1, 0, 1], vec![0, 1, 0], vec![0, 0, 1]]
+        );
+        assert_eq!(report.top_1_accuracy, 0.75);
+        assert_eq!(report.top_k_accuracy, 0.75);
+
+        assert_eq!(nn.accuracy(&data).unwrap(), 0.75);
+    }
+
+    #[test]
+    fn evaluate_classification_returns_error_on_dimension_mismatch() {
+        let nn = NeuralNetwork::new(vec![2, 3, 2]);
+        let data = vec![NDTrainingDataPoint::new(
+            column_vector![1.0, 2.0, 3.0],
+            column_vector![1.0, 0.0],
+        )];
+        assert!(nn.evaluate_classification(&data, 1).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! Periodic full cost updates: every few epochs, the cost and accuracy across the whole of each dataset, which are a
+//! much better guide to how training is going than the mini batch losses.
+
+use common::linalg::Float;
+
+use crate::callbacks::{EpochEnd, TrainBegin, TrainingCallback, TrainingControl};
+use crate::data_split::Datasets;
+use crate::errors::{NeuralNetworkError, TrainingOptionsError};
+use crate::training_log::FullCostUpdate;
+use crate::NeuralNetwork;
+
+/// Computes a `FullCostUpdate` across the training set, and the validation and test sets if there are any, every
+/// `every` epochs. It's logged, and filled in on the `EpochEnd` for the callbacks after this one, e.g. a
+/// `TrainingSessionLogger` to write it out. An `every` of 0 is rejected when training starts.
+pub struct FullCostUpdates<'a, T = f64> {
+    datasets: Datasets<'a, T>,
+    every: usize,
+}
+
+impl<'a, T: Float> FullCostUpdates<'a, T> {
+    pub fn new(datasets: Datasets<'a, T>, every: usize) -> Self {
+        Self { datasets, every }
+    }
+}
+
+impl<'a, T: Float> TrainingCallback<T> for FullCostUpdates<'a, T> {
+    fn on_train_begin(
+        &mut self,
+        nn: &NeuralNetwork<T>,
+        _train_begin: &TrainBegin<T>,
+    ) -> Result<(), NeuralNetworkError> {
+        if self.every == 0 {
+            return Err(TrainingOptionsError::InvalidFullCostUpdateEvery.into());
+        }
+
+        let Datasets {
+            training,
+            validation,
+            test,
+        } = self.datasets;
+        for data in [Some(training), validation, test].into_iter().flatten() {
+            nn.check_dimensions(data)
+                .map_err(NeuralNetworkError::VectorDimensionMismatch)?;
+        }
+        Ok(())
+    }
+
+    fn on_epoch_end(&mut self, nn: &NeuralNetwork<T>, epoch: &mut EpochEnd) -> TrainingControl {
+        if !epoch.epochs_completed.is_multiple_of(self.every) {
+            return TrainingControl::Continue;
+        }
+
+        let epochs_completed = epoch.epochs_completed;
+        let metrics = |set_name, data| {
+            nn.full_cost_update(set_name, data, epochs_completed)
+                .expect("the datasets' dimensions were checked in on_train_begin")
+        };
+        let full_cost_update = FullCostUpdate {
+            epochs_completed,
+            steps_completed: epoch.steps_completed,
+            learning_rate: epoch.learning_rate,
+            gradient_norm: epoch.gradient_norm,
+            training_set: metrics("training", self.datasets.training),
+            validation_set: self
+                .datasets
+                .validation
+                .map(|data| metrics("validation", data)),
+            test_set: self.datasets.test.map(|data| metrics("test", data)),
+        };
+        epoch.validation_cost = full_cost_update.validation_set.map(|m| m.cost);
+        epoch.full_cost_update = Some(full_cost_update);
+        TrainingControl::Continue
+    }
+}
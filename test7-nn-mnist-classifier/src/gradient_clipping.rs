@@ -0,0 +1,141 @@
+//! Gradient clipping, so that a single huge gradient (e.g. from badly initialized weights) can't throw the weights so
+//! far that they overflow to infinity or NaN.
+//!
+//! Value clipping clamps each element of the gradients. Norm clipping scales all of the gradients down together when
+//! their global L2 norm, taken across the whole `BigTheta`, is too large, which keeps their direction.
+
+use serde_derive::{Deserialize, Serialize};
+
+use common::linalg::Float;
+
+use crate::big_theta::BigTheta;
+
+/// How to clip the gradients before each optimizer step. Nothing is clipped by default. With both set, the values are
+/// clipped first and then the norm.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GradientClipping {
+    /// Clamps each element of the gradients to `[-max_value, max_value]`.
+    #[serde(default)]
+    pub max_value: Option<f64>,
+    /// Scales the gradients down so that their global L2 norm is at most `max_norm`.
+    #[serde(default)]
+    pub max_norm: Option<f64>,
+}
+
+impl GradientClipping {
+    pub fn by_value(max_value: f64) -> Self {
+        Self {
+            max_value: Some(max_value),
+            max_norm: None,
+        }
+    }
+
+    pub fn by_norm(max_norm: f64) -> Self {
+        Self {
+            max_value: None,
+            max_norm: Some(max_norm),
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.max_value.is_none() && self.max_norm.is_none()
+    }
+
+    /// `max_value` and `max_norm` have to be positive and finite when set.
+    pub fn is_valid(&self) -> bool {
+        let is_valid = |max: Option<f64>| max.is_none_or(|max| max.is_finite() && max > 0.0);
+        is_valid(self.max_value) && is_valid(self.max_norm)
+    }
+
+    /// Clips `gradients` in place, and returns their global L2 norm from before they were clipped (which is worth
+    /// logging either way, since it shows when training is becoming unstable). NaN gradients are left as NaN rather
+    /// than being clipped into finite ones, so the health checks still catch them.
+    pub fn clip<T: Float>(&self, gradients: &mut BigTheta<T>) -> f64 {
+        let norm = gradients.l2_norm();
+
+        if let Some(max_value) = self.max_value {
+            gradients.clamp_in_place(T::from_f64(max_value));
+        }
+
+        if let Some(max_norm) = self.max_norm {
+            // value clipping may have already brought the norm down
+            let norm = if self.max_value.is_some() {
+                gradients.l2_norm()
+            } else {
+                norm
+            };
+            if norm > max_norm {
+                gradients.mult_scalar_in_place(T::from_f64(max_norm / norm));
+            }
+        }
+
+        norm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradients() -> BigTheta {
+        // a 1-2 network: 2 weights and 2 biases, with a norm of 10
+        BigTheta::from_unrolled(&[1, 2], &[5.0, -5.0, 1.0, -7.0])
+    }
+
+    #[test]
+    fn no_clipping_leaves_the_gradients_alone() {
+        let mut g = gradients();
+        assert_eq!(GradientClipping::default().clip(&mut g), 10.0);
+        assert_eq!(g, gradients());
+    }
+
+    #[test]
+    fn value_clipping_clamps_each_element() {
+        let mut g = gradients();
+        assert_eq!(GradientClipping::by_value(6.0).clip(&mut g), 10.0);
+        assert_eq!(g.unroll(), vec![5.0, -5.0, 1.0, -6.0]);
+    }
+
+    #[test]
+    fn norm_clipping_scales_down_only_when_the_norm_is_too_large() {
+        let mut g = gradients();
+        assert_eq!(GradientClipping::by_norm(5.0).clip(&mut g), 10.0);
+        assert_eq!(g.unroll(), vec![2.5, -2.5, 0.5, -3.5]);
+        assert_eq!(g.l2_norm(), 5.0);
+
+        let mut g = gradients();
+        GradientClipping::by_norm(20.0).clip(&mut g);
+        assert_eq!(g, gradients());
+    }
+
+    #[test]
+    fn nan_gradients_are_not_clipped_into_finite_ones() {
+        let mut g = BigTheta::from_unrolled(&[1, 2], &[5.0, f64::NAN, 1.0, -7.0]);
+        let clipping = GradientClipping {
+            max_value: Some(6.0),
+            max_norm: Some(5.0),
+        };
+        assert!(clipping.clip(&mut g).is_nan());
+        let unrolled = g.unroll();
+        assert!(unrolled[1].is_nan());
+        assert_eq!(unrolled[3], -6.0);
+    }
+
+    #[test]
+    fn limits_must_be_positive_and_finite() {
+        assert!(GradientClipping::default().is_valid());
+        assert!(GradientClipping::by_norm(1.0).is_valid());
+        assert!(!GradientClipping::by_norm(0.0).is_valid());
+        assert!(!GradientClipping::by_value(-1.0).is_valid());
+        assert!(!GradientClipping::by_value(f64::NAN).is_valid());
+        assert!(!GradientClipping::by_norm(f64::INFINITY).is_valid());
+    }
+
+    #[test]
+    fn clipping_can_be_read_from_toml() {
+        let clipping: GradientClipping = toml::from_str("max_norm = 1.0").unwrap();
+        assert_eq!(clipping, GradientClipping::by_norm(1.0));
+        assert!(toml::from_str::<GradientClipping>("max_nrom = 1.0").is_err());
+    }
+}
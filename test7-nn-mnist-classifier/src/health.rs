@@ -0,0 +1,257 @@
+//! Health checks run after each training step, to catch training that has diverged (weights, gradients or activations
+//! that have overflowed to infinity or NaN) or stalled (ReLU units that have died, i.e. output zero for every example),
+//! rather than carrying on and printing NaN costs.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+use common::linalg::{ColumnVector, Float, Matrix};
+
+use crate::big_theta::BigTheta;
+use crate::{LayerIndex, NeuralNetwork};
+
+/// Which health checks to run after each training step, and what to do when one fails. Non-finite weights, gradients
+/// and activations always fail the checks; dead units only do with a `max_dead_fraction`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HealthChecks {
+    /// Fails the checks when more than this fraction of a hidden layer's units are dead for a whole mini batch.
+    #[serde(default)]
+    pub max_dead_fraction: Option<f64>,
+    #[serde(default)]
+    pub on_failure: OnHealthCheckFailure,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum OnHealthCheckFailure {
+    /// Stops training with a `NeuralNetworkError::UnhealthyTraining`.
+    #[default]
+    Abort,
+    /// Restores the network and training state from the start of the epoch, and carries on with the learning rate
+    /// multiplied by `learning_rate_factor` (for the rest of the run). The RNG isn't rolled back, so the epoch is retried
+    /// with different mini batches. Aborts once it has rolled back `max_rollbacks` times.
+    RollBack {
+        max_rollbacks: usize,
+        #[serde(default = "default_rollback_learning_rate_factor")]
+        learning_rate_factor: f64,
+    },
+}
+
+fn default_rollback_learning_rate_factor() -> f64 {
+    0.5
+}
+
+/// What a failed health check found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthProblem {
+    NonFiniteActivations {
+        layer_index: LayerIndex,
+    },
+    NonFiniteGradients {
+        layer_index: LayerIndex,
+    },
+    NonFiniteWeights {
+        layer_index: LayerIndex,
+    },
+    /// `dead_units` of the layer's `size` units output zero for every example in the mini batch.
+    DeadUnits {
+        layer_index: LayerIndex,
+        dead_units: usize,
+        size: usize,
+    },
+}
+
+impl fmt::Display for HealthProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HealthProblem::NonFiniteActivations { layer_index } => {
+                write!(f, "layer {} has NaN or infinite activations", layer_index)
+            }
+            HealthProblem::NonFiniteGradients { layer_index } => {
+                write!(f, "layer {} has NaN or infinite gradients", layer_index)
+            }
+            HealthProblem::NonFiniteWeights { layer_index } => write!(
+                f,
+                "layer {} has NaN or infinite weights, biases, scales or shifts",
+                layer_index
+            ),
+            HealthProblem::DeadUnits {
+                layer_index,
+                dead_units,
+                size,
+            } => write!(
+                f,
+                "{} of the {} units in layer {} are dead",
+                dead_units, size, layer_index
+            ),
+        }
+    }
+}
+
+/// What the health checks need to know about a layer's activations for a mini batch, before any dropout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ActivationSummary {
+    pub non_finite: bool,
+    /// The number of units whose activation was zero for every example.
+    pub dead_units: usize,
+}
+
+impl ActivationSummary {
+    /// Summarizes a layer's activations, one column per example.
+    pub(crate) fn of<T: Float>(activations: &Matrix<T>) -> Self {
+        let rows = activations.data.chunks(activations.num_columns());
+        Self {
+            non_finite: activations.data.iter().any(|a| !a.is_finite()),
+            dead_units: rows
+                .filter(|row| row.iter().all(|a| *a == T::zero()))
+                .count(),
+        }
+    }
+}
+
+impl HealthChecks {
+    /// Checks a mini batch's activations, then its gradients (before any clipping), before they're used to update the
+    /// network. Returns the first problem found.
+    pub(crate) fn check_mini_batch<T: Float>(
+        &self,
+        nn: &NeuralNetwork<T>,
+        activation_summaries: &HashMap<LayerIndex, ActivationSummary>,
+        gradients: &BigTheta<T>,
+    ) -> Option<HealthProblem> {
+        for l in 1..nn.num_layers() {
+            if activation_summaries[&l].non_finite {
+                return Some(HealthProblem::NonFiniteActivations { layer_index: l });
+            }
+        }
+
+        if let Some(max_dead_fraction) = self.max_dead_fraction {
+            for l in 1..nn.output_layer_index() {
+                let dead_units = activation_summaries[&l].dead_units;
+                let size = nn.sizes[l];
+                if dead_units as f64 > max_dead_fraction * size as f64 {
+                    return Some(HealthProblem::DeadUnits {
+                        layer_index: l,
+                        dead_units,
+                        size,
+                    });
+                }
+            }
+        }
+
+        let vectors = [
+            &gradients.bias_vectors,
+            &gradients.scale_vectors,
+            &gradients.shift_vectors,
+        ];
+        if let Some(layer_index) =
+            first_non_finite_layer(nn.num_layers(), &gradients.weights_matrices, vectors)
+        {
+            return Some(HealthProblem::NonFiniteGradients { layer_index });
+        }
+
+        None
+    }
+
+    /// Checks the network's weights, biases, scales and shifts after an update.
+    pub(crate) fn check_parameters<T: Float>(
+        &self,
+        nn: &NeuralNetwork<T>,
+    ) -> Option<HealthProblem> {
+        let vectors = [&nn.b, &nn.scales, &nn.shifts];
+        first_non_finite_layer(nn.num_layers(), &nn.w, vectors)
+            .map(|layer_index| HealthProblem::NonFiniteWeights { layer_index })
+    }
+}
+
+/// The first layer with a NaN or infinite weight or vector element, if any.
+fn first_non_finite_layer<T: Float>(
+    num_layers: usize,
+    weights: &HashMap<LayerIndex, Matrix<T>>,
+    vectors: [&HashMap<LayerIndex, ColumnVector<T>>; 3],
+) -> Option<LayerIndex> {
+    (1..num_layers).find(|l| {
+        let weights_are_finite = weights[l].data.iter().all(|x| x.is_finite());
+        let vectors_are_finite = vectors
+            .iter()
+            .filter_map(|v| v.get(l))
+            .all(|v| v.iter().all(|x| x.is_finite()));
+        !(weights_are_finite && vectors_are_finite)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::linalg::RowsMatrixBuilder;
+
+    #[test]
+    fn activation_summary_counts_units_that_are_zero_for_every_example() {
+        let activations = RowsMatrixBuilder::new()
+            .with_row(&[0.0, 0.0, 0.0])
+            .with_row(&[0.0, 1.5, 0.0])
+            .with_row(&[0.0, 0.0, 0.0])
+            .build();
+        assert_eq!(
+            ActivationSummary::of(&activations),
+            ActivationSummary {
+                non_finite: false,
+                dead_units: 2
+            }
+        );
+
+        let activations = RowsMatrixBuilder::new()
+            .with_row(&[0.0, f64::NAN])
+            .with_row(&[1.0, 2.0])
+            .build();
+        assert!(ActivationSummary::of(&activations).non_finite);
+    }
+
+    #[test]
+    fn finds_the_first_layer_with_non_finite_values() {
+        let mut big_theta = BigTheta::<f64>::zero_from_sizes(&[2, 3, 2, 1]);
+        let first_non_finite = |big_theta: &BigTheta| {
+            first_non_finite_layer(
+                4,
+                &big_theta.weights_matrices,
+                [
+                    &big_theta.bias_vectors,
+                    &big_theta.scale_vectors,
+                    &big_theta.shift_vectors,
+                ],
+            )
+        };
+        assert_eq!(first_non_finite(&big_theta), None);
+
+        big_theta.get_bias_vector_mut(&3).set(0, f64::INFINITY);
+        assert_eq!(first_non_finite(&big_theta), Some(3));
+        big_theta.get_weights_matrix_mut(&2).data[1] = f64::NAN;
+        assert_eq!(first_non_finite(&big_theta), Some(2));
+    }
+
+    #[test]
+    fn health_checks_can_be_read_from_toml() {
+        let health_checks: HealthChecks = toml::from_str(
+            r#"
+            max_dead_fraction = 0.5
+            on_failure = { RollBack = { max_rollbacks = 3 } }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            health_checks,
+            HealthChecks {
+                max_dead_fraction: Some(0.5),
+                on_failure: OnHealthCheckFailure::RollBack {
+                    max_rollbacks: 3,
+                    learning_rate_factor: 0.5
+                }
+            }
+        );
+        assert_eq!(
+            toml::from_str::<HealthChecks>("").unwrap(),
+            HealthChecks::default()
+        );
+    }
+}
@@ -1,8 +1,11 @@
 use crate::LayerIndex;
-use common::column_vec_of_random_values_from_distribution;
+use common::column_vec_of_random_values_from_distribution_with_rng;
 use common::linalg::{ColumnVector, Matrix};
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use tracing::debug;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Initializer {
     RandomBasic,
     Xavier,
@@ -10,6 +13,8 @@ pub enum Initializer {
     XavierNormalHOMLForSigmoid,
     HeForReLUAndVariants,
     // Random(f64, f64),
+    /// Can't be used in config files.
+    #[serde(skip)]
     Manual(Matrix, ColumnVector),
 }
 
@@ -26,10 +31,12 @@ impl std::fmt::Display for Initializer {
     }
 }
 
-pub fn get_init_weights_and_biases(
+/// Draws the initial weights and biases for layer `l` from `rng`.
+pub fn get_init_weights_and_biases<R: Rng + ?Sized>(
     l: LayerIndex,
     sizes: &[usize],
     initializer: Initializer,
+    rng: &mut R,
 ) -> (Matrix, ColumnVector) {
     if l == 0 {
         panic!("not valid for input layer");
@@ -37,14 +44,16 @@ pub fn get_init_weights_and_biases(
 
     match initializer {
         Initializer::RandomBasic => {
-            let weights_m = Matrix::new_matrix_with_random_values_from_normal_distribution(
+            let weights_m = Matrix::new_matrix_with_random_values_from_normal_distribution_with_rng(
                 sizes[l],
                 sizes[l - 1],
                 0.0,
                 1.0,
+                rng,
             );
 
-            let bias_v = column_vec_of_random_values_from_distribution(0.0, 1.0, sizes[l]);
+            let bias_v =
+                column_vec_of_random_values_from_distribution_with_rng(0.0, 1.0, sizes[l], rng);
 
             (weights_m, bias_v)
         }
@@ -54,12 +63,14 @@ pub fn get_init_weights_and_biases(
             let min = -1.0 / (num_nodes_in_previous_layer as f64).sqrt();
             let max = 1.0 / (num_nodes_in_previous_layer as f64).sqrt();
 
-            let weights_m = Matrix::new_matrix_with_random_values_from_uniform_distribution(
-                sizes[l],
-                sizes[l - 1],
-                min,
-                max,
-            );
+            let weights_m =
+                Matrix::new_matrix_with_random_values_from_uniform_distribution_with_rng(
+                    sizes[l],
+                    sizes[l - 1],
+                    min,
+                    max,
+                    rng,
+                );
 
             let bias_v = ColumnVector::new_zero_vector(sizes[l]);
 
@@ -69,29 +80,28 @@ pub fn get_init_weights_and_biases(
             let num_nodes_in_this_layer = sizes[l];
             let num_nodes_in_next_layer = sizes[l + 1];
 
-            println!(
-                "XavierNormalized: num_nodes_in_this_layer: {}",
-                num_nodes_in_this_layer
-            );
-            println!(
-                "XavierNormalized: num_nodes_in_next_layer: {}",
-                num_nodes_in_next_layer
-            );
-
             let x = 6.0_f64.sqrt()
                 / (num_nodes_in_this_layer as f64 + num_nodes_in_next_layer as f64).sqrt();
             let min = -x;
             let max = x;
 
-            println!("using XavierNormalized with +/- {}", x);
-
-            let weights_m = Matrix::new_matrix_with_random_values_from_uniform_distribution(
-                sizes[l],
-                sizes[l - 1],
-                min,
-                max,
+            debug!(
+                layer = l,
+                num_nodes_in_this_layer,
+                num_nodes_in_next_layer,
+                limit = x,
+                "using XavierNormalized"
             );
 
+            let weights_m =
+                Matrix::new_matrix_with_random_values_from_uniform_distribution_with_rng(
+                    sizes[l],
+                    sizes[l - 1],
+                    min,
+                    max,
+                    rng,
+                );
+
             let bias_v = ColumnVector::new_zero_vector(sizes[l]);
 
             (weights_m, bias_v)
@@ -102,16 +112,17 @@ pub fn get_init_weights_and_biases(
             let fan_avg = (fan_in as f64 + fan_out as f64) / 2.0;
             let std_dev = (1.0_f64 / fan_avg).sqrt(); // See Table 11-1 in HOML
 
-            println!("XavierNormalHOMLForSigmoid: fan_in: {}", fan_in);
-            println!("XavierNormalHOMLForSigmoid: fan_out: {}", fan_out);
-            println!("XavierNormalHOMLForSigmoid: fan_avg: {}", fan_avg);
-            println!("XavierNormalHOMLForSigmoid: using std dev {}", std_dev);
+            debug!(
+                layer = l,
+                fan_in, fan_out, fan_avg, std_dev, "using XavierNormalHOMLForSigmoid"
+            );
 
-            let weights_m = Matrix::new_matrix_with_random_values_from_normal_distribution(
+            let weights_m = Matrix::new_matrix_with_random_values_from_normal_distribution_with_rng(
                 sizes[l],
                 sizes[l - 1],
                 0.0,
                 std_dev,
+                rng,
             );
 
             let bias_v = ColumnVector::new_zero_vector(sizes[l]);
@@ -122,14 +133,14 @@ pub fn get_init_weights_and_biases(
             let fan_in = sizes[l - 1];
             let std_dev = (2.0_f64 / fan_in as f64).sqrt(); // See Table 11-1 in HOML
 
-            println!("HeForReLUAndVariants: fan_in: {}", fan_in);
-            println!("HeForReLUAndVariants: using std dev {}", std_dev);
+            debug!(layer = l, fan_in, std_dev, "using HeForReLUAndVariants");
 
-            let weights_m = Matrix::new_matrix_with_random_values_from_normal_distribution(
+            let weights_m = Matrix::new_matrix_with_random_values_from_normal_distribution_with_rng(
                 sizes[l],
                 sizes[l - 1],
                 0.0,
                 std_dev,
+                rng,
             );
 
             let bias_v = ColumnVector::new_zero_vector(sizes[l]);
@@ -1,10 +1,18 @@
 use crate::activation::ActivationFunction;
+use crate::normalization::Normalization;
+use crate::regularization::Regularization;
 
 #[derive(Debug, Clone)]
 pub struct LayerConfig {
     // Optional because the input layer doesn't have an activation function
     pub activation_function: Option<ActivationFunction>,
     pub initializer: Option<String>,
+    /// The penalty on this layer's weights. None by default.
+    pub regularization: Regularization,
+    /// The fraction of this layer's activations that are dropped while training. Only hidden layers can have one.
+    pub dropout_rate: Option<f64>,
+    /// Batch or layer normalization of the layer's weighted inputs. Only hidden layers can have one.
+    pub normalization: Option<Normalization>,
 }
 
 impl LayerConfig {
@@ -12,6 +20,9 @@ impl LayerConfig {
         Self {
             activation_function,
             initializer: None,
+            regularization: Regularization::default(),
+            dropout_rate: None,
+            normalization: None,
         }
     }
 
@@ -22,6 +33,9 @@ impl LayerConfig {
         Self {
             activation_function,
             initializer,
+            regularization: Regularization::default(),
+            dropout_rate: None,
+            normalization: None,
         }
     }
 }
@@ -1,13 +1,14 @@
 use std::collections::HashMap;
+use std::path::Path;
 
 // use common::activation_functions::{elu, relu, sigmoid, ActivationFunction};
 use activation::{ActivationFunction, VectorActivator};
 use common::column_vec_of_random_values_from_distribution;
 use common::datapoints::NDTrainingDataPoint;
 use metrics::SimpleTimer;
-use rand;
-use rand::Rng;
+use rand::RngCore;
 use rayon::prelude::*;
+use tracing::{debug, info, trace, warn};
 use anyhow;
 
 pub mod builder;
@@ -21,54 +22,109 @@ pub mod big_theta;
 use big_theta::BigTheta;
 
 pub mod training_log;
-use training_log::TrainingSessionLogger;
+use training_log::{SetMetrics, TrainingSessionLogger};
+
+pub mod charts;
 
 pub mod layer_config;
 use layer_config::LayerConfig;
 
+pub mod regularization;
+
+mod dropout;
+
+pub mod normalization;
+use normalization::RunningStatistics;
+
 pub mod cost;
-use cost::quadratic_cost;
+use cost::Coster;
 
 pub mod optimizer;
-use optimizer::Optimizer;
+use optimizer::{Optimizer, OptimizerState};
+
+pub mod saved_model;
+
+pub mod checkpoint;
+use checkpoint::{Checkpoint, TrainingState};
+
+pub mod evaluation;
+
+pub mod config;
+
+pub mod rng;
+
+pub mod mini_batch;
+
+mod batch;
+use batch::MiniBatchGradients;
+use mini_batch::MiniBatchSampling;
+
+pub mod lr_schedule;
+use lr_schedule::LearningRateSchedule;
+
+pub mod gradient_clipping;
+use gradient_clipping::GradientClipping;
+
+pub mod health;
+use health::{HealthChecks, HealthProblem, OnHealthCheckFailure};
+
+pub mod early_stopping;
+use early_stopping::{EarlyStopMonitor, EarlyStopping};
+
+pub mod full_cost_updates;
+use full_cost_updates::FullCostUpdates;
+
+pub mod callbacks;
+use callbacks::{EpochEnd, StepEnd, TrainBegin, TrainingCallback, TrainingControl};
+
+pub mod data_split;
+use data_split::Datasets;
 
 type LayerIndex = usize;
 
-use common::linalg::{euclidian_distance, euclidian_length, ColumnVector, Matrix, MatrixShape};
+use common::linalg::{
+    euclidian_distance, euclidian_length, ColumnVector, Float, Matrix, MatrixShape,
+};
 
-mod errors;
-use errors::{InvalidLayerIndex, NeuralNetworkError, VectorDimensionMismatch};
+pub mod errors;
+use errors::{
+    HealthCheckFailure, InvalidLayerIndex, NeuralNetworkError, TrainingOptionsError,
+    VectorDimensionMismatch,
+};
 
 /// FeedForwardIntermediates is used during the feed-forward of backpropagation.
 /// It contains the intermediate values that are needed during the backward pass to compute gradients.
-struct FeedForwardIntermediates {
-    z_v: ColumnVector,
-    activation_v: ColumnVector,
+struct FeedForwardIntermediates<T = f64> {
+    z_v: ColumnVector<T>,
+    activation_v: ColumnVector<T>,
+    /// The dropout mask that `activation_v` was multiplied by, if any.
+    dropout_mask: Option<ColumnVector<T>>,
 }
 
-impl FeedForwardIntermediates {
+impl<T: Float> FeedForwardIntermediates<T> {
     fn new_from(
-        maybe_z_v: Option<&ColumnVector>,
-        activation_v: &ColumnVector,
-    ) -> FeedForwardIntermediates {
+        maybe_z_v: Option<&ColumnVector<T>>,
+        activation_v: &ColumnVector<T>,
+    ) -> FeedForwardIntermediates<T> {
         let z_v = match maybe_z_v {
             Some(z_v) => z_v.clone(),
-            None => ColumnVector::fill_new(f64::NAN, activation_v.num_elements()),
+            None => ColumnVector::fill_new(T::nan(), activation_v.num_elements()),
         };
 
         FeedForwardIntermediates {
             z_v,
             activation_v: activation_v.clone(),
+            dropout_mask: None,
         }
     }
 }
 
 /// ForwardAndBackPassData encapsulates all the layer-specific data collected during the forward pass as well as the back pass.
-struct ForwardAndBackPassData {
+struct ForwardAndBackPassData<T = f64> {
     /// intermediates is a map from layer index to the intermediates for that layer, computed during the forward pass
-    intermediates: HashMap<LayerIndex, FeedForwardIntermediates>,
+    intermediates: HashMap<LayerIndex, FeedForwardIntermediates<T>>,
     /// error_vectors is a map from layer index to the error vector for that layer, computed during the back pass
-    error_vectors: HashMap<LayerIndex, ColumnVector>,
+    error_vectors: HashMap<LayerIndex, ColumnVector<T>>,
 }
 
 // /// ForwardPassTrainingExampleData encapsulates the data collected during the forward pass for all training examples
@@ -78,13 +134,13 @@ struct ForwardAndBackPassData {
 
 /// LayerGradients encapsulates the gradients for a single layer, seperately for the weights and biases.
 /// I think this is no longer used because where it was used is now using BigTheta instead.
-struct LayerGradients {
-    weight_gradients: Matrix,
-    bias_gradients: ColumnVector,
+struct LayerGradients<T = f64> {
+    weight_gradients: Matrix<T>,
+    bias_gradients: ColumnVector<T>,
 }
 
-impl LayerGradients {
-    fn new(weight_gradients: Matrix, bias_gradients: ColumnVector) -> Self {
+impl<T: Float> LayerGradients<T> {
+    fn new(weight_gradients: Matrix<T>, bias_gradients: ColumnVector<T>) -> Self {
         Self {
             weight_gradients,
             bias_gradients,
@@ -113,39 +169,205 @@ impl CheckOptions {
     }
 }
 
+/// Settings for `train_stochastic` that have a sensible default.
+#[derive(Debug, Clone, Default)]
+pub struct TrainingOptions {
+    pub mini_batch_sampling: MiniBatchSampling,
+    /// Seeds the RNG used for selecting the mini batches. Without a seed, a random one is used.
+    /// Either way, the seed is recorded in the training session info so the run can be reproduced.
+    pub seed: Option<u64>,
+    /// Scales the optimizer's learning rate as training progresses. Constant by default.
+    pub learning_rate_schedule: LearningRateSchedule,
+    /// Clips the gradients before each optimizer step. Off by default.
+    pub gradient_clipping: GradientClipping,
+    /// Checks for NaN/infinite values and dead units after each step. Off by default.
+    pub health_checks: Option<HealthChecks>,
+}
+
+impl TrainingOptions {
+    /// Checks the options that would otherwise only fail partway through training.
+    pub fn validate(&self) -> Result<(), TrainingOptionsError> {
+        if !self.learning_rate_schedule.is_valid() {
+            return Err(TrainingOptionsError::InvalidLearningRateSchedule);
+        }
+        if !self.gradient_clipping.is_valid() {
+            return Err(TrainingOptionsError::InvalidGradientClipping);
+        }
+        Ok(())
+    }
+}
+
 const GRADIENT_CHECK_EPSILON: f64 = 0.0001; // recommended value from Andrew Ng
 const GRADIENT_CHECK_TWICE_EPSILON: f64 = 2.0 * GRADIENT_CHECK_EPSILON;
 const GRADIENT_CHECK_EPSILON_SQUARED: f64 = GRADIENT_CHECK_EPSILON * GRADIENT_CHECK_EPSILON;
 
+/// The number of training examples whose gradients are summed serially by each parallel task in `compute_gradients_par_6`
+/// and `compute_mini_batch_gradients`.
+const GRADIENT_SUM_CHUNK_SIZE: usize = 32;
+
 /// z computes the z vector, i.e. the weighted sum of the inputs and the bias.
-fn z(weight_matrix: &Matrix, bias_v: &ColumnVector, input_v: &ColumnVector) -> ColumnVector {
+fn z<T: Float>(
+    weight_matrix: &Matrix<T>,
+    bias_v: &ColumnVector<T>,
+    input_v: &ColumnVector<T>,
+) -> ColumnVector<T> {
     weight_matrix.mult_vector(input_v).add_chaining(bias_v)
 }
 
-pub struct EarlyStopConfig<'a> {
-    pub test_data: &'a [NDTrainingDataPoint],
-    pub cost_threshold: f64,
+/// When to stop training early, based on periodic checks against the validation set (see `Datasets::validation`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EarlyStopConfig {
+    /// After how many epochs to check - at least 1.
     pub check_every: usize,
+    /// Stops as soon as the validation cost is at or below this.
+    pub cost_threshold: Option<f64>,
+    /// Stops after this many checks in a row where the monitored value didn't improve by more than `min_delta`.
+    pub patience: Option<usize>,
+    pub min_delta: f64,
+    pub monitor: EarlyStopMonitor,
+    /// Whether to put back the weights from the best check when training ends (whether early or not).
+    pub restore_best_weights: bool,
+}
+
+impl EarlyStopConfig {
+    /// Checks every `check_every` epochs, but never stops - set a `cost_threshold` and/or a `patience` for that.
+    pub fn new(check_every: usize) -> Self {
+        Self {
+            check_every,
+            cost_threshold: None,
+            patience: None,
+            min_delta: 0.0,
+            monitor: EarlyStopMonitor::Cost,
+            restore_best_weights: true,
+        }
+    }
+
+    /// Checks have to be every 1 or more epochs.
+    pub fn is_valid(&self) -> bool {
+        self.check_every > 0
+    }
+}
+
+/// A stopped timer's time, for logging.
+fn elapsed_ms(timer: &SimpleTimer) -> u64 {
+    timer.get_total_milliseconds().unwrap_or(0) as u64
+}
+
+/// The seed for the training RNG - `TrainingOptions::seed`, or a random one without it.
+fn training_seed(training_options: Option<&TrainingOptions>) -> u64 {
+    training_options
+        .and_then(|training_options| training_options.seed)
+        .unwrap_or_else(rng::random_seed)
+}
+
+/// What `train_stochastic_from_state` trains on and how, apart from the callbacks.
+struct TrainingRun<'a, T> {
+    datasets: Datasets<'a, T>,
+    epocs: usize,
+    optimizer: &'a dyn Optimizer<T>,
+    mini_batch_size: usize,
+    check_options: Option<&'a CheckOptions>,
+    training_options: Option<&'a TrainingOptions>,
+}
+
+/// The callbacks `train_stochastic` and `resume_from_checkpoint` build from their arguments.
+struct BuiltInCallbacks<'a, T> {
+    full_cost_updates: Option<FullCostUpdates<'a, T>>,
+    early_stopping: Option<EarlyStopping<'a, T>>,
+    session_logger: Option<TrainingSessionLogger>,
+}
+
+impl<'a, T: Float> BuiltInCallbacks<'a, T> {
+    fn new(
+        datasets: Datasets<'a, T>,
+        early_stop_config: Option<EarlyStopConfig>,
+        full_cost_update_every: Option<usize>,
+        session_logger: Option<TrainingSessionLogger>,
+    ) -> Result<Self, TrainingOptionsError> {
+        // even if there's no validation set for it to check against
+        if early_stop_config.is_some_and(|config| !config.is_valid()) {
+            return Err(TrainingOptionsError::InvalidEarlyStopConfig);
+        }
+
+        // the decisions made during training are based on the validation set, never the test set
+        let early_stopping = match (early_stop_config, datasets.validation) {
+            (Some(early_stop_config), Some(validation_data)) => {
+                Some(EarlyStopping::new(early_stop_config, validation_data))
+            }
+            (Some(_), None) => {
+                warn!("early stopping needs a validation set, so it is off");
+                None
+            }
+            (None, _) => None,
+        };
+
+        Ok(Self {
+            full_cost_updates: full_cost_update_every
+                .map(|every| FullCostUpdates::new(datasets, every)),
+            early_stopping,
+            session_logger,
+        })
+    }
+
+    /// The full cost updates come first, so that early stopping can use their validation set metrics rather than
+    /// computing them again, and the session logger can write them out.
+    fn as_dyn(&mut self) -> Vec<&mut dyn TrainingCallback<T>> {
+        let mut callbacks: Vec<&mut dyn TrainingCallback<T>> = Vec::new();
+        if let Some(full_cost_updates) = &mut self.full_cost_updates {
+            callbacks.push(full_cost_updates);
+        }
+        if let Some(early_stopping) = &mut self.early_stopping {
+            callbacks.push(early_stopping);
+        }
+        if let Some(session_logger) = &mut self.session_logger {
+            callbacks.push(session_logger);
+        }
+        callbacks
+    }
+}
+
+/// What `train_on_mini_batch` did, for the `StepEnd` callbacks.
+struct TrainedStep<T> {
+    loss: f64,
+    /// After any clipping.
+    gradients: BigTheta<T>,
+    /// From before the gradients were clipped.
+    gradient_norm: f64,
 }
 
-pub struct NeuralNetwork {
+/// A feed-forward neural network whose weights, biases and activations are `T`s.
+/// `T` defaults to `f64`; an `f32` network (see `cast`) needs half the memory and trains faster, at the cost of precision.
+pub struct NeuralNetwork<T = f64> {
     sizes: Vec<LayerIndex>,
 
     /// A HashMap of the weights keyed by the layer index.
     /// The dimensions will be [rows x columns] [# neurons in the previous layer x # neurons in the next layer].
-    w: HashMap<LayerIndex, Matrix>,
+    w: HashMap<LayerIndex, Matrix<T>>,
 
     /// A HashMap of the biases keyed by the layer index.
     /// The dimension of each ColumnVector will be the number neurons in the layer.
-    b: HashMap<LayerIndex, ColumnVector>,
+    b: HashMap<LayerIndex, ColumnVector<T>>,
+
+    /// The learned scales (gamma) of the layers that have a `Normalization`, keyed by the layer index.
+    scales: HashMap<LayerIndex, ColumnVector<T>>,
+
+    /// The learned shifts (beta) of the layers that have a `Normalization`, keyed by the layer index.
+    shifts: HashMap<LayerIndex, ColumnVector<T>>,
+
+    /// The running means and variances of the z's of the batch normalized layers, used at inference time.
+    running_statistics: HashMap<LayerIndex, RunningStatistics<T>>,
 
     /// Meta data about each layer, such as the activation function and the initializer used.
     layer_configs: HashMap<LayerIndex, LayerConfig>,
 
     cost: cost::CostFunc,
+
+    /// The seed the initial weights and biases were drawn from (see `NeuralNetworkBuilder::with_seed`), or `None` if
+    /// they weren't drawn from a seeded RNG.
+    initialization_seed: Option<u64>,
 }
 
-impl NeuralNetwork {
+impl<T: Float> NeuralNetwork<T> {
     /// Creates a new SimpleNeuralNetwork with the given number of layers. This is old and you should really use the builder instead.
     pub fn new(sizes: Vec<usize>) -> Self {
         let mut biases = HashMap::new();
@@ -175,8 +397,12 @@ impl NeuralNetwork {
             sizes,
             w: weights,
             b: biases,
+            scales: HashMap::new(),
+            shifts: HashMap::new(),
+            running_statistics: HashMap::new(),
             layer_configs: layer_infos,
             cost: cost::CostFunc::QuadraticCost,
+            initialization_seed: None,
         }
     }
 
@@ -185,6 +411,18 @@ impl NeuralNetwork {
         self.sizes.len()
     }
 
+    /// The seed the initial weights and biases were drawn from, if they were drawn from a seeded RNG. Building the
+    /// same configuration with this seed gives the same initial network.
+    pub fn initialization_seed(&self) -> Option<u64> {
+        self.initialization_seed
+    }
+
+    /// A zeroed `BigTheta` with the shape of the network's parameters, including the scales and shifts of any
+    /// normalized layers.
+    pub fn zero_params(&self) -> BigTheta<T> {
+        BigTheta::zero_from_sizes_with_normalized_layers(&self.sizes, &self.normalized_layers())
+    }
+
     /// Tells you if the given layer index corresponds to the output layer.
     pub fn is_output_layer(&self, layer_index: LayerIndex) -> bool {
         layer_index == self.num_layers() - 1
@@ -209,7 +447,7 @@ impl NeuralNetwork {
     fn weights_at_layer_mut(
         &mut self,
         layer_index: LayerIndex,
-    ) -> Result<&mut Matrix, NeuralNetworkError> {
+    ) -> Result<&mut Matrix<T>, NeuralNetworkError> {
         self.w
             .get_mut(&layer_index)
             .ok_or(NeuralNetworkError::InvalidLayerIndex(InvalidLayerIndex(
@@ -221,7 +459,7 @@ impl NeuralNetwork {
     fn bias_at_layer_mut(
         &mut self,
         layer_index: LayerIndex,
-    ) -> Result<&mut ColumnVector, NeuralNetworkError> {
+    ) -> Result<&mut ColumnVector<T>, NeuralNetworkError> {
         self.b
             .get_mut(&layer_index)
             .ok_or(NeuralNetworkError::InvalidLayerIndex(InvalidLayerIndex(
@@ -230,7 +468,7 @@ impl NeuralNetwork {
     }
 
     // TODO: use new methods here for getting layer weights / biases and return Result<ColumVector, NeuralNetworkError>
-    pub fn feed_forward(&self, input_activations: &ColumnVector) -> ColumnVector {
+    pub fn feed_forward(&self, input_activations: &ColumnVector<T>) -> ColumnVector<T> {
         let mut activation_v = input_activations.clone();
 
         for l in 1..self.sizes.len() {
@@ -241,11 +479,13 @@ impl NeuralNetwork {
                 self.b.get(&l).unwrap(),
                 &activation_v,
             );
+            let activation_input_v =
+                ColumnVector::from(self.activation_input_for_inference(l, Matrix::from(z_v)));
 
             activation_v = layer_info
                 .activation_function.as_ref()
                 .expect("need an activation function for any layer input layer")
-                .activate_vector(&z_v);
+                .activate_vector(&activation_input_v);
         }
 
         activation_v
@@ -259,8 +499,28 @@ impl NeuralNetwork {
     /// You could also say that the input vector (or input_activations) *is* the activations vector for the input layer.
     fn feed_forward_capturing_intermediates(
         &self,
-        input_activations: &ColumnVector,
-    ) -> HashMap<LayerIndex, FeedForwardIntermediates> {
+        input_activations: &ColumnVector<T>,
+    ) -> HashMap<LayerIndex, FeedForwardIntermediates<T>> {
+        self.feed_forward_capturing_intermediates_with_dropout(input_activations, None)
+    }
+
+    /// Like `feed_forward_capturing_intermediates`, but when training with `dropout_rng` the activations of each layer
+    /// with a dropout rate are multiplied by a mask drawn from it. The masks are kept in the intermediates for `backprop`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any layers have a `Normalization` - batch norm needs the whole batch, so normalized layers are only
+    /// supported by the batched forward and backward passes (see batch.rs).
+    fn feed_forward_capturing_intermediates_with_dropout(
+        &self,
+        input_activations: &ColumnVector<T>,
+        mut dropout_rng: Option<&mut dyn RngCore>,
+    ) -> HashMap<LayerIndex, FeedForwardIntermediates<T>> {
+        assert!(
+            self.scales.is_empty(),
+            "normalized layers are only supported by the batched forward pass"
+        );
+
         let mut intermediates = HashMap::new();
         let mut activation_v = input_activations.clone();
 
@@ -281,10 +541,19 @@ impl NeuralNetwork {
                     .expect("need an activation function for any layer input layer")
                     .activate_vector(&z_v);
 
-                intermediates.insert(
-                    l,
-                    FeedForwardIntermediates::new_from(Some(&z_v), &activation_v),
-                );
+                let mut dropout_mask = None;
+                if let (Some(rate), Some(rng)) =
+                    (layer_info.dropout_rate, dropout_rng.as_deref_mut())
+                {
+                    let mask = dropout::dropout_mask(rate, activation_v.num_elements(), rng);
+                    activation_v.hadamard_product_in_place(&mask);
+                    dropout_mask = Some(mask);
+                }
+
+                let mut layer_intermediates =
+                    FeedForwardIntermediates::new_from(Some(&z_v), &activation_v);
+                layer_intermediates.dropout_mask = dropout_mask;
+                intermediates.insert(l, layer_intermediates);
             }
         }
 
@@ -292,10 +561,11 @@ impl NeuralNetwork {
     }
 
     /// Given the single training example, feed the input forward through the network to compute the output.
-    /// Then compute the cost based on that computed output and the desired output (found in the training example).
+    /// Then compute the cost based on that computed output and the desired output (found in the training example),
+    /// using the network's configured cost function.
     pub fn cost_single_tr_ex(
         &self,
-        tr_ex: &NDTrainingDataPoint,
+        tr_ex: &NDTrainingDataPoint<T>,
     ) -> Result<f64, VectorDimensionMismatch> {
         if tr_ex.input_v.num_elements() != self.sizes[0] {
             return Err(VectorDimensionMismatch::new_with_msg(
@@ -306,51 +576,82 @@ impl NeuralNetwork {
         }
 
         let output_v = self.feed_forward(&tr_ex.input_v);
-        quadratic_cost(&tr_ex.desired_output_v, &output_v)
+        self.cost.cost(&tr_ex.desired_output_v, &output_v)
+    }
+
+    /// Checks that every example's input and desired output have one element per neuron in the input and output
+    /// layers, so that data that doesn't fit the network is caught before training starts rather than partway through.
+    pub fn check_dimensions(
+        &self,
+        data: &[NDTrainingDataPoint<T>],
+    ) -> Result<(), VectorDimensionMismatch> {
+        let output_size = self.sizes[self.output_layer_index()];
+        for tr_ex in data {
+            if tr_ex.input_v.num_elements() != self.sizes[0] {
+                return Err(VectorDimensionMismatch::new_with_msg(
+                    tr_ex.input_v.num_elements(),
+                    self.sizes[0],
+                    "input_v must have the same number of elements as the number of neurons in the input layer",
+                ));
+            }
+            if tr_ex.desired_output_v.num_elements() != output_size {
+                return Err(VectorDimensionMismatch::new_with_msg(
+                    tr_ex.desired_output_v.num_elements(),
+                    output_size,
+                    "desired_output_v must have the same number of elements as the number of neurons in the output layer",
+                ));
+            }
+        }
+        Ok(())
     }
 
-    /// Computes the cost for a set of training points
+    /// Computes the cost for a set of training points, including the regularization penalty on the weights (see
+    /// `regularization_cost`).
     pub fn cost_training_set(
         &self,
-        training_data: &[NDTrainingDataPoint],
+        training_data: &[NDTrainingDataPoint<T>],
     ) -> Result<f64, VectorDimensionMismatch> {
-        let sum = training_data
+        // the costs are summed in order rather than with a rayon reduce, whose grouping depends on how the work
+        // happens to get split between threads, so the cost is the same every time
+        let costs = training_data
             .par_iter()
             .map(|tr_ex| self.cost_single_tr_ex(tr_ex))
-            .try_reduce(|| 0.0, |acc, cost| Ok(acc + cost))?;
+            .collect::<Result<Vec<f64>, VectorDimensionMismatch>>()?;
+        let sum: f64 = costs.iter().sum();
 
-        Ok(sum / training_data.len() as f64)
+        Ok(sum / training_data.len() as f64 + self.regularization_cost())
     }
 
-    /// Computes the error in the output layer.
-    /// Backprop Equation (the one that is unlabeled but follows after BP1a. I assume they meant to label it BP1b)
-    /// from the Neilson book
-    fn err_output_layer(
+    /// The sum of each layer's `Regularization` penalty on its weights.
+    pub fn regularization_cost(&self) -> f64 {
+        (1..self.num_layers())
+            .map(|l| {
+                let layer_config = self.layer_configs.get(&l).unwrap();
+                layer_config.regularization.penalty(self.w.get(&l).unwrap())
+            })
+            .sum()
+    }
+
+    /// Adds the gradient of the layer's regularization penalty to the gradients of the cost with respect to its weights.
+    fn add_regularization_gradient(
         &self,
-        output_activation_v: &ColumnVector,
-        desired_output_v: &ColumnVector,
-        output_layer_z_v: &ColumnVector,
-    ) -> ColumnVector {
-        let layer_info = self.layer_configs.get(&self.output_layer_index()).unwrap();
-
-        output_activation_v
-            .subtract(desired_output_v)
-            .hadamard_product_chaining(
-                &layer_info.activation_function.as_ref()
-                    .expect("need an activation function for any layer input layer")
-                    .activate_derivative_vector(output_layer_z_v)
-            )
+        layer_index: LayerIndex,
+        weight_gradients: &mut Matrix<T>,
+    ) {
+        let layer_config = self.layer_configs.get(&layer_index).unwrap();
+        layer_config
+            .regularization
+            .add_penalty_gradient(self.w.get(&layer_index).unwrap(), weight_gradients);
     }
 
     /// Computes ∂C/∂z for the output layer
-    /// Use to be `err_output_layer` in previous iteration
     /// TODO: return error rather than unwrap
     fn grad_z_of_c_output_layer(
         &self,
-        z: &ColumnVector, // z vec of output layer, captured in feed forward
-        a: &ColumnVector, // output activations
-        y: &ColumnVector, // desired / expected
-    ) -> anyhow::Result<ColumnVector> {
+        z: &ColumnVector<T>, // z vec of output layer, captured in feed forward
+        a: &ColumnVector<T>, // output activations
+        y: &ColumnVector<T>, // desired / expected
+    ) -> anyhow::Result<ColumnVector<T>> {
         let output_cfg = self.layer_configs.get(&self.output_layer_index()).unwrap();
 
         let Some(act_fn) = output_cfg.activation_function.as_ref() else {
@@ -388,15 +689,14 @@ impl NeuralNetwork {
     fn err_non_last_layer(
         &self,
         layer: LayerIndex,
-        plus_one_layer_error_v: &ColumnVector,
-        this_layer_z_v: &ColumnVector,
-    ) -> ColumnVector {
+        plus_one_layer_error_v: &ColumnVector<T>,
+        this_layer_z_v: &ColumnVector<T>,
+    ) -> ColumnVector<T> {
         let weight_matrix = self.w.get(&(layer + 1)).unwrap();
         let layer_info = self.layer_configs.get(&layer).unwrap();
 
         weight_matrix
-            .transpose()
-            .mult_vector(plus_one_layer_error_v)
+            .transpose_mult_vector(plus_one_layer_error_v)
             .hadamard_product_chaining(
                 &layer_info
                     .activation_function.as_ref()
@@ -411,9 +711,9 @@ impl NeuralNetwork {
     /// and the layers they correspond to to reduce confusion.
     fn backprop(
         &self,
-        desired_output_v: &ColumnVector,
-        intermediates: &HashMap<LayerIndex, FeedForwardIntermediates>,
-    ) -> HashMap<LayerIndex, ColumnVector> {
+        desired_output_v: &ColumnVector<T>,
+        intermediates: &HashMap<LayerIndex, FeedForwardIntermediates<T>>,
+    ) -> HashMap<LayerIndex, ColumnVector<T>> {
         // loop through the layers from back to front, and compute the error at each one.
         // Create a column vector representing the errors at each layer
 
@@ -427,11 +727,16 @@ impl NeuralNetwork {
             let err_v = if l == last_layer_index {
                 let activations_v = &intermediates[&last_layer_index].activation_v;
                 self.grad_z_of_c_output_layer(&z_v, &activations_v, desired_output_v).expect("no error")
-                // self.err_output_layer(&activations_v, desired_output_v, &z_v)
             } else {
                 let error_vector_for_plus_one_layer = error_vectors.get(&(l + 1)).unwrap();
                 // println!("in backprop, l = {}", l);
-                self.err_non_last_layer(l, error_vector_for_plus_one_layer, z_v)
+                let err_v = self.err_non_last_layer(l, error_vector_for_plus_one_layer, z_v);
+
+                // the dropped activations didn't affect the cost, and the kept ones were scaled up
+                match &intermediates[&l].dropout_mask {
+                    Some(dropout_mask) => err_v.hadamard_product_chaining(dropout_mask),
+                    None => err_v,
+                }
             };
 
             error_vectors.insert(l, err_v);
@@ -445,8 +750,8 @@ impl NeuralNetwork {
     fn compute_gradients(
         &mut self,
         // A slice of `ForwardAndBackPassData` instances, one for each training example
-        forward_and_back_pass_data_for_all_training_examples: &[ForwardAndBackPassData],
-    ) -> BigTheta {
+        forward_and_back_pass_data_for_all_training_examples: &[ForwardAndBackPassData<T>],
+    ) -> BigTheta<T> {
         let num_training_examples = forward_and_back_pass_data_for_all_training_examples.len();
 
         let mut bt_weights = HashMap::new();
@@ -483,8 +788,9 @@ impl NeuralNetwork {
             }
 
             // Finish computing the average weight and bias gradients by dividing by the number of training examples
-            avg_weight_gradients.div_scalar_mut(num_training_examples as f64);
-            avg_bias_gradients.div_scalar_mut(num_training_examples as f64);
+            avg_weight_gradients.div_scalar_mut(T::from_f64(num_training_examples as f64));
+            avg_bias_gradients.div_scalar_mut(T::from_f64(num_training_examples as f64));
+            self.add_regularization_gradient(l, &mut avg_weight_gradients);
 
             bt_weights.insert(l, avg_weight_gradients);
             bt_biases.insert(l, avg_bias_gradients);
@@ -494,61 +800,76 @@ impl NeuralNetwork {
             sizes: self.sizes.clone(),
             weights_matrices: bt_weights,
             bias_vectors: bt_biases,
+            scale_vectors: HashMap::new(),
+            shift_vectors: HashMap::new(),
         }
     }
 
     /// Compute the gradients using parallelism.
+    /// Training now uses `compute_mini_batch_gradients`; this per-example version is kept as its reference implementation
+    /// for the tests in batch.rs.
     /// This impl uses par_iter with map/reduce for vastly improved performance compared to using mutexes, as in previous versions.
+    #[cfg(test)]
     fn compute_gradients_par_6(
         &mut self,
         per_tr_ex_data: &[(
-            HashMap<usize, FeedForwardIntermediates>,
-            HashMap<usize, ColumnVector>,
+            HashMap<usize, FeedForwardIntermediates<T>>,
+            HashMap<usize, ColumnVector<T>>,
         )],
-    ) -> BigTheta {
+    ) -> BigTheta<T> {
         let num_training_examples = per_tr_ex_data.len();
 
         // TODO: consider extracting this to a method to improve readability. Note that I made a test for this functionality, though
         // it is just testing the same code, it isn't testing a shared method - see `test_rev_layer_indexs_computation`.
         let layers_in_from_last_to_1th: Vec<usize> = (1..self.num_layers()).rev().collect();
 
-        let mut weights_matrices = HashMap::<LayerIndex, Matrix>::new();
-        let mut bias_vectors = HashMap::<LayerIndex, ColumnVector>::new();
+        let mut weights_matrices = HashMap::<LayerIndex, Matrix<T>>::new();
+        let mut bias_vectors = HashMap::<LayerIndex, ColumnVector<T>>::new();
+
+        let zero_partials = |layer_index: LayerIndex| {
+            (
+                Matrix::new_zero_matrix(self.sizes[layer_index], self.sizes[layer_index - 1]),
+                ColumnVector::new_zero_vector(self.sizes[layer_index]),
+            )
+        };
 
         for layer_index in layers_in_from_last_to_1th {
-            let (mut weights_partials_matrix_avg, mut bias_partials_vector_avg) = per_tr_ex_data
-                .par_iter()
-                .map(|(intermediates, error_vectors)| {
-                    let prev_layer_activations_v =
-                        &intermediates.get(&(layer_index - 1)).unwrap().activation_v;
+            // Each fixed-size chunk is summed serially and then the chunk sums are added up in order.
+            // Unlike a rayon `reduce`, whose grouping depends on how work happens to get split between threads,
+            // this always adds things up in the same order, so the gradients (and therefore the whole training run)
+            // are bit-for-bit reproducible.
+            let chunk_sums = per_tr_ex_data
+                .par_chunks(GRADIENT_SUM_CHUNK_SIZE)
+                .map(|chunk| {
+                    let (mut weights_acc, mut bias_acc) = zero_partials(layer_index);
 
-                    let this_layer_err_v = error_vectors.get(&layer_index).unwrap();
+                    for (intermediates, error_vectors) in chunk {
+                        let prev_layer_activations_v =
+                            &intermediates.get(&(layer_index - 1)).unwrap().activation_v;
 
-                    let weights_grad = this_layer_err_v.outer_product(&prev_layer_activations_v);
+                        let this_layer_err_v = error_vectors.get(&layer_index).unwrap();
 
-                    let bias_grad = this_layer_err_v.clone();
+                        let weights_grad =
+                            this_layer_err_v.outer_product(&prev_layer_activations_v);
 
-                    (weights_grad, bias_grad)
-                })
-                .reduce(
-                    || {
-                        (
-                            Matrix::new_zero_matrix(
-                                self.sizes[layer_index],
-                                self.sizes[layer_index - 1],
-                            ),
-                            ColumnVector::new_zero_vector(self.sizes[layer_index]),
-                        )
-                    },
-                    |(mut weights_acc, mut bias_acc), (weights_grad, bias_grad)| {
                         weights_acc.add_mut(&weights_grad);
-                        bias_acc.add_mut(&bias_grad);
-                        (weights_acc, bias_acc)
-                    },
-                );
+                        bias_acc.add_mut(this_layer_err_v);
+                    }
+
+                    (weights_acc, bias_acc)
+                })
+                .collect::<Vec<(Matrix<T>, ColumnVector<T>)>>();
+
+            let (mut weights_partials_matrix_avg, mut bias_partials_vector_avg) =
+                zero_partials(layer_index);
+            for (weights_sum, bias_sum) in chunk_sums.iter() {
+                weights_partials_matrix_avg.add_mut(weights_sum);
+                bias_partials_vector_avg.add_mut(bias_sum);
+            }
 
-            weights_partials_matrix_avg.div_scalar_mut(num_training_examples as f64);
-            bias_partials_vector_avg.div_scalar_mut(num_training_examples as f64);
+            weights_partials_matrix_avg.div_scalar_mut(T::from_f64(num_training_examples as f64));
+            bias_partials_vector_avg.div_scalar_mut(T::from_f64(num_training_examples as f64));
+            self.add_regularization_gradient(layer_index, &mut weights_partials_matrix_avg);
 
             weights_matrices.insert(layer_index, weights_partials_matrix_avg.clone());
             bias_vectors.insert(layer_index, bias_partials_vector_avg.clone());
@@ -558,12 +879,14 @@ impl NeuralNetwork {
             sizes: self.sizes.clone(),
             weights_matrices,
             bias_vectors,
+            scale_vectors: HashMap::new(),
+            shift_vectors: HashMap::new(),
         }
     }
 
     pub fn train(
         &mut self,
-        training_data: &Vec<NDTrainingDataPoint>,
+        training_data: &Vec<NDTrainingDataPoint<T>>,
         epocs: usize,
         learning_rate: f64,
         check_options: Option<&CheckOptions>,
@@ -571,7 +894,10 @@ impl NeuralNetwork {
         let initial_cost = self
             .cost_training_set(&training_data)
             .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
-        println!("initial cost across entire training set: {}", initial_cost);
+        info!(
+            cost = initial_cost,
+            "initial cost across entire training set"
+        );
 
         // here's what this does:
         // for epocs
@@ -589,13 +915,13 @@ impl NeuralNetwork {
 
         loop {
             if epocs_count >= epocs {
-                println!("stopping after {} epocs", epocs_count);
+                info!(epochs = epocs_count, "stopping after the last epoch");
                 break;
             }
 
             // Here I'll try to use my new structures to capture the forward pass info in a more obvious manner
             // forward_pass_data contains the forward pass data for each training example
-            let forward_pass_data: Vec<ForwardAndBackPassData> = training_data
+            let forward_pass_data: Vec<ForwardAndBackPassData<T>> = training_data
                 .iter()
                 .map(|tr_ex| {
                     let intermediates = self.feed_forward_capturing_intermediates(&tr_ex.input_v);
@@ -608,10 +934,7 @@ impl NeuralNetwork {
                 })
                 .collect();
 
-            println!(
-                "finished ff for all training points - epoch {}",
-                epocs_count
-            );
+            debug!(epoch = epocs_count, "finished ff for all training points");
 
             // note: compute_gradients takes data for ALL training examples
             let mut gradients = self.compute_gradients(&forward_pass_data);
@@ -623,14 +946,24 @@ impl NeuralNetwork {
             // println!("biases grad: \n{}", g1.1);
 
             if check_options.gradient_checking {
+                // gradient checking is always done in f64 - in f32 the finite differences would mostly be rounding error
+                let training_data_f64 = training_data
+                    .iter()
+                    .map(|tr_ex| tr_ex.cast())
+                    .collect::<Vec<NDTrainingDataPoint>>();
                 let approx_gradients_big_v = self
-                    .approximate_cost_gradient(training_data)
+                    .cast::<f64>()
+                    .approximate_cost_gradient(&training_data_f64)
                     .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
                 // unroll the actual gradients
-                let d_vec = gradients.unroll();
+                let d_vec = gradients
+                    .unroll()
+                    .iter()
+                    .map(|x| x.as_f64())
+                    .collect::<Vec<f64>>();
 
                 let ed = euclidian_distance(&approx_gradients_big_v, &d_vec);
-                println!("ed: {}", ed);
+                debug!(epoch = epocs_count, ed, "gradient check");
 
                 // if ed > GRADIENT_CHECK_EPSILON_SQUARED {
                 //     panic!("failed gradient check");
@@ -649,19 +982,19 @@ impl NeuralNetwork {
             for layer_index in 1..self.sizes.len() {
                 let weights_grad = gradients.weights_at_layer_mut(layer_index)?;
                 self.weights_at_layer_mut(layer_index)?
-                    .subtract_mut(weights_grad.mult_scalar_mut_chain(learning_rate));
+                    .subtract_mut(weights_grad.mult_scalar_mut_chain(T::from_f64(learning_rate)));
 
                 let bias_grad = gradients.bias_at_layer_mut(layer_index)?;
                 self.bias_at_layer_mut(layer_index)?
-                    .subtract_mut(bias_grad.mult_scalar_mut_chain(learning_rate));
+                    .subtract_mut(bias_grad.mult_scalar_mut_chain(T::from_f64(learning_rate)));
             }
 
             // Remove
             // Show weights and biases in l1
             let l1_weights = self.w.get(&1).unwrap();
-            println!("weights in layer 1: \n{}", l1_weights);
+            trace!("weights in layer 1: \n{}", l1_weights);
             let l1_biases = self.b.get(&1).unwrap();
-            println!("biases in layer 1: \n{}", l1_biases);
+            trace!("biases in layer 1: \n{}", l1_biases);
 
             if check_options.cost_decreasing_check {
                 let cost = self
@@ -685,38 +1018,197 @@ impl NeuralNetwork {
         let final_cost = self
             .cost_training_set(&training_data)
             .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
-        println!(
-            "\ncost across entire training set after {} epocs: {}",
-            epocs_count, final_cost,
+        info!(
+            epochs = epocs_count,
+            cost = final_cost,
+            "cost across entire training set after training"
         );
 
         Ok(())
     }
 
-    pub fn train_stochastic(
+    /// Trains the network with mini batch gradient descent for `epocs` epochs.
+    /// How the mini batches are drawn from the training data (and therefore how many optimization steps make up an epoch)
+    /// is set by `TrainingOptions::mini_batch_sampling`. `datasets` is either just the training data, or `Datasets` with
+    /// validation and/or test sets too, whose costs and accuracies are also computed every `full_cost_update_every` epochs.
+    /// Early stopping checks against the validation set, so it's off without one.
+    ///
+    /// This is `train_with_callbacks` with the callbacks built from the arguments - a `FullCostUpdates`, an
+    /// `EarlyStopping` and the session logger.
+    pub fn train_stochastic<'d, D: Into<Datasets<'d, T>>>(
         &mut self,
-        training_data: &Vec<NDTrainingDataPoint>,
+        datasets: D,
         epocs: usize,
-        optimizer: &Optimizer,
+        optimizer: &dyn Optimizer<T>,
         mini_batch_size: usize,
         check_options: Option<&CheckOptions>,
         early_stop_config: Option<EarlyStopConfig>,
         full_cost_update_every: Option<usize>, // After how every epocs do you want to do a full cost update across the entire training set, if at all.
         session_logger: Option<TrainingSessionLogger>,
+        training_options: Option<&TrainingOptions>,
+    ) -> Result<(), NeuralNetworkError> {
+        let datasets = datasets.into();
+        let training_state = TrainingState::new(
+            optimizer.init_state(&self.zero_params()),
+            training_seed(training_options),
+        );
+        let mut callbacks = BuiltInCallbacks::new(
+            datasets,
+            early_stop_config,
+            full_cost_update_every,
+            session_logger,
+        )?;
+        self.train_stochastic_from_state(
+            training_state,
+            TrainingRun {
+                datasets,
+                epocs,
+                optimizer,
+                mini_batch_size,
+                check_options,
+                training_options,
+            },
+            &mut callbacks.as_dyn(),
+        )
+    }
+
+    /// Trains the network with mini batch gradient descent like `train_stochastic`, but leaves everything that observes
+    /// training, or ends it early, to `callbacks` - e.g. a `full_cost_updates::FullCostUpdates` followed by a
+    /// `TrainingSessionLogger` to log the session, or an `early_stopping::EarlyStopping`.
+    pub fn train_with_callbacks<'d, D: Into<Datasets<'d, T>>>(
+        &mut self,
+        datasets: D,
+        epocs: usize,
+        optimizer: &dyn Optimizer<T>,
+        mini_batch_size: usize,
+        training_options: Option<&TrainingOptions>,
+        callbacks: &mut [&mut dyn TrainingCallback<T>],
+    ) -> Result<(), NeuralNetworkError> {
+        let training_state = TrainingState::new(
+            optimizer.init_state(&self.zero_params()),
+            training_seed(training_options),
+        );
+        self.train_stochastic_from_state(
+            training_state,
+            TrainingRun {
+                datasets: datasets.into(),
+                epocs,
+                optimizer,
+                mini_batch_size,
+                check_options: None,
+                training_options,
+            },
+            callbacks,
+        )
+    }
+
+    /// Loads a checkpoint written during a previous `train_stochastic` run (see `TrainingSessionLogger::with_checkpoint_every`)
+    /// and continues training from where it left off. The network parameters, optimizer state, epoch count and RNG state are
+    /// all restored, so given the same arguments the resumed run follows the same trajectory as an uninterrupted run would have.
+    /// `epocs` is the total number of epochs for the run, including the ones completed before the checkpoint was written.
+    pub fn resume_from_checkpoint<'d, P: AsRef<Path>, D: Into<Datasets<'d, T>>>(
+        checkpoint_path: P,
+        datasets: D,
+        epocs: usize,
+        optimizer: &dyn Optimizer<T>,
+        mini_batch_size: usize,
+        check_options: Option<&CheckOptions>,
+        early_stop_config: Option<EarlyStopConfig>,
+        full_cost_update_every: Option<usize>,
+        session_logger: Option<TrainingSessionLogger>,
+        training_options: Option<&TrainingOptions>,
+    ) -> anyhow::Result<NeuralNetwork<T>> {
+        let (mut nn, training_state) = Checkpoint::read_from_file(checkpoint_path)?.into_parts()?;
+        info!(
+            epochs = training_state.epochs_count,
+            steps = training_state.optimizer_state.steps_count,
+            "resuming training from checkpoint"
+        );
+
+        // the saved state has to be the kind that the optimizer keeps, e.g. it can't come from a different optimizer
+        let expected_buffers = optimizer.init_state(&nn.zero_params()).buffers;
+        if !training_state
+            .optimizer_state
+            .buffers
+            .keys()
+            .eq(expected_buffers.keys())
+        {
+            return Err(anyhow::anyhow!(
+                "the checkpoint's optimizer state has buffers {:?} but {:?} uses {:?}",
+                training_state
+                    .optimizer_state
+                    .buffers
+                    .keys()
+                    .collect::<Vec<_>>(),
+                optimizer,
+                expected_buffers.keys().collect::<Vec<_>>()
+            ));
+        }
+
+        let datasets = datasets.into();
+        let mut callbacks = BuiltInCallbacks::new(
+            datasets,
+            early_stop_config,
+            full_cost_update_every,
+            session_logger,
+        )?;
+        nn.train_stochastic_from_state(
+            training_state,
+            TrainingRun {
+                datasets,
+                epocs,
+                optimizer,
+                mini_batch_size,
+                check_options,
+                training_options,
+            },
+            &mut callbacks.as_dyn(),
+        )?;
+
+        Ok(nn)
+    }
+
+    fn train_stochastic_from_state(
+        &mut self,
+        training_state: TrainingState<T>,
+        run: TrainingRun<T>,
+        callbacks: &mut [&mut dyn TrainingCallback<T>],
     ) -> Result<(), NeuralNetworkError> {
-        println!("computing initial cross accross entire training dataset...");
+        let TrainingRun {
+            datasets,
+            epocs,
+            optimizer,
+            mini_batch_size,
+            check_options,
+            training_options,
+        } = run;
+        if mini_batch_size == 0 {
+            return Err(TrainingOptionsError::InvalidMiniBatchSize.into());
+        }
+        if let Some(training_options) = training_options {
+            training_options.validate()?;
+        }
+        let training_data = datasets.training;
+
+        debug!("computing initial cost across entire training dataset");
         let mut t_init_cost = SimpleTimer::start_new("t_init_cost");
         let initial_cost = self
             .cost_training_set(&training_data)
             .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
         t_init_cost.stop();
-        println!("initial cost across entire training set: {}", initial_cost);
-        println!("t_init_cost: {}", t_init_cost);
+        info!(
+            cost = initial_cost,
+            elapsed_ms = elapsed_ms(&t_init_cost),
+            "initial cost across entire training set"
+        );
 
-        if let Some(ref session_logger) = session_logger {
-            let network_config = training_log::NetworkConfig::from_neural_network(&self);
-            let optimizer_str = format!("{:?}", optimizer);
-            session_logger.write_training_session_file(initial_cost, network_config, optimizer_str);
+        let train_begin = TrainBegin {
+            initial_cost,
+            optimizer,
+            training_state: &training_state,
+        };
+        for callback in callbacks.iter_mut() {
+            callback.on_train_begin(self, &train_begin)?;
         }
 
         // here's what this does:
@@ -727,388 +1219,509 @@ impl NeuralNetwork {
         //     compute gradients for w and b
         //     update weights and biases
 
-        let mut epochs_count = 0;
-        let mut prev_cost = initial_cost;
+        // the state carried between epochs - see TrainingState
+        let mut state = training_state;
+        let mut prev_cost = state.prev_cost.unwrap_or(initial_cost);
 
         let default_check_options = CheckOptions::no_checks();
         let check_options = check_options.unwrap_or(&default_check_options);
+        let default_training_options = TrainingOptions::default();
+        let training_options = training_options.unwrap_or(&default_training_options);
         let num_samples = training_data.len();
-
-        // for the optimizers
-        let mut momentum = BigTheta::zero_from_sizes(&self.sizes); // used by both Momentum and Adam optimizers
-        let mut s = BigTheta::zero_from_sizes(&self.sizes); // used by Adam optimizer
+        // the norm of the gradients of the last step, before they were clipped
+        let mut gradient_norm = 0.0;
+
+        // when a failed health check rolls training back, it's to the network and training state from the start of
+        // the epoch, and the learning rate is reduced from then on - see OnHealthCheckFailure::RollBack
+        let on_health_check_failure = training_options
+            .health_checks
+            .map_or(OnHealthCheckFailure::Abort, |h| h.on_failure);
+        let mut last_good_checkpoint = None;
+        let mut rollbacks = 0;
+        let mut rollback_learning_rate_factor = 1.0;
+
+        // the learning rate schedule only watches the validation set, never the test set
+        if datasets.validation.is_none()
+            && training_options
+                .learning_rate_schedule
+                .watches_validation_cost()
+        {
+            warn!("ReduceOnPlateau needs a validation set, so the learning rate won't be reduced");
+        }
+        let mut stop_requested = false;
 
         loop {
-            if epochs_count >= epocs {
-                println!("stopping after {} epocs", epochs_count);
+            if state.epochs_count >= epocs {
+                info!(epochs = state.epochs_count, "stopping after the last epoch");
                 break;
             }
 
-            let mut mini_batch_start = 0;
-            let mut mini_batch_end = num_samples;
-
-            if mini_batch_size < num_samples {
-                // println!("num_samples: {}", num_samples);
-                // let mini_batch_size = 200;
-                let max_starting_point = num_samples - mini_batch_size;
-                // println!("max_starting_point: {}", max_starting_point);
-                mini_batch_start = rand::thread_rng().gen_range(0..max_starting_point); // note the upper limit is exclusive
-                mini_batch_end = mini_batch_start + mini_batch_size; // this will be exclusive when used in the slice range
+            if let OnHealthCheckFailure::RollBack { .. } = on_health_check_failure {
+                state.prev_cost = Some(prev_cost);
+                last_good_checkpoint = Some(Checkpoint::new(self, &state));
             }
 
-            println!(
-                "\nstarting mini batch from {} to {}",
-                mini_batch_start, mini_batch_end
-            );
-
-            let tr_data_mini_batch = &training_data[mini_batch_start..mini_batch_end];
-
-            // println!("mini batch size: {}", tr_data_mini_batch.len());
-
-            let mut t_mini_batch_ff = SimpleTimer::start_new("t_mini_batch_ff");
-
-            let per_tr_ex_data = tr_data_mini_batch
-                .par_iter()
-                .map(|tr_ex| {
-                    let intermediates = self.feed_forward_capturing_intermediates(&tr_ex.input_v);
-                    let errors = self.backprop(&tr_ex.desired_output_v, &intermediates);
-
-                    (intermediates, errors)
-                })
-                .collect::<Vec<(
-                    HashMap<usize, FeedForwardIntermediates>,
-                    HashMap<usize, ColumnVector>,
-                )>>();
-
-            // non ||: 3800ms
-            t_mini_batch_ff.stop();
-            println!("t_mini_batch_ff (all data points): {}", t_mini_batch_ff);
-
-            println!(
-                "finished ff for all training points - epoch {}",
-                epochs_count
+            let mini_batches = training_options.mini_batch_sampling.epoch_mini_batches(
+                num_samples,
+                mini_batch_size,
+                &mut state.rng,
             );
-
-            // note: compute_gradients takes data for ALL training examples
-            // TODO: actually, I think this comment is wrong - I think it only takes in the data for all the training examples in the current mini batch
-            println!("computing gradients...");
-            let mut t_compute_gradients = SimpleTimer::start_new("t_compute_gradients");
-            let mut gradients = self.compute_gradients_par_6(&per_tr_ex_data);
-
-            t_compute_gradients.stop();
-            println!(
-                "t_compute_gradients epoch {}: {}",
-                epochs_count, t_compute_gradients
+            info!(
+                epoch = state.epochs_count,
+                mini_batches = mini_batches.len(),
+                "starting epoch"
             );
 
-            // if check_options.gradient_checking {
-            //     let approx_gradients_big_v = self.approximate_cost_gradient(training_data);
-            //     // unroll the actual gradients
-            //     let d_vec = self.unroll_gradients(&gradients);
-
-            //     let ed = euclidian_distance(&approx_gradients_big_v, &d_vec);
-            //     println!("ed: {}", ed);
-
-            //     if ed > GRADIENT_CHECK_EPSILON_SQUARED {
-            //         panic!("failed gradient check");
-            //     }
-
-            //     let normalized_distance = euclidian_distance(&approx_gradients_big_v, &d_vec)
-            //         / (euclidian_length(&approx_gradients_big_v) + euclidian_length(&d_vec));
-
-            //     if normalized_distance > GRADIENT_CHECK_EPSILON_SQUARED {
-            //         panic!("failed gradient check");
-            //     }
-            // }
-
-            // let mut momentum: HashMap<LayerIndex, (Matrix, ColumnVector)> = HashMap::new();
-            // for l in 1..self.num_layers() {
-            //     let w_empty = Matrix::new_zero_matrix_with_shape(&self.get_weight_matrix_shape(l));
-            //     let b_empty = ColumnVector::new_zero_vector(self.sizes[l]);
-            //     momentum.insert(l, (w_empty, b_empty));
-            // }
-
-            // update the weights and biases
-            // TODO: extract to method for easy testing
-
-            // for layer_index in 1..self.sizes.len() {
-            //     match optimizer {
-            //         Optimizer::StanardGradientDescent(optimizer_config) => {
-            //             let weights_grad = gradients.get_weights_matrix_mut(&layer_index);
-            //             weights_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
-
-            //             let weights = self.weights.get_mut(&layer_index).unwrap();
-            //             weights.subtract_in_place(&weights_grad);
-
-            //             let bias_grad = gradients.get_bias_vector_mut(&layer_index);
-            //             bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
-            //             let biases = self.biases.get_mut(&layer_index).unwrap();
-            //             biases.minus_in_place(&bias_grad);
-            //         }
-            //         Optimizer::Momentum(optimizer_config) => {
-            //             let weights_grad = gradients.get_weights_matrix_mut(&layer_index);
-            //             weights_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
-
-            //             let m_w = momentum.get_weights_matrix_mut(&layer_index);
-            //             m_w.multiply_by_scalar_in_place(optimizer_config.momentum);
-            //             m_w.subtract_in_place(&weights_grad);
-            //             let weights = self.weights.get_mut(&layer_index).unwrap();
-            //             weights.add_in_place(&m_w);
-
-            //             let bias_grad = gradients.get_bias_vector_mut(&layer_index);
-            //             bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
-            //             let m_b = momentum.get_bias_vector_mut(&layer_index);
-            //             m_b.multiply_by_scalar_in_place(optimizer_config.momentum);
-            //             m_b.minus_in_place(&bias_grad); // TODO: standardize the subtract_in_place / minus_in_place naming
-
-            //             let biases = self.biases.get_mut(&layer_index).unwrap();
-            //             biases.plus_in_place(&m_b);
-            //         } // orig impl
-            //           // Optimizer::Momentum(optimizer_config) => {
-            //           //     let m = momentum.get_mut(&layer_index).unwrap();
-
-            //           //     let weights_grad = gradients.get_weights_matrix_mut(&layer_index);
-            //           //     weights_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
-            //           //     let m_w = &mut m.0;
-            //           //     m_w.multiply_by_scalar_in_place(optimizer_config.momentum);
-            //           //     m_w.subtract_in_place(&weights_grad);
-            //           //     let weights = self.weights.get_mut(&layer_index).unwrap();
-            //           //     weights.add_in_place(&m_w);
-
-            //           //     let bias_grad = gradients.get_bias_vector_mut(&layer_index);
-            //           //     bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
-            //           //     let m_b = &mut m.1;
-            //           //     m_b.multiply_by_scalar_in_place(optimizer_config.momentum);
-            //           //     m_b.minus_in_place(&bias_grad); // TODO: standardize the subtract_in_place / minus_in_place naming
-
-            //           //     let biases = self.biases.get_mut(&layer_index).unwrap();
-            //           //     biases.plus_in_place(&m_b);
-            //           // }
-            //     }
-            // }
-
-            match optimizer {
-                Optimizer::StanardGradientDescent(optimizer_config) => {
-                    for layer_index in 1..self.sizes.len() {
-                        let weights_grad = gradients.get_weights_matrix_mut(&layer_index);
-                        weights_grad.mult_scalar_mut(optimizer_config.learning_rate);
-
-                        let weights = self.w.get_mut(&layer_index).unwrap();
-                        weights.subtract_mut(&weights_grad);
-
-                        let bias_grad = gradients.get_bias_vector_mut(&layer_index);
-                        bias_grad.mult_scalar_mut(optimizer_config.learning_rate);
-                        let biases = self.b.get_mut(&layer_index).unwrap();
-                        biases.subtract_mut(&bias_grad);
-                    }
-                }
-                Optimizer::Momentum(optimizer_config) => {
-                    for layer_index in 1..self.sizes.len() {
-                        let weights_grad = gradients.get_weights_matrix_mut(&layer_index);
-                        weights_grad.mult_scalar_mut(optimizer_config.learning_rate);
-
-                        let m_w = momentum.get_weights_matrix_mut(&layer_index);
-                        m_w.mult_scalar_mut(optimizer_config.momentum);
-                        m_w.subtract_mut(&weights_grad);
-                        let weights = self.w.get_mut(&layer_index).unwrap();
-                        weights.add_mut(&m_w);
-
-                        let bias_grad = gradients.get_bias_vector_mut(&layer_index);
-                        bias_grad.mult_scalar_mut(optimizer_config.learning_rate);
-                        let m_b = momentum.get_bias_vector_mut(&layer_index);
-                        m_b.mult_scalar_mut(optimizer_config.momentum);
-                        m_b.subtract_mut(&bias_grad); // TODO: standardize the subtract_in_place / minus_in_place naming
-
-                        let biases = self.b.get_mut(&layer_index).unwrap();
-                        biases.add_mut(&m_b);
+            let mut rolled_back = false;
+            let mut loss_sum = 0.0;
+            for mini_batch in mini_batches.iter() {
+                state.optimizer_state.learning_rate_factor = training_options
+                    .learning_rate_schedule
+                    .factor(
+                        state.optimizer_state.steps_count,
+                        state.epochs_count,
+                        &state.schedule_state,
+                    )
+                    * rollback_learning_rate_factor;
+                let problem = match self.train_on_mini_batch(
+                    training_data,
+                    mini_batch,
+                    optimizer,
+                    &mut state.optimizer_state,
+                    training_options,
+                    &mut state.rng,
+                ) {
+                    Ok(step) => {
+                        gradient_norm = step.gradient_norm;
+                        loss_sum += step.loss;
+                        state.optimizer_state.steps_count += 1;
+
+                        let step_end = StepEnd {
+                            epochs_completed: state.epochs_count,
+                            steps_completed: state.optimizer_state.steps_count,
+                            learning_rate: state
+                                .optimizer_state
+                                .scheduled_learning_rate(optimizer.learning_rate()),
+                            loss: step.loss,
+                            gradients: &step.gradients,
+                            gradient_norm: step.gradient_norm,
+                        };
+                        let control = callbacks::call_each(callbacks, |callback| {
+                            callback.on_step_end(self, &step_end)
+                        });
+                        if control == TrainingControl::Stop {
+                            stop_requested = true;
+                            break;
+                        }
+                        continue;
                     }
-                }
-                Optimizer::Adam(adam_optimizer_config) => {
-                    // 1. update momentum
-                    momentum.mult_scalar_in_place(adam_optimizer_config.momentum_decay);
-                    let x = gradients
-                        .mult_scalar_return_new(1.0 - adam_optimizer_config.momentum_decay);
-                    momentum.subtract_in_place(&x);
-
-                    // 2. update s
-                    s.mult_scalar_in_place(adam_optimizer_config.scaling_decay);
-                    let mut x = gradients.clone(); // TODO: some chaining methods on BigTheta would be nice to clean this up
-                    x.elementwise_mult_in_place(&gradients);
-                    x.mult_scalar_in_place(1.0 - adam_optimizer_config.scaling_decay); // could make an elementwise_square
-                    s.add_in_place(&x);
-
-                    // compute momentum_decay_t and scaling_decay_t
-                    // see https://machinelearningmastery.com/adam-optimization-from-scratch/
-                    // and https://arxiv.org/pdf/1412.6980.pdf (the Adam paper)
-                    // let momentum_decay_t = 1.0 - adam_optimizer_config.momentum_decay.powf(1.0 / adam_optimizer_config.epochs);
-                    let momentum_decay_t = adam_optimizer_config
-                        .momentum_decay
-                        .powf(1.0 + epochs_count as f64);
-
-                    let scaling_decay_t = adam_optimizer_config
-                        .scaling_decay
-                        .powf(1.0 + epochs_count as f64);
-
-                    // 3. create m_hat (temp value)
-                    let mut m_hat = momentum.divide_scalar_return_new(1.0 - momentum_decay_t);
-
-                    // 4. create s_hat (temp value)
-                    let mut s_hat = s.divide_scalar_return_new(1.0 - scaling_decay_t);
-
-                    // 5. update weights and biases
-                    // TODO: could prett this up with chaining methods
-                    m_hat.mult_scalar_in_place(adam_optimizer_config.learning_rate);
-                    s_hat.add_scalar_to_each_element_in_place(adam_optimizer_config.epsilon);
-                    s_hat.elementwise_square_root_in_place();
-                    m_hat.elementwise_divide_in_place(&s_hat);
-
-                    // now do the layer by layer update (until I make BigTheta the main deal in the NN struct)
-                    for layer_index in 1..self.sizes.len() {
-                        let weights = self.w.get_mut(&layer_index).unwrap();
-                        let w = m_hat.get_weights_matrix_mut(&layer_index);
-                        weights.add_mut(&w);
-
-                        let biases = self.b.get_mut(&layer_index).unwrap();
-                        let b = m_hat.get_bias_vector_mut(&layer_index);
-                        biases.add_mut(&b);
+                    Err(problem) => problem,
+                };
+
+                let failure = HealthCheckFailure {
+                    epochs_completed: state.epochs_count,
+                    steps_completed: state.optimizer_state.steps_count,
+                    problem,
+                };
+                match on_health_check_failure {
+                    OnHealthCheckFailure::RollBack {
+                        max_rollbacks,
+                        learning_rate_factor,
+                    } if rollbacks < max_rollbacks => {
+                        warn!(
+                            epoch = state.epochs_count,
+                            step = failure.steps_completed,
+                            "{}, rolling back to the start of the epoch",
+                            failure.problem
+                        );
+                        let (nn, training_state) = last_good_checkpoint
+                            .take()
+                            .expect("taken at the start of the epoch")
+                            .into_parts()
+                            .expect("a checkpoint of this network can be restored");
+                        *self = nn;
+                        // but not the RNG, so the epoch is retried with different mini batches and dropout masks
+                        state = TrainingState {
+                            rng: state.rng,
+                            ..training_state
+                        };
+                        prev_cost = state.prev_cost.unwrap_or(initial_cost);
+
+                        rollbacks += 1;
+                        rollback_learning_rate_factor *= learning_rate_factor;
+                        rolled_back = true;
+                        break;
                     }
+                    _ => return Err(NeuralNetworkError::UnhealthyTraining(failure)),
                 }
             }
-
-            // gradients
-            //     .iter_mut()
-            //     .for_each(|(layer_index, (weights_grad, bias_grad))| {
-            //         let layer_index = *layer_index;
-
-            //         match optimizer {
-            //             Optimizer::StanardGradientDescent(optimizer_config) => {
-            //                 weights_grad
-            //                     .multiply_by_scalar_in_place(optimizer_config.learning_rate);
-            //                 bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
-
-            //                 let weights = self.weights.get_mut(&layer_index).unwrap();
-            //                 let biases = self.biases.get_mut(&layer_index).unwrap();
-            //                 weights.subtract_in_place(&weights_grad);
-            //                 biases.minus_in_place(&bias_grad);
-            //             }
-            //             Optimizer::Momentum(optimizer_config) => {
-            //                 weights_grad
-            //                     .multiply_by_scalar_in_place(optimizer_config.learning_rate);
-            //                 bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
-
-            //                 // update the momentum
-            //                 let m = momentum.get_mut(&layer_index).unwrap();
-            //                 let m_w = &mut m.0;
-            //                 let m_b = &mut m.1;
-            //                 m_w.multiply_by_scalar_in_place(optimizer_config.momentum);
-            //                 m_b.multiply_by_scalar_in_place(optimizer_config.momentum);
-            //                 m_w.subtract_in_place(&weights_grad);
-            //                 m_b.minus_in_place(&bias_grad); // TODO: standardize the subtract_in_place / minus_in_place naming
-
-            //                 let weights = self.weights.get_mut(&layer_index).unwrap();
-            //                 let biases = self.biases.get_mut(&layer_index).unwrap();
-            //                 weights.add_in_place(&m_w);
-            //                 biases.plus_in_place(&m_b);
-            //             }
-            //         }
-            //     });
+            if rolled_back {
+                continue;
+            }
+            if stop_requested {
+                info!(
+                    epoch = state.epochs_count,
+                    step = state.optimizer_state.steps_count,
+                    "stopping during the epoch as a callback asked to"
+                );
+                break;
+            }
 
             if check_options.cost_decreasing_check {
                 let cost = self
                     .cost_training_set(&training_data)
                     .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
-                println!(
-                    "cost across training set after epoch {}: {}",
-                    epochs_count, cost
-                );
+                debug!(epoch = state.epochs_count, cost, "cost across training set");
                 if cost > prev_cost {
                     panic!(
                         "cost across training set increased from {} to {} on epoc {}",
-                        prev_cost, cost, epochs_count
+                        prev_cost, cost, state.epochs_count
                     );
                 }
                 prev_cost = cost;
             }
 
-            epochs_count += 1;
-
-            let mut maybe_test_set_cost = None;
-
-            if let Some(full_cost_update_every) = full_cost_update_every {
-                if epochs_count % full_cost_update_every == 0 {
-                    println!(
-                        "\nCost Update\ncomputing cost across entire training dataset after {} epocs...",
-                        epochs_count
-                    );
-                    let training_set_cost = self
-                        .cost_training_set(&training_data)
-                        .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
-                    println!(
-                        "  - cost across entire training set after {} epocs: {}",
-                        epochs_count, training_set_cost,
-                    );
-
-                    println!(
-                        "computing cost across entire test dataset after {} epocs...",
-                        epochs_count
-                    );
-                    let test_set_cost = self
-                        .cost_training_set(&training_data)
-                        .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
-                    println!(
-                        "  - cost across test set after {} epocs: {}",
-                        epochs_count, training_set_cost,
-                    );
-                    maybe_test_set_cost = Some(training_set_cost);
-
-                    if let Some(ref session_logger) = session_logger {
-                        let epoch = epochs_count - 1;
-                        _ = session_logger.write_update(
-                            epoch,
-                            epochs_count,
-                            training_set_cost,
-                            test_set_cost,
-                        );
-                    }
-                }
+            state.epochs_count += 1;
+            state.prev_cost = Some(prev_cost);
+
+            let mut epoch_end = EpochEnd {
+                epochs_completed: state.epochs_count,
+                steps_completed: state.optimizer_state.steps_count,
+                learning_rate: state
+                    .optimizer_state
+                    .scheduled_learning_rate(optimizer.learning_rate()),
+                mean_loss: loss_sum / mini_batches.len().max(1) as f64,
+                gradient_norm,
+                full_cost_update: None,
+                validation_cost: None,
+                early_stop_state: None,
+            };
+            stop_requested = callbacks::call_each(callbacks, |callback| {
+                callback.on_epoch_end(self, &mut epoch_end)
+            }) == TrainingControl::Stop;
+
+            // before the state is checkpointed, so that a resumed run reduces the learning rate when this one would
+            if let Some(validation_cost) = epoch_end.validation_cost {
+                training_options
+                    .learning_rate_schedule
+                    .observe_validation_cost(validation_cost, &mut state.schedule_state);
+            }
+            if let Some(early_stop_state) = epoch_end.early_stop_state {
+                state.early_stop_state = early_stop_state;
+            }
+            for callback in callbacks.iter_mut() {
+                callback.on_epoch_state_updated(self, &state);
             }
 
-            if let Some(ref esc) = early_stop_config {
-                if epochs_count % esc.check_every == 0 {
-                    println!("TRAINING SET COST CHECK / EARLY STOP");
-
-                    let cost = if let Some(test_set_cost) = maybe_test_set_cost {
-                        // the test cost was previously compute in this epoch in the above full cost update
-                        // so don't re-compute it
-                        test_set_cost
-                    } else {
-                        let test_set_cost = self
-                            .cost_training_set(esc.test_data)
-                            .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
-                        test_set_cost
-                    };
-
-                    println!("test dataset cost after {} epocs: {}", epochs_count, cost);
-                    if cost <= esc.cost_threshold {
-                        println!("stopping after {} epocs", epochs_count);
-                        break;
-                    }
-                }
+            if stop_requested {
+                info!(
+                    epochs = state.epochs_count,
+                    "stopping after the epoch as a callback asked to"
+                );
+                break;
             }
         }
 
-        println!("computing final cross accross entire training dataset...");
+        for callback in callbacks.iter_mut() {
+            callback.on_train_end(self, state.epochs_count);
+        }
+
+        debug!("computing final cost across entire training dataset");
         let final_cost = self
             .cost_training_set(&training_data)
             .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
-        println!(
-            "\ncost across entire training set after {} epocs: {}",
-            epochs_count, final_cost,
+        info!(
+            epochs = state.epochs_count,
+            steps = state.optimizer_state.steps_count,
+            cost = final_cost,
+            "cost across entire training set after training"
         );
 
         Ok(())
     }
 
+    /// Computes the cost and accuracy across a whole dataset, for the periodic full cost updates.
+    pub(crate) fn full_cost_update(
+        &self,
+        set_name: &str,
+        data: &[NDTrainingDataPoint<T>],
+        epochs_count: usize,
+    ) -> Result<SetMetrics, NeuralNetworkError> {
+        let mut t_full_cost_update = SimpleTimer::start_new("t_full_cost_update");
+        let cost = self
+            .cost_training_set(data)
+            .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
+        let accuracy = self
+            .accuracy(data)
+            .map_err(|e| NeuralNetworkError::VectorDimensionMismatch(e))?;
+        t_full_cost_update.stop();
+        info!(
+            epochs = epochs_count,
+            set = set_name,
+            cost,
+            accuracy,
+            elapsed_ms = elapsed_ms(&t_full_cost_update),
+            "cost and accuracy across entire set"
+        );
+
+        Ok(SetMetrics { cost, accuracy })
+    }
+
+    /// Does a single optimization step using the training examples at the given indexes. The dropout masks, if any, are
+    /// drawn from `rng`. The running statistics of any batch normalized layers are updated with the mini batch's.
+    ///
+    /// With `training_options.health_checks`, the mini batch and then the updated network are checked, and the first
+    /// problem found is returned instead (without updating the network if it was found in the mini batch). Otherwise the
+    /// gradients are clipped with `training_options.gradient_clipping` before the step, and their norm from before
+    /// clipping is returned.
+    fn train_on_mini_batch(
+        &mut self,
+        training_data: &[NDTrainingDataPoint<T>],
+        mini_batch: &[usize],
+        optimizer: &dyn Optimizer<T>,
+        optimizer_state: &mut OptimizerState<T>,
+        training_options: &TrainingOptions,
+        rng: &mut dyn RngCore,
+    ) -> Result<TrainedStep<T>, HealthProblem> {
+        // forward and backward passes for the whole mini batch at once - see batch.rs
+        let mut t_compute_gradients = SimpleTimer::start_new("t_compute_gradients");
+        let MiniBatchGradients {
+            mut gradients,
+            loss,
+            batch_statistics,
+            activation_summaries,
+        } = self.compute_mini_batch_gradients(training_data, mini_batch, Some(rng));
+
+        t_compute_gradients.stop();
+
+        if let Some(health_checks) = &training_options.health_checks {
+            for l in 1..self.output_layer_index() {
+                let dead_units = activation_summaries[&l].dead_units;
+                if dead_units > 0 {
+                    debug!(
+                        step = optimizer_state.steps_count,
+                        layer = l,
+                        dead_units,
+                        size = self.sizes[l],
+                        "dead units"
+                    );
+                }
+            }
+            if let Some(problem) =
+                health_checks.check_mini_batch(self, &activation_summaries, &gradients)
+            {
+                return Err(problem);
+            }
+        }
+
+        self.update_running_statistics(&batch_statistics);
+        let gradient_norm = training_options.gradient_clipping.clip(&mut gradients);
+        debug!(
+            step = optimizer_state.steps_count,
+            loss,
+            gradient_norm,
+            elapsed_ms = elapsed_ms(&t_compute_gradients),
+            "computed gradients"
+        );
+
+        // if check_options.gradient_checking {
+        //     let approx_gradients_big_v = self.approximate_cost_gradient(training_data);
+        //     // unroll the actual gradients
+        //     let d_vec = self.unroll_gradients(&gradients);
+
+        //     let ed = euclidian_distance(&approx_gradients_big_v, &d_vec);
+        //     println!("ed: {}", ed);
+
+        //     if ed > GRADIENT_CHECK_EPSILON_SQUARED {
+        //         panic!("failed gradient check");
+        //     }
+
+        //     let normalized_distance = euclidian_distance(&approx_gradients_big_v, &d_vec)
+        //         / (euclidian_length(&approx_gradients_big_v) + euclidian_length(&d_vec));
+
+        //     if normalized_distance > GRADIENT_CHECK_EPSILON_SQUARED {
+        //         panic!("failed gradient check");
+        //     }
+        // }
+
+        // let mut momentum: HashMap<LayerIndex, (Matrix, ColumnVector)> = HashMap::new();
+        // for l in 1..self.num_layers() {
+        //     let w_empty = Matrix::new_zero_matrix_with_shape(&self.get_weight_matrix_shape(l));
+        //     let b_empty = ColumnVector::new_zero_vector(self.sizes[l]);
+        //     momentum.insert(l, (w_empty, b_empty));
+        // }
+
+        // update the weights and biases
+        // TODO: extract to method for easy testing
+
+        // for layer_index in 1..self.sizes.len() {
+        //     match optimizer {
+        //         Optimizer::StanardGradientDescent(optimizer_config) => {
+        //             let weights_grad = gradients.get_weights_matrix_mut(&layer_index);
+        //             weights_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
+
+        //             let weights = self.weights.get_mut(&layer_index).unwrap();
+        //             weights.subtract_in_place(&weights_grad);
+
+        //             let bias_grad = gradients.get_bias_vector_mut(&layer_index);
+        //             bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
+        //             let biases = self.biases.get_mut(&layer_index).unwrap();
+        //             biases.minus_in_place(&bias_grad);
+        //         }
+        //         Optimizer::Momentum(optimizer_config) => {
+        //             let weights_grad = gradients.get_weights_matrix_mut(&layer_index);
+        //             weights_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
+
+        //             let m_w = momentum.get_weights_matrix_mut(&layer_index);
+        //             m_w.multiply_by_scalar_in_place(optimizer_config.momentum);
+        //             m_w.subtract_in_place(&weights_grad);
+        //             let weights = self.weights.get_mut(&layer_index).unwrap();
+        //             weights.add_in_place(&m_w);
+
+        //             let bias_grad = gradients.get_bias_vector_mut(&layer_index);
+        //             bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
+        //             let m_b = momentum.get_bias_vector_mut(&layer_index);
+        //             m_b.multiply_by_scalar_in_place(optimizer_config.momentum);
+        //             m_b.minus_in_place(&bias_grad); // TODO: standardize the subtract_in_place / minus_in_place naming
+
+        //             let biases = self.biases.get_mut(&layer_index).unwrap();
+        //             biases.plus_in_place(&m_b);
+        //         } // orig impl
+        //           // Optimizer::Momentum(optimizer_config) => {
+        //           //     let m = momentum.get_mut(&layer_index).unwrap();
+
+        //           //     let weights_grad = gradients.get_weights_matrix_mut(&layer_index);
+        //           //     weights_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
+        //           //     let m_w = &mut m.0;
+        //           //     m_w.multiply_by_scalar_in_place(optimizer_config.momentum);
+        //           //     m_w.subtract_in_place(&weights_grad);
+        //           //     let weights = self.weights.get_mut(&layer_index).unwrap();
+        //           //     weights.add_in_place(&m_w);
+
+        //           //     let bias_grad = gradients.get_bias_vector_mut(&layer_index);
+        //           //     bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
+        //           //     let m_b = &mut m.1;
+        //           //     m_b.multiply_by_scalar_in_place(optimizer_config.momentum);
+        //           //     m_b.minus_in_place(&bias_grad); // TODO: standardize the subtract_in_place / minus_in_place naming
+
+        //           //     let biases = self.biases.get_mut(&layer_index).unwrap();
+        //           //     biases.plus_in_place(&m_b);
+        //           // }
+        //     }
+        // }
+
+        // the optimizers work on BigThetas, so lend it the weights and biases (and scales and shifts) for the update
+        let mut params = BigTheta {
+            sizes: self.sizes.clone(),
+            weights_matrices: std::mem::take(&mut self.w),
+            bias_vectors: std::mem::take(&mut self.b),
+            scale_vectors: std::mem::take(&mut self.scales),
+            shift_vectors: std::mem::take(&mut self.shifts),
+        };
+        optimizer.step(&mut params, &gradients, optimizer_state);
+        self.w = params.weights_matrices;
+        self.b = params.bias_vectors;
+        self.scales = params.scale_vectors;
+        self.shifts = params.shift_vectors;
+
+        // gradients
+        //     .iter_mut()
+        //     .for_each(|(layer_index, (weights_grad, bias_grad))| {
+        //         let layer_index = *layer_index;
+
+        //         match optimizer {
+        //             Optimizer::StanardGradientDescent(optimizer_config) => {
+        //                 weights_grad
+        //                     .multiply_by_scalar_in_place(optimizer_config.learning_rate);
+        //                 bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
+
+        //                 let weights = self.weights.get_mut(&layer_index).unwrap();
+        //                 let biases = self.biases.get_mut(&layer_index).unwrap();
+        //                 weights.subtract_in_place(&weights_grad);
+        //                 biases.minus_in_place(&bias_grad);
+        //             }
+        //             Optimizer::Momentum(optimizer_config) => {
+        //                 weights_grad
+        //                     .multiply_by_scalar_in_place(optimizer_config.learning_rate);
+        //                 bias_grad.multiply_by_scalar_in_place(optimizer_config.learning_rate);
+
+        //                 // update the momentum
+        //                 let m = momentum.get_mut(&layer_index).unwrap();
+        //                 let m_w = &mut m.0;
+        //                 let m_b = &mut m.1;
+        //                 m_w.multiply_by_scalar_in_place(optimizer_config.momentum);
+        //                 m_b.multiply_by_scalar_in_place(optimizer_config.momentum);
+        //                 m_w.subtract_in_place(&weights_grad);
+        //                 m_b.minus_in_place(&bias_grad); // TODO: standardize the subtract_in_place / minus_in_place naming
+
+        //                 let weights = self.weights.get_mut(&layer_index).unwrap();
+        //                 let biases = self.biases.get_mut(&layer_index).unwrap();
+        //                 weights.add_in_place(&m_w);
+        //                 biases.plus_in_place(&m_b);
+        //             }
+        //         }
+        //     });
+
+        if let Some(health_checks) = &training_options.health_checks {
+            if let Some(problem) = health_checks.check_parameters(self) {
+                return Err(problem);
+            }
+        }
+
+        Ok(TrainedStep {
+            loss,
+            gradients,
+            gradient_norm,
+        })
+    }
+
+    /// Converts the weights and biases to another float type, e.g. to train in `f32` a network that was built in `f64`.
+    pub fn cast<U: Float>(&self) -> NeuralNetwork<U> {
+        NeuralNetwork {
+            sizes: self.sizes.clone(),
+            w: self.w.iter().map(|(l, w)| (*l, w.cast())).collect(),
+            b: self.b.iter().map(|(l, b)| (*l, b.cast())).collect(),
+            scales: self.scales.iter().map(|(l, s)| (*l, s.cast())).collect(),
+            shifts: self.shifts.iter().map(|(l, s)| (*l, s.cast())).collect(),
+            running_statistics: self
+                .running_statistics
+                .iter()
+                .map(|(l, r)| (*l, r.cast()))
+                .collect(),
+            layer_configs: self.layer_configs.clone(),
+            cost: self.cost.clone(),
+            initialization_seed: self.initialization_seed,
+        }
+    }
+
+    //////////////////////////////////////////////
+    // The following I think are leftover code.
+    // They have to do with computing the initial w/b values but don't appear to be used.
+    //////////////////////////////////////////////
+
+    // THIS MAY NOT BE RIGHT!
+    // Not sure if fan in is the total number of inbound connections at a layer, or just
+    // the number of neurons in the previous layer.
+    pub fn get_fan_in(&self, l: LayerIndex) -> usize {
+        if l == 0 {
+            panic!("not valid for input layer");
+        }
+        self.sizes[l - 1] * self.sizes[l]
+    }
+
+    // THIS MAY NOT BE RIGHT!
+    // Not sure if fan out is the total number of outbound connections at a layer, or just
+    // the number of neurons in the next layer.
+    pub fn get_fan_out(&self, l: LayerIndex) -> usize {
+        if self.is_output_layer(l) {
+            panic!("not valid for output layer");
+        }
+        self.sizes[l] * self.sizes[l + 1]
+    }
+}
+
+/// Gradient checking is only done in f64 - an f32 network is cast to f64 first (see `train`).
+impl NeuralNetwork<f64> {
     //////////////////////////////////////////////
     // methods used for gradient checking
     // TODO: can these be refactored out of here?
@@ -1121,6 +1734,11 @@ impl NeuralNetwork {
         for l in 1..self.num_layers() {
             unrolled_vec.extend_from_slice(&self.w.get(&l).unwrap().data);
             unrolled_vec.extend_from_slice(&self.b.get(&l).unwrap().get_data_as_slice());
+            // in the same order as BigTheta::unroll
+            if let (Some(scale), Some(shift)) = (self.scales.get(&l), self.shifts.get(&l)) {
+                unrolled_vec.extend_from_slice(scale.get_data_as_slice());
+                unrolled_vec.extend_from_slice(shift.get_data_as_slice());
+            }
         }
         unrolled_vec
     }
@@ -1148,6 +1766,8 @@ impl NeuralNetwork {
 
         let mut weights = HashMap::new();
         let mut biases = HashMap::new();
+        let mut scales = HashMap::new();
+        let mut shifts = HashMap::new();
 
         let mut ptr: usize = 0;
 
@@ -1163,6 +1783,16 @@ impl NeuralNetwork {
 
             weights.insert(l, w);
             biases.insert(l, b);
+
+            if self.scales.contains_key(&l) {
+                for vectors in [&mut scales, &mut shifts] {
+                    vectors.insert(
+                        l,
+                        ColumnVector::new(&big_theta_v[ptr..(ptr + self.sizes[l])]),
+                    );
+                    ptr += self.sizes[l];
+                }
+            }
         }
 
         let layer_infos = self.layer_configs.clone();
@@ -1171,29 +1801,43 @@ impl NeuralNetwork {
             sizes: self.sizes.clone(),
             w: weights,
             b: biases,
+            scales,
+            shifts,
+            running_statistics: self.running_statistics.clone(),
             layer_configs: layer_infos,
             cost: self.cost.clone(),
+            initialization_seed: self.initialization_seed,
         }
     }
 
     /// Used for gradient checking
     fn approximate_cost_gradient(
         &self,
-        training_data: &Vec<NDTrainingDataPoint>,
+        training_data: &[NDTrainingDataPoint],
     ) -> Result<Vec<f64>, VectorDimensionMismatch> {
         let mut big_theta_v = self.unroll_weights_and_biases();
         let mut gradient = Vec::new();
 
-        for i in 0..big_theta_v.len() {
+        // with batch norm, the gradients are of the cost with the z's standardized by the batch's statistics (here
+        // the whole of training_data is the batch), not the running ones that cost_training_set uses
+        let cost = |nn: &NeuralNetwork| {
+            if nn.running_statistics.is_empty() {
+                nn.cost_training_set(training_data)
+            } else {
+                nn.cost_training_set_as_one_batch(training_data)
+            }
+        };
+
+        for i in 0..big_theta_v.len() {
             let orig_i_value = big_theta_v[i];
 
             big_theta_v[i] = orig_i_value + GRADIENT_CHECK_EPSILON;
             let temp_nn = self.reshape_weights_and_biases(&big_theta_v);
-            let cost_plus_epsilon = temp_nn.cost_training_set(&training_data)?;
+            let cost_plus_epsilon = cost(&temp_nn)?;
 
             big_theta_v[i] = orig_i_value - GRADIENT_CHECK_EPSILON;
             let temp_nn = self.reshape_weights_and_biases(&big_theta_v);
-            let cost_minus_epsilon = temp_nn.cost_training_set(training_data)?;
+            let cost_minus_epsilon = cost(&temp_nn)?;
 
             big_theta_v[i] = orig_i_value; // important - restore the orig value
 
@@ -1204,31 +1848,6 @@ impl NeuralNetwork {
 
         Ok(gradient)
     }
-
-    //////////////////////////////////////////////
-    // The following I think are leftover code.
-    // They have to do with computing the initial w/b values but don't appear to be used.
-    //////////////////////////////////////////////
-
-    // THIS MAY NOT BE RIGHT!
-    // Not sure if fan in is the total number of inbound connections at a layer, or just
-    // the number of neurons in the previous layer.
-    pub fn get_fan_in(&self, l: LayerIndex) -> usize {
-        if l == 0 {
-            panic!("not valid for input layer");
-        }
-        self.sizes[l - 1] * self.sizes[l]
-    }
-
-    // THIS MAY NOT BE RIGHT!
-    // Not sure if fan out is the total number of outbound connections at a layer, or just
-    // the number of neurons in the next layer.
-    pub fn get_fan_out(&self, l: LayerIndex) -> usize {
-        if self.is_output_layer(l) {
-            panic!("not valid for output layer");
-        }
-        self.sizes[l] * self.sizes[l + 1]
-    }
 }
 
 #[cfg(test)]
@@ -1238,7 +1857,9 @@ mod tests {
     use crate::activation::leaky_relu::LeakyReLU;
     use crate::builder::NeuralNetworkBuilder;
 
-    use super::optimizer;
+    use super::normalization::Normalization;
+    use super::optimizer::{self, OptimizerConfig};
+    use super::regularization::Regularization;
     use super::*;
     use common::column_vector;
     use common::linalg::RowsMatrixBuilder;
@@ -1284,8 +1905,12 @@ mod tests {
             ],
             w: weights,
             b: biases,
+            scales: HashMap::new(),
+            shifts: HashMap::new(),
+            running_statistics: HashMap::new(),
             layer_configs: layer_infos,
             cost: cost::CostFunc::QuadraticCost,
+            initialization_seed: None,
         };
 
         nn
@@ -1398,7 +2023,7 @@ mod tests {
 
     #[test]
     fn test_fan_in_fan_out() {
-        let nn = NeuralNetwork::new(vec![2, 3, 4, 2]);
+        let nn: NeuralNetwork = NeuralNetwork::new(vec![2, 3, 4, 2]);
 
         assert_eq!(
             panic::catch_unwind(|| {
@@ -1485,8 +2110,12 @@ mod tests {
             sizes,
             w: weights,
             b: biases,
+            scales: HashMap::new(),
+            shifts: HashMap::new(),
+            running_statistics: HashMap::new(),
             layer_configs: layer_infos,
             cost: cost::CostFunc::QuadraticCost,
+            initialization_seed: None,
         };
 
         let outputs = nn.feed_forward(&inputs);
@@ -1568,8 +2197,12 @@ mod tests {
             sizes,
             w: weights,
             b: biases,
+            scales: HashMap::new(),
+            shifts: HashMap::new(),
+            running_statistics: HashMap::new(),
             layer_configs: layer_infos,
             cost: cost::CostFunc::QuadraticCost,
+            initialization_seed: None,
         };
 
         let intermediates = nn.feed_forward_capturing_intermediates(&inputs);
@@ -1647,6 +2280,374 @@ mod tests {
         assert_eq!(c0, over_two);
     }
 
+    #[test]
+    pub fn test_cost_single_tr_ex_uses_configured_cost_fn() {
+        let nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_output_layer(
+                3,
+                Initializer::Manual(
+                    RowsMatrixBuilder::new()
+                        .with_row(&[1.0, 0.0])
+                        .with_row(&[0.0, 1.0])
+                        .with_row(&[0.5, 0.5])
+                        .build(),
+                    column_vector![0.0, 0.0, 0.0],
+                ),
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(cost::CostFunc::CrossEntropy)
+            .build();
+
+        let tr_ex =
+            NDTrainingDataPoint::new(column_vector![1.0, 2.0], column_vector![0.0, 1.0, 0.0]);
+        let output_v = nn.feed_forward(&tr_ex.input_v);
+
+        let c0 = nn.cost_single_tr_ex(&tr_ex).unwrap();
+        assert!(approx_eq!(f64, c0, -output_v.get(1).ln(), ulps = 2));
+    }
+
+    #[test]
+    pub fn gradients_match_approximate_gradients_for_softmax_with_cross_entropy() {
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(
+                4,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            )
+            .with_output_layer(
+                3,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(cost::CostFunc::CrossEntropy)
+            .build();
+
+        let training_data = vec![
+            NDTrainingDataPoint::new(column_vector![-1.0, 0.5], column_vector![1.0, 0.0, 0.0]),
+            NDTrainingDataPoint::new(column_vector![0.5, 1.0], column_vector![0.0, 1.0, 0.0]),
+            NDTrainingDataPoint::new(column_vector![2.0, -1.0], column_vector![0.0, 0.0, 1.0]),
+        ];
+
+        let forward_pass_data: Vec<ForwardAndBackPassData> = training_data
+            .iter()
+            .map(|tr_ex| {
+                let intermediates = nn.feed_forward_capturing_intermediates(&tr_ex.input_v);
+                let error_vectors = nn.backprop(&tr_ex.desired_output_v, &intermediates);
+                ForwardAndBackPassData {
+                    intermediates,
+                    error_vectors,
+                }
+            })
+            .collect();
+        let gradients = nn.compute_gradients(&forward_pass_data).unroll();
+        let approx_gradients = nn.approximate_cost_gradient(&training_data).unwrap();
+
+        assert_eq!(gradients.len(), approx_gradients.len());
+        for (g, approx_g) in gradients.iter().zip(approx_gradients.iter()) {
+            assert!((g - approx_g).abs() < 1e-7, "{} vs {}", g, approx_g);
+        }
+    }
+
+    fn build_regularized_network_for_test(regularization: Regularization) -> NeuralNetwork {
+        NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(
+                4,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            )
+            .with_regularization(regularization)
+            .with_output_layer(
+                3,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Softmax,
+            )
+            .with_regularization(regularization)
+            .with_cost_fn(cost::CostFunc::CrossEntropy)
+            .with_seed(5)
+            .build()
+    }
+
+    fn get_softmax_data_set() -> Vec<NDTrainingDataPoint> {
+        vec![
+            NDTrainingDataPoint::new(column_vector![-1.0, 0.5], column_vector![1.0, 0.0, 0.0]),
+            NDTrainingDataPoint::new(column_vector![0.5, 1.0], column_vector![0.0, 1.0, 0.0]),
+            NDTrainingDataPoint::new(column_vector![2.0, -1.0], column_vector![0.0, 0.0, 1.0]),
+        ]
+    }
+
+    #[test]
+    fn gradients_match_approximate_gradients_with_l1_and_l2_regularization() {
+        let training_data = get_softmax_data_set();
+        let mut nn = build_regularized_network_for_test(Regularization { l1: 0.05, l2: 0.2 });
+
+        let forward_pass_data: Vec<ForwardAndBackPassData> = training_data
+            .iter()
+            .map(|tr_ex| {
+                let intermediates = nn.feed_forward_capturing_intermediates(&tr_ex.input_v);
+                let error_vectors = nn.backprop(&tr_ex.desired_output_v, &intermediates);
+                ForwardAndBackPassData {
+                    intermediates,
+                    error_vectors,
+                }
+            })
+            .collect();
+        let gradients = nn.compute_gradients(&forward_pass_data).unroll();
+        let batched_gradients = nn
+            .compute_gradients_batched(&training_data, &[0, 1, 2], None)
+            .unroll();
+        let approx_gradients = nn.approximate_cost_gradient(&training_data).unwrap();
+
+        assert_eq!(gradients.len(), approx_gradients.len());
+        for ((g, batched_g), approx_g) in gradients
+            .iter()
+            .zip(batched_gradients.iter())
+            .zip(approx_gradients.iter())
+        {
+            assert!((g - approx_g).abs() < 1e-7, "{} vs {}", g, approx_g);
+            assert!(
+                (batched_g - approx_g).abs() < 1e-7,
+                "{} vs {}",
+                batched_g,
+                approx_g
+            );
+        }
+    }
+
+    #[test]
+    fn regularization_adds_to_the_cost_and_the_weight_gradients_only() {
+        let training_data = get_softmax_data_set();
+        let nn = build_regularized_network_for_test(Regularization::default());
+        let regularized_nn = build_regularized_network_for_test(Regularization::l2(0.5));
+        assert_eq!(regularized_nn.w, nn.w);
+
+        // 0.5 / 2 * the sum of the squared weights
+        let sum_of_squared_weights: f64 = (1..nn.num_layers())
+            .flat_map(|l| nn.w[&l].data.iter().map(|w| w * w))
+            .sum();
+        assert_eq!(nn.regularization_cost(), 0.0);
+        assert!(approx_eq!(
+            f64,
+            regularized_nn.regularization_cost(),
+            0.25 * sum_of_squared_weights,
+            epsilon = 1e-12
+        ));
+        assert!(approx_eq!(
+            f64,
+            regularized_nn.cost_training_set(&training_data).unwrap(),
+            nn.cost_training_set(&training_data).unwrap() + 0.25 * sum_of_squared_weights,
+            epsilon = 1e-12
+        ));
+
+        let gradients = nn.compute_gradients_batched(&training_data, &[0, 1, 2], None);
+        let regularized_gradients =
+            regularized_nn.compute_gradients_batched(&training_data, &[0, 1, 2], None);
+        for l in 1..nn.num_layers() {
+            assert_eq!(
+                regularized_gradients.get_bias_vector(&l),
+                gradients.get_bias_vector(&l)
+            );
+
+            let weight_gradients = &gradients.get_weights_matrix(&l).data;
+            let regularized_weight_gradients = &regularized_gradients.get_weights_matrix(&l).data;
+            for ((g, regularized_g), w) in weight_gradients
+                .iter()
+                .zip(regularized_weight_gradients.iter())
+                .zip(nn.w[&l].data.iter())
+            {
+                assert!(approx_eq!(
+                    f64,
+                    *regularized_g,
+                    g + 0.5 * w,
+                    epsilon = 1e-12
+                ));
+            }
+        }
+    }
+
+    fn build_dropout_network_for_test(dropout_rate: Option<f64>) -> NeuralNetwork {
+        let builder = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(
+                16,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            );
+        let builder = match dropout_rate {
+            Some(rate) => builder.with_dropout(rate),
+            None => builder,
+        };
+        builder
+            .with_output_layer(
+                3,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(cost::CostFunc::CrossEntropy)
+            .with_seed(5)
+            .build()
+    }
+
+    #[test]
+    fn dropout_only_applies_in_training() {
+        let training_data = get_softmax_data_set();
+        let nn = build_dropout_network_for_test(None);
+        let dropout_nn = build_dropout_network_for_test(Some(0.5));
+        assert_eq!(dropout_nn.w, nn.w);
+
+        // inference, and computing gradients without a training RNG, are unaffected
+        for data_point in training_data.iter() {
+            assert_eq!(
+                dropout_nn.feed_forward(&data_point.input_v),
+                nn.feed_forward(&data_point.input_v)
+            );
+        }
+        assert_eq!(
+            dropout_nn.cost_training_set(&training_data).unwrap(),
+            nn.cost_training_set(&training_data).unwrap()
+        );
+        let gradients = nn.compute_gradients_batched(&training_data, &[0, 1, 2], None);
+        assert_eq!(
+            dropout_nn
+                .compute_gradients_batched(&training_data, &[0, 1, 2], None)
+                .get_weights_matrix(&1),
+            gradients.get_weights_matrix(&1)
+        );
+
+        let mut rng = rng::seeded_rng(7, rng::RngStream::Training);
+        let dropout_gradients =
+            dropout_nn.compute_gradients_batched(&training_data, &[0, 1, 2], Some(&mut rng));
+        assert_ne!(
+            dropout_gradients.get_weights_matrix(&1),
+            gradients.get_weights_matrix(&1)
+        );
+    }
+
+    #[test]
+    fn training_with_dropout_is_reproducible_with_the_same_seed() {
+        let training_data = get_softmax_data_set();
+        let train = || {
+            let mut nn = build_dropout_network_for_test(Some(0.5));
+            nn.train_stochastic(
+                &training_data,
+                5,
+                &OptimizerConfig::standard_gradient_descent(0.5),
+                2,
+                None,
+                None,
+                None,
+                None,
+                Some(&TrainingOptions {
+                    seed: Some(3),
+                    ..TrainingOptions::default()
+                }),
+            )
+            .unwrap();
+            nn
+        };
+
+        let nn = train();
+        assert_eq!(train().w, nn.w);
+        assert_ne!(nn.w, build_dropout_network_for_test(Some(0.5)).w);
+    }
+
+    fn build_normalized_network_for_test(normalization: Normalization) -> NeuralNetwork {
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(
+                4,
+                Initializer::HeForReLUAndVariants,
+                ActivationFunction::LeakyReLU(0.1),
+            )
+            .with_normalization(normalization)
+            .with_hidden_layer(
+                3,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            )
+            .with_output_layer(
+                3,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(cost::CostFunc::CrossEntropy)
+            .with_seed(5)
+            .build();
+
+        // so the scales and shifts aren't the identity, which would hide mistakes in their gradients
+        let scales = nn.scales.get_mut(&1).unwrap();
+        let shifts = nn.shifts.get_mut(&1).unwrap();
+        for i in 0..4 {
+            scales.set(i, 0.5 + 0.25 * i as f64);
+            shifts.set(i, 0.1 * i as f64 - 0.2);
+        }
+        nn
+    }
+
+    #[test]
+    fn gradients_match_approximate_gradients_with_normalization() {
+        let training_data = get_softmax_data_set();
+
+        for normalization in [Normalization::batch_norm(), Normalization::layer_norm()] {
+            let nn = build_normalized_network_for_test(normalization);
+
+            let gradients = nn
+                .compute_gradients_batched(&training_data, &[0, 1, 2], None)
+                .unroll();
+            let approx_gradients = nn.approximate_cost_gradient(&training_data).unwrap();
+
+            // the weights, biases, scales and shifts of layer 1, then the weights and biases of layers 2 and 3
+            assert_eq!(gradients.len(), 8 + 4 + 4 + 4 + 12 + 3 + 9 + 3);
+            assert_eq!(gradients.len(), approx_gradients.len());
+            for (g, approx_g) in gradients.iter().zip(approx_gradients.iter()) {
+                assert!(
+                    (g - approx_g).abs() < 1e-7,
+                    "{:?}: {} vs {}",
+                    normalization,
+                    g,
+                    approx_g
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn training_with_batch_norm_updates_the_running_statistics_used_for_inference() {
+        let training_data = get_softmax_data_set();
+        let mut nn = build_normalized_network_for_test(Normalization::batch_norm());
+        let initial_scales = nn.scales.clone();
+        let initial_output = nn.feed_forward(&training_data[0].input_v);
+
+        nn.train_stochastic(
+            &training_data,
+            3,
+            &OptimizerConfig::standard_gradient_descent(0.5),
+            3,
+            None,
+            None,
+            None,
+            None,
+            Some(&TrainingOptions {
+                seed: Some(3),
+                ..TrainingOptions::default()
+            }),
+        )
+        .unwrap();
+
+        assert_ne!(nn.scales, initial_scales);
+        assert_ne!(nn.running_statistics[&1], RunningStatistics::new(4));
+        assert_ne!(nn.feed_forward(&training_data[0].input_v), initial_output);
+
+        // inference uses the running statistics, whatever else is in the batch
+        let inputs = batch::columns_to_matrix(2, training_data.iter().map(|tr_ex| &tr_ex.input_v));
+        let outputs = nn.feed_forward_batch(&inputs);
+        for (i, tr_ex) in training_data.iter().enumerate() {
+            assert_eq!(outputs.extract_column(i), nn.feed_forward(&tr_ex.input_v));
+        }
+    }
+
     #[test]
     pub fn test_cost_for_training_set_iterative_impl() {
         let nn = get_three_layer_multiple_output_nn_for_test();
@@ -1922,12 +2923,13 @@ mod tests {
         nn.train_stochastic(
             &training_data,
             1000,
-            &Optimizer::standard_gradient_descent(0.9),
+            &OptimizerConfig::standard_gradient_descent(0.9),
             training_data.len(),
             Some(&check_options),
             None,
             None,
             None,
+            None,
         )
         .unwrap();
 
@@ -2014,12 +3016,13 @@ mod tests {
         nn.train_stochastic(
             &training_data,
             1000,
-            &Optimizer::standard_gradient_descent(0.9),
+            &OptimizerConfig::standard_gradient_descent(0.9),
             training_data.len(),
             Some(&check_options),
             None,
             None,
             None,
+            None,
         )
         .unwrap();
 
@@ -2172,26 +3175,26 @@ mod tests {
 
         let mini_batch_size = training_data.len();
 
-        let test_data = vec![
+        let validation_data = vec![
             NDTrainingDataPoint::new(column_vector![2.0, 2.0], column_vector![1.0]),
             NDTrainingDataPoint::new(column_vector![-2.0, -2.0], column_vector![0.0]),
         ];
 
         let early_stop_config = EarlyStopConfig {
-            test_data: &test_data,
-            cost_threshold: 0.0001,
-            check_every: 10,
+            cost_threshold: Some(0.0001),
+            ..EarlyStopConfig::new(10)
         };
 
         nn.train_stochastic(
-            &training_data,
+            Datasets::new(&training_data).with_validation(&validation_data),
             epocs,
-            &Optimizer::standard_gradient_descent(0.9),
+            &OptimizerConfig::standard_gradient_descent(0.9),
             mini_batch_size,
             Some(&check_options),
             Some(early_stop_config),
             None,
             None,
+            None,
         )
         .unwrap();
 
@@ -2277,27 +3280,27 @@ mod tests {
 
         let mini_batch_size = training_data.len();
 
-        let test_data = vec![
+        let validation_data = vec![
             NDTrainingDataPoint::new(column_vector![2.0, 2.0], column_vector![1.0]),
             NDTrainingDataPoint::new(column_vector![-2.0, -2.0], column_vector![0.0]),
         ];
 
         let early_stop_config = EarlyStopConfig {
-            test_data: &test_data,
-            cost_threshold: 0.0001,
-            check_every: 10,
+            cost_threshold: Some(0.0001),
+            ..EarlyStopConfig::new(10)
         };
 
         nn.train_stochastic(
-            &training_data,
+            Datasets::new(&training_data).with_validation(&validation_data),
             epocs,
-            // &Optimizer::standard_gradient_descent(0.9),
-            &Optimizer::momentum(0.9, 0.9),
+            // &OptimizerConfig::standard_gradient_descent(0.9),
+            &OptimizerConfig::momentum(0.9, 0.9),
             mini_batch_size,
             Some(&check_options),
             Some(early_stop_config),
             None,
             None,
+            None,
         )
         .unwrap();
 
@@ -2382,27 +3385,27 @@ mod tests {
 
         let mini_batch_size = training_data.len();
 
-        let test_data = vec![
+        let validation_data = vec![
             NDTrainingDataPoint::new(column_vector![2.0, 2.0], column_vector![1.0]),
             NDTrainingDataPoint::new(column_vector![-2.0, -2.0], column_vector![0.0]),
         ];
 
         let early_stop_config = EarlyStopConfig {
-            test_data: &test_data,
-            cost_threshold: 0.0001,
-            check_every: 10,
+            cost_threshold: Some(0.0001),
+            ..EarlyStopConfig::new(10)
         };
 
         nn.train_stochastic(
-            &training_data,
+            Datasets::new(&training_data).with_validation(&validation_data),
             epocs,
-            // &Optimizer::standard_gradient_descent(0.9),
-            &Optimizer::Adam(optimizer::AdamConfig::with_learning_rate(0.01)),
+            // &OptimizerConfig::standard_gradient_descent(0.9),
+            &OptimizerConfig::Adam(optimizer::AdamConfig::with_learning_rate(0.01)),
             mini_batch_size,
             Some(&check_options),
             Some(early_stop_config),
             None,
             None,
+            None,
         )
         .unwrap();
 
@@ -2444,31 +3447,1420 @@ mod tests {
         // assert_eq!(1, 0);
     }
 
-    // #[test]
-    // fn test_with_mnist() {
-    //     time_test!();
+    #[test]
+    fn test_nn_using_more_hidden_layers_with_more_neurons_comparing_optimizers() {
+        time_test!();
 
-    //     let (training_data, test_data) = mnist_data::get_mnist_data();
-    //     let mut nn = NeuralNetworkBuilder::new()
-    //         .with_input_layer(784)
-    //         .with_hidden_layer(100, BuilderWeightsAndBiasesConfig::RandomBasic)
-    //         .with_hidden_layer(100, BuilderWeightsAndBiasesConfig::RandomBasic)
-    //         .with_output_layer(10, BuilderWeightsAndBiasesConfig::RandomBasic)
-    //         .build();
+        let training_data = get_data_set_1();
 
-    //     let check_options = CheckOptions {
-    //         gradient_checking: false,
-    //         cost_decreasing_check: false,
-    //     };
+        // the same 2 x 16 x 16 x 1 network (same seed, so same initial weights) for each optimizer
+        let build_nn = || {
+            NeuralNetworkBuilder::new()
+                .with_input_layer(2)
+                .with_hidden_layer(
+                    16,
+                    Initializer::XavierNormalHOMLForSigmoid,
+                    ActivationFunction::LeakyReLU(0.1),
+                )
+                .with_hidden_layer(
+                    16,
+                    Initializer::XavierNormalHOMLForSigmoid,
+                    ActivationFunction::LeakyReLU(0.1),
+                )
+                .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+                .with_cost_fn(cost::CostFunc::QuadraticCost)
+                .with_seed(1)
+                .build()
+        };
 
-    //     // let n = training_data[0].input_v.num_elements();
-    //     // assert_eq!(n, 0);
-    //     nn.train(&training_data, 1, 0.9, Some(check_options));
-    // }
+        let initial_cost = build_nn().cost_training_set(&training_data).unwrap();
+        println!("initial cost: {}", initial_cost);
+
+        let epocs = 75;
+        let optimizers = [
+            OptimizerConfig::momentum(0.9, 0.9),
+            OptimizerConfig::nesterov(0.9, 0.9),
+            OptimizerConfig::AdaGrad(optimizer::AdaGradConfig::with_learning_rate(0.1)),
+            OptimizerConfig::RMSProp(optimizer::RMSPropConfig::with_learning_rate(0.01)),
+            OptimizerConfig::Adam(optimizer::AdamConfig::with_learning_rate(0.01)),
+            OptimizerConfig::AdamW(optimizer::AdamWConfig::with_learning_rate(0.01)),
+            OptimizerConfig::AMSGrad(optimizer::AMSGradConfig::with_learning_rate(0.01)),
+        ];
+
+        let mut final_costs = vec![];
+        for optimizer in optimizers.iter() {
+            let mut nn = build_nn();
+            nn.train_stochastic(
+                &training_data,
+                epocs,
+                optimizer,
+                training_data.len(),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+            let cost = nn.cost_training_set(&training_data).unwrap();
+            let predicted_output_1 = nn.feed_forward(&column_vector![2.0, 2.0]).into_value();
+            let predicted_output_2 = nn.feed_forward(&column_vector![-2.0, -2.0]).into_value();
+            final_costs.push((optimizer, cost, predicted_output_1, predicted_output_2));
+        }
+
+        println!("\ncost after {} epocs:", epocs);
+        for (optimizer, cost, _, _) in final_costs.iter() {
+            println!("{:?}: {}", optimizer, cost);
+        }
+
+        for (optimizer, cost, predicted_output_1, predicted_output_2) in final_costs {
+            assert!(
+                cost < initial_cost / 10.0,
+                "{:?} only got the cost down to {}",
+                optimizer,
+                cost
+            );
+            assert!(approx_eq!(f64, predicted_output_1, BLUE, epsilon = 0.05));
+            assert!(approx_eq!(f64, predicted_output_2, ORANGE, epsilon = 0.05));
+        }
+    }
 
     #[test]
-    fn test_get_weight_matrix_shape() {
-        let nn = NeuralNetwork::new(vec![2, 3, 2]);
+    fn resume_from_checkpoint_follows_the_same_trajectory() {
+        let training_data = get_data_set_1();
+
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(
+                8,
+                Initializer::HeForReLUAndVariants,
+                ActivationFunction::LeakyReLU(0.1),
+            )
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .build();
+
+        let mut session_directory = std::env::temp_dir();
+        session_directory.push(format!(
+            "resume-from-checkpoint-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&session_directory).unwrap();
+
+        let mut session_logger = TrainingSessionLogger::new().with_checkpoint_every(10);
+        session_logger.full_session_output_directory = Some(session_directory.clone());
+
+        let epocs = 30;
+        let mini_batch_size = 5; // smaller than the training set so the mini batch RNG matters
+        let optimizer = OptimizerConfig::Adam(optimizer::AdamConfig::with_learning_rate(0.01));
+
+        nn.train_stochastic(
+            &training_data,
+            epocs,
+            &optimizer,
+            mini_batch_size,
+            None,
+            None,
+            None,
+            Some(session_logger),
+            None,
+        )
+        .unwrap();
+
+        let latest_checkpoint = training_log::latest_checkpoint_in(&session_directory)
+            .unwrap()
+            .unwrap();
+        assert!(latest_checkpoint.ends_with("checkpoint-epoch-30.bin"));
+
+        let mut checkpoint_path = session_directory.clone();
+        checkpoint_path.push("checkpoint-epoch-10.bin");
+
+        let resumed_nn = NeuralNetwork::resume_from_checkpoint(
+            &checkpoint_path,
+            &training_data,
+            epocs,
+            &optimizer,
+            mini_batch_size,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        std::fs::remove_dir_all(&session_directory).unwrap();
+
+        assert_eq!(resumed_nn.w, nn.w);
+        assert_eq!(resumed_nn.b, nn.b);
+    }
+
+    #[test]
+    fn resume_from_checkpoint_follows_the_same_trajectory_with_reduce_on_plateau() {
+        // the validation set has the opposite labels, so its cost goes up, and the learning rate comes down, as training
+        // goes on. The early stopping checks that compute the cost are on the same epochs as the checkpoints
+        let training_data = get_data_set_1();
+        let validation_data = get_data_set_1_with_opposite_labels();
+        let datasets = Datasets::new(&training_data).with_validation(&validation_data);
+
+        let mut session_directory = std::env::temp_dir();
+        session_directory.push(format!(
+            "resume-with-reduce-on-plateau-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&session_directory).unwrap();
+        let mut session_logger = TrainingSessionLogger::new().with_checkpoint_every(5);
+        session_logger.full_session_output_directory = Some(session_directory.clone());
+
+        let epocs = 20;
+        let mini_batch_size = 5;
+        let optimizer = OptimizerConfig::standard_gradient_descent(0.9);
+        let early_stop_config = Some(EarlyStopConfig {
+            restore_best_weights: false,
+            ..EarlyStopConfig::new(5)
+        });
+        let training_options = TrainingOptions {
+            seed: Some(3),
+            learning_rate_schedule: LearningRateSchedule::ReduceOnPlateau {
+                factor: 0.5,
+                patience: 0,
+                min_delta: 0.0,
+                min_factor: 0.0,
+            },
+            ..TrainingOptions::default()
+        };
+
+        let mut nn = build_relu_network_for_health_check_tests();
+        nn.train_stochastic(
+            datasets,
+            epocs,
+            &optimizer,
+            mini_batch_size,
+            None,
+            early_stop_config,
+            None,
+            Some(session_logger),
+            Some(&training_options),
+        )
+        .unwrap();
+
+        let mut checkpoint_path = session_directory.clone();
+        checkpoint_path.push("checkpoint-epoch-10.bin");
+        let (_, state_at_checkpoint) = Checkpoint::read_from_file(&checkpoint_path)
+            .unwrap()
+            .into_parts::<f64>()
+            .unwrap();
+        let resumed_nn = NeuralNetwork::resume_from_checkpoint(
+            &checkpoint_path,
+            datasets,
+            epocs,
+            &optimizer,
+            mini_batch_size,
+            None,
+            early_stop_config,
+            None,
+            None,
+            Some(&training_options),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&session_directory).unwrap();
+
+        // the check at epoch 10 didn't improve on the one at epoch 5, and the checkpoint has the reduction it caused
+        assert_eq!(state_at_checkpoint.schedule_state.plateau_factor, 0.5);
+        assert_eq!(resumed_nn.w, nn.w);
+        assert_eq!(resumed_nn.b, nn.b);
+    }
+
+    #[test]
+    fn resume_from_checkpoint_keeps_the_early_stopping_patience_and_best_weights() {
+        // the validation cost goes up, so the first check (at epoch 5) is the best, and the checks at 10 and 15 use up
+        // the patience. Resuming from epoch 10 has to stop at 15 too, and put back the weights from epoch 5
+        let training_data = get_data_set_1();
+        let validation_data = get_data_set_1_with_opposite_labels();
+        let datasets = Datasets::new(&training_data).with_validation(&validation_data);
+
+        let mut session_directory = std::env::temp_dir();
+        session_directory.push(format!(
+            "resume-with-early-stopping-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&session_directory).unwrap();
+        let mut session_logger = TrainingSessionLogger::new().with_checkpoint_every(5);
+        session_logger.full_session_output_directory = Some(session_directory.clone());
+
+        let epocs = 100;
+        let mini_batch_size = 5;
+        let optimizer = OptimizerConfig::standard_gradient_descent(0.9);
+        let early_stop_config = Some(EarlyStopConfig {
+            patience: Some(2),
+            ..EarlyStopConfig::new(5)
+        });
+        let training_options = TrainingOptions {
+            seed: Some(3),
+            ..TrainingOptions::default()
+        };
+
+        let mut nn = build_relu_network_for_health_check_tests();
+        nn.train_stochastic(
+            datasets,
+            epocs,
+            &optimizer,
+            mini_batch_size,
+            None,
+            early_stop_config,
+            None,
+            Some(session_logger),
+            Some(&training_options),
+        )
+        .unwrap();
+        let latest_checkpoint = training_log::latest_checkpoint_in(&session_directory)
+            .unwrap()
+            .unwrap();
+
+        let mut checkpoint_path = session_directory.clone();
+        checkpoint_path.push("checkpoint-epoch-10.bin");
+        let resumed_nn = NeuralNetwork::resume_from_checkpoint(
+            &checkpoint_path,
+            datasets,
+            epocs,
+            &optimizer,
+            mini_batch_size,
+            None,
+            early_stop_config,
+            None,
+            None,
+            Some(&training_options),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&session_directory).unwrap();
+
+        let mut nn_at_first_check = build_relu_network_for_health_check_tests();
+        train_for_early_stopping_tests(&mut nn_at_first_check, 5, None);
+        assert!(latest_checkpoint.ends_with("checkpoint-epoch-15.bin"));
+        assert_eq!(nn.w, nn_at_first_check.w);
+        assert_eq!(resumed_nn.w, nn.w);
+        assert_eq!(resumed_nn.b, nn.b);
+    }
+
+    /// Steps each weight and bias by the (scheduled) `learning_rate` in the direction that decreases the cost. Only
+    /// uses the public API, so stands in for an optimizer written in another crate.
+    #[derive(Debug)]
+    struct SignDescent {
+        learning_rate: f64,
+    }
+
+    impl<T: Float> Optimizer<T> for SignDescent {
+        fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+            OptimizerState::with_zero_buffers(params, &["last_update"])
+        }
+
+        fn learning_rate(&self) -> f64 {
+            self.learning_rate
+        }
+
+        fn step(
+            &self,
+            params: &mut BigTheta<T>,
+            gradients: &BigTheta<T>,
+            state: &mut OptimizerState<T>,
+        ) {
+            let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+            let [last_update] = state.buffers_mut(["last_update"]);
+            let update = gradients
+                .unroll()
+                .iter()
+                .map(|g| g.signum() * T::from_f64(learning_rate))
+                .collect::<Vec<T>>();
+            *last_update = BigTheta::from_unrolled(&params.sizes, &update);
+            params.subtract_in_place(last_update);
+        }
+    }
+
+    #[test]
+    fn train_stochastic_works_with_an_optimizer_from_outside_the_crate() {
+        let training_data = get_data_set_1();
+
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(
+                8,
+                Initializer::HeForReLUAndVariants,
+                ActivationFunction::LeakyReLU(0.1),
+            )
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .with_seed(3)
+            .build();
+        let initial_cost = nn.cost_training_set(&training_data).unwrap();
+
+        let mut session_directory = std::env::temp_dir();
+        session_directory.push(format!("custom-optimizer-test-{}", std::process::id()));
+        std::fs::create_dir_all(&session_directory).unwrap();
+        let mut session_logger = TrainingSessionLogger::new().with_checkpoint_every(10);
+        session_logger.full_session_output_directory = Some(session_directory.clone());
+
+        let optimizer = SignDescent {
+            learning_rate: 0.01,
+        };
+        nn.train_stochastic(
+            &training_data,
+            20,
+            &optimizer,
+            training_data.len(),
+            None,
+            None,
+            None,
+            Some(session_logger),
+            None,
+        )
+        .unwrap();
+
+        let cost = nn.cost_training_set(&training_data).unwrap();
+        assert!(cost < initial_cost);
+
+        // the optimizer's state is saved with checkpoints, where it can be inspected
+        let mut checkpoint_path = session_directory.clone();
+        checkpoint_path.push("checkpoint-epoch-20.bin");
+        let (checkpoint_nn, training_state) = Checkpoint::read_from_file(&checkpoint_path)
+            .unwrap()
+            .into_parts::<f64>()
+            .unwrap();
+        assert_eq!(checkpoint_nn.w, nn.w);
+        let optimizer_state = training_state.optimizer_state;
+        assert_eq!(optimizer_state.steps_count, 20);
+        let last_update = optimizer_state.buffer("last_update").unwrap().unroll();
+        assert!(last_update.iter().all(|x| x.abs() == 0.01));
+
+        // resuming with an optimizer that keeps different state fails rather than silently starting it over
+        let result = NeuralNetwork::resume_from_checkpoint(
+            &checkpoint_path,
+            &training_data,
+            30,
+            &OptimizerConfig::Adam(optimizer::AdamConfig::default()),
+            training_data.len(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        std::fs::remove_dir_all(&session_directory).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn train_stochastic_takes_one_step_per_mini_batch() {
+        let training_data = get_data_set_1(); // 20 examples
+
+        let steps_after_two_epochs = |mini_batch_sampling: MiniBatchSampling| {
+            let mut nn = NeuralNetwork::new(vec![2, 3, 1]);
+
+            let mut session_directory = std::env::temp_dir();
+            session_directory.push(format!(
+                "steps-per-epoch-test-{}-{:?}",
+                std::process::id(),
+                mini_batch_sampling
+            ));
+            std::fs::create_dir_all(&session_directory).unwrap();
+
+            let mut session_logger = TrainingSessionLogger::new().with_checkpoint_every(2);
+            session_logger.full_session_output_directory = Some(session_directory.clone());
+
+            nn.train_stochastic(
+                &training_data,
+                2,
+                &OptimizerConfig::Adam(optimizer::AdamConfig::default()),
+                6,
+                None,
+                None,
+                None,
+                Some(session_logger),
+                Some(&TrainingOptions {
+                    mini_batch_sampling,
+                    ..TrainingOptions::default()
+                }),
+            )
+            .unwrap();
+
+            let mut checkpoint_path = session_directory.clone();
+            checkpoint_path.push("checkpoint-epoch-2.bin");
+            let checkpoint = Checkpoint::read_from_file(&checkpoint_path).unwrap();
+            std::fs::remove_dir_all(&session_directory).unwrap();
+
+            assert_eq!(checkpoint.epochs_count, 2);
+            checkpoint.optimizer_state.steps_count
+        };
+
+        assert_eq!(
+            steps_after_two_epochs(MiniBatchSampling::ShuffledEpochs {
+                partial_batch: mini_batch::PartialBatch::Keep
+            }),
+            8
+        );
+        assert_eq!(
+            steps_after_two_epochs(MiniBatchSampling::ShuffledEpochs {
+                partial_batch: mini_batch::PartialBatch::Drop
+            }),
+            6
+        );
+        assert_eq!(
+            steps_after_two_epochs(MiniBatchSampling::RandomContiguousSlice),
+            2
+        );
+    }
+
+    #[test]
+    fn training_with_the_same_seed_is_reproducible() {
+        let training_data = get_data_set_1();
+
+        let train_with_seed = |seed: u64| {
+            let mut nn = NeuralNetworkBuilder::new()
+                .with_input_layer(2)
+                .with_hidden_layer(
+                    8,
+                    Initializer::HeForReLUAndVariants,
+                    ActivationFunction::LeakyReLU(0.1),
+                )
+                .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+                .with_cost_fn(cost::CostFunc::QuadraticCost)
+                .with_seed(seed)
+                .build();
+
+            let mut session_directory = std::env::temp_dir();
+            session_directory.push(format!(
+                "seeded-training-test-{}-{}",
+                std::process::id(),
+                seed
+            ));
+            std::fs::create_dir_all(&session_directory).unwrap();
+            let mut session_logger = TrainingSessionLogger::new();
+            session_logger.full_session_output_directory = Some(session_directory.clone());
+
+            nn.train_stochastic(
+                &training_data,
+                20,
+                &OptimizerConfig::Adam(optimizer::AdamConfig::with_learning_rate(0.01)),
+                5, // smaller than the training set so the mini batch RNG matters
+                None,
+                None,
+                Some(5),
+                Some(session_logger),
+                Some(&TrainingOptions {
+                    seed: Some(seed),
+                    ..TrainingOptions::default()
+                }),
+            )
+            .unwrap();
+
+            let read_json = |file_name: &str| {
+                let mut path = session_directory.clone();
+                path.push(file_name);
+                serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(path).unwrap())
+                    .unwrap()
+            };
+            let session_info = read_json("session-info.json");
+            let cost_curve = [4, 9, 14, 19].map(|epoch| {
+                read_json(&format!("epoch-{}.json", epoch))["training_set_cost"].clone()
+            });
+            std::fs::remove_dir_all(&session_directory).unwrap();
+
+            assert_eq!(session_info["seed"], seed);
+            (nn, cost_curve)
+        };
+
+        let (nn_1, cost_curve_1) = train_with_seed(7);
+        let (nn_2, cost_curve_2) = train_with_seed(7);
+        let (nn_3, _) = train_with_seed(8);
+
+        assert_eq!(nn_1.w, nn_2.w);
+        assert_eq!(nn_1.b, nn_2.b);
+        assert_eq!(cost_curve_1, cost_curve_2);
+        assert_ne!(nn_1.w, nn_3.w);
+    }
+
+    #[test]
+    fn scheduled_learning_rate_is_logged_with_each_update() {
+        let training_data = get_data_set_1();
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(
+                8,
+                Initializer::HeForReLUAndVariants,
+                ActivationFunction::LeakyReLU(0.1),
+            )
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .with_seed(3)
+            .build();
+
+        let mut session_directory = std::env::temp_dir();
+        session_directory.push(format!("lr-schedule-test-{}", std::process::id()));
+        std::fs::create_dir_all(&session_directory).unwrap();
+        let mut session_logger = TrainingSessionLogger::new();
+        session_logger.full_session_output_directory = Some(session_directory.clone());
+
+        nn.train_stochastic(
+            &training_data,
+            5,
+            &OptimizerConfig::standard_gradient_descent(0.8),
+            training_data.len(),
+            None,
+            None,
+            Some(1),
+            Some(session_logger),
+            Some(&TrainingOptions {
+                seed: Some(3),
+                learning_rate_schedule: LearningRateSchedule::StepDecay {
+                    every: 2,
+                    gamma: 0.5,
+                    per: lr_schedule::ScheduleUnit::Epoch,
+                },
+                ..TrainingOptions::default()
+            }),
+        )
+        .unwrap();
+
+        let learning_rates = (0..5)
+            .map(|epoch| {
+                let mut path = session_directory.clone();
+                path.push(format!("epoch-{}.json", epoch));
+                let update = serde_json::from_str::<serde_json::Value>(
+                    &std::fs::read_to_string(path).unwrap(),
+                )
+                .unwrap();
+                update["learning_rate"].as_f64().unwrap()
+            })
+            .collect::<Vec<f64>>();
+        std::fs::remove_dir_all(&session_directory).unwrap();
+
+        assert_eq!(learning_rates, vec![0.8, 0.8, 0.4, 0.4, 0.2]);
+    }
+
+    #[test]
+    fn invalid_learning_rate_schedules_are_rejected_before_training_starts() {
+        let training_data = get_data_set_1();
+        let mut nn = build_relu_network_for_health_check_tests();
+        let initial_weights = nn.w.clone();
+
+        let result = nn.train_stochastic(
+            &training_data,
+            5,
+            &OptimizerConfig::standard_gradient_descent(0.8),
+            5,
+            None,
+            None,
+            None,
+            None,
+            Some(&TrainingOptions {
+                learning_rate_schedule: LearningRateSchedule::CosineWithWarmRestarts {
+                    period: 0,
+                    period_mult: 1,
+                    min_factor: 0.0,
+                    per: lr_schedule::ScheduleUnit::Epoch,
+                },
+                ..TrainingOptions::default()
+            }),
+        );
+
+        assert!(matches!(
+            result,
+            Err(NeuralNetworkError::InvalidTrainingOptions(
+                TrainingOptionsError::InvalidLearningRateSchedule
+            ))
+        ));
+        assert_eq!(nn.w, initial_weights);
+    }
+
+    #[test]
+    fn a_zero_mini_batch_size_is_rejected_before_training_starts() {
+        let training_data = get_data_set_1();
+        let mut nn = build_relu_network_for_health_check_tests();
+        let initial_weights = nn.w.clone();
+
+        let result = nn.train_stochastic(
+            &training_data,
+            5,
+            &OptimizerConfig::standard_gradient_descent(0.8),
+            0,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        assert!(matches!(
+            result,
+            Err(NeuralNetworkError::InvalidTrainingOptions(
+                TrainingOptionsError::InvalidMiniBatchSize
+            ))
+        ));
+        assert_eq!(nn.w, initial_weights);
+    }
+
+    #[test]
+    fn gradient_norm_clipping_limits_the_step_and_logs_the_unclipped_norm() {
+        let training_data = get_data_set_1();
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(8, Initializer::RandomBasic, ActivationFunction::ReLU)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .with_seed(3)
+            .build();
+        let all_examples = (0..training_data.len()).collect::<Vec<usize>>();
+        let unclipped_norm = nn
+            .compute_gradients_batched(&training_data, &all_examples, None)
+            .l2_norm();
+        let max_norm = unclipped_norm / 4.0;
+        let initial_params = nn.unroll_weights_and_biases();
+
+        let mut session_directory = std::env::temp_dir();
+        session_directory.push(format!("gradient-clipping-test-{}", std::process::id()));
+        std::fs::create_dir_all(&session_directory).unwrap();
+        let mut session_logger = TrainingSessionLogger::new();
+        session_logger.full_session_output_directory = Some(session_directory.clone());
+
+        // a single full batch step
+        nn.train_stochastic(
+            &training_data,
+            1,
+            &OptimizerConfig::standard_gradient_descent(0.5),
+            training_data.len(),
+            None,
+            None,
+            Some(1),
+            Some(session_logger),
+            Some(&TrainingOptions {
+                seed: Some(3),
+                gradient_clipping: GradientClipping::by_norm(max_norm),
+                ..TrainingOptions::default()
+            }),
+        )
+        .unwrap();
+
+        let mut path = session_directory.clone();
+        path.push("epoch-0.json");
+        let update =
+            serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(path).unwrap())
+                .unwrap();
+        std::fs::remove_dir_all(&session_directory).unwrap();
+        let logged_norm = update["gradient_norm"].as_f64().unwrap();
+        assert!(approx_eq!(f64, logged_norm, unclipped_norm, epsilon = 1e-9));
+
+        // the step was the learning rate times the clipped gradients
+        let step_norm = nn
+            .unroll_weights_and_biases()
+            .iter()
+            .zip(initial_params.iter())
+            .map(|(after, before)| (after - before).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!(approx_eq!(f64, step_norm, 0.5 * max_norm, epsilon = 1e-9));
+    }
+
+    fn build_relu_network_for_health_check_tests() -> NeuralNetwork {
+        NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(8, Initializer::RandomBasic, ActivationFunction::ReLU)
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .with_seed(3)
+            .build()
+    }
+
+    fn train_for_health_check_tests(
+        nn: &mut NeuralNetwork,
+        learning_rate: f64,
+        health_checks: HealthChecks,
+    ) -> Result<(), NeuralNetworkError> {
+        let training_data = get_data_set_1();
+        nn.train_stochastic(
+            &training_data,
+            5,
+            &OptimizerConfig::standard_gradient_descent(learning_rate),
+            5,
+            None,
+            None,
+            None,
+            None,
+            Some(&TrainingOptions {
+                seed: Some(3),
+                health_checks: Some(health_checks),
+                ..TrainingOptions::default()
+            }),
+        )
+    }
+
+    #[test]
+    fn health_checks_abort_diverging_training() {
+        let mut nn = build_relu_network_for_health_check_tests();
+        let result = train_for_health_check_tests(&mut nn, 1e300, HealthChecks::default());
+
+        // the first step throws the weights out so far that the output layer's activations are NaN in the second
+        match result {
+            Err(NeuralNetworkError::UnhealthyTraining(failure)) => assert_eq!(
+                failure,
+                HealthCheckFailure {
+                    epochs_completed: 0,
+                    steps_completed: 1,
+                    problem: HealthProblem::NonFiniteActivations { layer_index: 2 }
+                }
+            ),
+            _ => panic!("expected a failed health check"),
+        }
+    }
+
+    #[test]
+    fn health_checks_can_roll_back_and_carry_on_with_a_lower_learning_rate() {
+        let mut nn = build_relu_network_for_health_check_tests();
+        let health_checks = HealthChecks {
+            max_dead_fraction: None,
+            on_failure: OnHealthCheckFailure::RollBack {
+                max_rollbacks: 1,
+                learning_rate_factor: 1e-300,
+            },
+        };
+        train_for_health_check_tests(&mut nn, 1e300, health_checks).unwrap();
+
+        assert!(nn.w.values().all(|w| w.data.iter().all(|x| x.is_finite())));
+
+        // but it only rolls back so many times
+        let mut nn = build_relu_network_for_health_check_tests();
+        let health_checks = HealthChecks {
+            max_dead_fraction: None,
+            on_failure: OnHealthCheckFailure::RollBack {
+                max_rollbacks: 2,
+                learning_rate_factor: 1.0,
+            },
+        };
+        assert!(matches!(
+            train_for_health_check_tests(&mut nn, 1e300, health_checks),
+            Err(NeuralNetworkError::UnhealthyTraining(_))
+        ));
+    }
+
+    #[test]
+    fn health_checks_can_detect_dead_units() {
+        let mut nn = build_relu_network_for_health_check_tests();
+        // every hidden unit's z is negative for every input, so they all output zero
+        for b in nn.b.get_mut(&1).unwrap().iter_mut() {
+            *b = -1000.0;
+        }
+
+        // dead units are only a problem with a max_dead_fraction
+        train_for_health_check_tests(&mut nn, 0.1, HealthChecks::default()).unwrap();
+
+        let health_checks = HealthChecks {
+            max_dead_fraction: Some(0.5),
+            ..HealthChecks::default()
+        };
+        match train_for_health_check_tests(&mut nn, 0.1, health_checks) {
+            Err(NeuralNetworkError::UnhealthyTraining(failure)) => assert_eq!(
+                failure,
+                HealthCheckFailure {
+                    epochs_completed: 0,
+                    steps_completed: 0,
+                    problem: HealthProblem::DeadUnits {
+                        layer_index: 1,
+                        dead_units: 8,
+                        size: 8
+                    }
+                }
+            ),
+            _ => panic!("expected a failed health check"),
+        }
+    }
+
+    fn get_data_set_1_with_opposite_labels() -> Vec<NDTrainingDataPoint> {
+        get_data_set_1()
+            .into_iter()
+            .map(|p| {
+                NDTrainingDataPoint::new(p.input_v, column_vector![1.0 - p.desired_output_v[0]])
+            })
+            .collect()
+    }
+
+    /// The validation set has the opposite labels to the training set, so its cost only gets worse as the network
+    /// learns, and the first early stop check is the best one.
+    fn train_for_early_stopping_tests(
+        nn: &mut NeuralNetwork,
+        epocs: usize,
+        early_stop_config: Option<EarlyStopConfig>,
+    ) {
+        let training_data = get_data_set_1();
+        let validation_data = get_data_set_1_with_opposite_labels();
+        nn.train_stochastic(
+            Datasets::new(&training_data).with_validation(&validation_data),
+            epocs,
+            &OptimizerConfig::standard_gradient_descent(0.9),
+            5,
+            None,
+            early_stop_config,
+            None,
+            None,
+            Some(&TrainingOptions {
+                seed: Some(3),
+                ..TrainingOptions::default()
+            }),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn early_stopping_runs_out_of_patience_and_restores_the_best_weights() {
+        let early_stop_config = EarlyStopConfig {
+            patience: Some(2),
+            ..EarlyStopConfig::new(2)
+        };
+
+        let mut nn = build_relu_network_for_health_check_tests();
+        train_for_early_stopping_tests(&mut nn, 100, Some(early_stop_config));
+
+        let mut nn_at_first_check = build_relu_network_for_health_check_tests();
+        train_for_early_stopping_tests(&mut nn_at_first_check, 2, None);
+        assert_eq!(nn.w, nn_at_first_check.w);
+        assert_eq!(nn.b, nn_at_first_check.b);
+
+        // without restoring, it's left with the weights from when it stopped, after the 3rd check
+        let mut nn = build_relu_network_for_health_check_tests();
+        let early_stop_config = EarlyStopConfig {
+            patience: Some(2),
+            restore_best_weights: false,
+            ..EarlyStopConfig::new(2)
+        };
+        train_for_early_stopping_tests(&mut nn, 100, Some(early_stop_config));
+
+        let mut nn_at_third_check = build_relu_network_for_health_check_tests();
+        train_for_early_stopping_tests(&mut nn_at_third_check, 6, None);
+        assert_eq!(nn.w, nn_at_third_check.w);
+    }
+
+    #[test]
+    fn reduce_on_plateau_ignores_the_test_set() {
+        let training_data = get_data_set_1();
+        // its cost only gets worse as the network learns, so it would reduce the learning rate at every update
+        let test_data = get_data_set_1_with_opposite_labels();
+        let train = |learning_rate_schedule| {
+            let mut nn = build_relu_network_for_health_check_tests();
+            nn.train_stochastic(
+                Datasets::new(&training_data).with_test(&test_data),
+                6,
+                &OptimizerConfig::standard_gradient_descent(0.9),
+                5,
+                None,
+                None,
+                Some(1),
+                None,
+                Some(&TrainingOptions {
+                    seed: Some(3),
+                    learning_rate_schedule,
+                    ..TrainingOptions::default()
+                }),
+            )
+            .unwrap();
+            nn
+        };
+
+        let nn = train(LearningRateSchedule::ReduceOnPlateau {
+            factor: 0.5,
+            patience: 0,
+            min_delta: 0.0,
+            min_factor: 0.0,
+        });
+        assert_eq!(nn.w, train(LearningRateSchedule::Constant).w);
+    }
+
+    #[test]
+    fn early_stopping_is_off_without_a_validation_set() {
+        let training_data = get_data_set_1();
+        let mut nn = build_relu_network_for_health_check_tests();
+        nn.train_stochastic(
+            &training_data,
+            10,
+            &OptimizerConfig::standard_gradient_descent(0.9),
+            5,
+            None,
+            Some(EarlyStopConfig {
+                patience: Some(2),
+                ..EarlyStopConfig::new(2)
+            }),
+            None,
+            None,
+            Some(&TrainingOptions {
+                seed: Some(3),
+                ..TrainingOptions::default()
+            }),
+        )
+        .unwrap();
+
+        // rather than checking against the test set, or the training set
+        let mut nn_after_all_epochs = build_relu_network_for_health_check_tests();
+        train_for_early_stopping_tests(&mut nn_after_all_epochs, 10, None);
+        assert_eq!(nn.w, nn_after_all_epochs.w);
+    }
+
+    #[test]
+    fn early_stop_checks_every_0_epochs_are_rejected_before_training() {
+        let training_data = get_data_set_1();
+        let validation_data = get_data_set_1_with_opposite_labels();
+        let mut nn = build_relu_network_for_health_check_tests();
+        let initial_weights = nn.w.clone();
+
+        // whether or not there's a validation set to check against
+        for datasets in [
+            Datasets::new(&training_data),
+            Datasets::new(&training_data).with_validation(&validation_data),
+        ] {
+            let result = nn.train_stochastic(
+                datasets,
+                10,
+                &OptimizerConfig::standard_gradient_descent(0.9),
+                5,
+                None,
+                Some(EarlyStopConfig::new(0)),
+                None,
+                None,
+                None,
+            );
+            assert!(matches!(
+                result,
+                Err(NeuralNetworkError::InvalidTrainingOptions(
+                    TrainingOptionsError::InvalidEarlyStopConfig
+                ))
+            ));
+        }
+        assert_eq!(nn.w, initial_weights);
+    }
+
+    #[test]
+    fn full_cost_updates_log_the_training_validation_and_test_sets_separately() {
+        let training_data = get_data_set_1();
+        let validation_data = &training_data[..5];
+        // the opposite labels, so the test cost can't be mistaken for the training cost
+        let test_data = get_data_set_1_with_opposite_labels();
+
+        let mut session_directory = std::env::temp_dir();
+        session_directory.push(format!("data-split-test-{}", std::process::id()));
+        std::fs::create_dir_all(&session_directory).unwrap();
+        let mut session_logger = TrainingSessionLogger::new();
+        session_logger.full_session_output_directory = Some(session_directory.clone());
+
+        let mut nn = build_relu_network_for_health_check_tests();
+        nn.train_stochastic(
+            Datasets::new(&training_data)
+                .with_validation(validation_data)
+                .with_test(&test_data),
+            2,
+            &OptimizerConfig::standard_gradient_descent(0.9),
+            5,
+            None,
+            None,
+            Some(2),
+            Some(session_logger),
+            Some(&TrainingOptions {
+                seed: Some(3),
+                ..TrainingOptions::default()
+            }),
+        )
+        .unwrap();
+
+        let mut path = session_directory.clone();
+        path.push("epoch-1.json");
+        let update =
+            serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(path).unwrap())
+                .unwrap();
+        std::fs::remove_dir_all(&session_directory).unwrap();
+
+        let logged = |field: &str| update[field].as_f64().unwrap();
+        assert_eq!(
+            logged("training_set_cost"),
+            nn.cost_training_set(&training_data).unwrap()
+        );
+        assert_eq!(
+            logged("validation_set_cost"),
+            nn.cost_training_set(validation_data).unwrap()
+        );
+        assert_eq!(
+            logged("test_set_cost"),
+            nn.cost_training_set(&test_data).unwrap()
+        );
+        assert_eq!(
+            logged("test_set_accuracy"),
+            nn.accuracy(&test_data).unwrap()
+        );
+        assert!(logged("test_set_cost") > logged("training_set_cost"));
+        assert_ne!(
+            logged("test_set_accuracy"),
+            logged("training_set_accuracy")
+        );
+    }
+
+    /// Records what it's told, and asks to stop once it's seen `stop_after_steps` steps.
+    #[derive(Default)]
+    struct RecordingCallback {
+        stop_after_steps: Option<usize>,
+        initial_cost: Option<f64>,
+        step_losses: Vec<f64>,
+        /// (epochs completed, steps completed, mean loss)
+        epoch_ends: Vec<(usize, usize, f64)>,
+        epochs_completed_at_train_end: Option<usize>,
+    }
+
+    impl TrainingCallback for RecordingCallback {
+        fn on_train_begin(
+            &mut self,
+            _nn: &NeuralNetwork,
+            train_begin: &TrainBegin,
+        ) -> Result<(), NeuralNetworkError> {
+            self.initial_cost = Some(train_begin.initial_cost);
+            Ok(())
+        }
+
+        fn on_step_end(&mut self, _nn: &NeuralNetwork, step: &StepEnd) -> TrainingControl {
+            assert_eq!(step.steps_completed, self.step_losses.len() + 1);
+            self.step_losses.push(step.loss);
+            match self.stop_after_steps {
+                Some(steps) if step.steps_completed >= steps => TrainingControl::Stop,
+                _ => TrainingControl::Continue,
+            }
+        }
+
+        fn on_epoch_end(&mut self, _nn: &NeuralNetwork, epoch: &mut EpochEnd) -> TrainingControl {
+            self.epoch_ends.push((
+                epoch.epochs_completed,
+                epoch.steps_completed,
+                epoch.mean_loss,
+            ));
+            TrainingControl::Continue
+        }
+
+        fn on_train_end(&mut self, _nn: &mut NeuralNetwork, epochs_completed: usize) {
+            self.epochs_completed_at_train_end = Some(epochs_completed);
+        }
+    }
+
+    fn train_with_callbacks_for_tests(
+        nn: &mut NeuralNetwork,
+        epocs: usize,
+        callbacks: &mut [&mut dyn TrainingCallback],
+    ) {
+        nn.train_with_callbacks(
+            &get_data_set_1(),
+            epocs,
+            &OptimizerConfig::standard_gradient_descent(0.9),
+            5,
+            Some(&TrainingOptions {
+                seed: Some(3),
+                ..TrainingOptions::default()
+            }),
+            callbacks,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn callbacks_see_every_step_and_epoch() {
+        let mut nn = build_relu_network_for_health_check_tests();
+        let mut recorder = RecordingCallback::default();
+        train_with_callbacks_for_tests(&mut nn, 3, &mut [&mut recorder]);
+
+        // 20 examples in mini batches of 5
+        assert_eq!(recorder.step_losses.len(), 12);
+        assert_eq!(
+            recorder
+                .epoch_ends
+                .iter()
+                .map(|&(epochs_completed, steps_completed, _)| (epochs_completed, steps_completed))
+                .collect::<Vec<_>>(),
+            vec![(1, 4), (2, 8), (3, 12)]
+        );
+        let last_epoch_loss_sum = recorder.step_losses[8..]
+            .iter()
+            .fold(0.0, |sum, loss| sum + loss);
+        assert_eq!(recorder.epoch_ends[2].2, last_epoch_loss_sum / 4.0);
+        assert!(recorder.initial_cost.is_some());
+        assert_eq!(recorder.epochs_completed_at_train_end, Some(3));
+    }
+
+    #[test]
+    fn a_callback_can_stop_training_mid_epoch() {
+        let mut nn = build_relu_network_for_health_check_tests();
+        let mut stopper = RecordingCallback {
+            stop_after_steps: Some(6),
+            ..RecordingCallback::default()
+        };
+        let mut recorder = RecordingCallback::default();
+        train_with_callbacks_for_tests(&mut nn, 10, &mut [&mut stopper, &mut recorder]);
+
+        // the other callbacks still see the step it stopped at
+        assert_eq!(stopper.step_losses.len(), 6);
+        assert_eq!(recorder.step_losses.len(), 6);
+        assert_eq!(recorder.epoch_ends.len(), 1);
+        assert_eq!(recorder.epochs_completed_at_train_end, Some(1));
+
+        let mut nn_after_6_steps = build_relu_network_for_health_check_tests();
+        let mut stopper = RecordingCallback {
+            stop_after_steps: Some(6),
+            ..RecordingCallback::default()
+        };
+        train_with_callbacks_for_tests(&mut nn_after_6_steps, 2, &mut [&mut stopper]);
+        assert_eq!(nn.w, nn_after_6_steps.w);
+    }
+
+    #[test]
+    fn early_stopping_can_be_a_callback() {
+        let validation_data = get_data_set_1_with_opposite_labels();
+        let mut early_stopping = EarlyStopping::new(
+            EarlyStopConfig {
+                patience: Some(2),
+                ..EarlyStopConfig::new(2)
+            },
+            &validation_data,
+        );
+        let mut recorder = RecordingCallback::default();
+        let mut nn = build_relu_network_for_health_check_tests();
+        train_with_callbacks_for_tests(&mut nn, 100, &mut [&mut early_stopping, &mut recorder]);
+
+        // the same as with train_stochastic's early stopping
+        assert_eq!(recorder.epoch_ends.len(), 6);
+        let mut nn_at_first_check = build_relu_network_for_health_check_tests();
+        train_for_early_stopping_tests(&mut nn_at_first_check, 2, None);
+        assert_eq!(nn.w, nn_at_first_check.w);
+    }
+
+    #[test]
+    fn a_session_can_be_logged_with_callbacks() {
+        let training_data = get_data_set_1();
+        let validation_data = get_data_set_1_with_opposite_labels();
+        let datasets = Datasets::new(&training_data).with_validation(&validation_data);
+
+        let mut session_directory = std::env::temp_dir();
+        session_directory.push(format!("callbacks-session-test-{}", std::process::id()));
+        std::fs::create_dir_all(&session_directory).unwrap();
+        let mut session_logger = TrainingSessionLogger::new().with_checkpoint_every(2);
+        session_logger.full_session_output_directory = Some(session_directory.clone());
+
+        let mut nn = build_relu_network_for_health_check_tests();
+        nn.train_with_callbacks(
+            datasets,
+            4,
+            &OptimizerConfig::standard_gradient_descent(0.9),
+            5,
+            Some(&TrainingOptions {
+                seed: Some(3),
+                ..TrainingOptions::default()
+            }),
+            &mut [
+                &mut FullCostUpdates::new(datasets, 2),
+                &mut session_logger,
+            ],
+        )
+        .unwrap();
+
+        let checkpoint_written = session_directory.join("checkpoint-epoch-4.bin").exists();
+        let session_log =
+            training_log::SessionLog::read_from_directory(&session_directory).unwrap();
+        std::fs::remove_dir_all(&session_directory).unwrap();
+
+        assert!(checkpoint_written);
+        assert_eq!(session_log.session.seed, 3);
+        assert_eq!(
+            session_log
+                .updates
+                .iter()
+                .map(|update| update.epochs_completed)
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert_eq!(
+            session_log.updates[1].validation_set_cost,
+            Some(nn.cost_training_set(&validation_data).unwrap())
+        );
+    }
+
+    #[test]
+    fn validation_data_that_does_not_fit_the_network_is_rejected_before_training() {
+        // the network has 2 inputs
+        let validation_data = vec![NDTrainingDataPoint::new(
+            column_vector![1.0, 2.0, 3.0],
+            column_vector![1.0],
+        )];
+        let mut early_stopping = EarlyStopping::new(EarlyStopConfig::new(1), &validation_data);
+        let mut nn = build_relu_network_for_health_check_tests();
+        let initial_weights = nn.w.clone();
+
+        let result = nn.train_with_callbacks(
+            &get_data_set_1(),
+            3,
+            &OptimizerConfig::standard_gradient_descent(0.9),
+            5,
+            None,
+            &mut [&mut early_stopping],
+        );
+        assert!(matches!(
+            result,
+            Err(NeuralNetworkError::VectorDimensionMismatch(_))
+        ));
+        assert_eq!(nn.w, initial_weights);
+    }
+
+    #[test]
+    fn full_cost_updates_and_checkpoints_every_0_epochs_are_rejected_before_training() {
+        let training_data = get_data_set_1();
+        let mut nn = build_relu_network_for_health_check_tests();
+        let initial_weights = nn.w.clone();
+        let optimizer = OptimizerConfig::standard_gradient_descent(0.9);
+
+        let result = nn.train_stochastic(
+            &training_data,
+            3,
+            &optimizer,
+            5,
+            None,
+            None,
+            Some(0),
+            None,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(NeuralNetworkError::InvalidTrainingOptions(
+                TrainingOptionsError::InvalidFullCostUpdateEvery
+            ))
+        ));
+
+        let result = nn.train_stochastic(
+            &training_data,
+            3,
+            &optimizer,
+            5,
+            None,
+            None,
+            None,
+            Some(TrainingSessionLogger::new().with_checkpoint_every(0)),
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(NeuralNetworkError::InvalidTrainingOptions(
+                TrainingOptionsError::InvalidCheckpointEvery
+            ))
+        ));
+        assert_eq!(nn.w, initial_weights);
+    }
+
+    fn build_f64_network_for_precision_tests() -> NeuralNetwork {
+        NeuralNetworkBuilder::new()
+            .with_input_layer(2)
+            .with_hidden_layer(
+                4,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Sigmoid,
+            )
+            .with_output_layer(1, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_cost_fn(cost::CostFunc::QuadraticCost)
+            .build()
+    }
+
+    #[test]
+    fn f32_network_gradients_match_f64_numerical_gradients() {
+        let training_data = get_data_set_1();
+        let training_data_f32 = training_data
+            .iter()
+            .map(|tr_ex| tr_ex.cast())
+            .collect::<Vec<NDTrainingDataPoint<f32>>>();
+
+        let nn = build_f64_network_for_precision_tests();
+        let mut nn_f32 = nn.cast::<f32>();
+
+        let forward_and_back_pass_data = training_data_f32
+            .iter()
+            .map(|tr_ex| {
+                let intermediates = nn_f32.feed_forward_capturing_intermediates(&tr_ex.input_v);
+                let error_vectors = nn_f32.backprop(&tr_ex.desired_output_v, &intermediates);
+                ForwardAndBackPassData {
+                    intermediates,
+                    error_vectors,
+                }
+            })
+            .collect::<Vec<_>>();
+        let gradients = nn_f32
+            .compute_gradients(&forward_and_back_pass_data)
+            .unroll()
+            .iter()
+            .map(|x| x.as_f64())
+            .collect::<Vec<f64>>();
+
+        let approx_gradients = nn.approximate_cost_gradient(&training_data).unwrap();
+
+        let normalized_distance = euclidian_distance(&approx_gradients, &gradients)
+            / (euclidian_length(&approx_gradients) + euclidian_length(&gradients));
+        assert!(normalized_distance < 1e-4, "{}", normalized_distance);
+    }
+
+    #[test]
+    fn train_stochastic_works_for_f32_networks() {
+        let training_data = get_data_set_1()
+            .iter()
+            .map(|tr_ex| tr_ex.cast())
+            .collect::<Vec<NDTrainingDataPoint<f32>>>();
+
+        let nn = build_f64_network_for_precision_tests();
+        let mut nn_f32 = nn.cast::<f32>();
+
+        // casting loses nothing that matters for the outputs
+        for tr_ex in get_data_set_1().iter() {
+            let output_f64 = nn.feed_forward(&tr_ex.input_v);
+            let output_f32 = nn_f32.feed_forward(&tr_ex.input_v.cast());
+            assert!((output_f64.get(0) - output_f32.get(0).as_f64()).abs() < 1e-6);
+        }
+
+        let initial_cost = nn_f32.cost_training_set(&training_data).unwrap();
+        nn_f32
+            .train_stochastic(
+                &training_data,
+                100,
+                &OptimizerConfig::Adam(optimizer::AdamConfig::with_learning_rate(0.01)),
+                training_data.len(),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let final_cost = nn_f32.cost_training_set(&training_data).unwrap();
+
+        assert!(final_cost < initial_cost);
+    }
+
+    // #[test]
+    // fn test_with_mnist() {
+    //     time_test!();
+
+    //     let (training_data, test_data) = mnist_data::get_mnist_data();
+    //     let mut nn = NeuralNetworkBuilder::new()
+    //         .with_input_layer(784)
+    //         .with_hidden_layer(100, BuilderWeightsAndBiasesConfig::RandomBasic)
+    //         .with_hidden_layer(100, BuilderWeightsAndBiasesConfig::RandomBasic)
+    //         .with_output_layer(10, BuilderWeightsAndBiasesConfig::RandomBasic)
+    //         .build();
+
+    //     let check_options = CheckOptions {
+    //         gradient_checking: false,
+    //         cost_decreasing_check: false,
+    //     };
+
+    //     // let n = training_data[0].input_v.num_elements();
+    //     // assert_eq!(n, 0);
+    //     nn.train(&training_data, 1, 0.9, Some(check_options));
+    // }
+
+    #[test]
+    fn test_get_weight_matrix_shape() {
+        let nn: NeuralNetwork = NeuralNetwork::new(vec![2, 3, 2]);
         let weight_matrix_l1_shape = nn.weight_matrix_shape(1);
         let weight_matrix_l2_shape = nn.weight_matrix_shape(2);
         assert_eq!(weight_matrix_l1_shape.rows, 3);
@@ -0,0 +1,436 @@
+//! Learning rate schedules. A schedule gives a factor that the optimizer's configured learning rate is multiplied by,
+//! which changes as training progresses.
+
+use std::f64::consts::PI;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// What a schedule counts - optimization steps (i.e. mini batches) or epochs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ScheduleUnit {
+    Step,
+    #[default]
+    Epoch,
+}
+
+/// How the learning rate changes over a training run. `t` below is the number of steps or epochs (see `ScheduleUnit`)
+/// completed so far.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum LearningRateSchedule {
+    /// The configured learning rate for the whole run.
+    #[default]
+    Constant,
+    /// Multiplies the learning rate by `gamma` every `every` steps or epochs.
+    StepDecay {
+        every: usize,
+        gamma: f64,
+        #[serde(default)]
+        per: ScheduleUnit,
+    },
+    /// Multiplies the learning rate by `gamma` every step or epoch.
+    ExponentialDecay {
+        gamma: f64,
+        #[serde(default)]
+        per: ScheduleUnit,
+    },
+    /// Anneals the factor from 1 down to `min_factor` along a half cosine over `period` steps or epochs, then
+    /// restarts from 1. Each period is `period_mult` times as long as the one before. See
+    /// https://arxiv.org/pdf/1608.03983.pdf (SGDR).
+    CosineWithWarmRestarts {
+        period: usize,
+        #[serde(default = "default_period_mult")]
+        period_mult: usize,
+        #[serde(default)]
+        min_factor: f64,
+        #[serde(default)]
+        per: ScheduleUnit,
+    },
+    /// Ramps the learning rate up linearly over the first `length` steps or epochs, on top of the `then` schedule.
+    /// `then` runs from the start of training, as it would without the warmup.
+    LinearWarmup {
+        length: usize,
+        #[serde(default)]
+        per: ScheduleUnit,
+        #[serde(default)]
+        then: Box<LearningRateSchedule>,
+    },
+    /// Multiplies the learning rate by `factor` (down to `min_factor` overall) whenever the validation cost hasn't
+    /// improved by more than `min_delta` for more than `patience` of the periodic validation cost computations in a row.
+    ReduceOnPlateau {
+        factor: f64,
+        patience: usize,
+        #[serde(default)]
+        min_delta: f64,
+        #[serde(default)]
+        min_factor: f64,
+    },
+}
+
+fn default_period_mult() -> usize {
+    1
+}
+
+/// The part of a schedule that depends on how training has gone so far, rather than just how far it's got.
+/// Saved with checkpoints so a resumed run carries on with the same learning rate.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleState {
+    /// The product of the `ReduceOnPlateau` reductions so far.
+    pub plateau_factor: f64,
+    /// The lowest validation cost so far.
+    pub best_cost: Option<f64>,
+    /// The number of validation cost computations since `best_cost` improved.
+    pub checks_without_improvement: usize,
+}
+
+impl Default for ScheduleState {
+    fn default() -> Self {
+        Self {
+            plateau_factor: 1.0,
+            best_cost: None,
+            checks_without_improvement: 0,
+        }
+    }
+}
+
+impl LearningRateSchedule {
+    /// A `StepDecay` has to decay every 1 or more steps or epochs, a `CosineWithWarmRestarts` period has to be at
+    /// least 1, and a `ReduceOnPlateau` needs a `factor` above 0 and at most 1, a `min_factor` from 0 to 1 and a
+    /// `min_delta` of at least 0 - including in the schedule after a `LinearWarmup`.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            LearningRateSchedule::StepDecay { every, .. } => *every > 0,
+            LearningRateSchedule::CosineWithWarmRestarts { period, .. } => *period > 0,
+            LearningRateSchedule::LinearWarmup { then, .. } => then.is_valid(),
+            LearningRateSchedule::ReduceOnPlateau {
+                factor,
+                min_delta,
+                min_factor,
+                ..
+            } => {
+                *factor > 0.0
+                    && *factor <= 1.0
+                    && (0.0..=1.0).contains(min_factor)
+                    && min_delta.is_finite()
+                    && *min_delta >= 0.0
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether the schedule depends on the validation cost (see `observe_validation_cost`), so needs a validation set.
+    pub fn watches_validation_cost(&self) -> bool {
+        match self {
+            LearningRateSchedule::ReduceOnPlateau { .. } => true,
+            LearningRateSchedule::LinearWarmup { then, .. } => then.watches_validation_cost(),
+            _ => false,
+        }
+    }
+
+    /// The factor to multiply the configured learning rate by for the next step, after `steps_count` steps and
+    /// `epochs_count` epochs.
+    pub fn factor(&self, steps_count: usize, epochs_count: usize, state: &ScheduleState) -> f64 {
+        let t = |per: &ScheduleUnit| match per {
+            ScheduleUnit::Step => steps_count,
+            ScheduleUnit::Epoch => epochs_count,
+        };
+
+        match self {
+            LearningRateSchedule::Constant => 1.0,
+            LearningRateSchedule::StepDecay { every, gamma, per } => {
+                assert!(*every > 0, "StepDecay every must be greater than 0");
+                gamma.powi((t(per) / every) as i32)
+            }
+            LearningRateSchedule::ExponentialDecay { gamma, per } => gamma.powi(t(per) as i32),
+            LearningRateSchedule::CosineWithWarmRestarts {
+                period,
+                period_mult,
+                min_factor,
+                per,
+            } => {
+                assert!(
+                    *period > 0,
+                    "CosineWithWarmRestarts period must be greater than 0"
+                );
+
+                // find how far into the current period t is
+                let mut t = t(per);
+                let mut period = *period;
+                if *period_mult <= 1 {
+                    t %= period;
+                } else {
+                    while t >= period {
+                        t -= period;
+                        period *= period_mult;
+                    }
+                }
+
+                let progress = t as f64 / period as f64;
+                min_factor + (1.0 - min_factor) * (1.0 + (PI * progress).cos()) / 2.0
+            }
+            LearningRateSchedule::LinearWarmup { length, per, then } => {
+                let warmup_factor = if t(per) < *length {
+                    (t(per) + 1) as f64 / *length as f64
+                } else {
+                    1.0
+                };
+                warmup_factor * then.factor(steps_count, epochs_count, state)
+            }
+            LearningRateSchedule::ReduceOnPlateau { .. } => state.plateau_factor,
+        }
+    }
+
+    /// Called with each periodic validation cost, for `ReduceOnPlateau`. Does nothing for the other schedules.
+    pub fn observe_validation_cost(&self, validation_cost: f64, state: &mut ScheduleState) {
+        match self {
+            LearningRateSchedule::LinearWarmup { then, .. } => {
+                then.observe_validation_cost(validation_cost, state)
+            }
+            LearningRateSchedule::ReduceOnPlateau {
+                factor,
+                patience,
+                min_delta,
+                min_factor,
+            } => {
+                let improved = match state.best_cost {
+                    Some(best_cost) => validation_cost < best_cost - min_delta,
+                    None => true,
+                };
+
+                if improved {
+                    state.best_cost = Some(validation_cost);
+                    state.checks_without_improvement = 0;
+                } else {
+                    state.checks_without_improvement += 1;
+                    if state.checks_without_improvement > *patience {
+                        state.plateau_factor = (state.plateau_factor * factor).max(*min_factor);
+                        state.checks_without_improvement = 0;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::approx_eq;
+
+    fn factors_per_epoch(schedule: &LearningRateSchedule, epochs: usize) -> Vec<f64> {
+        let state = ScheduleState::default();
+        (0..epochs)
+            .map(|epoch| schedule.factor(0, epoch, &state))
+            .collect()
+    }
+
+    #[test]
+    fn constant_is_always_one() {
+        assert_eq!(
+            factors_per_epoch(&LearningRateSchedule::Constant, 3),
+            vec![1.0, 1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn step_decay_drops_every_few_epochs() {
+        let schedule = LearningRateSchedule::StepDecay {
+            every: 2,
+            gamma: 0.5,
+            per: ScheduleUnit::Epoch,
+        };
+        assert_eq!(
+            factors_per_epoch(&schedule, 5),
+            vec![1.0, 1.0, 0.5, 0.5, 0.25]
+        );
+    }
+
+    #[test]
+    fn exponential_decay_counts_steps_when_asked_to() {
+        let schedule = LearningRateSchedule::ExponentialDecay {
+            gamma: 0.5,
+            per: ScheduleUnit::Step,
+        };
+        let state = ScheduleState::default();
+        assert_eq!(schedule.factor(0, 7, &state), 1.0);
+        assert_eq!(schedule.factor(3, 7, &state), 0.125);
+    }
+
+    #[test]
+    fn cosine_with_warm_restarts_restarts_with_longer_periods() {
+        let schedule = LearningRateSchedule::CosineWithWarmRestarts {
+            period: 2,
+            period_mult: 2,
+            min_factor: 0.0,
+            per: ScheduleUnit::Epoch,
+        };
+        let factors = factors_per_epoch(&schedule, 8);
+
+        // periods of 2 then 4 epochs, each starting back at 1 and halfway down the cosine at its midpoint
+        let expected = [
+            1.0,
+            0.5,
+            1.0,
+            0.8535533905932737,
+            0.5,
+            0.1464466094067262,
+            1.0,
+            0.9619397662556434,
+        ];
+        for (factor, expected) in factors.iter().zip(expected.iter()) {
+            assert!(approx_eq!(f64, *factor, *expected, epsilon = 1e-12));
+        }
+    }
+
+    #[test]
+    fn cosine_with_warm_restarts_stays_above_min_factor() {
+        let schedule = LearningRateSchedule::CosineWithWarmRestarts {
+            period: 10,
+            period_mult: 1,
+            min_factor: 0.1,
+            per: ScheduleUnit::Epoch,
+        };
+        for factor in factors_per_epoch(&schedule, 30) {
+            assert!(factor > 0.1 && factor <= 1.0);
+        }
+    }
+
+    #[test]
+    fn linear_warmup_ramps_up_then_follows_the_next_schedule() {
+        let schedule = LearningRateSchedule::LinearWarmup {
+            length: 4,
+            per: ScheduleUnit::Epoch,
+            then: Box::new(LearningRateSchedule::StepDecay {
+                every: 6,
+                gamma: 0.1,
+                per: ScheduleUnit::Epoch,
+            }),
+        };
+        let factors = factors_per_epoch(&schedule, 7);
+        assert_eq!(&factors[..5], &[0.25, 0.5, 0.75, 1.0, 1.0]);
+        assert!(approx_eq!(f64, factors[6], 0.1, epsilon = 1e-12));
+    }
+
+    #[test]
+    fn reduce_on_plateau_reduces_after_patience_checks_without_improvement() {
+        let schedule = LearningRateSchedule::ReduceOnPlateau {
+            factor: 0.5,
+            patience: 1,
+            min_delta: 0.01,
+            min_factor: 0.2,
+        };
+        let mut state = ScheduleState::default();
+
+        let mut factors = vec![];
+        for validation_cost in [1.0, 0.8, 0.795, 0.79, 0.7, 0.7, 0.7, 0.7, 0.7, 0.7] {
+            schedule.observe_validation_cost(validation_cost, &mut state);
+            factors.push(schedule.factor(0, 0, &state));
+        }
+
+        // 0.795 and 0.79 aren't enough of an improvement, so the second of them triggers a reduction. The rest go
+        // down to min_factor
+        assert_eq!(
+            factors,
+            vec![1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.25, 0.25, 0.2, 0.2]
+        );
+        assert_eq!(state.best_cost, Some(0.7));
+    }
+
+    #[test]
+    fn schedules_that_never_advance_are_invalid() {
+        let step_decay = |every| LearningRateSchedule::StepDecay {
+            every,
+            gamma: 0.5,
+            per: ScheduleUnit::Epoch,
+        };
+        assert!(step_decay(1).is_valid());
+        assert!(!step_decay(0).is_valid());
+
+        let warm_restarts_after_warmup = LearningRateSchedule::LinearWarmup {
+            length: 5,
+            per: ScheduleUnit::Step,
+            then: Box::new(LearningRateSchedule::CosineWithWarmRestarts {
+                period: 0,
+                period_mult: 1,
+                min_factor: 0.0,
+                per: ScheduleUnit::Epoch,
+            }),
+        };
+        assert!(!warm_restarts_after_warmup.is_valid());
+    }
+
+    #[test]
+    fn reduce_on_plateau_must_only_ever_reduce() {
+        let reduce_on_plateau =
+            |factor, min_delta, min_factor| LearningRateSchedule::ReduceOnPlateau {
+                factor,
+                patience: 0,
+                min_delta,
+                min_factor,
+            };
+        assert!(reduce_on_plateau(0.5, 0.0, 0.0).is_valid());
+        assert!(reduce_on_plateau(1.0, 0.01, 1.0).is_valid());
+
+        for factor in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(!reduce_on_plateau(factor, 0.0, 0.0).is_valid());
+        }
+        for min_factor in [-0.1, 1.1, f64::NAN] {
+            assert!(!reduce_on_plateau(0.5, 0.0, min_factor).is_valid());
+        }
+        for min_delta in [-0.1, f64::NAN, f64::INFINITY] {
+            assert!(!reduce_on_plateau(0.5, min_delta, 0.0).is_valid());
+        }
+
+        assert!(!LearningRateSchedule::LinearWarmup {
+            length: 5,
+            per: ScheduleUnit::Step,
+            then: Box::new(reduce_on_plateau(2.0, 0.0, 0.0)),
+        }
+        .is_valid());
+    }
+
+    #[test]
+    fn reduce_on_plateau_watches_the_validation_cost_even_after_a_warmup() {
+        let reduce_on_plateau = LearningRateSchedule::ReduceOnPlateau {
+            factor: 0.5,
+            patience: 1,
+            min_delta: 0.0,
+            min_factor: 0.0,
+        };
+        assert!(reduce_on_plateau.watches_validation_cost());
+        assert!(LearningRateSchedule::LinearWarmup {
+            length: 5,
+            per: ScheduleUnit::Step,
+            then: Box::new(reduce_on_plateau),
+        }
+        .watches_validation_cost());
+        assert!(!LearningRateSchedule::Constant.watches_validation_cost());
+    }
+
+    #[test]
+    fn schedules_can_be_read_from_toml() {
+        let schedule: LearningRateSchedule = toml::from_str(
+            r#"
+            [LinearWarmup]
+            length = 100
+            per = "Step"
+            then = { CosineWithWarmRestarts = { period = 10, min_factor = 0.01 } }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            schedule,
+            LearningRateSchedule::LinearWarmup {
+                length: 100,
+                per: ScheduleUnit::Step,
+                then: Box::new(LearningRateSchedule::CosineWithWarmRestarts {
+                    period: 10,
+                    period_mult: 1,
+                    min_factor: 0.01,
+                    per: ScheduleUnit::Epoch,
+                }),
+            }
+        );
+    }
+}
@@ -6,16 +6,32 @@ use test7_nn_mnist_classifier::{CheckOptions, EarlyStopConfig};
 
 use test7_nn_mnist_classifier::activation;
 use test7_nn_mnist_classifier::builder::NeuralNetworkBuilder;
+use test7_nn_mnist_classifier::data_split::{DataSplit, Datasets};
 use test7_nn_mnist_classifier::initializer::Initializer;
-use test7_nn_mnist_classifier::optimizer::{AdamConfig, Optimizer};
-use test7_nn_mnist_classifier::training_log::TrainingSessionLogger;
+use test7_nn_mnist_classifier::optimizer::{AdamConfig, OptimizerConfig};
+use test7_nn_mnist_classifier::training_log::{TrainingSessionLogger, UpdateFormat};
+use tracing_subscriber::EnvFilter;
 
 fn main() {
+    // the training progress is logged at info, and per-step details at debug - override with RUST_LOG
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
     println!("experimenting with training log stuff");
 
     let (training_data, test_data) = mnist_data::get_mnist_data(50000, 10000);
     println!("got the MNIST training data");
 
+    // hold out a validation set from the training data for early stopping, so the test set is only used for reporting
+    let DataSplit {
+        training: training_data,
+        validation: validation_data,
+        ..
+    } = DataSplit::split(training_data, 0.1, 0.0, 42);
+
     // let jelu_instance = activation::jelu::JELU::new(-3.0);
 
     let mut nn = NeuralNetworkBuilder::new()
@@ -75,15 +91,16 @@ fn main() {
     let mut t_total = SimpleTimer::start_new("t_total");
 
     let early_stop_config = EarlyStopConfig {
-        // test_data: &test_data,
-        test_data: &test_data[0..10000],
-        cost_threshold: 0.001,
-        check_every: 10,
+        cost_threshold: Some(0.001),
+        patience: Some(5),
+        ..EarlyStopConfig::new(10)
     };
 
     // TODO: should probably replace the ::new() + create_training_log_directory() thing with
     // an init() constructor that does both
-    let mut session_logger = TrainingSessionLogger::new();
+    let mut session_logger = TrainingSessionLogger::new()
+        .with_checkpoint_every(100)
+        .with_update_format(UpdateFormat::JsonLines);
     session_logger
         .create_training_log_directory()
         .expect("failed creating traininig log directory");
@@ -97,20 +114,27 @@ fn main() {
     );
 
     nn.train_stochastic(
-        &training_data,
-        10_000,
-        // &Optimizer::standard_gradient_descent(0.9),
-        // &Optimizer::momentum(0.9, 0.9),
-        &Optimizer::Adam(AdamConfig::default()),
-        // &Optimizer::momentum(0.9, 0.9),
+        Datasets::new(&training_data)
+            .with_validation(&validation_data)
+            .with_test(&test_data),
+        1_000,
+        // &OptimizerConfig::standard_gradient_descent(0.9),
+        // &OptimizerConfig::momentum(0.9, 0.9),
+        &OptimizerConfig::Adam(AdamConfig::default()),
+        // &OptimizerConfig::momentum(0.9, 0.9),
         5000,
         Some(&check_options),
         Some(early_stop_config),
         Some(10),
         Some(session_logger),
+        None,
     );
     println!("done training");
 
+    nn.save("mnist-model.bin")
+        .expect("failed saving the trained model");
+    println!("saved trained model to mnist-model.bin");
+
     let t0 = test_data.get(0).unwrap();
     let p_output_v = nn.feed_forward(&t0.input_v);
     println!("p_output_v: {}", p_output_v);
@@ -130,6 +154,9 @@ fn main() {
     let test_set_cost = nn.cost_training_set(&test_data).unwrap();
     println!("\ntest_set_cost: {}", test_set_cost);
 
+    let test_set_report = nn.evaluate_classification(&test_data, 3).unwrap();
+    println!("\ntest set evaluation:\n{}", test_set_report);
+
     t_total.stop();
     println!("\nt_total: {}", t_total);
 }
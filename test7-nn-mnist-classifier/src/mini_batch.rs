@@ -0,0 +1,144 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+
+/// What to do with the leftover examples when the training set size isn't a multiple of the mini batch size.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PartialBatch {
+    /// Train on the leftover examples as a final, smaller mini batch.
+    Keep,
+    /// Skip the leftover examples for this epoch. Since the data is reshuffled every epoch, they'll most likely
+    /// be picked up in a later epoch.
+    Drop,
+}
+
+/// How the mini batches are drawn from the training data.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MiniBatchSampling {
+    /// Each epoch the training data is shuffled and then split into mini batches, so every example is seen once per epoch.
+    ShuffledEpochs { partial_batch: PartialBatch },
+    /// The original behaviour: each epoch is a single step on one randomly placed contiguous slice of
+    /// `mini_batch_size` examples. Some examples may never be seen and neighbouring examples are always batched together.
+    RandomContiguousSlice,
+}
+
+impl Default for MiniBatchSampling {
+    fn default() -> Self {
+        MiniBatchSampling::ShuffledEpochs {
+            partial_batch: PartialBatch::Keep,
+        }
+    }
+}
+
+impl MiniBatchSampling {
+    /// Returns the mini batches for one epoch, each as a list of indexes into the training data.
+    /// A `mini_batch_size` of at least `num_samples` gives a single batch containing the whole training set.
+    pub fn epoch_mini_batches<R: Rng>(
+        &self,
+        num_samples: usize,
+        mini_batch_size: usize,
+        rng: &mut R,
+    ) -> Vec<Vec<usize>> {
+        assert!(
+            mini_batch_size > 0,
+            "mini_batch_size must be greater than 0"
+        );
+
+        match self {
+            MiniBatchSampling::ShuffledEpochs { partial_batch } => {
+                let mut indexes: Vec<usize> = (0..num_samples).collect();
+                indexes.shuffle(rng);
+
+                indexes
+                    .chunks(mini_batch_size)
+                    .filter(|chunk| {
+                        chunk.len() == mini_batch_size
+                            || chunk.len() == num_samples
+                            || *partial_batch == PartialBatch::Keep
+                    })
+                    .map(|chunk| chunk.to_vec())
+                    .collect()
+            }
+            MiniBatchSampling::RandomContiguousSlice => {
+                let mut mini_batch_start = 0;
+                let mut mini_batch_end = num_samples;
+
+                if mini_batch_size < num_samples {
+                    let max_starting_point = num_samples - mini_batch_size;
+                    mini_batch_start = rng.gen_range(0..max_starting_point); // note the upper limit is exclusive
+                    mini_batch_end = mini_batch_start + mini_batch_size; // this will be exclusive when used in the slice range
+                }
+
+                vec![(mini_batch_start..mini_batch_end).collect()]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn sorted(batches: &[Vec<usize>]) -> Vec<usize> {
+        let mut all = batches.concat();
+        all.sort();
+        all
+    }
+
+    #[test]
+    fn shuffled_epochs_visits_every_example_once() {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let sampling = MiniBatchSampling::default();
+
+        let batches = sampling.epoch_mini_batches(10, 3, &mut rng);
+        assert_eq!(
+            batches.iter().map(|b| b.len()).collect::<Vec<usize>>(),
+            vec![3, 3, 3, 1]
+        );
+        assert_eq!(sorted(&batches), (0..10).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn shuffled_epochs_can_drop_the_partial_batch() {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let sampling = MiniBatchSampling::ShuffledEpochs {
+            partial_batch: PartialBatch::Drop,
+        };
+
+        let batches = sampling.epoch_mini_batches(10, 3, &mut rng);
+        assert_eq!(batches.len(), 3);
+        assert!(batches.iter().all(|b| b.len() == 3));
+
+        // the whole training set is never dropped, even if it's smaller than a mini batch
+        let batches = sampling.epoch_mini_batches(2, 3, &mut rng);
+        assert_eq!(sorted(&batches), vec![0, 1]);
+    }
+
+    #[test]
+    fn shuffled_epochs_reshuffles_each_epoch() {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let sampling = MiniBatchSampling::default();
+
+        let epoch_1 = sampling.epoch_mini_batches(100, 10, &mut rng);
+        let epoch_2 = sampling.epoch_mini_batches(100, 10, &mut rng);
+        assert_ne!(epoch_1, epoch_2);
+        assert_eq!(sorted(&epoch_1), sorted(&epoch_2));
+    }
+
+    #[test]
+    fn random_contiguous_slice_gives_a_single_contiguous_batch() {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let sampling = MiniBatchSampling::RandomContiguousSlice;
+
+        let batches = sampling.epoch_mini_batches(100, 10, &mut rng);
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.len(), 10);
+        assert!(batch.windows(2).all(|w| w[1] == w[0] + 1));
+
+        let batches = sampling.epoch_mini_batches(5, 10, &mut rng);
+        assert_eq!(batches, vec![vec![0, 1, 2, 3, 4]]);
+    }
+}
@@ -0,0 +1,518 @@
+//! Batch normalization and layer normalization. A normalized layer standardizes its weighted inputs z to zero mean and
+//! unit variance, then multiplies them by a learned scale (gamma) and adds a learned shift (beta) before applying its
+//! activation function. The scales and shifts are trained along with the weights and biases (see `BigTheta`).
+//!
+//! Batch normalization standardizes each neuron's z over the examples in a mini batch, and keeps running averages of
+//! the batch means and variances to standardize with at inference time. See https://arxiv.org/abs/1502.03167.
+//!
+//! Layer normalization standardizes each example's z over the neurons in the layer, so it works the same way in
+//! training and inference. See https://arxiv.org/abs/1607.06450.
+
+use serde_derive::{Deserialize, Serialize};
+
+use common::linalg::{ColumnVector, Float, Matrix};
+
+use crate::{LayerIndex, NeuralNetwork};
+
+const DEFAULT_MOMENTUM: f64 = 0.9;
+const DEFAULT_EPSILON: f64 = 1e-5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    BatchNorm {
+        /// How much of the running mean and variance is kept when they're updated with a mini batch's.
+        #[serde(default = "default_momentum")]
+        momentum: f64,
+        /// Added to the variance, so a neuron whose z doesn't vary isn't divided by zero.
+        #[serde(default = "default_epsilon")]
+        epsilon: f64,
+    },
+    LayerNorm {
+        #[serde(default = "default_epsilon")]
+        epsilon: f64,
+    },
+}
+
+fn default_momentum() -> f64 {
+    DEFAULT_MOMENTUM
+}
+
+fn default_epsilon() -> f64 {
+    DEFAULT_EPSILON
+}
+
+impl Normalization {
+    /// Batch normalization with a momentum of 0.9 and an epsilon of 1e-5.
+    pub fn batch_norm() -> Self {
+        Normalization::BatchNorm {
+            momentum: DEFAULT_MOMENTUM,
+            epsilon: DEFAULT_EPSILON,
+        }
+    }
+
+    /// Layer normalization with an epsilon of 1e-5.
+    pub fn layer_norm() -> Self {
+        Normalization::LayerNorm {
+            epsilon: DEFAULT_EPSILON,
+        }
+    }
+
+    pub fn epsilon(&self) -> f64 {
+        match self {
+            Normalization::BatchNorm { epsilon, .. } => *epsilon,
+            Normalization::LayerNorm { epsilon } => *epsilon,
+        }
+    }
+
+    /// The epsilon has to be positive, and the momentum at least 0 and less than 1.
+    pub fn is_valid(&self) -> bool {
+        let momentum_is_valid = match self {
+            Normalization::BatchNorm { momentum, .. } => (0.0..1.0).contains(momentum),
+            Normalization::LayerNorm { .. } => true,
+        };
+        momentum_is_valid && self.epsilon() > 0.0
+    }
+
+    /// Standardizes a batch of z's, one column per example. Batch norm uses the batch's own mean and variance, which
+    /// are returned for updating the running statistics.
+    pub(crate) fn standardize_batch<T: Float>(
+        &self,
+        z: &Matrix<T>,
+    ) -> (Standardized<T>, Option<BatchStatistics<T>>) {
+        match self {
+            Normalization::BatchNorm { epsilon, .. } => {
+                let batch_statistics = BatchStatistics::of_rows(z);
+                let standardized = standardize_rows(
+                    z,
+                    &batch_statistics.mean,
+                    &batch_statistics.variance,
+                    *epsilon,
+                );
+                (standardized, Some(batch_statistics))
+            }
+            Normalization::LayerNorm { epsilon } => (standardize_columns(z, *epsilon), None),
+        }
+    }
+
+    /// Standardizes z's for inference, when batch norm uses the running statistics rather than the batch's.
+    pub(crate) fn standardize_for_inference<T: Float>(
+        &self,
+        z: &Matrix<T>,
+        running_statistics: Option<&RunningStatistics<T>>,
+    ) -> Matrix<T> {
+        match self {
+            Normalization::BatchNorm { epsilon, .. } => {
+                let running_statistics =
+                    running_statistics.expect("batch norm needs running statistics");
+                standardize_rows(
+                    z,
+                    &running_statistics.mean,
+                    &running_statistics.variance,
+                    *epsilon,
+                )
+                .x_hat
+            }
+            Normalization::LayerNorm { epsilon } => standardize_columns(z, *epsilon).x_hat,
+        }
+    }
+
+    /// Given the gradients of the cost with respect to the standardized z's from `standardize_batch`, returns the
+    /// gradients with respect to the z's. This accounts for each z also affecting the mean and variance, and therefore
+    /// the other standardized z's in its batch (for batch norm) or layer (for layer norm).
+    ///
+    /// Like the rest of backprop, this works with the gradients of each example's own cost, which are averaged over the
+    /// examples afterwards.
+    pub(crate) fn standardize_batch_backward<T: Float>(
+        &self,
+        grad_x_hat: &Matrix<T>,
+        standardized: &Standardized<T>,
+    ) -> Matrix<T> {
+        match self {
+            Normalization::BatchNorm { .. } => {
+                standardize_rows_backward(grad_x_hat, &standardized.x_hat, &standardized.inv_std)
+            }
+            Normalization::LayerNorm { .. } => standardize_rows_backward(
+                &grad_x_hat.transpose(),
+                &standardized.x_hat.transpose(),
+                &standardized.inv_std,
+            )
+            .transpose(),
+        }
+    }
+}
+
+/// The running averages of a batch normalized layer's mini batch means and variances, which stand in for the batch's
+/// own at inference time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunningStatistics<T = f64> {
+    pub mean: ColumnVector<T>,
+    pub variance: ColumnVector<T>,
+}
+
+impl<T: Float> RunningStatistics<T> {
+    /// Zero mean and unit variance, i.e. no change to the z's until the first update.
+    pub fn new(size: usize) -> Self {
+        Self {
+            mean: ColumnVector::new_zero_vector(size),
+            variance: ColumnVector::fill_new(T::one(), size),
+        }
+    }
+
+    /// Moves the running averages towards a mini batch's mean and variance.
+    pub(crate) fn update(&mut self, batch_statistics: &BatchStatistics<T>, momentum: f64) {
+        let kept = T::from_f64(momentum);
+        let added = T::from_f64(1.0 - momentum);
+        for (running, batch) in [
+            (&mut self.mean, &batch_statistics.mean),
+            (&mut self.variance, &batch_statistics.variance),
+        ] {
+            for (r, b) in running.iter_mut().zip(batch.iter()) {
+                *r = kept * *r + added * *b;
+            }
+        }
+    }
+
+    pub fn cast<U: Float>(&self) -> RunningStatistics<U> {
+        RunningStatistics {
+            mean: self.mean.cast(),
+            variance: self.variance.cast(),
+        }
+    }
+}
+
+/// The mean and (biased) variance of each neuron's z over the examples in a mini batch.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BatchStatistics<T> {
+    pub mean: ColumnVector<T>,
+    pub variance: ColumnVector<T>,
+}
+
+impl<T: Float> BatchStatistics<T> {
+    fn of_rows(z: &Matrix<T>) -> Self {
+        let n = T::from_f64(z.num_columns() as f64);
+        let mut mean = ColumnVector::new_zero_vector(z.num_rows());
+        let mut variance = ColumnVector::new_zero_vector(z.num_rows());
+
+        for ((row, mean), variance) in z
+            .data
+            .chunks(z.num_columns())
+            .zip(mean.iter_mut())
+            .zip(variance.iter_mut())
+        {
+            *mean = row.iter().fold(T::zero(), |sum, x| sum + *x) / n;
+            *variance = row
+                .iter()
+                .fold(T::zero(), |sum, x| sum + (*x - *mean) * (*x - *mean))
+                / n;
+        }
+
+        Self { mean, variance }
+    }
+}
+
+/// A batch of standardized z's, and what's needed to backprop through the standardization.
+pub(crate) struct Standardized<T> {
+    /// (z - mean) / sqrt(variance + epsilon), one column per example.
+    pub x_hat: Matrix<T>,
+    /// 1 / sqrt(variance + epsilon) for each neuron (batch norm) or example (layer norm).
+    pub inv_std: Vec<T>,
+}
+
+/// Standardizes each row of `z` with the given mean and variance.
+fn standardize_rows<T: Float>(
+    z: &Matrix<T>,
+    mean: &[T],
+    variance: &[T],
+    epsilon: f64,
+) -> Standardized<T> {
+    let epsilon = T::from_f64(epsilon);
+    let inv_std = variance
+        .iter()
+        .map(|v| T::one() / (*v + epsilon).sqrt())
+        .collect::<Vec<T>>();
+
+    let mut x_hat = z.clone();
+    let num_columns = x_hat.num_columns();
+    for ((row, mean), inv_std) in x_hat
+        .data
+        .chunks_mut(num_columns)
+        .zip(mean.iter())
+        .zip(inv_std.iter())
+    {
+        row.iter_mut().for_each(|x| *x = (*x - *mean) * *inv_std);
+    }
+
+    Standardized { x_hat, inv_std }
+}
+
+/// Standardizes each column of `z` with its own mean and variance.
+fn standardize_columns<T: Float>(z: &Matrix<T>, epsilon: f64) -> Standardized<T> {
+    let z_t = z.transpose();
+    let statistics = BatchStatistics::of_rows(&z_t);
+    let standardized = standardize_rows(&z_t, &statistics.mean, &statistics.variance, epsilon);
+    Standardized {
+        x_hat: standardized.x_hat.transpose(),
+        inv_std: standardized.inv_std,
+    }
+}
+
+/// The backward pass of standardizing each row with its own mean and variance:
+/// dz = inv_std * (dx_hat - mean(dx_hat) - x_hat * mean(dx_hat * x_hat)), with the means taken along the row.
+fn standardize_rows_backward<T: Float>(
+    grad_x_hat: &Matrix<T>,
+    x_hat: &Matrix<T>,
+    inv_std: &[T],
+) -> Matrix<T> {
+    let num_columns = grad_x_hat.num_columns();
+    let n = T::from_f64(num_columns as f64);
+
+    let mut grad_z = grad_x_hat.clone();
+    for ((grad_row, x_hat_row), inv_std) in grad_z
+        .data
+        .chunks_mut(num_columns)
+        .zip(x_hat.data.chunks(num_columns))
+        .zip(inv_std.iter())
+    {
+        let mean_grad = grad_row.iter().fold(T::zero(), |sum, g| sum + *g) / n;
+        let mean_grad_x_hat = grad_row
+            .iter()
+            .zip(x_hat_row.iter())
+            .fold(T::zero(), |sum, (g, x)| sum + *g * *x)
+            / n;
+        for (g, x) in grad_row.iter_mut().zip(x_hat_row.iter()) {
+            *g = *inv_std * (*g - mean_grad - *x * mean_grad_x_hat);
+        }
+    }
+    grad_z
+}
+
+/// Multiplies each row of `m` by the corresponding element of `v`.
+pub(crate) fn scale_rows<T: Float>(mut m: Matrix<T>, v: &ColumnVector<T>) -> Matrix<T> {
+    let num_columns = m.num_columns();
+    for (row, v_i) in m.data.chunks_mut(num_columns).zip(v.iter()) {
+        row.iter_mut().for_each(|x| *x *= *v_i);
+    }
+    m
+}
+
+impl<T: Float> NeuralNetwork<T> {
+    /// The layers that have a `Normalization`, in order.
+    pub fn normalized_layers(&self) -> Vec<LayerIndex> {
+        (1..self.num_layers())
+            .filter(|l| self.normalization(*l).is_some())
+            .collect()
+    }
+
+    pub(crate) fn normalization(&self, layer_index: LayerIndex) -> Option<Normalization> {
+        self.layer_configs.get(&layer_index).unwrap().normalization
+    }
+
+    /// What layer `l` applies its activation function to at inference time, given its z's (one column per example):
+    /// the z's themselves, or for a normalized layer, the z's standardized and then scaled and shifted.
+    pub(crate) fn activation_input_for_inference(
+        &self,
+        layer_index: LayerIndex,
+        z: Matrix<T>,
+    ) -> Matrix<T> {
+        match self.normalization(layer_index) {
+            Some(normalization) => {
+                let x_hat = normalization
+                    .standardize_for_inference(&z, self.running_statistics.get(&layer_index));
+                self.scale_and_shift(layer_index, x_hat)
+            }
+            None => z,
+        }
+    }
+
+    /// Multiplies the standardized z's of a normalized layer by its scales and adds its shifts.
+    pub(crate) fn scale_and_shift(&self, layer_index: LayerIndex, x_hat: Matrix<T>) -> Matrix<T> {
+        let mut y = scale_rows(x_hat, &self.scales[&layer_index]);
+        let num_columns = y.num_columns();
+        for (row, shift) in y
+            .data
+            .chunks_mut(num_columns)
+            .zip(self.shifts[&layer_index].iter())
+        {
+            row.iter_mut().for_each(|x| *x += *shift);
+        }
+        y
+    }
+
+    /// Updates the running statistics of the batch normalized layers with the statistics of the last mini batch.
+    pub(crate) fn update_running_statistics(
+        &mut self,
+        batch_statistics: &std::collections::HashMap<LayerIndex, BatchStatistics<T>>,
+    ) {
+        for (l, batch_statistics) in batch_statistics.iter() {
+            if let Some(Normalization::BatchNorm { momentum, .. }) = self.normalization(*l) {
+                self.running_statistics
+                    .get_mut(l)
+                    .unwrap()
+                    .update(batch_statistics, momentum);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::linalg::RowsMatrixBuilder;
+    use float_cmp::approx_eq;
+
+    fn z() -> Matrix<f64> {
+        RowsMatrixBuilder::new()
+            .with_row(&[1.0, 2.0, 3.0, 6.0])
+            .with_row(&[-1.0, 0.0, 4.0, 1.0])
+            .build()
+    }
+
+    fn assert_approx_eq(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!(approx_eq!(f64, *a, *e, epsilon = 1e-9), "{} vs {}", a, e);
+        }
+    }
+
+    #[test]
+    fn batch_norm_standardizes_each_neuron_over_the_batch() {
+        let (standardized, batch_statistics) = Normalization::BatchNorm {
+            momentum: 0.9,
+            epsilon: 0.0,
+        }
+        .standardize_batch(&z());
+
+        let batch_statistics = batch_statistics.unwrap();
+        assert_eq!(batch_statistics.mean.get_data_as_slice(), &[3.0, 1.0]);
+        assert_eq!(batch_statistics.variance.get_data_as_slice(), &[3.5, 3.5]);
+
+        for row in standardized.x_hat.data.chunks(4) {
+            let mean = row.iter().sum::<f64>() / 4.0;
+            let variance = row.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 4.0;
+            assert!(approx_eq!(f64, mean, 0.0, epsilon = 1e-12));
+            assert!(approx_eq!(f64, variance, 1.0, epsilon = 1e-12));
+        }
+    }
+
+    #[test]
+    fn layer_norm_standardizes_each_example_over_the_layer() {
+        let (standardized, batch_statistics) =
+            Normalization::LayerNorm { epsilon: 0.0 }.standardize_batch(&z());
+        assert!(batch_statistics.is_none());
+
+        // each column is a pair of values, which standardize to -1 and 1
+        assert_approx_eq(
+            &standardized.x_hat.data,
+            &[1.0, 1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0],
+        );
+        assert_eq!(
+            Normalization::LayerNorm { epsilon: 0.0 }
+                .standardize_for_inference(&z(), None)
+                .data,
+            standardized.x_hat.data
+        );
+    }
+
+    #[test]
+    fn batch_norm_uses_the_running_statistics_for_inference() {
+        let normalization = Normalization::BatchNorm {
+            momentum: 0.5,
+            epsilon: 0.0,
+        };
+
+        // the initial running statistics leave the z's as they are
+        let mut running_statistics = RunningStatistics::new(2);
+        assert_eq!(
+            normalization.standardize_for_inference(&z(), Some(&running_statistics)),
+            z()
+        );
+
+        let (_, batch_statistics) = normalization.standardize_batch(&z());
+        running_statistics.update(&batch_statistics.unwrap(), 0.5);
+        assert_eq!(running_statistics.mean.get_data_as_slice(), &[1.5, 0.5]);
+        assert_eq!(
+            running_statistics.variance.get_data_as_slice(),
+            &[2.25, 2.25]
+        );
+
+        let x_hat = normalization.standardize_for_inference(&z(), Some(&running_statistics));
+        assert_approx_eq(
+            &x_hat.data,
+            &[
+                -1.0 / 3.0,
+                1.0 / 3.0,
+                1.0,
+                3.0,
+                -1.0,
+                -1.0 / 3.0,
+                7.0 / 3.0,
+                1.0 / 3.0,
+            ],
+        );
+    }
+
+    #[test]
+    fn standardize_backward_matches_finite_differences() {
+        // a cost that weights each standardized z differently, so its gradient isn't trivially zero
+        let weights = RowsMatrixBuilder::new()
+            .with_row(&[0.3, -1.2, 0.5, 2.0])
+            .with_row(&[1.5, 0.1, -0.7, 0.4])
+            .build();
+        let cost = |normalization: &Normalization, z: &Matrix<f64>| {
+            let (standardized, _) = normalization.standardize_batch(z);
+            standardized
+                .x_hat
+                .data
+                .iter()
+                .zip(weights.data.iter())
+                .map(|(x, w)| x * w)
+                .sum::<f64>()
+        };
+
+        for normalization in [Normalization::batch_norm(), Normalization::layer_norm()] {
+            let (standardized, _) = normalization.standardize_batch(&z());
+            let grad_z = normalization.standardize_batch_backward(&weights, &standardized);
+
+            for i in 0..z().data.len() {
+                let mut z_plus = z();
+                z_plus.data[i] += 1e-6;
+                let mut z_minus = z();
+                z_minus.data[i] -= 1e-6;
+                let approx_grad =
+                    (cost(&normalization, &z_plus) - cost(&normalization, &z_minus)) / 2e-6;
+                assert!(
+                    (grad_z.data[i] - approx_grad).abs() < 1e-6,
+                    "{:?} {}: {} vs {}",
+                    normalization,
+                    i,
+                    grad_z.data[i],
+                    approx_grad
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn normalizations_can_be_read_from_toml_with_defaults() {
+        #[derive(Deserialize)]
+        struct Layer {
+            normalization: Normalization,
+        }
+
+        let layer: Layer = toml::from_str("normalization = { BatchNorm = {} }").unwrap();
+        assert_eq!(layer.normalization, Normalization::batch_norm());
+
+        let layer: Layer =
+            toml::from_str("normalization = { LayerNorm = { epsilon = 0.001 } }").unwrap();
+        assert_eq!(
+            layer.normalization,
+            Normalization::LayerNorm { epsilon: 0.001 }
+        );
+        assert!(layer.normalization.is_valid());
+        assert!(!Normalization::BatchNorm {
+            momentum: 1.0,
+            epsilon: 1e-5
+        }
+        .is_valid());
+    }
+}
@@ -1,40 +1,375 @@
-#[derive(Debug)]
-pub enum Optimizer {
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+use common::linalg::Float;
+
+use crate::big_theta::BigTheta;
+
+/// An update rule for the weights and biases, given the gradients of the cost with respect to them.
+///
+/// `train_stochastic` takes any `Optimizer`, so new ones can be written outside of this crate. Everything an
+/// optimizer carries from one step to the next goes in its `OptimizerState`, rather than in the optimizer itself,
+/// so that it can be inspected and saved with checkpoints.
+pub trait Optimizer<T: Float = f64>: fmt::Debug {
+    /// Creates the state for updating `params` (or parameters with the same shape), as it is before the first step.
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T>;
+
+    /// The configured learning rate, before `OptimizerState::learning_rate_factor` is applied.
+    fn learning_rate(&self) -> f64;
+
+    /// Updates `params` in place given the `gradients` of the cost with respect to them.
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    );
+}
+
+/// The state an `Optimizer` carries from one step to the next.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizerState<T = f64> {
+    /// The number of steps taken before the current one. The training loop increments this after each step.
+    pub steps_count: usize,
+    /// What the learning rate schedule multiplies the learning rate by for the current step. Set by the training
+    /// loop before each step.
+    pub learning_rate_factor: f64,
+    /// Values kept for each of the parameters (e.g. the momentum), by name.
+    pub buffers: BTreeMap<String, BigTheta<T>>,
+}
+
+impl<T: Float> OptimizerState<T> {
+    /// A state with a buffer for each of `names`, shaped like `params` and zeroed.
+    pub fn with_zero_buffers(params: &BigTheta<T>, names: &[&str]) -> Self {
+        let zero = BigTheta::zero_from_sizes_with_normalized_layers(
+            &params.sizes,
+            &params.normalized_layers(),
+        );
+        Self {
+            steps_count: 0,
+            learning_rate_factor: 1.0,
+            buffers: names
+                .iter()
+                .map(|name| (name.to_string(), zero.clone()))
+                .collect(),
+        }
+    }
+
+    /// The learning rate for the current step, given the optimizer's configured one.
+    pub fn scheduled_learning_rate(&self, learning_rate: f64) -> f64 {
+        learning_rate * self.learning_rate_factor
+    }
+
+    pub fn buffer(&self, name: &str) -> Option<&BigTheta<T>> {
+        self.buffers.get(name)
+    }
+
+    /// Returns the named buffers, in the order of `names`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the buffers don't exist, or if a name is given more than once.
+    ///
+    pub fn buffers_mut<const N: usize>(&mut self, names: [&str; N]) -> [&mut BigTheta<T>; N] {
+        let mut found: [Option<&mut BigTheta<T>>; N] = [(); N].map(|_| None);
+        for (name, buffer) in self.buffers.iter_mut() {
+            if let Some(i) = names.iter().position(|n| n == name) {
+                found[i] = Some(buffer);
+            }
+        }
+
+        for (name, buffer) in names.iter().zip(found.iter()) {
+            if buffer.is_none() {
+                panic!("optimizer state has no {:?} buffer", name);
+            }
+        }
+        found.map(|buffer| buffer.unwrap())
+    }
+}
+
+const MOMENTUM: &str = "momentum";
+const S: &str = "s";
+const S_MAX: &str = "s_max";
+
+/// The built in optimizers, in a form that can be read from config files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OptimizerConfig {
+    #[serde(alias = "StandardGradientDescent")]
     StanardGradientDescent(StandardGradientDescentConfig),
     Momentum(MomentumConfig),
-    // Nesterov(f64, f64),
-    // AdaGrad(f64),
-    // Adam(f64, f64, f64, f64),
+    Nesterov(NesterovConfig),
+    AdaGrad(AdaGradConfig),
+    RMSProp(RMSPropConfig),
     Adam(AdamConfig),
+    AdamW(AdamWConfig),
+    AMSGrad(AMSGradConfig),
 }
 
-impl Optimizer {
+impl OptimizerConfig {
     pub fn standard_gradient_descent(learning_rate: f64) -> Self {
-        Optimizer::StanardGradientDescent(StandardGradientDescentConfig {
+        OptimizerConfig::StanardGradientDescent(StandardGradientDescentConfig {
             learning_rate: learning_rate,
         })
     }
 
     pub fn momentum(learning_rate: f64, momentum: f64) -> Self {
-        Optimizer::Momentum(MomentumConfig {
+        OptimizerConfig::Momentum(MomentumConfig {
             learning_rate: learning_rate,
             momentum: momentum,
         })
     }
+
+    pub fn nesterov(learning_rate: f64, momentum: f64) -> Self {
+        OptimizerConfig::Nesterov(NesterovConfig {
+            learning_rate,
+            momentum,
+        })
+    }
+
+    fn as_optimizer<T: Float>(&self) -> &dyn Optimizer<T> {
+        match self {
+            OptimizerConfig::StanardGradientDescent(optimizer_config) => optimizer_config,
+            OptimizerConfig::Momentum(optimizer_config) => optimizer_config,
+            OptimizerConfig::Nesterov(optimizer_config) => optimizer_config,
+            OptimizerConfig::AdaGrad(optimizer_config) => optimizer_config,
+            OptimizerConfig::RMSProp(optimizer_config) => optimizer_config,
+            OptimizerConfig::Adam(optimizer_config) => optimizer_config,
+            OptimizerConfig::AdamW(optimizer_config) => optimizer_config,
+            OptimizerConfig::AMSGrad(optimizer_config) => optimizer_config,
+        }
+    }
 }
 
-#[derive(Debug)]
+impl<T: Float> Optimizer<T> for OptimizerConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        self.as_optimizer().init_state(params)
+    }
+
+    fn learning_rate(&self) -> f64 {
+        Optimizer::<T>::learning_rate(self.as_optimizer())
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        self.as_optimizer().step(params, gradients, state)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StandardGradientDescentConfig {
     pub learning_rate: f64,
 }
 
-#[derive(Debug)]
+impl<T: Float> Optimizer<T> for StandardGradientDescentConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        OptimizerState::with_zero_buffers(params, &[])
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+        let update = gradients.mult_scalar_return_new(T::from_f64(learning_rate));
+        params.subtract_in_place(&update);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MomentumConfig {
     pub learning_rate: f64,
     pub momentum: f64,
 }
 
-#[derive(Debug)]
+impl<T: Float> Optimizer<T> for MomentumConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        OptimizerState::with_zero_buffers(params, &[MOMENTUM])
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+        let [momentum] = state.buffers_mut([MOMENTUM]);
+
+        let scaled_gradients = gradients.mult_scalar_return_new(T::from_f64(learning_rate));
+        momentum.mult_scalar_in_place(T::from_f64(self.momentum));
+        momentum.subtract_in_place(&scaled_gradients);
+        params.add_in_place(momentum);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NesterovConfig {
+    pub learning_rate: f64,
+    pub momentum: f64,
+}
+
+impl<T: Float> Optimizer<T> for NesterovConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        OptimizerState::with_zero_buffers(params, &[MOMENTUM])
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+        let [momentum] = state.buffers_mut([MOMENTUM]);
+
+        // the momentum update is the same as for Momentum, but the step looks ahead along the new momentum.
+        // this is the usual reformulation (as in the Sutskever et al. paper) that avoids computing the
+        // gradients at the look-ahead point, see https://arxiv.org/pdf/1212.0901.pdf
+        let scaled_gradients = gradients.mult_scalar_return_new(T::from_f64(learning_rate));
+        momentum.mult_scalar_in_place(T::from_f64(self.momentum));
+        momentum.subtract_in_place(&scaled_gradients);
+
+        let mut update = momentum.mult_scalar_return_new(T::from_f64(self.momentum));
+        update.subtract_in_place(&scaled_gradients);
+        params.add_in_place(&update);
+    }
+}
+
+/// Any hyperparameters missing from a config file take their `AdaGradConfig::default()` values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaGradConfig {
+    pub learning_rate: f64,
+    pub epsilon: f64,
+}
+
+impl Default for AdaGradConfig {
+    fn default() -> Self {
+        Self {
+            learning_rate: 0.01,
+            epsilon: 1e-7,
+        }
+    }
+}
+
+impl AdaGradConfig {
+    pub fn with_learning_rate(learning_rate: f64) -> Self {
+        Self {
+            learning_rate,
+            ..Self::default()
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for AdaGradConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        OptimizerState::with_zero_buffers(params, &[S])
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+        let [s] = state.buffers_mut([S]);
+
+        // s accumulates the squared gradients over the whole run
+        let mut x = gradients.clone();
+        x.elementwise_mult_in_place(gradients);
+        s.add_in_place(&x);
+
+        let update = scaled_by_root_of(gradients, s, learning_rate, self.epsilon);
+        params.subtract_in_place(&update);
+    }
+}
+
+/// Any hyperparameters missing from a config file take their `RMSPropConfig::default()` values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RMSPropConfig {
+    pub learning_rate: f64,
+    pub decay: f64, // rho in HOML
+    pub epsilon: f64,
+}
+
+impl Default for RMSPropConfig {
+    fn default() -> Self {
+        Self {
+            learning_rate: 0.001,
+            decay: 0.9,
+            epsilon: 1e-7,
+        }
+    }
+}
+
+impl RMSPropConfig {
+    pub fn with_learning_rate(learning_rate: f64) -> Self {
+        Self {
+            learning_rate,
+            ..Self::default()
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for RMSPropConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        OptimizerState::with_zero_buffers(params, &[S])
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+        let [s] = state.buffers_mut([S]);
+
+        // like AdaGrad but s only remembers the recent squared gradients
+        s.mult_scalar_in_place(T::from_f64(self.decay));
+        let mut x = gradients.clone();
+        x.elementwise_mult_in_place(gradients);
+        x.mult_scalar_in_place(T::from_f64(1.0 - self.decay));
+        s.add_in_place(&x);
+
+        let update = scaled_by_root_of(gradients, s, learning_rate, self.epsilon);
+        params.subtract_in_place(&update);
+    }
+}
+
+/// Any hyperparameters missing from a config file take their `AdamConfig::default()` values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default = "AdamConfig::default")]
 pub struct AdamConfig {
     pub learning_rate: f64,
     pub momentum_decay: f64, // beta_1 in HOML
@@ -60,3 +395,549 @@ impl AdamConfig {
         }
     }
 }
+
+impl<T: Float> Optimizer<T> for AdamConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        OptimizerState::with_zero_buffers(params, &[MOMENTUM, S])
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        let steps_count = state.steps_count;
+        let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+        let [momentum, s] = state.buffers_mut([MOMENTUM, S]);
+
+        let update = adam_update(
+            self,
+            gradients,
+            momentum,
+            s,
+            None,
+            learning_rate,
+            steps_count,
+        );
+        params.add_in_place(&update);
+    }
+}
+
+/// Adam with decoupled weight decay. Any hyperparameters missing from a config file take their
+/// `AdamWConfig::default()` values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdamWConfig {
+    pub learning_rate: f64,
+    pub momentum_decay: f64,
+    pub scaling_decay: f64,
+    pub epsilon: f64,
+    /// Each step the weights are multiplied by `1 - learning_rate * weight_decay`.
+    pub weight_decay: f64,
+}
+
+impl Default for AdamWConfig {
+    fn default() -> Self {
+        Self {
+            learning_rate: 0.001,
+            momentum_decay: 0.9,
+            scaling_decay: 0.999,
+            epsilon: 1e-7,
+            weight_decay: 0.01,
+        }
+    }
+}
+
+impl AdamWConfig {
+    pub fn with_learning_rate(learning_rate: f64) -> Self {
+        Self {
+            learning_rate,
+            ..Self::default()
+        }
+    }
+
+    fn adam_config(&self) -> AdamConfig {
+        AdamConfig {
+            learning_rate: self.learning_rate,
+            momentum_decay: self.momentum_decay,
+            scaling_decay: self.scaling_decay,
+            epsilon: self.epsilon,
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for AdamWConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        OptimizerState::with_zero_buffers(params, &[MOMENTUM, S])
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        let steps_count = state.steps_count;
+        let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+        let [momentum, s] = state.buffers_mut([MOMENTUM, S]);
+
+        // the weight decay is applied directly to the weights rather than being added to the gradients
+        // (which is what makes it different from L2 regularization with Adam), see
+        // https://arxiv.org/pdf/1711.05101.pdf. The biases aren't decayed.
+        let decay_factor = T::from_f64(1.0 - learning_rate * self.weight_decay);
+        for (_, w) in params.weights_matrices.iter_mut() {
+            w.mult_scalar_mut(decay_factor);
+        }
+
+        let update = adam_update(
+            &self.adam_config(),
+            gradients,
+            momentum,
+            s,
+            None,
+            learning_rate,
+            steps_count,
+        );
+        params.add_in_place(&update);
+    }
+}
+
+/// Adam using the max of the past `s` values. Any hyperparameters missing from a config file take their
+/// `AMSGradConfig::default()` values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AMSGradConfig {
+    pub learning_rate: f64,
+    pub momentum_decay: f64,
+    pub scaling_decay: f64,
+    pub epsilon: f64,
+}
+
+impl Default for AMSGradConfig {
+    fn default() -> Self {
+        Self {
+            learning_rate: 0.001,
+            momentum_decay: 0.9,
+            scaling_decay: 0.999,
+            epsilon: 1e-7,
+        }
+    }
+}
+
+impl AMSGradConfig {
+    pub fn with_learning_rate(learning_rate: f64) -> Self {
+        Self {
+            learning_rate,
+            ..Self::default()
+        }
+    }
+
+    fn adam_config(&self) -> AdamConfig {
+        AdamConfig {
+            learning_rate: self.learning_rate,
+            momentum_decay: self.momentum_decay,
+            scaling_decay: self.scaling_decay,
+            epsilon: self.epsilon,
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for AMSGradConfig {
+    fn init_state(&self, params: &BigTheta<T>) -> OptimizerState<T> {
+        OptimizerState::with_zero_buffers(params, &[MOMENTUM, S, S_MAX])
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(
+        &self,
+        params: &mut BigTheta<T>,
+        gradients: &BigTheta<T>,
+        state: &mut OptimizerState<T>,
+    ) {
+        let steps_count = state.steps_count;
+        let learning_rate = state.scheduled_learning_rate(self.learning_rate);
+        let [momentum, s, s_max] = state.buffers_mut([MOMENTUM, S, S_MAX]);
+
+        let update = adam_update(
+            &self.adam_config(),
+            gradients,
+            momentum,
+            s,
+            Some(s_max),
+            learning_rate,
+            steps_count,
+        );
+        params.add_in_place(&update);
+    }
+}
+
+/// Returns `learning_rate * gradients / sqrt(s + epsilon)`, elementwise.
+fn scaled_by_root_of<T: Float>(
+    gradients: &BigTheta<T>,
+    s: &BigTheta<T>,
+    learning_rate: f64,
+    epsilon: f64,
+) -> BigTheta<T> {
+    let mut root_s = s.clone();
+    root_s.add_scalar_to_each_element_in_place(T::from_f64(epsilon));
+    root_s.elementwise_square_root_in_place();
+
+    let mut update = gradients.mult_scalar_return_new(T::from_f64(learning_rate));
+    update.elementwise_divide_in_place(&root_s);
+    update
+}
+
+/// Updates the Adam `momentum` and `s` and returns the change to make to the weights and biases.
+///
+/// `learning_rate` is used in place of the config's, so that it can be scheduled. If `s_max` is given it's updated to the elementwise max of itself and `s`, and is used in place of `s` (AMSGrad).
+fn adam_update<T: Float>(
+    adam_optimizer_config: &AdamConfig,
+    gradients: &BigTheta<T>,
+    momentum: &mut BigTheta<T>,
+    s: &mut BigTheta<T>,
+    s_max: Option<&mut BigTheta<T>>,
+    learning_rate: f64,
+    steps_count: usize,
+) -> BigTheta<T> {
+    // 1. update momentum
+    momentum.mult_scalar_in_place(T::from_f64(adam_optimizer_config.momentum_decay));
+    let x =
+        gradients.mult_scalar_return_new(T::from_f64(1.0 - adam_optimizer_config.momentum_decay));
+    momentum.subtract_in_place(&x);
+
+    // 2. update s
+    s.mult_scalar_in_place(T::from_f64(adam_optimizer_config.scaling_decay));
+    let mut x = gradients.clone(); // TODO: some chaining methods on BigTheta would be nice to clean this up
+    x.elementwise_mult_in_place(gradients);
+    x.mult_scalar_in_place(T::from_f64(1.0 - adam_optimizer_config.scaling_decay)); // could make an elementwise_square
+    s.add_in_place(&x);
+
+    // AMSGrad never lets the scaling shrink, see https://openreview.net/pdf?id=ryQu7f-RZ
+    let s = match s_max {
+        Some(s_max) => {
+            s_max.elementwise_max_in_place(s);
+            s_max
+        }
+        None => s,
+    };
+
+    // compute momentum_decay_t and scaling_decay_t
+    // see https://machinelearningmastery.com/adam-optimization-from-scratch/
+    // and https://arxiv.org/pdf/1412.6980.pdf (the Adam paper)
+    // the bias correction is per step (i.e. per mini batch), not per epoch
+    let momentum_decay_t = adam_optimizer_config
+        .momentum_decay
+        .powf(1.0 + steps_count as f64);
+
+    let scaling_decay_t = adam_optimizer_config
+        .scaling_decay
+        .powf(1.0 + steps_count as f64);
+
+    // 3. create m_hat (temp value)
+    let mut m_hat = momentum.divide_scalar_return_new(T::from_f64(1.0 - momentum_decay_t));
+
+    // 4. create s_hat (temp value)
+    let mut s_hat = s.divide_scalar_return_new(T::from_f64(1.0 - scaling_decay_t));
+
+    // 5. compute the update to the weights and biases
+    m_hat.mult_scalar_in_place(T::from_f64(learning_rate));
+    s_hat.add_scalar_to_each_element_in_place(T::from_f64(adam_optimizer_config.epsilon));
+    s_hat.elementwise_square_root_in_place();
+    m_hat.elementwise_divide_in_place(&s_hat);
+    m_hat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::column_vector;
+    use common::linalg::{ColumnVector, RowsMatrixBuilder};
+
+    const SIZES: [usize; 3] = [2, 2, 1];
+
+    /// The minimum of the quadratic `0.5 * sum(a_i * (x_i - c_i)^2)`, with every weight and bias as one of the `x_i`s.
+    fn quadratic_minimum() -> BigTheta {
+        BigTheta::from_unrolled(&SIZES, &[1.0, -2.0, 3.0, 0.5, -1.0, 2.0, 4.0, -3.0, 1.5])
+    }
+
+    /// The curvatures `a_i`, different for each parameter so the problem isn't just a scaled identity.
+    fn quadratic_curvatures() -> BigTheta {
+        BigTheta::from_unrolled(&SIZES, &[1.0, 2.0, 0.5, 4.0, 1.0, 3.0, 0.25, 2.0, 1.0])
+    }
+
+    fn quadratic_gradients(params: &BigTheta) -> BigTheta {
+        let mut gradients = params.clone();
+        gradients.subtract_in_place(&quadratic_minimum());
+        gradients.elementwise_mult_in_place(&quadratic_curvatures());
+        gradients
+    }
+
+    /// Runs `optimizer` from all zeros on the quadratic and returns the final parameters.
+    fn minimize_quadratic(optimizer: &dyn Optimizer, steps: usize) -> BigTheta {
+        let mut params = BigTheta::zero_from_sizes(&SIZES);
+        let mut state = optimizer.init_state(&params);
+
+        for _ in 0..steps {
+            let gradients = quadratic_gradients(&params);
+            optimizer.step(&mut params, &gradients, &mut state);
+            state.steps_count += 1;
+        }
+        params
+    }
+
+    fn assert_close_to_minimum(params: &BigTheta, tolerance: f64) {
+        let expected = quadratic_minimum().unroll();
+        let actual = params.unroll();
+        for (x, c) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (x - c).abs() < tolerance,
+                "expected {:?} to be within {} of {:?}",
+                actual,
+                tolerance,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn standard_gradient_descent_minimizes_a_quadratic() {
+        let params = minimize_quadratic(&OptimizerConfig::standard_gradient_descent(0.2), 500);
+        assert_close_to_minimum(&params, 1e-3);
+    }
+
+    #[test]
+    fn momentum_minimizes_a_quadratic() {
+        let params = minimize_quadratic(&OptimizerConfig::momentum(0.1, 0.8), 500);
+        assert_close_to_minimum(&params, 1e-3);
+    }
+
+    #[test]
+    fn nesterov_minimizes_a_quadratic() {
+        let params = minimize_quadratic(&OptimizerConfig::nesterov(0.1, 0.8), 500);
+        assert_close_to_minimum(&params, 1e-3);
+    }
+
+    #[test]
+    fn nesterov_first_step_looks_ahead_along_the_momentum() {
+        let optimizer = OptimizerConfig::nesterov(0.1, 0.5);
+        let sizes = [1, 1];
+        let mut params = BigTheta::from_unrolled(&sizes, &[0.0, 0.0]);
+        let gradients = BigTheta::from_unrolled(&sizes, &[1.0, -2.0]);
+        let mut state = optimizer.init_state(&params);
+
+        optimizer.step(&mut params, &gradients, &mut state);
+
+        // momentum = -0.1 * g, and the step is 0.5 * momentum - 0.1 * g = -0.15 * g
+        assert_eq!(state.buffer("momentum").unwrap().unroll(), vec![-0.1, 0.2]);
+        assert_eq!(
+            params.unroll(),
+            vec![-0.15000000000000002, 0.30000000000000004]
+        );
+    }
+
+    #[test]
+    fn learning_rate_factor_scales_the_step() {
+        let optimizer = OptimizerConfig::standard_gradient_descent(0.5);
+        let sizes = [1, 1];
+        let mut params = BigTheta::zero_from_sizes(&sizes);
+        let gradients = BigTheta::from_unrolled(&sizes, &[1.0, -2.0]);
+        let mut state = optimizer.init_state(&params);
+        assert_eq!(state.learning_rate_factor, 1.0);
+
+        state.learning_rate_factor = 0.1;
+        optimizer.step(&mut params, &gradients, &mut state);
+
+        assert_eq!(Optimizer::<f64>::learning_rate(&optimizer), 0.5);
+        assert_eq!(params.unroll(), vec![-0.05, 0.1]);
+    }
+
+    #[test]
+    fn adagrad_minimizes_a_quadratic() {
+        let params = minimize_quadratic(
+            &OptimizerConfig::AdaGrad(AdaGradConfig::with_learning_rate(1.0)),
+            2000,
+        );
+        assert_close_to_minimum(&params, 1e-3);
+    }
+
+    #[test]
+    fn rmsprop_minimizes_a_quadratic() {
+        let params = minimize_quadratic(
+            &OptimizerConfig::RMSProp(RMSPropConfig::with_learning_rate(0.01)),
+            2000,
+        );
+        assert_close_to_minimum(&params, 2e-2);
+    }
+
+    #[test]
+    fn adam_minimizes_a_quadratic() {
+        let params = minimize_quadratic(
+            &OptimizerConfig::Adam(AdamConfig::with_learning_rate(0.05)),
+            2000,
+        );
+        assert_close_to_minimum(&params, 1e-3);
+    }
+
+    #[test]
+    fn adamw_minimizes_a_quadratic_with_the_weights_pulled_towards_zero() {
+        let mut config = AdamWConfig::with_learning_rate(0.05);
+        config.weight_decay = 0.0;
+        let params = minimize_quadratic(&OptimizerConfig::AdamW(config.clone()), 2000);
+        assert_close_to_minimum(&params, 1e-3);
+
+        // with weight decay the weights settle closer to zero than the unregularized minimum, the biases don't move
+        config.weight_decay = 0.1;
+        let params = minimize_quadratic(&OptimizerConfig::AdamW(config), 2000);
+        let minimum = quadratic_minimum();
+        for l in 1..SIZES.len() {
+            let w = params.get_weights_matrix(&l);
+            let w_min = minimum.get_weights_matrix(&l);
+            for (x, c) in w.data.iter().zip(w_min.data.iter()) {
+                assert!(
+                    x.abs() < c.abs(),
+                    "{} should be closer to zero than {}",
+                    x,
+                    c
+                );
+                assert_eq!(x.signum(), c.signum());
+            }
+
+            let b = params.get_bias_vector(&l).get_data_as_slice();
+            let b_min = minimum.get_bias_vector(&l).get_data_as_slice();
+            for (x, c) in b.iter().zip(b_min.iter()) {
+                assert!((x - c).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn adamw_decays_only_the_weights() {
+        let optimizer = OptimizerConfig::AdamW(AdamWConfig {
+            learning_rate: 0.1,
+            weight_decay: 0.5,
+            ..AdamWConfig::default()
+        });
+        let sizes = [2, 1];
+        let mut params = BigTheta::zero_from_sizes(&sizes);
+        *params.get_weights_matrix_mut(&1) =
+            RowsMatrixBuilder::new().with_row(&[2.0, -4.0]).build();
+        *params.get_bias_vector_mut(&1) = column_vector![3.0];
+        let gradients = BigTheta::zero_from_sizes(&sizes);
+        let mut state = optimizer.init_state(&params);
+
+        optimizer.step(&mut params, &gradients, &mut state);
+
+        // with zero gradients the Adam part of the update is zero, so only the decay is left
+        assert_eq!(params.get_weights_matrix(&1).data, vec![1.9, -3.8]);
+        assert_eq!(params.get_bias_vector(&1), &column_vector![3.0]);
+    }
+
+    #[test]
+    fn amsgrad_minimizes_a_quadratic() {
+        let params = minimize_quadratic(
+            &OptimizerConfig::AMSGrad(AMSGradConfig::with_learning_rate(0.05)),
+            2000,
+        );
+        assert_close_to_minimum(&params, 1e-3);
+    }
+
+    #[test]
+    fn amsgrad_s_max_never_decreases() {
+        let optimizer = OptimizerConfig::AMSGrad(AMSGradConfig::default());
+        let sizes = [1, 1];
+        let mut params = BigTheta::zero_from_sizes(&sizes);
+        let mut state = optimizer.init_state(&params);
+
+        // a large gradient followed by small ones - s decays but s_max holds on to the large one
+        let gradient_values = [[10.0, -10.0], [0.1, 0.1], [0.1, -0.1], [0.1, 0.1]];
+        let mut prev_s_max = state.buffer("s_max").unwrap().unroll();
+        for values in gradient_values.iter() {
+            let gradients = BigTheta::from_unrolled(&sizes, values);
+            optimizer.step(&mut params, &gradients, &mut state);
+            state.steps_count += 1;
+
+            let current_s_max = state.buffer("s_max").unwrap().unroll();
+            for ((prev, current), s) in prev_s_max
+                .iter()
+                .zip(current_s_max.iter())
+                .zip(state.buffer("s").unwrap().unroll())
+            {
+                assert!(current >= prev);
+                assert!(*current >= s);
+            }
+            prev_s_max = current_s_max;
+        }
+
+        assert!(
+            state.buffer("s").unwrap().unroll()[0] < state.buffer("s_max").unwrap().unroll()[0]
+        );
+    }
+
+    #[test]
+    fn init_state_has_a_zeroed_buffer_for_each_running_value() {
+        // with a normalized layer, so there are scales and shifts as well as weights and biases
+        let params = BigTheta::from_unrolled_with_normalized_layers(&[2, 3, 1], &[1], &[1.0; 19]);
+        let buffer_names = |optimizer: &OptimizerConfig| {
+            let state: OptimizerState = optimizer.init_state(&params);
+            assert_eq!(state.steps_count, 0);
+            for buffer in state.buffers.values() {
+                assert_eq!(
+                    buffer,
+                    &BigTheta::zero_from_sizes_with_normalized_layers(&[2, 3, 1], &[1])
+                );
+            }
+            state.buffers.keys().cloned().collect::<Vec<String>>()
+        };
+
+        assert!(buffer_names(&OptimizerConfig::standard_gradient_descent(0.1)).is_empty());
+        assert_eq!(
+            buffer_names(&OptimizerConfig::momentum(0.1, 0.9)),
+            vec!["momentum"]
+        );
+        assert_eq!(
+            buffer_names(&OptimizerConfig::RMSProp(RMSPropConfig::default())),
+            vec!["s"]
+        );
+        assert_eq!(
+            buffer_names(&OptimizerConfig::Adam(AdamConfig::default())),
+            vec!["momentum", "s"]
+        );
+        assert_eq!(
+            buffer_names(&OptimizerConfig::AMSGrad(AMSGradConfig::default())),
+            vec!["momentum", "s", "s_max"]
+        );
+    }
+
+    #[test]
+    fn buffers_mut_returns_the_buffers_in_the_order_asked_for() {
+        let params = BigTheta::zero_from_sizes(&[1, 1]);
+        let mut state: OptimizerState = OptimizerState::with_zero_buffers(&params, &["a", "b"]);
+
+        let [b, a] = state.buffers_mut(["b", "a"]);
+        b.add_scalar_to_each_element_in_place(2.0);
+        a.add_scalar_to_each_element_in_place(1.0);
+
+        assert_eq!(state.buffer("a").unwrap().unroll(), vec![1.0, 1.0]);
+        assert_eq!(state.buffer("b").unwrap().unroll(), vec![2.0, 2.0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn buffers_mut_panics_if_a_buffer_is_missing() {
+        let mut state: OptimizerState =
+            OptimizerState::with_zero_buffers(&BigTheta::zero_from_sizes(&[1, 1]), &["momentum"]);
+        state.buffers_mut(["momentum", "s"]);
+    }
+}
@@ -0,0 +1,104 @@
+use serde_derive::{Deserialize, Serialize};
+
+use common::linalg::{Float, Matrix};
+
+/// L1 and L2 penalties on the size of a layer's weights, to discourage overfitting. The biases aren't penalized.
+///
+/// The penalty `l1 * sum(|w|) + l2 / 2 * sum(w^2)` is added to the average cost over the examples, so its gradient
+/// `l1 * sign(w) + l2 * w` is added to the weight gradients whatever the mini batch size.
+/// For weight decay that is decoupled from the gradients, use the AdamW optimizer instead.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Regularization {
+    #[serde(default)]
+    pub l1: f64,
+    #[serde(default)]
+    pub l2: f64,
+}
+
+impl Regularization {
+    pub fn l1(l1: f64) -> Self {
+        Self { l1, l2: 0.0 }
+    }
+
+    pub fn l2(l2: f64) -> Self {
+        Self { l1: 0.0, l2 }
+    }
+
+    /// Both penalties have to be at least 0 (and not NaN).
+    pub fn is_valid(&self) -> bool {
+        self.l1 >= 0.0 && self.l2 >= 0.0
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.l1 == 0.0 && self.l2 == 0.0
+    }
+
+    /// The penalty for the given weights.
+    pub fn penalty<T: Float>(&self, weights: &Matrix<T>) -> f64 {
+        if self.is_none() {
+            return 0.0;
+        }
+
+        let sum_abs: f64 = weights.data.iter().map(|w| w.as_f64().abs()).sum();
+        let sum_squares: f64 = weights.data.iter().map(|w| w.as_f64().powi(2)).sum();
+        self.l1 * sum_abs + 0.5 * self.l2 * sum_squares
+    }
+
+    /// Adds the gradient of the penalty with respect to `weights` to `weight_gradients`.
+    pub fn add_penalty_gradient<T: Float>(
+        &self,
+        weights: &Matrix<T>,
+        weight_gradients: &mut Matrix<T>,
+    ) {
+        if self.is_none() {
+            return;
+        }
+
+        let l1 = T::from_f64(self.l1);
+        let l2 = T::from_f64(self.l2);
+        for (g, w) in weight_gradients.data.iter_mut().zip(weights.data.iter()) {
+            // the L1 penalty isn't differentiable at 0, this uses 0 as its (sub)gradient there
+            let sign = if *w == T::zero() {
+                T::zero()
+            } else {
+                w.signum()
+            };
+            *g += l1 * sign + l2 * *w;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::linalg::RowsMatrixBuilder;
+
+    fn weights() -> Matrix<f64> {
+        RowsMatrixBuilder::new()
+            .with_row(&[1.0, -2.0])
+            .with_row(&[0.0, 3.0])
+            .build()
+    }
+
+    #[test]
+    fn penalty_combines_l1_and_l2() {
+        assert_eq!(Regularization::default().penalty(&weights()), 0.0);
+        assert_eq!(Regularization::l1(0.5).penalty(&weights()), 3.0);
+        assert_eq!(Regularization::l2(0.5).penalty(&weights()), 3.5);
+        assert_eq!(Regularization { l1: 0.5, l2: 0.5 }.penalty(&weights()), 6.5);
+    }
+
+    #[test]
+    fn penalty_gradient_is_added_to_the_weight_gradients() {
+        let mut weight_gradients = RowsMatrixBuilder::new()
+            .with_row(&[0.1, 0.1])
+            .with_row(&[0.1, 0.1])
+            .build();
+
+        Regularization { l1: 0.5, l2: 0.25 }
+            .add_penalty_gradient(&weights(), &mut weight_gradients);
+
+        assert_eq!(weight_gradients.data, vec![0.85, -0.9, 0.1, 1.35]);
+    }
+}
@@ -0,0 +1,65 @@
+//! Seeded random number generation, so that two runs with the same seed produce identical weights and cost curves.
+//!
+//! Everything random is drawn from a `ChaCha8Rng` derived from a single seed. Each use gets its own stream of that
+//! seed, so e.g. adding a layer (and therefore drawing more initial weights) doesn't change which mini batches are
+//! selected. Random values must not be drawn from a shared RNG inside rayon tasks - the order the tasks run in isn't
+//! fixed, so the values each one got wouldn't be either.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// The independent streams drawn from a seed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RngStream {
+    /// The initial weights and biases, drawn by `NeuralNetworkBuilder`.
+    Initialization,
+    /// The mini batch selection and dropout masks in `train_stochastic`.
+    Training,
+    /// The shuffle before splitting examples into training, validation and test sets, in `DataSplit::split`.
+    DataSplit,
+}
+
+impl RngStream {
+    fn index(self) -> u64 {
+        match self {
+            RngStream::Initialization => 0,
+            RngStream::Training => 1,
+            RngStream::DataSplit => 2,
+        }
+    }
+}
+
+/// Returns the RNG for the given stream of `seed`.
+pub fn seeded_rng(seed: u64, stream: RngStream) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(stream.index());
+    rng
+}
+
+/// Picks a seed from the OS entropy source, for when none was given. It's recorded in the session info so the run can
+/// still be reproduced.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn same_seed_and_stream_give_same_values() {
+        let mut a = seeded_rng(7, RngStream::Training);
+        let mut b = seeded_rng(7, RngStream::Training);
+        for _ in 0..10 {
+            assert_eq!(a.gen::<u64>(), b.gen::<u64>());
+        }
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut a = seeded_rng(7, RngStream::Initialization);
+        let mut b = seeded_rng(7, RngStream::Training);
+        assert_ne!(a.gen::<u64>(), b.gen::<u64>());
+    }
+}
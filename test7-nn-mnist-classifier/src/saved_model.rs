@@ -0,0 +1,542 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use common::linalg::{ColumnVector, Float, Matrix, MatrixShape};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::activation::ActivationFunction;
+use crate::cost::CostFunc;
+use crate::layer_config::LayerConfig;
+use crate::normalization::{Normalization, RunningStatistics};
+use crate::regularization::Regularization;
+use crate::NeuralNetwork;
+
+/// The version of the saved model format written by this version of the code. Only files with this version can be
+/// loaded - there's no migration from older versions, so bump it whenever the layout of `SavedModel` changes.
+pub const SAVED_MODEL_FORMAT_VERSION: u32 = 5;
+
+/// Written at the start of every binary model file so that we can fail fast on files that aren't models.
+const BINARY_MAGIC: &[u8; 8] = b"NNMODEL\0";
+
+/// The on-disk format for a saved model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModelFormat {
+    /// Pretty-printed JSON. Easy to read and diff, but large.
+    Json,
+    /// Compact binary (bincode) encoding.
+    Binary,
+}
+
+impl ModelFormat {
+    /// Picks the format from the file extension - `.json` is JSON, anything else is binary.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ModelFormat::Json,
+            _ => ModelFormat::Binary,
+        }
+    }
+}
+
+/// A serializable snapshot of everything needed to re-create a `NeuralNetwork`.
+/// The weights and biases are always stored as `f64`s, whatever the precision of the network.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedModel {
+    pub format_version: u32,
+    pub sizes: Vec<usize>,
+    pub cost: CostFunc,
+    /// One entry per layer, including the input layer (which has no weights or biases).
+    pub layers: Vec<SavedLayer>,
+    /// The seed the network's initial weights and biases were drawn from, if any.
+    pub initialization_seed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedLayer {
+    pub activation_function: Option<ActivationFunction>,
+    pub initializer: Option<String>,
+    /// Only used in training, but kept so that a loaded (or resumed) network trains the same way.
+    pub regularization: Regularization,
+    /// Only used in training, like `regularization`.
+    pub dropout_rate: Option<f64>,
+    /// The weights matrix in row-major order. Empty for the input layer.
+    pub weights: Vec<f64>,
+    /// Empty for the input layer.
+    pub biases: Vec<f64>,
+    pub normalization: Option<SavedNormalization>,
+}
+
+/// A normalized layer's `Normalization` and learned parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedNormalization {
+    pub normalization: Normalization,
+    pub scales: Vec<f64>,
+    pub shifts: Vec<f64>,
+    /// The running statistics used at inference time. Empty for layer norm, which doesn't have any.
+    pub running_mean: Vec<f64>,
+    pub running_variance: Vec<f64>,
+}
+
+/// Only used to check the version before trying to deserialize the whole thing.
+#[derive(Deserialize)]
+struct SavedModelVersion {
+    format_version: u32,
+}
+
+impl SavedModel {
+    pub fn from_neural_network<T: Float>(nn: &NeuralNetwork<T>) -> Self {
+        let mut layers = Vec::new();
+
+        for l in 0..nn.num_layers() {
+            let layer_config = nn.layer_configs.get(&l).unwrap();
+
+            let (weights, biases) = if l == 0 {
+                (Vec::new(), Vec::new())
+            } else {
+                (
+                    nn.w.get(&l).unwrap().cast().data,
+                    nn.b.get(&l).unwrap().cast().get_data_as_vec(),
+                )
+            };
+
+            let normalization = layer_config.normalization.map(|normalization| {
+                let (running_mean, running_variance) = match nn.running_statistics.get(&l) {
+                    Some(running_statistics) => (
+                        running_statistics.mean.cast().get_data_as_vec(),
+                        running_statistics.variance.cast().get_data_as_vec(),
+                    ),
+                    None => (Vec::new(), Vec::new()),
+                };
+                SavedNormalization {
+                    normalization,
+                    scales: nn.scales[&l].cast().get_data_as_vec(),
+                    shifts: nn.shifts[&l].cast().get_data_as_vec(),
+                    running_mean,
+                    running_variance,
+                }
+            });
+
+            layers.push(SavedLayer {
+                activation_function: layer_config.activation_function.clone(),
+                initializer: layer_config.initializer.clone(),
+                regularization: layer_config.regularization,
+                dropout_rate: layer_config.dropout_rate,
+                weights,
+                biases,
+                normalization,
+            });
+        }
+
+        Self {
+            format_version: SAVED_MODEL_FORMAT_VERSION,
+            sizes: nn.sizes.clone(),
+            cost: nn.cost.clone(),
+            layers,
+            initialization_seed: nn.initialization_seed,
+        }
+    }
+
+    /// Re-creates the `NeuralNetwork`, checking that the weights and biases have the right shapes for `sizes`.
+    /// The weights and biases are converted to `T`.
+    pub fn into_neural_network<T: Float>(self) -> anyhow::Result<NeuralNetwork<T>> {
+        if self.sizes.len() < 2 {
+            return Err(anyhow!(
+                "a saved model needs at least an input and an output layer, got {} layers",
+                self.sizes.len()
+            ));
+        }
+
+        if self.layers.len() != self.sizes.len() {
+            return Err(anyhow!(
+                "saved model has {} layer sizes but {} layers",
+                self.sizes.len(),
+                self.layers.len()
+            ));
+        }
+
+        let mut w = HashMap::new();
+        let mut b = HashMap::new();
+        let mut scales = HashMap::new();
+        let mut shifts = HashMap::new();
+        let mut running_statistics = HashMap::new();
+        let mut layer_configs = HashMap::new();
+
+        for (l, layer) in self.layers.into_iter().enumerate() {
+            if l > 0 {
+                let w_shape = MatrixShape::new(self.sizes[l], self.sizes[l - 1]);
+                if layer.weights.len() != w_shape.data_length() {
+                    return Err(anyhow!(
+                        "layer {} should have {} weights ({}x{}) but has {}",
+                        l,
+                        w_shape.data_length(),
+                        w_shape.rows,
+                        w_shape.columns,
+                        layer.weights.len()
+                    ));
+                }
+                if layer.biases.len() != self.sizes[l] {
+                    return Err(anyhow!(
+                        "layer {} should have {} biases but has {}",
+                        l,
+                        self.sizes[l],
+                        layer.biases.len()
+                    ));
+                }
+                if layer.activation_function.is_none() {
+                    return Err(anyhow!("layer {} is missing its activation function", l));
+                }
+
+                w.insert(
+                    l,
+                    Matrix::new_with_shape_and_values(&w_shape, &layer.weights).cast(),
+                );
+                b.insert(l, ColumnVector::from_vec(layer.biases).cast());
+            }
+
+            let normalization = match layer.normalization {
+                Some(saved) => {
+                    if l == 0 || l == self.sizes.len() - 1 {
+                        return Err(anyhow!(
+                            "only hidden layers can be normalized, not layer {}",
+                            l
+                        ));
+                    }
+                    let has_running_statistics =
+                        matches!(saved.normalization, Normalization::BatchNorm { .. });
+                    let running_statistics_len = if has_running_statistics {
+                        self.sizes[l]
+                    } else {
+                        0
+                    };
+                    if saved.scales.len() != self.sizes[l]
+                        || saved.shifts.len() != self.sizes[l]
+                        || saved.running_mean.len() != running_statistics_len
+                        || saved.running_variance.len() != running_statistics_len
+                    {
+                        return Err(anyhow!(
+                            "layer {} has the wrong number of normalization parameters",
+                            l
+                        ));
+                    }
+
+                    scales.insert(l, ColumnVector::from_vec(saved.scales).cast());
+                    shifts.insert(l, ColumnVector::from_vec(saved.shifts).cast());
+                    if has_running_statistics {
+                        running_statistics.insert(
+                            l,
+                            RunningStatistics {
+                                mean: ColumnVector::from_vec(saved.running_mean),
+                                variance: ColumnVector::from_vec(saved.running_variance),
+                            }
+                            .cast(),
+                        );
+                    }
+                    Some(saved.normalization)
+                }
+                None => None,
+            };
+
+            layer_configs.insert(
+                l,
+                LayerConfig {
+                    regularization: layer.regularization,
+                    dropout_rate: layer.dropout_rate,
+                    normalization,
+                    ..LayerConfig::new_with_initializer(
+                        layer.activation_function,
+                        layer.initializer,
+                    )
+                },
+            );
+        }
+
+        Ok(NeuralNetwork {
+            sizes: self.sizes,
+            w,
+            b,
+            scales,
+            shifts,
+            running_statistics,
+            layer_configs,
+            cost: self.cost,
+            initialization_seed: self.initialization_seed,
+        })
+    }
+
+    pub fn to_bytes(&self, format: ModelFormat) -> anyhow::Result<Vec<u8>> {
+        match format {
+            ModelFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            ModelFormat::Binary => {
+                let mut bytes = BINARY_MAGIC.to_vec();
+                bytes.extend_from_slice(&self.format_version.to_le_bytes());
+                bytes.extend(bincode::serialize(self)?);
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8], format: ModelFormat) -> anyhow::Result<Self> {
+        match format {
+            ModelFormat::Json => {
+                let version: SavedModelVersion = serde_json::from_slice(bytes)
+                    .context("not a saved model - missing format_version")?;
+                check_format_version(version.format_version)?;
+                Ok(serde_json::from_slice(bytes)?)
+            }
+            ModelFormat::Binary => {
+                let header_len = BINARY_MAGIC.len() + 4;
+                if bytes.len() < header_len || &bytes[..BINARY_MAGIC.len()] != BINARY_MAGIC {
+                    return Err(anyhow!("not a binary saved model - bad header"));
+                }
+                let mut version_bytes = [0u8; 4];
+                version_bytes.copy_from_slice(&bytes[BINARY_MAGIC.len()..header_len]);
+                check_format_version(u32::from_le_bytes(version_bytes))?;
+                Ok(bincode::deserialize(&bytes[header_len..])?)
+            }
+        }
+    }
+}
+
+fn check_format_version(format_version: u32) -> anyhow::Result<()> {
+    if format_version != SAVED_MODEL_FORMAT_VERSION {
+        return Err(anyhow!(
+            "unsupported saved model format version {} (expected {})",
+            format_version,
+            SAVED_MODEL_FORMAT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+impl<T: Float> NeuralNetwork<T> {
+    /// Saves the network to `path`. The format is picked from the extension - see `ModelFormat::from_path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        self.save_with_format(path, ModelFormat::from_path(path))
+    }
+
+    pub fn save_with_format<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: ModelFormat,
+    ) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let bytes = SavedModel::from_neural_network(self).to_bytes(format)?;
+        fs::write(path, bytes).with_context(|| format!("failed writing model to {:?}", path))?;
+        Ok(())
+    }
+
+    /// Loads a network previously written with `save`. The format is picked from the extension - see `ModelFormat::from_path`.
+    /// Only files with the current `SAVED_MODEL_FORMAT_VERSION` can be loaded.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<NeuralNetwork<T>> {
+        let path = path.as_ref();
+        Self::load_with_format(path, ModelFormat::from_path(path))
+    }
+
+    pub fn load_with_format<P: AsRef<Path>>(
+        path: P,
+        format: ModelFormat,
+    ) -> anyhow::Result<NeuralNetwork<T>> {
+        let path = path.as_ref();
+        let bytes =
+            fs::read(path).with_context(|| format!("failed reading model from {:?}", path))?;
+        SavedModel::from_bytes(&bytes, format)?.into_neural_network()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::NeuralNetworkBuilder;
+    use crate::initializer::Initializer;
+    use common::column_vector;
+    use std::path::PathBuf;
+
+    fn temp_model_path(file_name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "saved-model-test-{}-{}",
+            std::process::id(),
+            file_name
+        ));
+        path
+    }
+
+    fn get_nn_for_test() -> NeuralNetwork {
+        let mut nn = NeuralNetworkBuilder::new()
+            .with_input_layer(4)
+            .with_hidden_layer(
+                8,
+                Initializer::HeForReLUAndVariants,
+                ActivationFunction::LeakyReLU(0.1),
+            )
+            .with_regularization(Regularization { l1: 1e-5, l2: 1e-4 })
+            .with_dropout(0.2)
+            .with_hidden_layer(5, Initializer::RandomBasic, ActivationFunction::Sigmoid)
+            .with_normalization(Normalization::batch_norm())
+            .with_output_layer(
+                3,
+                Initializer::XavierNormalHOMLForSigmoid,
+                ActivationFunction::Softmax,
+            )
+            .with_cost_fn(CostFunc::CrossEntropy)
+            .build();
+
+        // as though it had been trained for a bit
+        nn.scales.get_mut(&2).unwrap().set(0, 1.5);
+        nn.shifts.get_mut(&2).unwrap().set(1, -0.25);
+        let running_statistics = nn.running_statistics.get_mut(&2).unwrap();
+        running_statistics.mean.set(2, 0.7);
+        running_statistics.variance.set(3, 2.5);
+        nn
+    }
+
+    fn assert_same_network(nn: &NeuralNetwork, loaded: &NeuralNetwork) {
+        assert_eq!(loaded.sizes, nn.sizes);
+        assert_eq!(loaded.cost, nn.cost);
+        assert_eq!(loaded.w, nn.w);
+        assert_eq!(loaded.b, nn.b);
+        assert_eq!(loaded.scales, nn.scales);
+        assert_eq!(loaded.shifts, nn.shifts);
+        assert_eq!(loaded.running_statistics, nn.running_statistics);
+        assert_eq!(loaded.initialization_seed, nn.initialization_seed);
+        for l in 0..nn.num_layers() {
+            let expected = nn.layer_configs.get(&l).unwrap();
+            let actual = loaded.layer_configs.get(&l).unwrap();
+            assert_eq!(actual.activation_function, expected.activation_function);
+            assert_eq!(actual.initializer, expected.initializer);
+            assert_eq!(actual.regularization, expected.regularization);
+            assert_eq!(actual.dropout_rate, expected.dropout_rate);
+            assert_eq!(actual.normalization, expected.normalization);
+        }
+
+        let inputs = [
+            column_vector![0.0, 0.5, 1.0, -1.0],
+            column_vector![0.123456789, -7.5, 3.3333333333, 1e-9],
+        ];
+        for input_v in inputs.iter() {
+            let expected = nn.feed_forward(input_v);
+            let actual = loaded.feed_forward(input_v);
+            let expected_bits: Vec<u64> = expected.iter().map(|x| x.to_bits()).collect();
+            let actual_bits: Vec<u64> = actual.iter().map(|x| x.to_bits()).collect();
+            assert_eq!(actual_bits, expected_bits);
+        }
+    }
+
+    #[test]
+    fn json_round_trip_is_bit_identical() {
+        let nn = get_nn_for_test();
+        let path = temp_model_path("round-trip.json");
+
+        nn.save(&path).unwrap();
+        let loaded = NeuralNetwork::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_same_network(&nn, &loaded);
+        assert_eq!(
+            loaded.layer_configs.get(&1).unwrap().activation_function,
+            Some(ActivationFunction::LeakyReLU(0.1))
+        );
+    }
+
+    #[test]
+    fn binary_round_trip_is_bit_identical() {
+        let nn = get_nn_for_test();
+        let path = temp_model_path("round-trip.model");
+
+        nn.save(&path).unwrap();
+        let loaded = NeuralNetwork::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_same_network(&nn, &loaded);
+    }
+
+    #[test]
+    fn f32_round_trip_is_bit_identical() {
+        let nn = get_nn_for_test().cast::<f32>();
+        let path = temp_model_path("round-trip-f32.model");
+
+        nn.save(&path).unwrap();
+        let loaded = NeuralNetwork::<f32>::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // f32 -> f64 -> f32 is lossless
+        assert_eq!(nn.w, loaded.w);
+        assert_eq!(nn.b, loaded.b);
+    }
+
+    #[test]
+    fn binary_is_smaller_than_json() {
+        let saved_model = SavedModel::from_neural_network(&get_nn_for_test());
+        let json = saved_model.to_bytes(ModelFormat::Json).unwrap();
+        let binary = saved_model.to_bytes(ModelFormat::Binary).unwrap();
+        assert!(binary.len() < json.len());
+    }
+
+    #[test]
+    fn format_is_picked_from_extension() {
+        assert_eq!(
+            ModelFormat::from_path(Path::new("model.json")),
+            ModelFormat::Json
+        );
+        assert_eq!(
+            ModelFormat::from_path(Path::new("model.JSON")),
+            ModelFormat::Json
+        );
+        assert_eq!(
+            ModelFormat::from_path(Path::new("model.bin")),
+            ModelFormat::Binary
+        );
+        assert_eq!(
+            ModelFormat::from_path(Path::new("model")),
+            ModelFormat::Binary
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_format_version() {
+        let mut saved_model = SavedModel::from_neural_network(&get_nn_for_test());
+
+        // older versions too - they aren't migrated
+        for format_version in [
+            SAVED_MODEL_FORMAT_VERSION - 1,
+            SAVED_MODEL_FORMAT_VERSION + 1,
+        ] {
+            saved_model.format_version = format_version;
+            for format in [ModelFormat::Json, ModelFormat::Binary] {
+                let bytes = saved_model.to_bytes(format).unwrap();
+                let err = SavedModel::from_bytes(&bytes, format).unwrap_err();
+                assert!(err
+                    .to_string()
+                    .contains("unsupported saved model format version"));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_weights_shape() {
+        let mut saved_model = SavedModel::from_neural_network(&get_nn_for_test());
+        saved_model.layers[2].weights.pop();
+        assert!(saved_model.into_neural_network::<f64>().is_err());
+    }
+
+    #[test]
+    fn rejects_mismatched_normalization_parameters() {
+        let mut saved_model = SavedModel::from_neural_network(&get_nn_for_test());
+        saved_model.layers[2]
+            .normalization
+            .as_mut()
+            .unwrap()
+            .running_mean
+            .pop();
+        assert!(saved_model.into_neural_network::<f64>().is_err());
+    }
+
+    #[test]
+    fn rejects_non_model_binary_file() {
+        let err =
+            SavedModel::from_bytes(b"definitely not a model", ModelFormat::Binary).unwrap_err();
+        assert!(err.to_string().contains("bad header"));
+    }
+}
@@ -1,36 +1,226 @@
 use std::env;
 use std::fs;
+use std::io::{self, BufRead, Write};
 use std::path;
 
+use anyhow::{anyhow, Context};
+
+use crate::callbacks::{EpochEnd, TrainBegin, TrainingCallback, TrainingControl};
+use crate::checkpoint::{Checkpoint, TrainingState};
+use crate::errors::{NeuralNetworkError, TrainingOptionsError};
 use crate::NeuralNetwork;
+use common::linalg::Float;
 use metrics::epoch_timestamp;
 use serde_derive::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+const SESSION_INFO_FILE_NAME: &str = "session-info.json";
+const JSON_LINES_FILE_NAME: &str = "updates.jsonl";
+const CSV_FILE_NAME: &str = "updates.csv";
 
+/// The session's `session-info.json`, written before training starts.
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct TrainingSession {
-    training_session_id: u128,
-    start_time_epoch: u128,
-    initial_cost: f64,
-    network_config: NetworkConfig,
-    optimizer: String,
+    pub training_session_id: u128,
+    pub start_time_epoch: u128,
+    #[serde(with = "non_finite_f64")]
+    pub initial_cost: f64,
+    pub network_config: NetworkConfig,
+    pub optimizer: String,
+    /// The seed for the training RNG (see `TrainingOptions::seed`).
+    pub seed: u64,
+    /// The seed the network's initial weights and biases were drawn from (see `NeuralNetworkBuilder::with_seed`), if
+    /// they were drawn from a seeded RNG.
+    #[serde(default)]
+    pub initialization_seed: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct TrainingUpdate {
-    epoch: usize,
-    epochs_completed: usize,
-    timestamp_epoch: u128,
-    training_set_cost: f64,
-    test_set_cost: f64,
+    pub epoch: usize,
+    pub epochs_completed: usize,
+    pub steps_completed: usize,
+    pub timestamp_epoch: u128,
+    /// The learning rate used for the last step, after the learning rate schedule was applied.
+    pub learning_rate: f64,
+    /// The global L2 norm of the last step's gradients, from before they were clipped (see `GradientClipping`).
+    #[serde(default, with = "non_finite_f64")]
+    pub gradient_norm: f64,
+    #[serde(with = "non_finite_f64")]
+    pub training_set_cost: f64,
+    #[serde(with = "non_finite_f64")]
+    pub training_set_accuracy: f64,
+    /// The validation and test set costs and accuracies are only there if the training had those sets.
+    #[serde(default, with = "non_finite_optional_f64")]
+    pub validation_set_cost: Option<f64>,
+    #[serde(default, with = "non_finite_optional_f64")]
+    pub validation_set_accuracy: Option<f64>,
+    #[serde(default, with = "non_finite_optional_f64")]
+    pub test_set_cost: Option<f64>,
+    #[serde(default, with = "non_finite_optional_f64")]
+    pub test_set_accuracy: Option<f64>,
+}
+
+/// JSON has no numbers for NaN and infinity, and serde_json writes them as `null`, which can't be read back into an
+/// `f64`. So a diverged run's costs are written as strings instead ("NaN", "inf" or "-inf"), and read back from either.
+mod non_finite_f64 {
+    use serde::{Deserialize as _, Deserializer, Serializer};
+    use serde_derive::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(f64),
+        String(String),
+    }
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else {
+            serializer.serialize_str(&value.to_string())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        match NumberOrString::deserialize(deserializer)? {
+            NumberOrString::Number(value) => Ok(value),
+            NumberOrString::String(value) => value.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// `non_finite_f64` for the values that are only there with a validation or test set.
+mod non_finite_optional_f64 {
+    use serde::{Deserialize as _, Deserializer, Serializer};
+    use serde_derive::Deserialize;
+
+    #[derive(Deserialize)]
+    struct NonFinite(#[serde(with = "super::non_finite_f64")] f64);
+
+    pub fn serialize<S: Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::non_finite_f64::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<f64>, D::Error> {
+        Ok(Option::<NonFinite>::deserialize(deserializer)?.map(|NonFinite(value)| value))
+    }
+}
+
+/// The columns of `updates.csv`, in order.
+const CSV_COLUMNS: [&str; 12] = [
+    "epoch",
+    "epochs_completed",
+    "steps_completed",
+    "timestamp_epoch",
+    "learning_rate",
+    "gradient_norm",
+    "training_set_cost",
+    "training_set_accuracy",
+    "validation_set_cost",
+    "validation_set_accuracy",
+    "test_set_cost",
+    "test_set_accuracy",
+];
+
+impl TrainingUpdate {
+    /// The update as a row of `updates.csv`. The missing validation and test set values are left empty.
+    fn to_csv_row(&self) -> String {
+        fn optional(value: Option<f64>) -> String {
+            value.map(|v| v.to_string()).unwrap_or_default()
+        }
+
+        [
+            self.epoch.to_string(),
+            self.epochs_completed.to_string(),
+            self.steps_completed.to_string(),
+            self.timestamp_epoch.to_string(),
+            self.learning_rate.to_string(),
+            self.gradient_norm.to_string(),
+            self.training_set_cost.to_string(),
+            self.training_set_accuracy.to_string(),
+            optional(self.validation_set_cost),
+            optional(self.validation_set_accuracy),
+            optional(self.test_set_cost),
+            optional(self.test_set_accuracy),
+        ]
+        .join(",")
+    }
+
+    fn from_csv_row(row: &str) -> anyhow::Result<Self> {
+        let values: Vec<&str> = row.split(',').collect();
+        if values.len() != CSV_COLUMNS.len() {
+            return Err(anyhow!(
+                "expected {} values but found {}",
+                CSV_COLUMNS.len(),
+                values.len()
+            ));
+        }
+
+        fn parse<V: std::str::FromStr>(value: &str, column: usize) -> anyhow::Result<V>
+        where
+            V::Err: std::error::Error + Send + Sync + 'static,
+        {
+            value
+                .parse()
+                .with_context(|| format!("bad {}: {:?}", CSV_COLUMNS[column], value))
+        }
+        fn optional(value: &str, column: usize) -> anyhow::Result<Option<f64>> {
+            (!value.is_empty())
+                .then(|| parse(value, column))
+                .transpose()
+        }
+
+        Ok(Self {
+            epoch: parse(values[0], 0)?,
+            epochs_completed: parse(values[1], 1)?,
+            steps_completed: parse(values[2], 2)?,
+            timestamp_epoch: parse(values[3], 3)?,
+            learning_rate: parse(values[4], 4)?,
+            gradient_norm: parse(values[5], 5)?,
+            training_set_cost: parse(values[6], 6)?,
+            training_set_accuracy: parse(values[7], 7)?,
+            validation_set_cost: optional(values[8], 8)?,
+            validation_set_accuracy: optional(values[9], 9)?,
+            test_set_cost: optional(values[10], 10)?,
+            test_set_accuracy: optional(values[11], 11)?,
+        })
+    }
+}
+
+/// The cost and accuracy across a whole dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SetMetrics {
+    pub cost: f64,
+    pub accuracy: f64,
+}
+
+/// What a periodic full cost update found, for `TrainingSessionLogger::write_update`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FullCostUpdate {
+    pub epochs_completed: usize,
+    pub steps_completed: usize,
+    /// The learning rate used for the last step, after the learning rate schedule was applied.
+    pub learning_rate: f64,
+    /// The global L2 norm of the last step's gradients, from before they were clipped.
+    pub gradient_norm: f64,
+    pub training_set: SetMetrics,
+    pub validation_set: Option<SetMetrics>,
+    pub test_set: Option<SetMetrics>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct NetworkConfig {
-    layers: Vec<LoggerLayerInfo>,
+    pub layers: Vec<LoggerLayerInfo>,
 }
 
 impl NetworkConfig {
-    pub fn from_neural_network(nn: &NeuralNetwork) -> Self {
+    pub fn from_neural_network<T: Float>(nn: &NeuralNetwork<T>) -> Self {
         let mut layers = Vec::new();
 
         for l in 0..nn.sizes.len() {
@@ -53,15 +243,45 @@ impl NetworkConfig {
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
-struct LoggerLayerInfo {
-    size: usize,
-    activation_function: String,
-    initializer: Option<String>,
+pub struct LoggerLayerInfo {
+    pub size: usize,
+    pub activation_function: String,
+    pub initializer: Option<String>,
+}
+
+/// How `write_update` writes the training updates to the session directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum UpdateFormat {
+    /// A pretty-printed `epoch-<epoch>.json` file per update.
+    #[default]
+    JsonFiles,
+    /// One JSON object per line, appended to `updates.jsonl`.
+    JsonLines,
+    /// One row per update, appended to `updates.csv` after a header row. Missing values are left empty.
+    Csv,
+}
+
+/// When the appended updates (`UpdateFormat::JsonLines` and `UpdateFormat::Csv`) are written out of the logger's buffer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum FlushPolicy {
+    /// Only when the buffer fills up or the logger is dropped, which is the cheapest but means the file lags behind.
+    Buffered,
+    /// After every update, so that the file can be followed while training runs.
+    #[default]
+    EveryUpdate,
+    /// After every update, and then fsync'd, so that the updates written survive the machine crashing too.
+    Sync,
 }
 
 pub struct TrainingSessionLogger {
     pub training_session_id: u128,
     pub full_session_output_directory: Option<path::PathBuf>,
+    /// If set, a checkpoint is written to the session directory every this many epochs.
+    pub checkpoint_every: Option<usize>,
+    pub update_format: UpdateFormat,
+    pub flush_policy: FlushPolicy,
+    /// The `updates.jsonl` or `updates.csv` file, once the first update has been appended to it.
+    updates_writer: Option<io::BufWriter<fs::File>>,
 }
 
 impl TrainingSessionLogger {
@@ -70,41 +290,50 @@ impl TrainingSessionLogger {
         Self {
             training_session_id,
             full_session_output_directory: None,
+            checkpoint_every: None,
+            update_format: UpdateFormat::default(),
+            flush_policy: FlushPolicy::default(),
+            updates_writer: None,
         }
     }
 
-    pub fn create_training_log_directory(&mut self) -> std::io::Result<()> {
-        let maybe_training_log_home = std::env::var("TRAINING_LOG_HOME");
+    /// Writes a checkpoint every `checkpoint_every` epochs. It has to be at least 1 - 0 is rejected when training starts.
+    pub fn with_checkpoint_every(mut self, checkpoint_every: usize) -> Self {
+        self.checkpoint_every = Some(checkpoint_every);
+        self
+    }
 
-        let mut training_sessions_path = if let Ok(training_log_home) = maybe_training_log_home {
-            let training_home = path::PathBuf::from(training_log_home);
-            training_home
-        } else {
-            let mut training_sessions_path = env::current_dir()?;
-            println!(
-                "The current directory is {}",
-                training_sessions_path.display()
-            );
-            training_sessions_path.push(path::Path::new("training-sessions"));
-            println!(
-                "The training sessions directory is: {}",
-                training_sessions_path.display()
-            );
-            training_sessions_path
-        };
+    pub fn with_update_format(mut self, update_format: UpdateFormat) -> Self {
+        self.update_format = update_format;
+        self
+    }
+
+    pub fn with_flush_policy(mut self, flush_policy: FlushPolicy) -> Self {
+        self.flush_policy = flush_policy;
+        self
+    }
 
+    pub fn create_training_log_directory(&mut self) -> std::io::Result<()> {
+        self.create_training_log_directory_in(default_training_sessions_directory()?)
+    }
+
+    /// Creates the directory for this session inside `training_sessions_path`, which must already exist.
+    pub fn create_training_log_directory_in(
+        &mut self,
+        mut training_sessions_path: path::PathBuf,
+    ) -> std::io::Result<()> {
         // I think this code is screwy
         // make sure the directory exists and fail if it does not
         let p_exists = training_sessions_path.exists();
         if p_exists {
-            println!("dir exists: {:?}", training_sessions_path);
+            debug!(path = ?training_sessions_path, "training sessions directory exists");
             // create sub dir for this sesh
             let this_tr_sesh_segment = format!("{}", self.training_session_id);
             training_sessions_path.push(path::Path::new(&this_tr_sesh_segment));
             fs::create_dir(&training_sessions_path)?;
             self.full_session_output_directory = Some(training_sessions_path);
         } else {
-            println!("dir does not exist: {:?}", training_sessions_path);
+            warn!(path = ?training_sessions_path, "training sessions directory does not exist");
             let error_message = format!("directory does not exist: {:?}", training_sessions_path);
             return Err(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
@@ -120,6 +349,8 @@ impl TrainingSessionLogger {
         initial_cost: f64,
         network_config: NetworkConfig,
         optimizer: String,
+        seed: u64,
+        initialization_seed: Option<u64>,
     ) -> Result<(), std::io::Error> {
         let training_session = TrainingSession {
             training_session_id: self.training_session_id,
@@ -127,11 +358,13 @@ impl TrainingSessionLogger {
             initial_cost,
             network_config,
             optimizer,
+            seed,
+            initialization_seed,
         };
 
         if let Some(ref output_dir) = self.full_session_output_directory {
             let mut full_output_path = output_dir.clone();
-            full_output_path.push(path::Path::new("session-info.json"));
+            full_output_path.push(path::Path::new(SESSION_INFO_FILE_NAME));
             let serialized_graph_json_string =
                 serde_json::to_string_pretty(&training_session).unwrap();
             fs::write(&full_output_path, serialized_graph_json_string)?;
@@ -145,34 +378,536 @@ impl TrainingSessionLogger {
         Ok(())
     }
 
-    pub fn write_update(
-        &self,
-        epoch: usize,
-        epochs_completed: usize,
-        training_set_cost: f64,
-        test_set_cost: f64,
-    ) -> Result<(), std::io::Error> {
+    /// Writes the update in the logger's `update_format`.
+    pub fn write_update(&mut self, update: &FullCostUpdate) -> Result<(), std::io::Error> {
+        // the (zero based) epoch the update was computed after
+        let epoch = update.epochs_completed - 1;
         let training_update = TrainingUpdate {
             epoch,
-            epochs_completed,
+            epochs_completed: update.epochs_completed,
+            steps_completed: update.steps_completed,
             timestamp_epoch: epoch_timestamp(),
-            training_set_cost,
-            test_set_cost,
+            learning_rate: update.learning_rate,
+            gradient_norm: update.gradient_norm,
+            training_set_cost: update.training_set.cost,
+            training_set_accuracy: update.training_set.accuracy,
+            validation_set_cost: update.validation_set.map(|m| m.cost),
+            validation_set_accuracy: update.validation_set.map(|m| m.accuracy),
+            test_set_cost: update.test_set.map(|m| m.cost),
+            test_set_accuracy: update.test_set.map(|m| m.accuracy),
+        };
+
+        let output_dir = match self.full_session_output_directory {
+            Some(ref output_dir) => output_dir.clone(),
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    String::from("you need to call create_training_log_directory() to complete the setup of the training session logger"),
+                ));
+            }
+        };
+
+        let line = match self.update_format {
+            UpdateFormat::JsonFiles => {
+                let mut full_output_path = output_dir;
+                full_output_path.push(path::Path::new(&format!("epoch-{}.json", epoch)));
+                let serialized_graph_json_string =
+                    serde_json::to_string_pretty(&training_update).unwrap();
+                return fs::write(&full_output_path, serialized_graph_json_string);
+            }
+            UpdateFormat::JsonLines => serde_json::to_string(&training_update).unwrap(),
+            UpdateFormat::Csv => training_update.to_csv_row(),
         };
 
+        let writer = match self.updates_writer {
+            Some(ref mut writer) => writer,
+            None => self
+                .updates_writer
+                .insert(Self::open_updates_file(&output_dir, self.update_format)?),
+        };
+        writeln!(writer, "{}", line)?;
+        match self.flush_policy {
+            FlushPolicy::Buffered => {}
+            FlushPolicy::EveryUpdate => writer.flush()?,
+            FlushPolicy::Sync => {
+                writer.flush()?;
+                writer.get_ref().sync_data()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Opens `updates.jsonl` or `updates.csv` for appending, so that a resumed session carries on the same file, and
+    /// starts a new CSV file with the header row.
+    fn open_updates_file(
+        output_dir: &path::Path,
+        update_format: UpdateFormat,
+    ) -> std::io::Result<io::BufWriter<fs::File>> {
+        let file_name = match update_format {
+            UpdateFormat::Csv => CSV_FILE_NAME,
+            _ => JSON_LINES_FILE_NAME,
+        };
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(output_dir.join(file_name))?;
+        let is_new = file.metadata()?.len() == 0;
+
+        let mut writer = io::BufWriter::new(file);
+        if is_new && update_format == UpdateFormat::Csv {
+            writeln!(writer, "{}", CSV_COLUMNS.join(","))?;
+        }
+        Ok(writer)
+    }
+
+    /// Writes `checkpoint-epoch-<epochs_completed>.bin` to the session directory and returns its path.
+    pub fn write_checkpoint(
+        &self,
+        epochs_completed: usize,
+        checkpoint: &Checkpoint,
+    ) -> anyhow::Result<path::PathBuf> {
         if let Some(ref output_dir) = self.full_session_output_directory {
             let mut full_output_path = output_dir.clone();
-            full_output_path.push(path::Path::new(&format!("epoch-{}.json", epoch)));
-            let serialized_graph_json_string =
-                serde_json::to_string_pretty(&training_update).unwrap();
-            fs::write(&full_output_path, serialized_graph_json_string)?;
+            full_output_path.push(path::Path::new(&format!(
+                "checkpoint-epoch-{}.bin",
+                epochs_completed
+            )));
+            checkpoint.write_to_file(&full_output_path)?;
+            Ok(full_output_path)
         } else {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                String::from("you need to call create_training_log_directory() to complete the setup of the training session logger"),
-            ));
+            Err(anyhow::anyhow!(
+                "you need to call create_training_log_directory() to complete the setup of the training session logger"
+            ))
+        }
+    }
+}
+
+/// Logs a training run to the session directory: the session info when training starts, each full cost update (so a
+/// `FullCostUpdates` has to come before the logger in the callbacks) and, every `checkpoint_every` epochs, a checkpoint
+/// (in `on_epoch_state_updated`, so that it has what the other callbacks found at the end of the epoch).
+/// Failing to write any of them is logged rather than ending training, but a `checkpoint_every` of 0 is rejected when
+/// training starts.
+impl<T: Float> TrainingCallback<T> for TrainingSessionLogger {
+    fn on_train_begin(
+        &mut self,
+        nn: &NeuralNetwork<T>,
+        train_begin: &TrainBegin<T>,
+    ) -> Result<(), NeuralNetworkError> {
+        if self.checkpoint_every == Some(0) {
+            return Err(TrainingOptionsError::InvalidCheckpointEvery.into());
         }
 
+        if let Err(e) = self.write_training_session_file(
+            train_begin.initial_cost,
+            NetworkConfig::from_neural_network(nn),
+            format!("{:?}", train_begin.optimizer),
+            train_begin.training_state.seed,
+            nn.initialization_seed(),
+        ) {
+            warn!("failed writing training session info: {}", e);
+        }
         Ok(())
     }
+
+    fn on_epoch_end(&mut self, _nn: &NeuralNetwork<T>, epoch: &mut EpochEnd) -> TrainingControl {
+        if let Some(full_cost_update) = &epoch.full_cost_update {
+            if let Err(e) = self.write_update(full_cost_update) {
+                warn!(
+                    epochs = epoch.epochs_completed,
+                    "failed writing training update: {}", e
+                );
+            }
+        }
+        TrainingControl::Continue
+    }
+
+    fn on_epoch_state_updated(&mut self, nn: &NeuralNetwork<T>, training_state: &TrainingState<T>) {
+        let epochs_completed = training_state.epochs_count;
+        if let Some(checkpoint_every) = self.checkpoint_every {
+            if epochs_completed.is_multiple_of(checkpoint_every) {
+                let checkpoint = Checkpoint::new(nn, training_state);
+                match self.write_checkpoint(epochs_completed, &checkpoint) {
+                    Ok(checkpoint_path) => {
+                        info!(epochs = epochs_completed, path = ?checkpoint_path, "wrote checkpoint")
+                    }
+                    Err(e) => warn!(
+                        epochs = epochs_completed,
+                        "failed writing checkpoint: {}", e
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// `$TRAINING_LOG_HOME`, or `./training-sessions` if it isn't set.
+pub fn default_training_sessions_directory() -> std::io::Result<path::PathBuf> {
+    let maybe_training_log_home = std::env::var("TRAINING_LOG_HOME");
+
+    let training_sessions_path = if let Ok(training_log_home) = maybe_training_log_home {
+        let training_home = path::PathBuf::from(training_log_home);
+        training_home
+    } else {
+        let mut training_sessions_path = env::current_dir()?;
+        training_sessions_path.push(path::Path::new("training-sessions"));
+        debug!(
+            path = %training_sessions_path.display(),
+            "TRAINING_LOG_HOME isn't set, using the training sessions directory in the current directory"
+        );
+        training_sessions_path
+    };
+
+    Ok(training_sessions_path)
+}
+
+/// The session directories (the ones with a `session-info.json`) in `training_sessions_path`, oldest first.
+pub fn session_directories_in(
+    training_sessions_path: &path::Path,
+) -> std::io::Result<Vec<path::PathBuf>> {
+    let mut session_directories = Vec::new();
+
+    for entry in fs::read_dir(training_sessions_path)? {
+        let entry_path = entry?.path();
+        if entry_path.join(SESSION_INFO_FILE_NAME).is_file() {
+            session_directories.push(entry_path);
+        }
+    }
+
+    // the directories are named after the session id, which is the start time
+    session_directories.sort_by_key(|session_directory| {
+        let file_name = session_directory.file_name().unwrap_or_default();
+        let session_id = file_name
+            .to_str()
+            .and_then(|name| name.parse::<u128>().ok());
+        (session_id, file_name.to_os_string())
+    });
+    Ok(session_directories)
+}
+
+/// Finds the checkpoint with the most epochs completed in the given session directory, if there is one.
+pub fn latest_checkpoint_in(
+    session_directory: &path::Path,
+) -> std::io::Result<Option<path::PathBuf>> {
+    let mut latest: Option<(usize, path::PathBuf)> = None;
+
+    for entry in fs::read_dir(session_directory)? {
+        let entry_path = entry?.path();
+        let maybe_epochs_completed = entry_path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| file_name.strip_prefix("checkpoint-epoch-"))
+            .and_then(|rest| rest.strip_suffix(".bin"))
+            .and_then(|epochs_completed| epochs_completed.parse::<usize>().ok());
+
+        if let Some(epochs_completed) = maybe_epochs_completed {
+            if latest
+                .as_ref()
+                .is_none_or(|(latest_epochs, _)| epochs_completed > *latest_epochs)
+            {
+                latest = Some((epochs_completed, entry_path));
+            }
+        }
+    }
+
+    Ok(latest.map(|(_, latest_path)| latest_path))
+}
+
+/// A session directory read back in.
+#[derive(Debug, PartialEq)]
+pub struct SessionLog {
+    pub session: TrainingSession,
+    /// In the order they were written.
+    pub updates: Vec<TrainingUpdate>,
+}
+
+impl SessionLog {
+    /// Reads `session-info.json` and the training updates, from `updates.jsonl`, `updates.csv` or the `epoch-<epoch>.json`
+    /// files - whichever `UpdateFormat` the session was logged with.
+    pub fn read_from_directory<P: AsRef<path::Path>>(session_directory: P) -> anyhow::Result<Self> {
+        let session_directory = session_directory.as_ref();
+
+        let session_info_path = session_directory.join(SESSION_INFO_FILE_NAME);
+        let session = serde_json::from_str(
+            &fs::read_to_string(&session_info_path)
+                .with_context(|| format!("failed reading {:?}", session_info_path))?,
+        )
+        .with_context(|| format!("failed parsing {:?}", session_info_path))?;
+
+        let json_lines_path = session_directory.join(JSON_LINES_FILE_NAME);
+        let csv_path = session_directory.join(CSV_FILE_NAME);
+        let updates = if json_lines_path.exists() {
+            read_updates_from_lines(&json_lines_path, 0, |line| Ok(serde_json::from_str(line)?))?
+        } else if csv_path.exists() {
+            read_updates_from_lines(&csv_path, 1, TrainingUpdate::from_csv_row)?
+        } else {
+            read_updates_from_json_files(session_directory)?
+        };
+
+        Ok(Self { session, updates })
+    }
+}
+
+/// Parses every non-empty line after the first `header_lines` as an update.
+fn read_updates_from_lines(
+    path: &path::Path,
+    header_lines: usize,
+    parse_line: impl Fn(&str) -> anyhow::Result<TrainingUpdate>,
+) -> anyhow::Result<Vec<TrainingUpdate>> {
+    let file = fs::File::open(path).with_context(|| format!("failed reading {:?}", path))?;
+
+    let mut updates = Vec::new();
+    for (i, line) in io::BufReader::new(file)
+        .lines()
+        .enumerate()
+        .skip(header_lines)
+    {
+        let line = line.with_context(|| format!("failed reading {:?}", path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let update = parse_line(&line)
+            .with_context(|| format!("failed parsing line {} of {:?}", i + 1, path))?;
+        updates.push(update);
+    }
+    Ok(updates)
+}
+
+fn read_updates_from_json_files(
+    session_directory: &path::Path,
+) -> anyhow::Result<Vec<TrainingUpdate>> {
+    let mut updates = Vec::new();
+
+    for entry in fs::read_dir(session_directory)? {
+        let entry_path = entry?.path();
+        let is_update_file = entry_path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| file_name.strip_prefix("epoch-"))
+            .and_then(|rest| rest.strip_suffix(".json"))
+            .is_some_and(|epoch| epoch.parse::<usize>().is_ok());
+
+        if is_update_file {
+            let update: TrainingUpdate = serde_json::from_str(&fs::read_to_string(&entry_path)?)
+                .with_context(|| format!("failed parsing {:?}", entry_path))?;
+            updates.push(update);
+        }
+    }
+
+    updates.sort_by_key(|update| update.epoch);
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_session_directory(name: &str) -> path::PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("training-log-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn logger_for(
+        session_directory: &path::Path,
+        update_format: UpdateFormat,
+    ) -> TrainingSessionLogger {
+        let mut session_logger = TrainingSessionLogger::new().with_update_format(update_format);
+        session_logger.full_session_output_directory = Some(session_directory.to_path_buf());
+        session_logger
+    }
+
+    fn write_updates(session_logger: &mut TrainingSessionLogger, epochs: std::ops::Range<usize>) {
+        for epoch in epochs {
+            let validation_set = (epoch % 2 == 1).then_some(SetMetrics {
+                cost: 0.5 / (epoch + 1) as f64,
+                accuracy: 0.9,
+            });
+            session_logger
+                .write_update(&FullCostUpdate {
+                    epochs_completed: epoch + 1,
+                    steps_completed: 10 * (epoch + 1),
+                    learning_rate: 0.1,
+                    gradient_norm: 1.0 / 3.0,
+                    training_set: SetMetrics {
+                        cost: 1.0 / (epoch + 1) as f64,
+                        accuracy: 0.8,
+                    },
+                    validation_set,
+                    test_set: None,
+                })
+                .unwrap();
+        }
+    }
+
+    fn read_session(session_directory: &path::Path) -> SessionLog {
+        let session_log = SessionLog::read_from_directory(session_directory).unwrap();
+        fs::remove_dir_all(session_directory).unwrap();
+        session_log
+    }
+
+    fn assert_updates_read_back(updates: &[TrainingUpdate], epochs: usize) {
+        assert_eq!(updates.len(), epochs);
+        for (epoch, update) in updates.iter().enumerate() {
+            assert_eq!(update.epoch, epoch);
+            assert_eq!(update.steps_completed, 10 * (epoch + 1));
+            assert_eq!(update.gradient_norm, 1.0 / 3.0);
+            assert_eq!(update.training_set_cost, 1.0 / (epoch + 1) as f64);
+            assert_eq!(
+                update.validation_set_cost,
+                (epoch % 2 == 1).then_some(0.5 / (epoch + 1) as f64)
+            );
+            assert_eq!(update.test_set_accuracy, None);
+        }
+    }
+
+    #[test]
+    fn json_lines_updates_are_read_back_with_the_session_info() {
+        let session_directory = temp_session_directory("json-lines");
+        let mut session_logger = logger_for(&session_directory, UpdateFormat::JsonLines);
+        session_logger
+            .write_training_session_file(
+                2.5,
+                NetworkConfig { layers: vec![] },
+                "Adam".into(),
+                7,
+                Some(5),
+            )
+            .unwrap();
+        write_updates(&mut session_logger, 0..3);
+
+        let session_log = read_session(&session_directory);
+        assert_eq!(session_log.session.initial_cost, 2.5);
+        assert_eq!(session_log.session.seed, 7);
+        assert_eq!(session_log.session.initialization_seed, Some(5));
+        assert_updates_read_back(&session_log.updates, 3);
+    }
+
+    #[test]
+    fn non_finite_costs_are_read_back() {
+        let session_directory = temp_session_directory("non-finite");
+        let mut session_logger = logger_for(&session_directory, UpdateFormat::JsonLines);
+        session_logger
+            .write_training_session_file(
+                f64::INFINITY,
+                NetworkConfig { layers: vec![] },
+                "Adam".into(),
+                7,
+                None,
+            )
+            .unwrap();
+        session_logger
+            .write_update(&FullCostUpdate {
+                epochs_completed: 1,
+                steps_completed: 10,
+                learning_rate: 0.1,
+                gradient_norm: f64::NAN,
+                training_set: SetMetrics {
+                    cost: f64::NAN,
+                    accuracy: 0.1,
+                },
+                validation_set: Some(SetMetrics {
+                    cost: f64::NEG_INFINITY,
+                    accuracy: 0.1,
+                }),
+                test_set: None,
+            })
+            .unwrap();
+
+        let session_log = read_session(&session_directory);
+        assert_eq!(session_log.session.initial_cost, f64::INFINITY);
+        let update = &session_log.updates[0];
+        assert!(update.gradient_norm.is_nan());
+        assert!(update.training_set_cost.is_nan());
+        assert_eq!(update.training_set_accuracy, 0.1);
+        assert_eq!(update.validation_set_cost, Some(f64::NEG_INFINITY));
+        assert_eq!(update.test_set_cost, None);
+    }
+
+    #[test]
+    fn csv_updates_are_appended_after_a_single_header_row() {
+        let session_directory = temp_session_directory("csv");
+        let mut session_logger =
+            logger_for(&session_directory, UpdateFormat::Csv).with_flush_policy(FlushPolicy::Sync);
+        session_logger
+            .write_training_session_file(
+                2.5,
+                NetworkConfig { layers: vec![] },
+                "Adam".into(),
+                7,
+                Some(5),
+            )
+            .unwrap();
+        write_updates(&mut session_logger, 0..2);
+        drop(session_logger);
+
+        // e.g. resuming from a checkpoint
+        let mut session_logger = logger_for(&session_directory, UpdateFormat::Csv);
+        write_updates(&mut session_logger, 2..4);
+        drop(session_logger);
+
+        let csv = fs::read_to_string(session_directory.join(CSV_FILE_NAME)).unwrap();
+        assert_eq!(
+            csv.lines()
+                .filter(|line| line.starts_with("epoch,"))
+                .count(),
+            1
+        );
+
+        let session_log = read_session(&session_directory);
+        assert_updates_read_back(&session_log.updates, 4);
+    }
+
+    #[test]
+    fn session_directories_are_listed_oldest_first() {
+        let training_sessions_path = temp_session_directory("listing");
+        for session_id in [20, 3] {
+            let mut session_logger = TrainingSessionLogger::new();
+            session_logger.training_session_id = session_id;
+            session_logger
+                .create_training_log_directory_in(training_sessions_path.clone())
+                .unwrap();
+            session_logger
+                .write_training_session_file(
+                    1.0,
+                    NetworkConfig { layers: vec![] },
+                    "Adam".into(),
+                    7,
+                    Some(5),
+                )
+                .unwrap();
+        }
+        // e.g. a run that failed before training started
+        fs::create_dir(training_sessions_path.join("4")).unwrap();
+
+        let session_directories = session_directories_in(&training_sessions_path).unwrap();
+        fs::remove_dir_all(&training_sessions_path).unwrap();
+        assert_eq!(
+            session_directories,
+            vec![
+                training_sessions_path.join("3"),
+                training_sessions_path.join("20")
+            ]
+        );
+    }
+
+    #[test]
+    fn json_files_updates_are_read_back_in_epoch_order() {
+        let session_directory = temp_session_directory("json-files");
+        let mut session_logger = logger_for(&session_directory, UpdateFormat::JsonFiles);
+        session_logger
+            .write_training_session_file(
+                2.5,
+                NetworkConfig { layers: vec![] },
+                "Adam".into(),
+                7,
+                Some(5),
+            )
+            .unwrap();
+        write_updates(&mut session_logger, 0..12);
+
+        let session_log = read_session(&session_directory);
+        assert_updates_read_back(&session_log.updates, 12);
+    }
 }